@@ -1,9 +1,16 @@
-use std::{collections::BTreeMap, time::Duration};
+use std::{
+    collections::{hash_map::RandomState, BTreeMap},
+    hash::{BuildHasher, Hasher},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
 
 use futures_util::{
     future::{ready, BoxFuture, FutureExt},
     sink::SinkExt,
-    stream::{self, BoxStream, StreamExt},
+    stream::{self, BoxStream, Stream, StreamExt},
 };
 use log::*;
 use serde::de::DeserializeOwned;
@@ -28,7 +35,7 @@ use thiserror::Error;
 use tokio::{
     sync::{
         mpsc::{self, UnboundedSender},
-        oneshot,
+        oneshot, watch,
     },
     task::JoinHandle,
 };
@@ -78,13 +85,149 @@ pub enum PubsubClientError {
 
     #[error("could not find node version: {0}")]
     UnexpectedGetVersionResponse(String),
+
+    #[error("reconnect failed after {attempts} attempts: {reason}")]
+    ReconnectFailed { attempts: u32, reason: String },
+}
+
+/// Connection state of the underlying Ws
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// initial connection in progress
+    Connecting,
+    /// connected, subscriptions are active
+    Connected,
+    /// connection lost, reconnecting after `attempt` consecutive failures
+    Reconnecting { attempt: u32 },
+    /// reconnect policy exhausted, no further messages will be received
+    Failed,
+    /// shutdown by the user
+    Shutdown,
+}
+
+/// Reconnect behaviour of the `PubsubClient` when its Ws disconnects
+///
+/// Delays grow exponentially from `initial_delay` by `multiplier` per attempt, capped at `max_delay`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    /// max consecutive failed attempts before giving up, `None` retries forever
+    pub max_attempts: Option<u32>,
+    /// delay before the first attempt
+    pub initial_delay: Duration,
+    /// upper bound of any delay
+    pub max_delay: Duration,
+    /// growth factor of the delay per attempt
+    pub multiplier: u32,
+    /// fraction of each delay to randomize (0.0..=1.0), spreads out reconnecting clients
+    pub jitter: f64,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: Some(3),
+            initial_delay: Duration::from_secs(8),
+            max_delay: Duration::from_secs(64),
+            multiplier: 2,
+            jitter: 0.0,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Exponential backoff giving up after `max_attempts` consecutive failures
+    pub fn exponential(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: Some(max_attempts),
+            ..Default::default()
+        }
+    }
+    /// Exponential backoff that never gives up
+    pub fn infinite() -> Self {
+        Self {
+            max_attempts: None,
+            ..Default::default()
+        }
+    }
+    /// Set the backoff delays
+    pub fn with_backoff(
+        mut self,
+        initial_delay: Duration,
+        max_delay: Duration,
+        multiplier: u32,
+    ) -> Self {
+        self.initial_delay = initial_delay;
+        self.max_delay = max_delay;
+        self.multiplier = multiplier;
+        self
+    }
+    /// Set the fraction of each delay to randomize
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+    /// Delay before the reconnect `attempt` (starting at 1)
+    ///
+    /// Returns `None` once attempts are exhausted
+    pub fn delay(&self, attempt: u32) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| attempt > max) {
+            return None;
+        }
+        let factor = self
+            .multiplier
+            .checked_pow(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        let delay = self
+            .initial_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+        if self.jitter > 0.0 {
+            // uniform in [0, 1)
+            let rand =
+                (RandomState::new().build_hasher().finish() >> 11) as f64 / (1_u64 << 53) as f64;
+            Some(delay.mul_f64(1.0 - self.jitter * rand))
+        } else {
+            Some(delay)
+        }
+    }
 }
 
 type UnsubscribeFn = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+/// Subscription notification, or the error ending the subscription
+type NotificationMsg = Result<Value, PubsubClientError>;
 type SubscribeResponseMsg =
-    Result<(mpsc::UnboundedReceiver<Value>, UnsubscribeFn), PubsubClientError>;
+    Result<(mpsc::UnboundedReceiver<NotificationMsg>, UnsubscribeFn), PubsubClientError>;
 type SubscribeRequestMsg = (String, Value, oneshot::Sender<SubscribeResponseMsg>);
-type SubscribeResult<'a, T> = PubsubClientResult<(BoxStream<'a, T>, UnsubscribeFn)>;
+/// Subscription stream and unsubscribe fn
+type SubscribeResult<'a, T> = PubsubClientResult<(SubscriptionStream<'a, T>, UnsubscribeFn)>;
+
+/// Stream of subscription notifications
+///
+/// The stream ends if unsubscribed, the subscription is dropped by the server, a resubscribe
+/// after reconnecting fails or the reconnect policy is exhausted.
+/// The latter two are reported by `take_error`
+pub struct SubscriptionStream<'a, T> {
+    notifications: BoxStream<'a, T>,
+    error: Arc<Mutex<Option<PubsubClientError>>>,
+}
+
+impl<T> SubscriptionStream<'_, T> {
+    /// Take the error that ended the stream
+    ///
+    /// `None` while the stream is active or if it ended without error
+    pub fn take_error(&self) -> Option<PubsubClientError> {
+        self.error.lock().unwrap().take()
+    }
+}
+
+impl<T> Stream for SubscriptionStream<'_, T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.notifications.poll_next_unpin(cx)
+    }
+}
 type RequestMsg = (
     String,
     Value,
@@ -93,7 +236,7 @@ type RequestMsg = (
 
 #[derive(Clone)]
 struct SubscriptionInfo {
-    sender: UnboundedSender<Value>,
+    sender: UnboundedSender<NotificationMsg>,
     payload: String,
 }
 
@@ -106,20 +249,36 @@ pub struct PubsubClient {
     _request_sender: mpsc::UnboundedSender<RequestMsg>,
     shutdown_sender: oneshot::Sender<()>,
     ws: JoinHandle<Result<(), PubsubClientError>>,
+    state: watch::Receiver<ConnectionState>,
+    reconnect_policy: ReconnectPolicy,
     url: Url,
 }
 
 impl PubsubClient {
+    /// Create a new `PubsubClient` with the default `ReconnectPolicy`
     pub async fn new(url: &str) -> PubsubClientResult<Self> {
+        Self::new_with_reconnect_policy(url, ReconnectPolicy::default()).await
+    }
+
+    /// Create a new `PubsubClient`
+    ///
+    /// * `reconnect_policy` - controls reconnect attempts after the Ws disconnects
+    pub async fn new_with_reconnect_policy(
+        url: &str,
+        reconnect_policy: ReconnectPolicy,
+    ) -> PubsubClientResult<Self> {
         let url = Url::parse(url)?;
 
         let (subscribe_sender, subscribe_receiver) = mpsc::unbounded_channel();
         let (_request_sender, request_receiver) = mpsc::unbounded_channel();
         let (shutdown_sender, shutdown_receiver) = oneshot::channel();
+        let (state_sender, state) = watch::channel(ConnectionState::Connecting);
 
         // spawn Ws manager task
         let ws_handle = tokio::spawn(PubsubClient::run_ws(
             url.clone(),
+            reconnect_policy,
+            state_sender,
             subscribe_receiver,
             request_receiver,
             shutdown_receiver,
@@ -131,6 +290,8 @@ impl PubsubClient {
             _request_sender,
             shutdown_sender,
             ws: ws_handle,
+            state,
+            reconnect_policy,
             url,
         })
     }
@@ -147,6 +308,23 @@ impl PubsubClient {
         !self.ws.is_finished()
     }
 
+    /// Returns the current connection state
+    pub fn connection_state(&self) -> ConnectionState {
+        *self.state.borrow()
+    }
+
+    /// Returns the reconnect policy of the underlying Ws
+    ///
+    /// Subscribers may use it to pace their own resubscribe attempts
+    pub fn reconnect_policy(&self) -> ReconnectPolicy {
+        self.reconnect_policy
+    }
+
+    /// Returns a channel to watch for connection state changes
+    pub fn watch_connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.clone()
+    }
+
     pub async fn shutdown(self) -> PubsubClientResult {
         let _ = self.shutdown_sender.send(());
         self.ws.await.unwrap() // WS future should not be cancelled or panicked
@@ -165,10 +343,25 @@ impl PubsubClient {
             .await
             .map_err(|err| PubsubClientError::ConnectionClosed(err.to_string()))??;
 
+        let error = Arc::new(Mutex::new(None));
+        let notifications = UnboundedReceiverStream::new(notifications)
+            .scan(Arc::clone(&error), |error, msg| {
+                ready(match msg {
+                    Ok(value) => Some(serde_json::from_value::<T>(value).ok()),
+                    Err(err) => {
+                        *error.lock().unwrap() = Some(err);
+                        None
+                    }
+                })
+            })
+            .filter_map(ready)
+            .boxed();
+
         Ok((
-            UnboundedReceiverStream::new(notifications)
-                .filter_map(|value| ready(serde_json::from_value::<T>(value).ok()))
-                .boxed(),
+            SubscriptionStream {
+                notifications,
+                error,
+            },
             unsubscribe,
         ))
     }
@@ -335,65 +528,90 @@ impl PubsubClient {
 
     async fn run_ws(
         url: Url,
+        reconnect_policy: ReconnectPolicy,
+        state_sender: watch::Sender<ConnectionState>,
         mut subscribe_receiver: mpsc::UnboundedReceiver<SubscribeRequestMsg>,
         mut request_receiver: mpsc::UnboundedReceiver<RequestMsg>,
         mut shutdown_receiver: oneshot::Receiver<()>,
     ) -> PubsubClientResult {
         // manage Ws requests and forward subscription messages to subscribers
-        // this loop will retry indefinitely unless the consumer invokes `shutdown` or successive failures exceed maximum
-        let max_retry_count = 3;
-        let mut retry_count = 0;
+        // this loop will retry until the consumer invokes `shutdown` or the reconnect policy is exhausted
+        let mut attempt = 0;
 
         // all existing subscriptions here
         let mut request_id: u64 = 0;
         let mut subscriptions = BTreeMap::<u64, SubscriptionInfo>::new();
         let mut request_id_to_sid = BTreeMap::<u64, u64>::new();
+        // subscribe requests awaiting a response, resent on reconnect
+        let mut inflight_subscribes =
+            BTreeMap::<u64, (String, String, oneshot::Sender<SubscribeResponseMsg>)>::new();
         let (unsubscribe_sender, mut unsubscribe_receiver) = mpsc::unbounded_channel();
 
         'reconnect: loop {
             log::debug!(target: "ws", "PubsubClient connecting: {:?}", url.as_str());
-            let mut ws = match connect_async(url.as_str()).await {
-                Ok((ws, response)) => {
-                    if response.status().is_server_error() || response.status().is_client_error() {
-                        log::warn!(target: "ws", "couldn't reconnect: {response:?}");
-                        retry_count += 1;
-                        let delay = 2_u64.pow(2 + retry_count);
-                        info!(target: "ws", "PubsubClient trying reconnect after {delay}s, attempt: {retry_count}/{max_retry_count}");
-                        tokio::time::sleep(Duration::from_secs(delay)).await;
-                        continue 'reconnect;
-                    }
-
-                    retry_count = 0;
+            let connect_result = match connect_async(url.as_str()).await {
+                Ok((_ws, response))
+                    if response.status().is_server_error()
+                        || response.status().is_client_error() =>
+                {
+                    Err(format!("{response:?}"))
+                }
+                Ok((ws, _response)) => Ok(ws),
+                Err(err) => Err(format!("{err:?}")),
+            };
+            let mut ws = match connect_result {
+                Ok(ws) => {
+                    attempt = 0;
                     ws
                 }
                 Err(err) => {
-                    log::warn!(target: "ws", "couldn't reconnect: {err:?}");
-                    if retry_count >= max_retry_count {
-                        log::error!(target: "ws", "reached max reconnect attempts: {err:?}");
-                        panic!("PubsubCliwnt reached max reconnect attempts: {err:?}");
+                    log::warn!(target: "ws", "couldn't reconnect: {err}");
+                    attempt += 1;
+                    let Some(delay) = reconnect_policy.delay(attempt) else {
+                        log::error!(target: "ws", "reached max reconnect attempts: {err}");
+                        state_sender.send_replace(ConnectionState::Failed);
+                        let failed = || PubsubClientError::ReconnectFailed {
+                            attempts: attempt - 1,
+                            reason: err.clone(),
+                        };
+                        for (_, (_, _, response_sender)) in inflight_subscribes {
+                            let _ = response_sender.send(Err(failed()));
+                        }
+                        for sub in subscriptions.values() {
+                            let _ = sub.sender.send(Err(failed()));
+                        }
+                        break 'reconnect Err(failed());
+                    };
+                    state_sender.send_replace(ConnectionState::Reconnecting { attempt });
+                    info!(target: "ws", "PubsubClient trying reconnect after {delay:?}, attempt: {attempt}/{:?}", reconnect_policy.max_attempts);
+                    tokio::select! {
+                        _ = (&mut shutdown_receiver) => {
+                            log::info!(target: "ws", "PubsubClient received shutdown");
+                            state_sender.send_replace(ConnectionState::Shutdown);
+                            break 'reconnect Ok(());
+                        }
+                        _ = tokio::time::sleep(delay) => continue 'reconnect,
                     }
-                    retry_count += 1;
-                    let delay = 2_u64.pow(2 + retry_count);
-                    info!(target: "ws", "PubsubClient trying reconnect after {delay}s, attempt: {retry_count}/{max_retry_count}");
-                    tokio::time::sleep(Duration::from_secs(delay)).await;
-                    continue 'reconnect;
                 }
             };
 
-            let mut inflight_subscribes =
-                BTreeMap::<u64, (String, String, oneshot::Sender<SubscribeResponseMsg>)>::new();
             let mut inflight_unsubscribes = BTreeMap::<u64, oneshot::Sender<()>>::new();
             let mut inflight_requests = BTreeMap::<u64, oneshot::Sender<_>>::new();
 
-            // resend subscriptions
-            if !subscriptions.is_empty() {
+            // resend subscriptions, including those still awaiting a response
+            if !subscriptions.is_empty() || !inflight_subscribes.is_empty() {
                 info!(target: "ws", "resubscribing: {:?}", subscriptions.values().map(|x| x.payload.clone()).collect::<Vec<String>>());
                 if let Err(err) = ws
                     .send_all(&mut stream::iter(
                         subscriptions
                             .values()
-                            .cloned()
-                            .map(|s| Ok(Message::text(s.payload))),
+                            .map(|s| s.payload.clone())
+                            .chain(
+                                inflight_subscribes
+                                    .values()
+                                    .map(|(_, text, _)| text.clone()),
+                            )
+                            .map(|text| Ok(Message::text(text))),
                     ))
                     .await
                 {
@@ -401,6 +619,7 @@ impl PubsubClient {
                     continue 'reconnect;
                 }
             }
+            state_sender.send_replace(ConnectionState::Connected);
 
             let mut liveness_check = tokio::time::interval(Duration::from_secs(60));
             let _ = liveness_check.tick().await;
@@ -417,6 +636,7 @@ impl PubsubClient {
                         let frame = CloseFrame { code: CloseCode::Normal, reason: "".into() };
                         let _ = ws.send(Message::Close(Some(frame))).await;
                         let _ = ws.flush().await;
+                        state_sender.send_replace(ConnectionState::Shutdown);
                         break 'reconnect Ok(());
                     },
                     // Read incoming WebSocket message
@@ -451,7 +671,7 @@ impl PubsubClient {
 
                             if let Some(sub) = subscriptions.get(&sid) {
                                 let result = params.get("result");
-                                if result.exists() && sub.sender.send(serde_json::from_str(result.json()).expect("valid json")).is_err() {
+                                if result.exists() && sub.sender.send(Ok(serde_json::from_str(result.json()).expect("valid json"))).is_err() {
                                     unsubscribe_required = true;
                                 }
                             } else {
@@ -518,7 +738,8 @@ impl PubsubClient {
                                         // Subscribe Id
                                         let sid = gjson::get(text, "result");
                                         if !sid.exists() {
-                                            return Err(PubsubClientError::SubscribeFailed { reason: "invalid `result` field".into(), message: text.to_string() });
+                                            let _ = response_sender.send(Err(PubsubClientError::SubscribeFailed { reason: "invalid `result` field".into(), message: text.to_string() }));
+                                            continue 'manager;
                                         }
                                         let sid = sid.u64();
 
@@ -545,18 +766,17 @@ impl PubsubClient {
                                     }
                                 }
                             } else if let Some(previous_sid) = request_id_to_sid.remove(&id) {
+                                let sid = gjson::get(text, "result");
+                                let err = err.or_else(|| (!sid.exists()).then(|| "invalid `result` field".to_string()));
                                 match err {
                                     Some(reason) => {
+                                        // end the subscription stream, subscriber may retry
                                         log::error!(target: "ws", "resubscription failed: {:?}, {reason:?}", text);
-                                        panic!();
+                                        if let Some(sub) = subscriptions.remove(&previous_sid) {
+                                            let _ = sub.sender.send(Err(PubsubClientError::SubscribeFailed { reason, message: text.to_string() }));
+                                        }
                                     },
                                     None => {
-                                        // Subscribe Id
-                                        let sid = gjson::get(text, "result");
-                                        if !sid.exists() {
-                                            log::error!(target: "ws", "resubscription failed. invalid `result` field: {:?}", text);
-                                            panic!();
-                                        }
                                         let new_sid = sid.u64();
 
                                         info!(target: "ws", "resubscribed: {previous_sid:>} => {new_sid:?}");
@@ -612,7 +832,10 @@ impl PubsubClient {
                         inflight_requests.insert(request_id, response_sender);
                     },
                     _ = heartbeat.tick() => {
-                        ws.send(Message::Ping(Default::default())).await?;
+                        if let Err(err) = ws.send(Message::Ping(Default::default())).await {
+                            log::warn!(target: "ws", "sending heartbeat failed: {err:?}");
+                            break 'manager;
+                        }
                     },
                     _ = liveness_check.tick() => {
                         warn!(target: "ws", "PubsubClient timed out");
@@ -621,6 +844,74 @@ impl PubsubClient {
                 }
             }
             log::debug!(target: "ws", "manager finished");
+            state_sender.send_replace(ConnectionState::Reconnecting { attempt: 0 });
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reconnect_policy_delays() {
+        let policy = ReconnectPolicy::exponential(3).with_backoff(
+            Duration::from_secs(1),
+            Duration::from_secs(3),
+            2,
+        );
+        assert_eq!(policy.delay(1), Some(Duration::from_secs(1)));
+        assert_eq!(policy.delay(2), Some(Duration::from_secs(2)));
+        assert_eq!(policy.delay(3), Some(Duration::from_secs(3)));
+        assert_eq!(policy.delay(4), None);
+
+        let policy = ReconnectPolicy::infinite();
+        assert_eq!(policy.delay(1_000), Some(policy.max_delay));
+
+        let base_policy = ReconnectPolicy::infinite();
+        let jitter = 0.5;
+        let policy = base_policy.with_jitter(jitter);
+        for attempt in 1..10 {
+            let base = base_policy.delay(attempt).unwrap();
+            let delay = policy.delay(attempt).unwrap();
+            assert!(base.mul_f64(1.0 - jitter) <= delay && delay <= base);
+        }
+    }
+
+    #[tokio::test]
+    async fn subscriptions_end_with_error_when_reconnect_fails() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            // confirm the subscription, then drop the connection and refuse reconnects
+            let request = ws.next().await.unwrap().unwrap();
+            let id = gjson::get(request.to_text().unwrap(), "id").u64();
+            ws.send(Message::text(
+                json!({"jsonrpc":"2.0","result":42,"id":id}).to_string(),
+            ))
+            .await
+            .unwrap();
+        });
+
+        let policy = ReconnectPolicy::exponential(1).with_backoff(
+            Duration::from_millis(10),
+            Duration::from_millis(10),
+            1,
+        );
+        let client = PubsubClient::new_with_reconnect_policy(&format!("ws://{addr}"), policy)
+            .await
+            .unwrap();
+        let (mut slots, _unsubscribe) = client.slot_subscribe().await.unwrap();
+        assert!(slots.take_error().is_none());
+        server.await.unwrap();
+
+        assert!(slots.next().await.is_none());
+        assert!(matches!(
+            slots.take_error(),
+            Some(PubsubClientError::ReconnectFailed { attempts: 1, .. })
+        ));
+        assert_eq!(client.connection_state(), ConnectionState::Failed);
+    }
+}
@@ -0,0 +1,309 @@
+//! Batched account fetching with request coalescing
+//!
+//! Concurrent fetches arriving within a short window are merged into deduplicated
+//! `getMultipleAccounts` calls of up to 100 keys each
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures_util::future::join_all;
+use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    solana_sdk::{
+        account::Account, clock::Slot, commitment_config::CommitmentConfig, pubkey::Pubkey,
+    },
+    types::{SdkError, SdkResult},
+};
+
+const LOG_TARGET: &str = "fetcher";
+
+/// Max keys per `getMultipleAccounts` request
+pub const MAX_ACCOUNTS_PER_REQUEST: usize = 100;
+
+/// Default time to wait for more requests before fetching
+const DEFAULT_WINDOW: Duration = Duration::from_millis(5);
+
+/// Accounts (`None` if missing) and the lowest slot they were retrieved at
+type FetchResult = SdkResult<(Vec<Option<Account>>, Slot)>;
+
+struct FetchRequest {
+    pubkeys: Vec<Pubkey>,
+    response: oneshot::Sender<FetchResult>,
+}
+
+/// Fetches accounts over RPC, coalescing concurrent requests into batched `getMultipleAccounts` calls
+///
+/// Cheaply cloneable, clones share the same batches.
+/// The batching task is started on first use, on the caller's runtime. It is restarted on the next
+/// use if that runtime has shut down
+#[derive(Clone)]
+pub struct AccountFetcher {
+    rpc: Arc<RpcClient>,
+    commitment: CommitmentConfig,
+    window: Duration,
+    requests: Arc<Mutex<Option<mpsc::UnboundedSender<FetchRequest>>>>,
+}
+
+impl AccountFetcher {
+    /// Create a new `AccountFetcher`
+    ///
+    /// * `rpc` - RPC client to fetch with
+    /// * `commitment` - commitment level of fetched accounts
+    pub fn new(rpc: Arc<RpcClient>, commitment: CommitmentConfig) -> Self {
+        Self {
+            rpc,
+            commitment,
+            window: DEFAULT_WINDOW,
+            requests: Arc::default(),
+        }
+    }
+
+    /// Set the time to wait for concurrent requests before fetching
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// RPC client used for fetches
+    pub fn rpc(&self) -> &RpcClient {
+        &self.rpc
+    }
+
+    /// Commitment level of fetched accounts
+    pub fn commitment(&self) -> CommitmentConfig {
+        self.commitment
+    }
+
+    /// Fetch a single account
+    ///
+    /// Returns the account (`None` if it does not exist) and slot it was retrieved at
+    pub async fn get_account(&self, pubkey: &Pubkey) -> SdkResult<(Option<Account>, Slot)> {
+        let (mut accounts, slot) = self.get_multiple_accounts(&[*pubkey]).await?;
+        Ok((accounts.pop().flatten(), slot))
+    }
+
+    /// Fetch multiple accounts
+    ///
+    /// Returns accounts in the order of `pubkeys` (`None` if it does not exist) and the lowest slot
+    /// they were retrieved at
+    pub async fn get_multiple_accounts(
+        &self,
+        pubkeys: &[Pubkey],
+    ) -> SdkResult<(Vec<Option<Account>>, Slot)> {
+        if pubkeys.is_empty() {
+            return Ok((vec![], 0));
+        }
+        let (response, response_rx) = oneshot::channel();
+        self.sender()
+            .send(FetchRequest {
+                pubkeys: pubkeys.to_vec(),
+                response,
+            })
+            .map_err(|_| SdkError::Generic("account fetcher stopped".into()))?;
+
+        response_rx
+            .await
+            .map_err(|_| SdkError::Generic("account fetcher stopped".into()))?
+    }
+
+    /// Sender to the batching task, (re)starting it if not running
+    ///
+    /// The receiver is dropped along with the task when its runtime shuts down
+    fn sender(&self) -> mpsc::UnboundedSender<FetchRequest> {
+        let mut requests = self.requests.lock().unwrap();
+        match requests.as_ref() {
+            Some(tx) if !tx.is_closed() => tx.clone(),
+            _ => {
+                log::debug!(target: LOG_TARGET, "starting batch task");
+                let (tx, rx) = mpsc::unbounded_channel();
+                tokio::spawn(Self::run(
+                    Arc::clone(&self.rpc),
+                    self.commitment,
+                    self.window,
+                    rx,
+                ));
+                *requests = Some(tx.clone());
+                tx
+            }
+        }
+    }
+
+    /// Collect requests arriving within `window` of each other and fetch them as one batch
+    async fn run(
+        rpc: Arc<RpcClient>,
+        commitment: CommitmentConfig,
+        window: Duration,
+        mut requests: mpsc::UnboundedReceiver<FetchRequest>,
+    ) {
+        while let Some(first) = requests.recv().await {
+            let mut batch = vec![first];
+            let deadline = tokio::time::sleep(window);
+            tokio::pin!(deadline);
+            loop {
+                tokio::select! {
+                    biased;
+                    next = requests.recv() => match next {
+                        Some(request) => batch.push(request),
+                        None => break,
+                    },
+                    _ = &mut deadline => break,
+                }
+            }
+            tokio::spawn(Self::fetch_batch(Arc::clone(&rpc), commitment, batch));
+        }
+    }
+
+    async fn fetch_batch(
+        rpc: Arc<RpcClient>,
+        commitment: CommitmentConfig,
+        batch: Vec<FetchRequest>,
+    ) {
+        let keys = unique_keys(&batch);
+        log::debug!(
+            target: LOG_TARGET,
+            "fetching {} accounts for {} requests",
+            keys.len(),
+            batch.len()
+        );
+
+        let responses = join_all(keys.chunks(MAX_ACCOUNTS_PER_REQUEST).map(|chunk| {
+            let rpc = &rpc;
+            async move {
+                rpc.get_multiple_accounts_with_commitment(chunk, commitment)
+                    .await
+            }
+        }))
+        .await;
+
+        let mut fetched =
+            HashMap::<Pubkey, Result<(Option<Account>, Slot), String>>::with_capacity(keys.len());
+        for (chunk, response) in keys.chunks(MAX_ACCOUNTS_PER_REQUEST).zip(responses) {
+            match response {
+                Ok(response) => {
+                    let slot = response.context.slot;
+                    for (pubkey, account) in chunk.iter().zip(response.value) {
+                        fetched.insert(*pubkey, Ok((account, slot)));
+                    }
+                }
+                Err(err) => {
+                    log::warn!(target: LOG_TARGET, "getMultipleAccounts failed: {err:?}");
+                    let err = err.to_string();
+                    for pubkey in chunk {
+                        fetched.insert(*pubkey, Err(err.clone()));
+                    }
+                }
+            }
+        }
+
+        for request in batch {
+            let _ = request
+                .response
+                .send(collect_result(&request.pubkeys, &fetched));
+        }
+    }
+}
+
+/// Deduplicated keys of all requests in the batch, in order of first appearance
+fn unique_keys(batch: &[FetchRequest]) -> Vec<Pubkey> {
+    let mut seen = ahash::HashSet::default();
+    batch
+        .iter()
+        .flat_map(|r| r.pubkeys.iter())
+        .filter(|p| seen.insert(**p))
+        .copied()
+        .collect()
+}
+
+/// Result for a single request from the batch results
+fn collect_result(
+    pubkeys: &[Pubkey],
+    fetched: &HashMap<Pubkey, Result<(Option<Account>, Slot), String>>,
+) -> FetchResult {
+    let mut accounts = Vec::with_capacity(pubkeys.len());
+    let mut min_slot = Slot::MAX;
+    for pubkey in pubkeys {
+        match fetched.get(pubkey) {
+            Some(Ok((account, slot))) => {
+                accounts.push(account.clone());
+                min_slot = min_slot.min(*slot);
+            }
+            Some(Err(err)) => return Err(SdkError::Generic(err.clone())),
+            None => return Err(SdkError::Generic(format!("account not fetched: {pubkey}"))),
+        }
+    }
+    Ok((accounts, min_slot))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(pubkeys: &[Pubkey]) -> FetchRequest {
+        FetchRequest {
+            pubkeys: pubkeys.to_vec(),
+            response: oneshot::channel().0,
+        }
+    }
+
+    #[test]
+    fn batch_keys_deduplicated() {
+        let (a, b, c) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let batch = vec![request(&[a, b]), request(&[b, c, a])];
+        assert_eq!(unique_keys(&batch), vec![a, b, c]);
+    }
+
+    #[test]
+    fn results_split_per_request() {
+        let (a, b, c) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let account = Account {
+            lamports: 1,
+            ..Default::default()
+        };
+        let fetched = HashMap::from_iter([
+            (a, Ok((Some(account.clone()), 100))),
+            (b, Ok((None, 101))),
+            (c, Err("rpc down".to_string())),
+        ]);
+
+        let (accounts, slot) = collect_result(&[b, a], &fetched).unwrap();
+        assert_eq!(accounts, vec![None, Some(account)]);
+        assert_eq!(slot, 100);
+
+        assert!(collect_result(&[a, c], &fetched).is_err());
+    }
+
+    #[test]
+    fn restarts_after_runtime_shutdown() {
+        let fetcher = AccountFetcher::new(
+            Arc::new(RpcClient::new("http://localhost:8899".into())),
+            CommitmentConfig::confirmed(),
+        );
+        let runtime = || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+        };
+
+        let rt = runtime();
+        let first = rt.block_on(async { fetcher.sender() });
+        drop(rt);
+        assert!(first.is_closed());
+
+        let rt = runtime();
+        let second = rt.block_on(async { fetcher.sender() });
+        assert!(!second.is_closed());
+    }
+}
@@ -6,9 +6,9 @@ use std::{
 };
 
 use crate::solana_sdk::{clock::Slot, commitment_config::CommitmentConfig, pubkey::Pubkey};
-use anchor_lang::Discriminator;
+use anchor_lang::{AccountDeserialize, Discriminator};
 use bytemuck::Pod;
-use dashmap::DashMap;
+use dashmap::{mapref::entry::Entry, DashMap};
 use drift_pubsub_client::PubsubClient;
 use log::debug;
 use solana_account_decoder_client_types::UiAccountEncoding;
@@ -17,12 +17,16 @@ use solana_rpc_client_api::{
     config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
     filter::RpcFilterType,
 };
+use tokio::sync::broadcast;
 
 use crate::{
+    account_fetcher::AccountFetcher,
     constants::PROGRAM_ID,
     grpc::AccountUpdate,
     polled_account_subscriber::PolledAccountSubscriber,
-    types::{DataAndSlot, EMPTY_ACCOUNT_CALLBACK},
+    staleness::is_stale,
+    types::{DataAndSlot, SdkError, SubscriptionHandle, EMPTY_ACCOUNT_CALLBACK},
+    update_stream::{publish, RawAccountUpdate, UpdateStream, UPDATE_CHANNEL_CAPACITY},
     websocket_account_subscriber::WebsocketAccountSubscriber,
     SdkResult, UnsubHandle,
 };
@@ -39,13 +43,18 @@ pub struct AccountSlot {
 
 /// Set of subscriptions to network accounts
 ///
-/// Accounts are subscribed by either Ws or polling at fixed intervals
+/// Accounts are subscribed by either Ws or polling at fixed intervals.
+/// Subscriptions are refcounted, an account stays subscribed until every subscriber has unsubscribed
 pub struct AccountMap {
     pubsub: Arc<PubsubClient>,
     rpc: Arc<RpcClient>,
     commitment: CommitmentConfig,
     inner: Arc<DashMap<Pubkey, AccountSlot, ahash::RandomState>>,
     subscriptions: Arc<DashMap<Pubkey, AccountSub<Subscribed>, ahash::RandomState>>,
+    /// batched RPC fetcher for cache misses and resyncs
+    fetcher: AccountFetcher,
+    /// applied account updates
+    updates: broadcast::Sender<RawAccountUpdate>,
 }
 
 impl AccountMap {
@@ -56,10 +65,12 @@ impl AccountMap {
     ) -> Self {
         Self {
             pubsub,
+            fetcher: AccountFetcher::new(Arc::clone(&rpc), commitment),
             rpc,
             commitment,
             inner: Arc::default(),
             subscriptions: Arc::default(),
+            updates: broadcast::channel(UPDATE_CHANNEL_CAPACITY).0,
         }
     }
     pub fn iter_accounts_with<'a, T: Pod + Discriminator>(
@@ -75,11 +86,24 @@ impl AccountMap {
     ///
     /// * `account` pubkey to subscribe
     ///
+    /// Adds a reference if the account is already subscribed, release it with `unsubscribe_account`
     pub async fn subscribe_account(&self, account: &Pubkey) -> SdkResult<()> {
         self.subscribe_account_inner(account, EMPTY_ACCOUNT_CALLBACK)
             .await
     }
 
+    /// Subscribe account with Ws, returning a handle that unsubscribes on drop
+    ///
+    /// * `account` pubkey to subscribe
+    ///
+    pub async fn subscribe_account_handle(
+        &self,
+        account: &Pubkey,
+    ) -> SdkResult<SubscriptionHandle> {
+        self.subscribe_account(account).await?;
+        Ok(self.release_handle(*account))
+    }
+
     /// Subscribe account with Ws callback
     ///
     /// * `account` pubkey to subscribe
@@ -105,14 +129,16 @@ impl AccountMap {
     where
         F: Fn(&crate::AccountUpdate) + Send + Sync + 'static + Clone,
     {
-        if self.inner.contains_key(account) {
+        if self.acquire(account) {
             return Ok(());
         }
         debug!(target: LOG_TARGET, "subscribing: {account:?}");
 
         let user = AccountSub::new(Arc::clone(&self.pubsub), self.commitment, *account);
-        let sub = user.subscribe(Arc::clone(&self.inner), on_account).await?;
-        self.subscriptions.insert(*account, sub);
+        let sub = user
+            .subscribe(Arc::clone(&self.inner), self.updates.clone(), on_account)
+            .await?;
+        self.insert_subscription(sub);
 
         Ok(())
     }
@@ -131,6 +157,20 @@ impl AccountMap {
             .await
     }
 
+    /// Subscribe account with RPC polling, returning a handle that unsubscribes on drop
+    ///
+    /// * `account` pubkey to subscribe
+    /// * `interval` to poll the account
+    ///
+    pub async fn subscribe_account_polled_handle(
+        &self,
+        account: &Pubkey,
+        interval: Option<Duration>,
+    ) -> SdkResult<SubscriptionHandle> {
+        self.subscribe_account_polled(account, interval).await?;
+        Ok(self.release_handle(*account))
+    }
+
     pub async fn subscribe_account_polled_with_callback<F>(
         &self,
         account: &Pubkey,
@@ -159,7 +199,7 @@ impl AccountMap {
     where
         F: Fn(&crate::AccountUpdate) + Send + Sync + 'static + Clone,
     {
-        if self.inner.contains_key(account) {
+        if self.acquire(account) {
             return Ok(());
         }
         debug!(
@@ -168,62 +208,158 @@ impl AccountMap {
         );
 
         let user = AccountSub::polled(Arc::clone(&self.rpc), *account, interval);
-        let sub = user.subscribe(Arc::clone(&self.inner), on_account).await?;
-        self.subscriptions.insert(*account, sub);
+        let sub = user
+            .subscribe(Arc::clone(&self.inner), self.updates.clone(), on_account)
+            .await?;
+        self.insert_subscription(sub);
 
         Ok(())
     }
 
+    /// Acquire a handle to an existing subscription of `account`
+    ///
+    /// Works with any subscription kind, e.g. to keep a gRPC subscribed account alive.
+    /// Returns `None` if the account is not subscribed
+    pub fn account_handle(&self, account: &Pubkey) -> Option<SubscriptionHandle> {
+        if self.acquire(account) {
+            Some(self.release_handle(*account))
+        } else {
+            None
+        }
+    }
+
+    /// Add a reference to an existing subscription, returns false if there is none
+    fn acquire(&self, account: &Pubkey) -> bool {
+        match self.subscriptions.get_mut(account) {
+            Some(mut sub) => {
+                sub.state.refs += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Store a new subscription, merging with any made concurrently for the same account
+    fn insert_subscription(&self, sub: AccountSub<Subscribed>) {
+        match self.subscriptions.entry(sub.pubkey) {
+            Entry::Occupied(mut existing) => {
+                existing.get_mut().state.refs += 1;
+                drop(existing);
+                let _ = sub.unsubscribe();
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(sub);
+            }
+        }
+    }
+
+    /// Handle releasing one reference to the `account` subscription on drop
+    fn release_handle(&self, account: Pubkey) -> SubscriptionHandle {
+        let subscriptions = Arc::clone(&self.subscriptions);
+        let inner = Arc::clone(&self.inner);
+        SubscriptionHandle::new(move || release(&subscriptions, &inner, &account))
+    }
+
     /// On account hook for gRPC subscriber
     pub fn on_account_fn(&self) -> impl Fn(&AccountUpdate) {
         let accounts = Arc::clone(&self.inner);
         let subscriptions = Arc::clone(&self.subscriptions);
+        let updates = self.updates.clone();
         move |update| {
             if update.lamports == 0 {
                 accounts.remove(&update.pubkey);
                 return;
             }
-            accounts
+            let mut stale = false;
+            let entry = accounts
                 .entry(update.pubkey)
                 .and_modify(|x| {
                     if update.write_version < x.write_version {
                         log::debug!(target: LOG_TARGET, "skip stale update pubkey={:?}. update: {}, current: {}", update.pubkey, update.write_version, x.write_version);
+                        stale = true;
                         return;
                     }
                     x.slot = update.slot;
                     x.raw = Arc::from(update.data);
                 })
                 .or_insert({
-                    subscriptions.insert(
-                        update.pubkey,
-                        AccountSub {
+                    // held by the gRPC subscriber
+                    subscriptions
+                        .entry(update.pubkey)
+                        .or_insert_with(|| AccountSub {
                             pubkey: update.pubkey,
                             subscription: SubscriptionImpl::Grpc,
                             state: Subscribed {
                                 unsub: Mutex::default(),
+                                refs: 1,
                             },
-                        },
-                    );
+                        });
                     AccountSlot {
                         slot: update.slot,
                         raw: Arc::from(update.data),
                         write_version: update.write_version,
                     }
                 });
+            if !stale {
+                publish(&updates, || RawAccountUpdate {
+                    pubkey: update.pubkey,
+                    slot: entry.slot,
+                    data: Arc::clone(&entry.raw),
+                });
+            }
         }
     }
     /// Unsubscribe user account
+    ///
+    /// Releases one reference, the subscription ends once no references remain
     pub fn unsubscribe_account(&self, account: &Pubkey) {
-        if let Some((acc, sub)) = self.subscriptions.remove(account) {
-            debug!(target: LOG_TARGET, "unsubscribing: {acc:?}");
-            self.inner.remove(account);
-            let _ = sub.unsubscribe();
-        }
+        release(&self.subscriptions, &self.inner, account);
+    }
+    /// Stream of updates to accounts of type `T` matching `filter`
+    ///
+    /// Yields Ws, polled and gRPC updates of subscribed accounts along with their pubkey
+    pub fn account_updates<T, F>(&self, filter: F) -> UpdateStream<(Pubkey, DataAndSlot<T>)>
+    where
+        T: Pod + Discriminator + Send,
+        F: Fn(&Pubkey, &T) -> bool + Send + 'static,
+    {
+        UpdateStream::new(self.updates.subscribe(), move |update: RawAccountUpdate| {
+            if !update.data.starts_with(T::DISCRIMINATOR) {
+                return None;
+            }
+            let data = bytemuck::try_pod_read_unaligned::<T>(&update.data[8..]).ok()?;
+            filter(&update.pubkey, &data).then_some((
+                update.pubkey,
+                DataAndSlot {
+                    slot: update.slot,
+                    data,
+                },
+            ))
+        })
+    }
+    /// Batched RPC fetcher shared by this map
+    ///
+    /// Use it to fetch accounts not present in the map
+    pub fn fetcher(&self) -> &AccountFetcher {
+        &self.fetcher
     }
     /// Return data of the given `account` as T, if it exists
     pub fn account_data<T: Pod>(&self, account: &Pubkey) -> Option<T> {
         self.account_data_and_slot(account).map(|x| x.data)
     }
+    /// Return data of the given `account` deserialized as T and slot, if it exists
+    ///
+    /// For variable length accounts that are not `Pod` e.g. `ConstituentTargetBase`
+    pub fn account_data_deserialized<T: AccountDeserialize>(
+        &self,
+        account: &Pubkey,
+    ) -> Option<SdkResult<DataAndSlot<T>>> {
+        self.inner.get(account).map(|x| {
+            T::try_deserialize(&mut x.raw.as_ref())
+                .map(|data| DataAndSlot { slot: x.slot, data })
+                .map_err(|err| SdkError::Anchor(Box::new(err)))
+        })
+    }
     /// Return data of the given `account` as T and slot, if it exists
     pub fn account_data_and_slot<T: Pod>(&self, account: &Pubkey) -> Option<DataAndSlot<T>> {
         self.inner.get(account).map(|x| {
@@ -239,17 +375,27 @@ impl AccountMap {
     }
 
     pub async fn sync_stats_accounts(&self) -> SdkResult<()> {
+        self.sync_stats_accounts_with_filters(vec![]).await
+    }
+
+    /// Sync `UserStats` accounts matching all of `filters`
+    pub async fn sync_stats_accounts_with_filters(
+        &self,
+        mut filters: Vec<RpcFilterType>,
+    ) -> SdkResult<()> {
         // TODO: rust sdk does not surface with_context slot on GPA
         let slot = self
             .rpc
             .get_slot_with_commitment(CommitmentConfig::confirmed())
             .await?;
+        filters.insert(0, crate::memcmp::get_user_stats_filter());
+
         let stats_sync_result = self
             .rpc
             .get_program_ui_accounts_with_config(
                 &PROGRAM_ID,
                 RpcProgramAccountsConfig {
-                    filters: Some(vec![crate::memcmp::get_user_stats_filter()]),
+                    filters: Some(filters),
                     account_config: RpcAccountInfoConfig {
                         encoding: Some(UiAccountEncoding::Base64Zstd),
                         ..Default::default()
@@ -312,10 +458,146 @@ impl AccountMap {
 
         Ok(())
     }
+
+    /// Re-fetch all gRPC subscribed accounts over RPC
+    ///
+    /// Used to recover updates missed while a gRPC stream was disconnected.
+    /// Entries are only replaced when the RPC response is at least as recent as the cached slot
+    pub async fn resync_grpc_accounts(&self) -> SdkResult<()> {
+        let pubkeys: Vec<Pubkey> = self
+            .subscriptions
+            .iter()
+            .filter(|s| matches!(s.subscription, SubscriptionImpl::Grpc))
+            .map(|s| *s.key())
+            .collect();
+        debug!(target: LOG_TARGET, "resync {} gRPC accounts", pubkeys.len());
+
+        let (accounts, slot) = self.fetcher.get_multiple_accounts(&pubkeys).await?;
+        for (pubkey, account) in pubkeys.iter().zip(accounts) {
+            match account {
+                Some(account) => {
+                    if let Some(mut entry) = self.inner.get_mut(pubkey) {
+                        if entry.slot <= slot {
+                            let changed = *entry.raw != *account.data;
+                            entry.slot = slot;
+                            entry.raw = Arc::from(account.data.as_slice());
+                            if changed {
+                                publish(&self.updates, || RawAccountUpdate {
+                                    pubkey: *pubkey,
+                                    slot,
+                                    data: Arc::clone(&entry.raw),
+                                });
+                            }
+                        }
+                    }
+                }
+                None => {
+                    // closed while disconnected
+                    self.inner.remove(pubkey);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Re-fetch Ws subscribed accounts whose cached slot is more than `max_age` slots behind
+    /// `current_slot`
+    ///
+    /// gRPC and polled accounts are skipped. The fetched data replaces the cache and an account
+    /// whose data differs from the cache gets its Ws subscription restarted
+    ///
+    /// Returns the pubkeys of stale accounts
+    pub async fn resync_stale_ws_accounts(
+        &self,
+        current_slot: Slot,
+        max_age: u64,
+    ) -> SdkResult<Vec<Pubkey>> {
+        let ws_accounts: Vec<Pubkey> = self
+            .subscriptions
+            .iter()
+            .filter(|s| matches!(s.subscription, SubscriptionImpl::Ws(_)))
+            .map(|s| *s.key())
+            .collect();
+        let stale: Vec<Pubkey> = ws_accounts
+            .into_iter()
+            .filter(|pubkey| {
+                self.inner
+                    .get(pubkey)
+                    .is_none_or(|x| is_stale(x.slot, current_slot, max_age))
+            })
+            .collect();
+
+        let (accounts, slot) = self.fetcher.get_multiple_accounts(&stale).await?;
+        for (pubkey, account) in stale.iter().zip(accounts) {
+            let data = account.map(|a| a.data);
+            let missed_update = match self.inner.get(pubkey) {
+                // Ws update arrived in the meantime
+                Some(cached) if cached.slot >= slot => continue,
+                Some(cached) => data.as_deref() != Some(&*cached.raw),
+                None => data.is_some(),
+            };
+            if missed_update {
+                log::warn!(target: LOG_TARGET, "stale subscription: {pubkey:?}");
+                if let Some(sub) = self.subscriptions.get(pubkey) {
+                    if let SubscriptionImpl::Ws(ref ws) = sub.subscription {
+                        ws.resubscribe();
+                    }
+                }
+            }
+            match data {
+                Some(data) => {
+                    let entry = self
+                        .inner
+                        .entry(*pubkey)
+                        .and_modify(|x| {
+                            x.slot = slot;
+                            x.raw = Arc::from(data.as_slice());
+                        })
+                        .or_insert(AccountSlot {
+                            slot,
+                            raw: Arc::from(data.as_slice()),
+                            write_version: 0,
+                        });
+                    if missed_update {
+                        publish(&self.updates, || RawAccountUpdate {
+                            pubkey: *pubkey,
+                            slot,
+                            data: Arc::clone(&entry.raw),
+                        });
+                    }
+                }
+                None => {
+                    self.inner.remove(pubkey);
+                }
+            }
+        }
+
+        Ok(stale)
+    }
+}
+
+/// Release one reference to the `account` subscription, unsubscribing on the last
+fn release(
+    subscriptions: &DashMap<Pubkey, AccountSub<Subscribed>, ahash::RandomState>,
+    inner: &DashMap<Pubkey, AccountSlot, ahash::RandomState>,
+    account: &Pubkey,
+) {
+    let removed = subscriptions.remove_if_mut(account, |_, sub| {
+        sub.state.refs = sub.state.refs.saturating_sub(1);
+        sub.state.refs == 0
+    });
+    if let Some((acc, sub)) = removed {
+        debug!(target: LOG_TARGET, "unsubscribing: {acc:?}");
+        inner.remove(account);
+        let _ = sub.unsubscribe();
+    }
 }
 
 struct Subscribed {
     unsub: Mutex<Option<UnsubHandle>>,
+    /// number of subscribers holding the subscription
+    refs: usize,
 }
 struct Unsubscribed;
 
@@ -359,6 +641,7 @@ impl AccountSub<Unsubscribed> {
     pub async fn subscribe<F>(
         self,
         accounts: Arc<DashMap<Pubkey, AccountSlot, ahash::RandomState>>,
+        updates: broadcast::Sender<RawAccountUpdate>,
         on_account: F,
     ) -> SdkResult<AccountSub<Subscribed>>
     where
@@ -367,13 +650,14 @@ impl AccountSub<Unsubscribed> {
         let unsub = match self.subscription {
             SubscriptionImpl::Ws(ref ws) => {
                 let on_account = on_account.clone();
+                let updates = updates.clone();
                 let unsub = ws
                     .subscribe(Self::SUBSCRIPTION_ID, true, move |update| {
                         if update.lamports == 0 {
                             accounts.remove(&update.pubkey);
                             return;
                         }
-                        accounts
+                        let entry = accounts
                             .entry(update.pubkey)
                             .and_modify(|x| {
                                 x.slot = update.slot;
@@ -384,6 +668,12 @@ impl AccountSub<Unsubscribed> {
                                 slot: update.slot,
                                 write_version: 0,
                             });
+                        publish(&updates, || RawAccountUpdate {
+                            pubkey: update.pubkey,
+                            slot: update.slot,
+                            data: Arc::clone(&entry.raw),
+                        });
+                        drop(entry);
 
                         on_account(update);
                     })
@@ -397,7 +687,7 @@ impl AccountSub<Unsubscribed> {
                         accounts.remove(&update.pubkey);
                         return;
                     }
-                    accounts
+                    let entry = accounts
                         .entry(update.pubkey)
                         .and_modify(|x| {
                             x.slot = update.slot;
@@ -408,6 +698,12 @@ impl AccountSub<Unsubscribed> {
                             slot: update.slot,
                             write_version: 0,
                         });
+                    publish(&updates, || RawAccountUpdate {
+                        pubkey: update.pubkey,
+                        slot: update.slot,
+                        data: Arc::clone(&entry.raw),
+                    });
+                    drop(entry);
 
                     on_account(update);
                 });
@@ -421,6 +717,7 @@ impl AccountSub<Unsubscribed> {
             subscription: self.subscription,
             state: Subscribed {
                 unsub: Mutex::new(unsub),
+                refs: 1,
             },
         })
     }
@@ -516,6 +813,13 @@ mod tests {
 
             let state_account = account_map.account_data::<State>(state_account());
             assert!(state_account.is_some());
+
+            // subscription outlives the first unsubscriber
+            let user_2_handle = account_map.account_handle(&user_2).expect("subscribed");
+            account_map.unsubscribe_account(&user_2);
+            assert!(account_map.account_data::<User>(&user_2).is_some());
+            drop(user_2_handle);
+            assert!(account_map.account_data::<User>(&user_2).is_none());
         });
 
         assert!(handle.await.is_ok());
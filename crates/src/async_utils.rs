@@ -19,6 +19,12 @@ pub mod retry_policy {
         /// called pre-retry, returns whether retry should proceed or not
         fn check(&mut self, _attempts: u32) -> BoxFuture<'_, bool>;
     }
+
+    impl TaskRetryPolicy for Box<dyn TaskRetryPolicy> {
+        fn check(&mut self, attempts: u32) -> BoxFuture<'_, bool> {
+            (**self).check(attempts)
+        }
+    }
     /// Create a new fail fast policy
     pub fn never() -> FailFast {
         FailFast {}
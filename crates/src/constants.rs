@@ -16,6 +16,10 @@ pub const SYSVAR_INSTRUCTIONS_PUBKEY: Pubkey =
 pub const SYSVAR_RENT_PUBKEY: Pubkey =
     solana_pubkey::pubkey!("SysvarRent111111111111111111111111111111111");
 
+/// https://github.com/solana-foundation/solana-web3.js/blob/4e9988cfc561f3ed11f4c5016a29090a61d129a8/src/sysvar.ts
+pub const SYSVAR_CLOCK_PUBKEY: Pubkey =
+    solana_pubkey::pubkey!("SysvarC1ock11111111111111111111111111111111");
+
 /// Drift program address
 pub const PROGRAM_ID: Pubkey =
     solana_pubkey::pubkey!("dRiftyHA39MWEi3m9aunc5MzRF1JYuBsbn6VPcn33UH");
@@ -127,6 +131,79 @@ pub fn derive_revenue_share_escrow(authority: &Pubkey) -> Pubkey {
     account
 }
 
+/// calculate the PDA of an LP pool given id
+pub fn derive_lp_pool(lp_pool_id: u8) -> Pubkey {
+    let (account, _seed) =
+        Pubkey::find_program_address(&[&b"lp_pool"[..], &[lp_pool_id]], &PROGRAM_ID);
+    account
+}
+
+/// calculate the PDA of an LP pool's constituent given its spot market index
+pub fn derive_constituent(lp_pool: &Pubkey, spot_market_index: u16) -> Pubkey {
+    let (account, _seed) = Pubkey::find_program_address(
+        &[
+            &b"CONSTITUENT"[..],
+            lp_pool.as_ref(),
+            &spot_market_index.to_le_bytes(),
+        ],
+        &PROGRAM_ID,
+    );
+    account
+}
+
+/// calculate the PDA of an LP pool constituent's token vault given its spot market index
+pub fn derive_constituent_vault(lp_pool: &Pubkey, spot_market_index: u16) -> Pubkey {
+    let (account, _seed) = Pubkey::find_program_address(
+        &[
+            &b"CONSTITUENT_VAULT"[..],
+            lp_pool.as_ref(),
+            &spot_market_index.to_le_bytes(),
+        ],
+        &PROGRAM_ID,
+    );
+    account
+}
+
+/// calculate the PDA of an LP pool's LP token vault
+pub fn derive_lp_pool_token_vault(lp_pool: &Pubkey) -> Pubkey {
+    let (account, _seed) = Pubkey::find_program_address(
+        &[&b"LP_POOL_TOKEN_VAULT"[..], lp_pool.as_ref()],
+        &PROGRAM_ID,
+    );
+    account
+}
+
+/// calculate the PDA of an LP pool's perp market to constituent mapping
+pub fn derive_amm_constituent_mapping(lp_pool: &Pubkey) -> Pubkey {
+    let (account, _seed) =
+        Pubkey::find_program_address(&[&b"AMM_MAP"[..], lp_pool.as_ref()], &PROGRAM_ID);
+    account
+}
+
+/// calculate the PDA of an LP pool's constituent target base
+pub fn derive_constituent_target_base(lp_pool: &Pubkey) -> Pubkey {
+    let (account, _seed) = Pubkey::find_program_address(
+        &[&b"constituent_target_base"[..], lp_pool.as_ref()],
+        &PROGRAM_ID,
+    );
+    account
+}
+
+/// calculate the PDA of an LP pool's constituent correlations
+pub fn derive_constituent_correlations(lp_pool: &Pubkey) -> Pubkey {
+    let (account, _seed) = Pubkey::find_program_address(
+        &[&b"constituent_correlations"[..], lp_pool.as_ref()],
+        &PROGRAM_ID,
+    );
+    account
+}
+
+/// calculate the PDA of the perp market AMM cache
+pub fn derive_amm_cache() -> Pubkey {
+    let (account, _seed) = Pubkey::find_program_address(&[&b"amm_cache"[..]], &PROGRAM_ID);
+    account
+}
+
 /// Helper methods for market data structs
 pub trait MarketExt {
     fn market_type(&self) -> &'static str;
@@ -37,14 +37,20 @@ use tokio::{
 use crate::{
     constants::{self, PROGRAM_ID},
     drift_idl::{
-        events::{FundingPaymentRecord, OrderActionRecord, OrderRecord},
-        types::{MarketType, Order, OrderAction, OrderActionExplanation, PositionDirection},
+        events::{
+            FundingPaymentRecord, FundingRateRecord, OrderActionRecord, OrderRecord,
+            RevenueShareSettleRecord, SignedMsgOrderRecord,
+        },
+        types::{
+            MarketType, Order, OrderAction, OrderActionExplanation, OrderParams, PositionDirection,
+        },
     },
     grpc::{
         grpc_subscriber::{DriftGrpcClient, GeyserSubscribeOpts, GrpcConnectionOpts},
         TransactionUpdate,
     },
     types::{events::SwapRecord, SdkResult},
+    Wallet,
 };
 
 const LOG_TARGET: &str = "events";
@@ -660,6 +666,12 @@ pub enum DriftEvent {
         signature: String,
         tx_idx: usize,
     },
+    /// A market's funding rate update
+    FundingRate {
+        record: FundingRateRecord,
+        signature: String,
+        tx_idx: usize,
+    },
     Swap {
         user: Pubkey,
         amount_in: u64,
@@ -679,6 +691,30 @@ pub enum DriftEvent {
         /// base asset amount
         amount: u64,
     },
+    /// A swift (signed msg) order was placed
+    SignedMsgOrder {
+        /// taker sub-account
+        user: Pubkey,
+        /// hash of the signed message
+        hash: String,
+        order_params: OrderParams,
+        /// user order id assigned to the placed order
+        user_order_id: u32,
+        /// last slot the signed message was valid for
+        max_slot: u64,
+        uuid: [u8; 8],
+        ts: u64,
+        signature: String,
+        tx_idx: usize,
+    },
+    /// Builder fees or referral rewards settled to a builder/referrer
+    ///
+    /// pertains to the builder/referrer sub-account at `record.builder_sub_account_id`
+    RevenueShareSettle {
+        record: RevenueShareSettleRecord,
+        signature: String,
+        tx_idx: usize,
+    },
 }
 
 impl DriftEvent {
@@ -696,8 +732,16 @@ impl DriftEvent {
             Self::OrderExpire { user, .. } => user == subject,
             Self::OrderCancelMissing { .. } => true,
             Self::FundingPayment { user, .. } => *user == sub_account,
+            Self::FundingRate { .. } => false,
             Self::Swap { user, .. } => *user == sub_account,
             Self::OrderTrigger { user, .. } => *user == sub_account,
+            Self::SignedMsgOrder { user, .. } => *user == sub_account,
+            Self::RevenueShareSettle { record, .. } => {
+                record.builder.or(record.referrer).is_some_and(|authority| {
+                    Wallet::derive_user_account(&authority, record.builder_sub_account_id)
+                        == sub_account
+                })
+            }
         }
     }
     /// Deserialize drift event by discriminant
@@ -724,11 +768,26 @@ impl DriftEvent {
                 signature,
                 tx_idx,
             )),
+            FundingRateRecord::DISCRIMINATOR => Some(Self::FundingRate {
+                record: FundingRateRecord::deserialize(data).expect("deserializes"),
+                signature: signature.to_string(),
+                tx_idx,
+            }),
             SwapRecord::DISCRIMINATOR => Some(Self::from_swap_record(
                 SwapRecord::deserialize(data).expect("deserializes"),
                 signature,
                 tx_idx,
             )),
+            SignedMsgOrderRecord::DISCRIMINATOR => Some(Self::from_signed_msg_order_record(
+                SignedMsgOrderRecord::deserialize(data).expect("deserializes"),
+                signature,
+                tx_idx,
+            )),
+            RevenueShareSettleRecord::DISCRIMINATOR => Some(Self::RevenueShareSettle {
+                record: RevenueShareSettleRecord::deserialize(data).expect("deserializes"),
+                signature: signature.to_string(),
+                tx_idx,
+            }),
             _ => {
                 debug!(target: LOG_TARGET, "unhandled event: {disc:?}");
                 None
@@ -762,6 +821,23 @@ impl DriftEvent {
             tx_idx,
         }
     }
+    fn from_signed_msg_order_record(
+        value: SignedMsgOrderRecord,
+        signature: &str,
+        tx_idx: usize,
+    ) -> Self {
+        Self::SignedMsgOrder {
+            user: value.user,
+            hash: value.hash,
+            order_params: value.matching_order_params,
+            user_order_id: value.user_order_id,
+            max_slot: value.signed_msg_order_max_slot,
+            uuid: value.signed_msg_order_uuid,
+            ts: value.ts.unsigned_abs(),
+            signature: signature.to_string(),
+            tx_idx,
+        }
+    }
     fn from_order_record(value: OrderRecord, signature: &str, tx_idx: usize) -> Option<Self> {
         Some(DriftEvent::OrderCreate {
             order: value.order,
@@ -1030,6 +1106,74 @@ mod test {
         assert!(found_trigger);
     }
 
+    #[test]
+    fn parses_signed_msg_order_record() {
+        let record = SignedMsgOrderRecord {
+            user: Pubkey::new_unique(),
+            hash: "hash".into(),
+            user_order_id: 7,
+            signed_msg_order_max_slot: 1_234,
+            signed_msg_order_uuid: *b"abcd1234",
+            ts: 1_700_000_000,
+            ..Default::default()
+        };
+        let mut data = SignedMsgOrderRecord::DISCRIMINATOR.to_vec();
+        record.serialize(&mut data).unwrap();
+        let log = format!(
+            "{PROGRAM_DATA}{}",
+            base64::engine::general_purpose::STANDARD.encode(data)
+        );
+
+        let event = try_parse_log(&log, "sig", 1).expect("parses");
+        assert!(event.pertains_to(record.user));
+        assert_eq!(
+            event,
+            DriftEvent::SignedMsgOrder {
+                user: record.user,
+                hash: "hash".into(),
+                order_params: Default::default(),
+                user_order_id: 7,
+                max_slot: 1_234,
+                uuid: *b"abcd1234",
+                ts: 1_700_000_000,
+                signature: "sig".into(),
+                tx_idx: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_revenue_share_settle_record() {
+        let record = RevenueShareSettleRecord {
+            ts: 1_700_000_000,
+            builder: Some(Pubkey::new_unique()),
+            fee_settled: 1_000,
+            market_index: 1,
+            builder_total_builder_rewards: 5_000,
+            ..Default::default()
+        };
+        let mut data = RevenueShareSettleRecord::DISCRIMINATOR.to_vec();
+        record.serialize(&mut data).unwrap();
+        let log = format!(
+            "{PROGRAM_DATA}{}",
+            base64::engine::general_purpose::STANDARD.encode(data)
+        );
+
+        let event = try_parse_log(&log, "sig", 2).expect("parses");
+        let builder = record.builder.unwrap();
+        assert!(event.pertains_to(Wallet::derive_user_account(&builder, 0)));
+        assert!(!event.pertains_to(Wallet::derive_user_account(&builder, 1)));
+        assert!(!event.pertains_to(builder));
+        assert_eq!(
+            event,
+            DriftEvent::RevenueShareSettle {
+                record,
+                signature: "sig".into(),
+                tx_idx: 2,
+            }
+        );
+    }
+
     #[ignore = "base64 encoded logs need updating"]
     #[test]
     fn parses_jit_proxy_logs() {
@@ -0,0 +1,220 @@
+//! Merge redundant gRPC streams with first-arrival deduplication
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use ahash::HashSet;
+use dashmap::{mapref::entry::Entry, DashMap};
+use futures_util::{future::join_all, FutureExt};
+use log::warn;
+
+use super::grpc_subscriber::{DriftGrpcClient, GeyserSubscribeOpts, GrpcError};
+use crate::{
+    solana_sdk::{clock::Slot, commitment_config::CommitmentLevel, pubkey::Pubkey},
+    types::UnsubHandle,
+};
+
+/// Number of recent transaction signatures remembered for deduplication
+const SEEN_TRANSACTIONS_CAPACITY: usize = 8_192;
+
+/// Slots an account update may lag the latest accepted account update.
+/// Older updates are dropped and pubkeys not updated within it are evicted
+const ACCOUNT_RETENTION_SLOTS: Slot = 150;
+
+/// Shared gate between redundant gRPC streams, forwarding each update once
+///
+/// Account updates are accepted if their `(slot, write_version)` is higher than the last
+/// accepted for the same pubkey. Slot and block meta updates are accepted once per slot,
+/// transactions once per signature
+#[derive(Default)]
+pub struct GrpcDedup {
+    /// latest accepted `(slot, write_version)`, by pubkey
+    accounts: DashMap<Pubkey, (Slot, u64), ahash::RandomState>,
+    /// latest slot of any accepted account update
+    account_slot: AtomicU64,
+    slot: AtomicU64,
+    block_meta_slot: AtomicU64,
+    transactions: Mutex<SeenTransactions>,
+}
+
+impl GrpcDedup {
+    /// Returns true if the account update is newer than the latest accepted for `pubkey`
+    ///
+    /// Updates more than `ACCOUNT_RETENTION_SLOTS` behind the latest accepted slot are dropped
+    pub fn accept_account(&self, pubkey: &Pubkey, slot: Slot, write_version: u64) -> bool {
+        let latest_slot = self.account_slot.load(Ordering::Relaxed);
+        if slot.saturating_add(ACCOUNT_RETENTION_SLOTS) < latest_slot {
+            return false;
+        }
+        let accepted = match self.accounts.entry(*pubkey) {
+            Entry::Occupied(mut entry) => {
+                if (slot, write_version) > *entry.get() {
+                    entry.insert((slot, write_version));
+                    true
+                } else {
+                    false
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((slot, write_version));
+                true
+            }
+        };
+        if accepted && slot > latest_slot {
+            self.account_slot.fetch_max(slot, Ordering::Relaxed);
+            // evict once per retention window
+            if slot / ACCOUNT_RETENTION_SLOTS > latest_slot / ACCOUNT_RETENTION_SLOTS {
+                self.evict_accounts(slot.saturating_sub(ACCOUNT_RETENTION_SLOTS));
+            }
+        }
+        accepted
+    }
+    /// Forget pubkeys last updated before `min_slot`
+    fn evict_accounts(&self, min_slot: Slot) {
+        self.accounts.retain(|_, (slot, _)| *slot >= min_slot);
+    }
+    /// Returns true if `slot` is newer than any previously accepted
+    pub fn accept_slot(&self, slot: Slot) -> bool {
+        self.slot.fetch_max(slot, Ordering::Relaxed) < slot
+    }
+    /// Returns true if block meta for `slot` has not been accepted yet
+    pub fn accept_block_meta(&self, slot: Slot) -> bool {
+        self.block_meta_slot.fetch_max(slot, Ordering::Relaxed) < slot
+    }
+    /// Returns true if the transaction `signature` has not been accepted recently
+    pub fn accept_transaction(&self, signature: &[u8]) -> bool {
+        self.transactions.lock().unwrap().insert(signature)
+    }
+}
+
+/// Bounded set of recently seen transaction signatures
+#[derive(Default)]
+struct SeenTransactions {
+    order: VecDeque<Vec<u8>>,
+    seen: HashSet<Vec<u8>>,
+}
+
+impl SeenTransactions {
+    /// Returns true if `signature` was not already present
+    fn insert(&mut self, signature: &[u8]) -> bool {
+        if self.seen.contains(signature) {
+            return false;
+        }
+        if self.order.len() >= SEEN_TRANSACTIONS_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.order.push_back(signature.to_vec());
+        self.seen.insert(signature.to_vec());
+        true
+    }
+}
+
+/// Subscribe redundant `clients` with the same request
+///
+/// Endpoints that fail to connect are logged and skipped, it only fails if no client connected
+///
+/// Returns unsub handles of the connected clients
+pub async fn subscribe_redundant(
+    clients: Vec<DriftGrpcClient>,
+    commitment: CommitmentLevel,
+    subscribe_opts: GeyserSubscribeOpts,
+) -> Result<Vec<UnsubHandle>, GrpcError> {
+    let results = join_all(clients.into_iter().map(|client| {
+        let endpoint = client.status().endpoint().to_string();
+        client
+            .subscribe(commitment, subscribe_opts.clone())
+            .map(move |res| (endpoint, res))
+    }))
+    .await;
+
+    let mut unsubs = Vec::with_capacity(results.len());
+    let mut last_error = None;
+    for (endpoint, res) in results {
+        match res {
+            Ok(unsub) => unsubs.push(unsub),
+            Err(err) => {
+                warn!(target: "grpc", "redundant endpoint failed: {endpoint}, {err}");
+                last_error = Some(err);
+            }
+        }
+    }
+
+    match last_error {
+        Some(err) if unsubs.is_empty() => Err(err),
+        _ => Ok(unsubs),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn accounts_dedup_by_slot_and_write_version() {
+        let dedup = GrpcDedup::default();
+        let pubkey = Pubkey::new_unique();
+
+        assert!(dedup.accept_account(&pubkey, 100, 5));
+        // same update from a redundant stream
+        assert!(!dedup.accept_account(&pubkey, 100, 5));
+        // another write in the same slot, even if the data is identical
+        assert!(dedup.accept_account(&pubkey, 100, 6));
+        assert!(!dedup.accept_account(&pubkey, 100, 4));
+        // older
+        assert!(!dedup.accept_account(&pubkey, 99, 10));
+        // newer
+        assert!(dedup.accept_account(&pubkey, 101, 1));
+
+        assert!(dedup.accept_account(&Pubkey::new_unique(), 101, 1));
+    }
+
+    #[test]
+    fn accounts_evicted_outside_retention() {
+        let dedup = GrpcDedup::default();
+        let stale = Pubkey::new_unique();
+        let live = Pubkey::new_unique();
+        assert!(dedup.accept_account(&stale, 100, 1));
+        assert!(dedup.accept_account(&live, 200, 1));
+        assert_eq!(dedup.accounts.len(), 2);
+
+        assert!(dedup.accept_account(&live, 300, 1));
+        assert_eq!(dedup.accounts.len(), 1);
+        assert!(dedup.accounts.contains_key(&live));
+        // updates older than the retention window are dropped, evicted or not
+        assert!(!dedup.accept_account(&stale, 100, 2));
+        assert!(dedup.accept_account(&stale, 200, 2));
+    }
+
+    #[test]
+    fn slots_and_block_meta_accepted_once() {
+        let dedup = GrpcDedup::default();
+        assert!(dedup.accept_slot(10));
+        assert!(!dedup.accept_slot(10));
+        assert!(!dedup.accept_slot(9));
+        assert!(dedup.accept_slot(11));
+
+        assert!(dedup.accept_block_meta(10));
+        assert!(!dedup.accept_block_meta(10));
+    }
+
+    #[test]
+    fn transactions_accepted_once_with_bounded_memory() {
+        let dedup = GrpcDedup::default();
+        assert!(dedup.accept_transaction(&[1; 64]));
+        assert!(!dedup.accept_transaction(&[1; 64]));
+
+        let mut seen = SeenTransactions::default();
+        for i in 0..=SEEN_TRANSACTIONS_CAPACITY {
+            assert!(seen.insert(&(i as u64).to_le_bytes()));
+        }
+        assert_eq!(seen.order.len(), SEEN_TRANSACTIONS_CAPACITY);
+        // oldest evicted
+        assert!(seen.insert(&0_u64.to_le_bytes()));
+    }
+}
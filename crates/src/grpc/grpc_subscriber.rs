@@ -1,4 +1,4 @@
-use std::{collections::HashMap, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use crate::solana_sdk::{clock::Slot, commitment_config::CommitmentLevel, pubkey::Pubkey};
 use ahash::HashSet;
@@ -23,12 +23,19 @@ use yellowstone_grpc_proto::{
         SubscribeRequestFilterAccountsFilter, SubscribeRequestFilterAccountsFilterMemcmp,
         SubscribeRequestFilterSlots, SubscribeRequestFilterTransactions, SubscribeRequestPing,
     },
-    tonic::{codec::CompressionEncoding, transport::Certificate, Status},
+    tonic::{codec::CompressionEncoding, transport::Certificate, Code, Status},
 };
 
-use crate::types::UnsubHandle;
+use crate::{
+    async_utils::retry_policy::{self, TaskRetryPolicy},
+    types::UnsubHandle,
+};
 
-use super::{AccountUpdate, OnAccountFn, OnTransactionFn, TransactionUpdate};
+use super::{
+    dedup::GrpcDedup,
+    status::{GrpcConnectionState, GrpcStatus, GrpcUpdateKind},
+    AccountUpdate, OnAccountFn, OnResyncFn, OnTransactionFn, TransactionUpdate,
+};
 
 type SlotsFilterMap = HashMap<String, SubscribeRequestFilterSlots>;
 type AccountFilterMap = HashMap<String, SubscribeRequestFilterAccounts>;
@@ -233,6 +240,11 @@ pub struct DriftGrpcClient {
     on_slot: Box<dyn Fn(Slot) + Send + Sync + 'static>,
     on_transaction_hooks: TransactionHooks,
     on_block_meta: Box<dyn Fn(SubscribeUpdateBlockMeta) + Send + Sync + 'static>,
+    on_resync: Box<OnResyncFn>,
+    retry_policy: Box<dyn TaskRetryPolicy>,
+    from_slot_replay: bool,
+    status: GrpcStatus,
+    dedup: Option<Arc<GrpcDedup>>,
 }
 
 impl DriftGrpcClient {
@@ -241,13 +253,18 @@ impl DriftGrpcClient {
     /// It can be started by calling `subscribe`
     pub fn new(endpoint: String, x_token: String) -> Self {
         Self {
-            endpoint,
             x_token,
             on_account_hooks: Default::default(),
             on_transaction_hooks: Default::default(),
             grpc_opts: None,
             on_slot: Box::new(move |_slot| {}),
             on_block_meta: Box::new(move |_meta| {}),
+            on_resync: Box::new(move |_slot| {}),
+            retry_policy: Box::new(retry_policy::exponential_backoff(3)),
+            from_slot_replay: true,
+            status: GrpcStatus::new(endpoint.as_str()),
+            dedup: None,
+            endpoint,
         }
     }
 
@@ -257,6 +274,40 @@ impl DriftGrpcClient {
         self
     }
 
+    /// Set the reconnect policy (default: exponential backoff, 3 attempts)
+    ///
+    /// The attempt count resets once a reconnected stream delivers an update
+    pub fn retry_policy(mut self, retry_policy: impl TaskRetryPolicy) -> Self {
+        self.retry_policy = Box::new(retry_policy);
+        self
+    }
+
+    /// Toggle replay of missed updates with `from_slot` on reconnect (default: true)
+    ///
+    /// If the endpoint rejects the replay request the client falls back to the `on_resync` callback
+    pub fn from_slot_replay(mut self, enabled: bool) -> Self {
+        self.from_slot_replay = enabled;
+        self
+    }
+
+    /// Share a dedup gate with redundant clients subscribed to other endpoints
+    ///
+    /// Only the first arrival of each update across the clients invokes the callbacks
+    pub fn dedup(mut self, dedup: Arc<GrpcDedup>) -> Self {
+        self.dedup = Some(dedup);
+        self
+    }
+
+    /// Add a callback invoked after reconnecting without replay
+    ///
+    /// It receives the last slot processed before the disconnect, updates since then may have been missed
+    /// and should be re-fetched e.g. over RPC
+    ///
+    /// `on_resync` must prioritize fast handling or risk blocking the gRPC thread
+    pub fn on_resync<F: Fn(Slot) + Send + Sync + 'static>(&mut self, on_resync: F) {
+        self.on_resync = Box::new(on_resync);
+    }
+
     /// Add a callback on slot updates
     ///
     /// `on_slot` must prioritize fast handling or risk blocking the gRPC thread
@@ -264,6 +315,23 @@ impl DriftGrpcClient {
         self.on_slot = Box::new(on_slot);
     }
 
+    /// Returns a handle to the connection's status and metrics
+    ///
+    /// It remains valid for the lifetime of the subscription
+    pub fn status(&self) -> GrpcStatus {
+        self.status.clone()
+    }
+
+    /// Add a callback on connection state transitions
+    ///
+    /// `on_state_change` must prioritize fast handling or risk blocking the gRPC thread
+    pub fn on_state_change<F: Fn(&GrpcStatus, GrpcConnectionState) + Send + Sync + 'static>(
+        &mut self,
+        on_state_change: F,
+    ) {
+        self.status.on_state_change(on_state_change);
+    }
+
     /// Add a callback on block meta updates
     ///
     /// `on_block_meta` must prioritize fast handling or risk blocking the gRPC thread
@@ -319,15 +387,22 @@ impl DriftGrpcClient {
         .await
         .map_err(|err| {
             error!(target: "grpc", "connect failed: {err:?}");
+            self.status.set_error(err.to_string());
+            self.status.set_state(GrpcConnectionState::Failed);
             GrpcError::Geyser(err)
         })?;
 
-        let resp = grpc_client.get_version().await.map_err(GrpcError::Client)?;
+        let resp = grpc_client.get_version().await.map_err(|err| {
+            self.status.set_error(err.to_string());
+            self.status.set_state(GrpcConnectionState::Failed);
+            GrpcError::Client(err)
+        })?;
         info!("gRPC connected 🔌: {}", resp.version);
         let request = subscribe_opts.to_subscribe_request(commitment);
         info!(target: "grpc", "gRPC subscribing: {request:?}");
 
         let (unsub_tx, mut unsub_rx) = tokio::sync::oneshot::channel::<()>();
+        let status = self.status.clone();
 
         // gRPC receives updates very frequently, don't want tokio scheduler moving it
         std::thread::spawn(|| {
@@ -343,6 +418,11 @@ impl DriftGrpcClient {
                 self.on_transaction_hooks,
                 self.on_slot,
                 self.on_block_meta,
+                self.on_resync,
+                self.retry_policy,
+                GapRecovery::new(self.from_slot_replay),
+                self.status,
+                self.dedup,
             ));
             let mut waiter = FuturesUnordered::new();
             waiter.push(geyser_task);
@@ -352,13 +432,14 @@ impl DriftGrpcClient {
             ls.block_on(&rt, async move {
                 tokio::select! {
                     biased;
-                    _ = &mut unsub_rx => (),
+                    _ = &mut unsub_rx => status.set_state(GrpcConnectionState::Disconnected),
                     res = waiter.next() => {
                         if let Ok(Some(err)) = res.unwrap() {
                             log::error!(target: "grpc", "subscription task failed: {err:?}");
                         } else {
                             log::error!(target: "grpc", "subscription task ended unexpectedly");
                         }
+                        status.set_state(GrpcConnectionState::Failed);
                     }
                 }
             });
@@ -371,7 +452,10 @@ impl DriftGrpcClient {
 
     /// Run the gRPC subscription task
     ///
-    /// It receives all configured updates and routes them to registered callbacks
+    /// It receives all configured updates and routes them to registered callbacks.
+    /// Dropped streams are reconnected according to `retry_policy`, recovering missed updates
+    /// with a `from_slot` replay or else the `on_resync` callback
+    #[allow(clippy::too_many_arguments)]
     async fn geyser_subscribe(
         mut client: GeyserGrpcClient<impl Interceptor>,
         request: SubscribeRequest,
@@ -379,36 +463,68 @@ impl DriftGrpcClient {
         on_transaction: TransactionHooks,
         on_slot: impl Fn(Slot),
         on_block_meta: impl Fn(SubscribeUpdateBlockMeta),
+        on_resync: impl Fn(Slot),
+        mut retry_policy: Box<dyn TaskRetryPolicy>,
+        mut recovery: GapRecovery,
+        status: GrpcStatus,
+        dedup: Option<Arc<GrpcDedup>>,
     ) -> Option<GrpcError> {
-        let max_retries = 3;
-        let mut retry_count = 0;
+        let mut attempts = 0;
         let mut latest_slot = 0;
         let mut last_error: Option<GrpcError> = None;
+        let mut reconnecting = false;
         loop {
-            if retry_count >= max_retries {
-                log::warn!(target: "grpc", "max retry attempts reached. disconnecting...");
-                break;
+            if reconnecting {
+                status.set_state(GrpcConnectionState::Reconnecting);
+                if !retry_policy.check(attempts).await {
+                    log::warn!(target: "grpc", "max retry attempts reached. disconnecting...");
+                    break;
+                }
+                attempts += 1;
+                status.inc_reconnects();
             }
+            reconnecting = true;
+            let mut connected = false;
+
+            let (request, action) = recovery.next_request(&request);
             let (mut subscribe_tx, mut stream) =
-                match client.subscribe_with_request(Some(request.clone())).await {
-                    Ok(res) => {
-                        retry_count = 0;
-                        res
-                    }
+                match client.subscribe_with_request(Some(request)).await {
+                    Ok(res) => res,
                     Err(err) => {
                         log::warn!(target: "grpc", "failed subscription: {err:?}");
-                        retry_count += 1;
-                        tokio::time::sleep(Duration::from_secs(2_u64.pow(retry_count + 1))).await;
+                        status.set_error(err.to_string());
+                        if let GeyserGrpcClientError::TonicStatus(ref err_status) = err {
+                            recovery.on_error(err_status);
+                        }
                         let _ = last_error.insert(GrpcError::Client(err));
                         continue;
                     }
                 };
 
+            match action {
+                Reconnect::Fresh => (),
+                Reconnect::Replay(slot) => {
+                    info!(target: "grpc", "resubscribed, replaying from slot: {slot}");
+                }
+                Reconnect::Resync(slot) => {
+                    info!(target: "grpc", "resubscribed, resyncing from slot: {slot}");
+                    on_resync(slot);
+                }
+            }
+
             while let Some(message) = stream.next().await {
                 match message {
                     Ok(msg) => {
+                        if !connected {
+                            connected = true;
+                            recovery.on_update();
+                            attempts = 0;
+                            status.set_state(GrpcConnectionState::Connected);
+                        }
                         match msg.update_oneof {
                             Some(UpdateOneof::Account(account_update)) => {
+                                recovery.observe_slot(account_update.slot);
+                                status.record_update(GrpcUpdateKind::Account, account_update.slot);
                                 let account = match account_update.account {
                                     Some(ref account) => account,
                                     None => {
@@ -420,6 +536,15 @@ impl DriftGrpcClient {
                                     account.pubkey.as_slice().try_into().unwrap(),
                                 );
                                 log::trace!(target: "grpc", "account update: {pubkey}");
+                                if dedup.as_ref().is_some_and(|d| {
+                                    !d.accept_account(
+                                        &pubkey,
+                                        account_update.slot,
+                                        account.write_version,
+                                    )
+                                }) {
+                                    continue;
+                                }
                                 let update = AccountUpdate {
                                     owner: Pubkey::new_from_array(
                                         account.owner.as_slice().try_into().unwrap(),
@@ -440,6 +565,7 @@ impl DriftGrpcClient {
                                 }
                             }
                             Some(UpdateOneof::Transaction(tx_update)) => {
+                                status.record_update(GrpcUpdateKind::Transaction, tx_update.slot);
                                 let tx = match tx_update.transaction {
                                     Some(ref tx) => tx,
                                     None => {
@@ -461,6 +587,12 @@ impl DriftGrpcClient {
                                         continue;
                                     }
                                 };
+                                if dedup
+                                    .as_ref()
+                                    .is_some_and(|d| !d.accept_transaction(&tx.signature))
+                                {
+                                    continue;
+                                }
                                 for hook in &on_transaction {
                                     let update = TransactionUpdate {
                                         slot: tx_update.slot,
@@ -476,12 +608,21 @@ impl DriftGrpcClient {
                                 if msg.status() as u8 > 2 {
                                     log::debug!(target: "grpc", "slot: {}, {}", msg.slot, msg.status);
                                 }
+                                recovery.observe_slot(msg.slot);
+                                status.record_update(GrpcUpdateKind::Slot, msg.slot);
                                 if msg.slot > latest_slot {
                                     latest_slot = msg.slot;
-                                    on_slot(latest_slot);
+                                    if dedup.as_ref().is_none_or(|d| d.accept_slot(msg.slot)) {
+                                        on_slot(latest_slot);
+                                    }
+                                }
+                            }
+                            Some(UpdateOneof::BlockMeta(msg)) => {
+                                status.record_update(GrpcUpdateKind::BlockMeta, msg.slot);
+                                if dedup.as_ref().is_none_or(|d| d.accept_block_meta(msg.slot)) {
+                                    on_block_meta(msg);
                                 }
                             }
-                            Some(UpdateOneof::BlockMeta(msg)) => on_block_meta(msg),
                             Some(UpdateOneof::Ping(_)) => {
                                 // This is necessary to keep load balancers that expect client pings alive. If your load balancer doesn't
                                 // require periodic client pings then this is unnecessary
@@ -517,9 +658,11 @@ impl DriftGrpcClient {
                             }
                         }
                     }
-                    Err(status) => {
-                        error!(target: "grpc", "stream error: {status:?}");
-                        let _ = last_error.insert(GrpcError::Stream(status));
+                    Err(err_status) => {
+                        error!(target: "grpc", "stream error: {err_status:?}");
+                        recovery.on_error(&err_status);
+                        status.set_error(err_status.to_string());
+                        let _ = last_error.insert(GrpcError::Stream(err_status));
                         break;
                     }
                 }
@@ -531,6 +674,75 @@ impl DriftGrpcClient {
     }
 }
 
+/// Recovery strategy for a (re)subscription
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Reconnect {
+    /// first subscription, or nothing processed yet
+    Fresh,
+    /// endpoint replays updates from the given slot
+    Replay(Slot),
+    /// updates since the given slot must be re-fetched
+    Resync(Slot),
+}
+
+/// Tracks stream progress across reconnects to recover missed updates
+#[derive(Debug, Default)]
+struct GapRecovery {
+    /// highest slot processed from the stream
+    last_slot: Slot,
+    /// endpoint is assumed to support `from_slot` replay
+    replay: bool,
+    /// a replay request is in-flight and unconfirmed
+    replay_pending: bool,
+}
+
+impl GapRecovery {
+    fn new(replay: bool) -> Self {
+        Self {
+            replay,
+            ..Default::default()
+        }
+    }
+
+    fn observe_slot(&mut self, slot: Slot) {
+        self.last_slot = self.last_slot.max(slot);
+    }
+
+    /// Build the next subscribe request from `base`
+    fn next_request(&mut self, base: &SubscribeRequest) -> (SubscribeRequest, Reconnect) {
+        let mut request = base.clone();
+        if self.last_slot == 0 {
+            return (request, Reconnect::Fresh);
+        }
+        if self.replay {
+            request.from_slot = Some(self.last_slot);
+            self.replay_pending = true;
+            (request, Reconnect::Replay(self.last_slot))
+        } else {
+            (request, Reconnect::Resync(self.last_slot))
+        }
+    }
+
+    /// Handle a received update, confirming any in-flight replay
+    fn on_update(&mut self) {
+        self.replay_pending = false;
+    }
+
+    /// Handle a subscription error, disabling replay if it was rejected by the endpoint
+    fn on_error(&mut self, status: &Status) {
+        if self.replay_pending
+            && matches!(
+                status.code(),
+                Code::InvalidArgument | Code::Unimplemented | Code::OutOfRange
+            )
+        {
+            warn!(target: "grpc", "from_slot replay rejected: {}. falling back to resync", status.message());
+            self.replay = false;
+        }
+        self.replay_pending = false;
+    }
+}
+
 impl GeyserSubscribeOpts {
     fn to_subscribe_request(&self, commitment: CommitmentLevel) -> SubscribeRequest {
         let mut accounts = AccountFilterMap::default();
@@ -769,4 +981,47 @@ mod test {
 
         assert!(filter.matches(&pubkey, &account));
     }
+
+    #[test]
+    fn gap_recovery_replays_from_last_slot() {
+        let base = GeyserSubscribeOpts::default().to_subscribe_request(CommitmentLevel::Confirmed);
+        let mut recovery = GapRecovery::new(true);
+
+        let (request, action) = recovery.next_request(&base);
+        assert_eq!(action, Reconnect::Fresh);
+        assert_eq!(request.from_slot, None);
+
+        recovery.observe_slot(100);
+        recovery.observe_slot(99);
+        let (request, action) = recovery.next_request(&base);
+        assert_eq!(action, Reconnect::Replay(100));
+        assert_eq!(request.from_slot, Some(100));
+
+        // replay confirmed, a transient error keeps replay enabled
+        recovery.on_update();
+        recovery.on_error(&Status::unavailable("disconnected"));
+        let (_, action) = recovery.next_request(&base);
+        assert_eq!(action, Reconnect::Replay(100));
+    }
+
+    #[test]
+    fn gap_recovery_falls_back_to_resync() {
+        let base = GeyserSubscribeOpts::default().to_subscribe_request(CommitmentLevel::Confirmed);
+        let mut recovery = GapRecovery::new(true);
+        recovery.observe_slot(100);
+
+        let (_, action) = recovery.next_request(&base);
+        assert_eq!(action, Reconnect::Replay(100));
+        recovery.on_error(&Status::invalid_argument("from_slot is not supported"));
+
+        let (request, action) = recovery.next_request(&base);
+        assert_eq!(action, Reconnect::Resync(100));
+        assert_eq!(request.from_slot, None);
+
+        let mut recovery = GapRecovery::new(false);
+        recovery.observe_slot(5);
+        let (request, action) = recovery.next_request(&base);
+        assert_eq!(action, Reconnect::Resync(5));
+        assert_eq!(request.from_slot, None);
+    }
 }
@@ -6,8 +6,12 @@ use crate::solana_sdk::{
     pubkey::Pubkey,
 };
 use anchor_lang::Discriminator;
+pub mod dedup;
 pub mod grpc_subscriber;
+pub mod status;
+use crate::async_utils::retry_policy::{self, TaskRetryPolicy};
 use grpc_subscriber::{AccountFilter, GrpcConnectionOpts};
+use status::{GrpcConnectionState, GrpcStatus, OnStateChangeFn};
 use yellowstone_grpc_proto::{
     geyser::SubscribeUpdateBlockMeta,
     prelude::{Transaction, TransactionStatusMeta},
@@ -25,6 +29,10 @@ pub type OnAccountFn = dyn Fn(&AccountUpdate) + Send + Sync + 'static;
 pub type OnSlotFn = dyn Fn(Slot) + Send + Sync + 'static;
 /// grpc block metadata update callback
 pub type OnBlockMetaFn = dyn Fn(SubscribeUpdateBlockMeta) + Send + Sync + 'static;
+/// grpc resync callback, receives the last slot processed before a gap
+pub type OnResyncFn = dyn Fn(Slot) + Send + Sync + 'static;
+/// builds a reconnect policy for each gRPC connection
+pub type RetryPolicyFn = dyn Fn() -> Box<dyn TaskRetryPolicy> + Send + Sync + 'static;
 
 /// Account update from gRPC
 #[derive(PartialEq, Eq, Clone)]
@@ -105,6 +113,16 @@ pub struct GrpcSubscribeOpts {
     pub on_block_meta: Option<Box<OnBlockMetaFn>>,
     /// Subscribe to slot updates
     pub subscribe_slot_updates: bool,
+    /// reconnect policy for dropped gRPC streams
+    pub retry_policy: Box<RetryPolicyFn>,
+    /// Replay missed updates with `from_slot` on reconnect, if supported by the endpoint (default: true)
+    pub from_slot_replay: bool,
+    /// custom callback invoked after reconnecting without replay
+    pub on_resync: Option<Box<OnResyncFn>>,
+    /// custom callback for connection state transitions
+    pub on_state_change: Option<Box<OnStateChangeFn>>,
+    /// additional (endpoint, x_token) pairs subscribed for redundancy
+    pub redundant_endpoints: Vec<(String, String)>,
 }
 
 impl Default for GrpcSubscribeOpts {
@@ -125,6 +143,11 @@ impl Default for GrpcSubscribeOpts {
             subscribe_block_meta_updates: false,
             subscribe_slot_updates: true,
             on_block_meta: None,
+            retry_policy: Box::new(|| Box::new(retry_policy::exponential_backoff(3))),
+            from_slot_replay: true,
+            on_resync: None,
+            on_state_change: None,
+            redundant_endpoints: Default::default(),
         }
     }
 }
@@ -275,4 +298,60 @@ impl GrpcSubscribeOpts {
         self.subscribe_block_meta_updates = subscribe;
         self
     }
+    /// Set the reconnect policy for dropped gRPC streams (default: exponential backoff, 3 attempts)
+    ///
+    /// * `retry_policy` - builds a policy for each underlying gRPC connection
+    ///
+    /// ```example(no_run)
+    ///  let opts = GrpcSubscribeOpts::default()
+    ///                 .retry_policy(|| retry_policy::forever(5));
+    /// ```
+    pub fn retry_policy<P: TaskRetryPolicy>(
+        mut self,
+        retry_policy: impl Fn() -> P + Send + Sync + 'static,
+    ) -> Self {
+        self.retry_policy = Box::new(move || Box::new(retry_policy()));
+        self
+    }
+    /// Replay missed updates with `from_slot` on reconnect (default: true)
+    ///
+    /// When disabled, or rejected by the endpoint, missed updates are re-fetched over RPC instead
+    pub fn from_slot_replay(mut self, enabled: bool) -> Self {
+        self.from_slot_replay = enabled;
+        self
+    }
+    /// Set a callback to invoke after a gRPC stream reconnects without replay
+    /// It is called in addition to the SDK's RPC resync of cached accounts
+    ///
+    /// * `on_resync` - receives the last slot processed before the disconnect
+    ///
+    /// ! `on_resync` must not block the gRPC task
+    pub fn on_resync(mut self, on_resync: impl Fn(Slot) + Send + Sync + 'static) -> Self {
+        self.on_resync = Some(Box::new(on_resync));
+        self
+    }
+    /// Add a redundant gRPC endpoint
+    ///
+    /// Updates from all endpoints are merged, callbacks receive only the first arrival of each update.
+    /// Account updates are matched across endpoints by slot and tx signature, see `GrpcDedup`.
+    /// The subscription continues uninterrupted while any endpoint remains connected
+    ///
+    /// This may be called many times to add multiple endpoints
+    pub fn redundant_endpoint(mut self, endpoint: String, x_token: String) -> Self {
+        self.redundant_endpoints.push((endpoint, x_token));
+        self
+    }
+    /// Set a callback to invoke on gRPC connection state transitions
+    /// e.g. to stop quoting while the feed is unhealthy
+    ///
+    /// * `on_state_change` - receives the connection's status handle and its new state
+    ///
+    /// ! `on_state_change` must not block the gRPC task
+    pub fn on_state_change(
+        mut self,
+        on_state_change: impl Fn(&GrpcStatus, GrpcConnectionState) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_state_change = Some(Box::new(on_state_change));
+        self
+    }
 }
@@ -0,0 +1,383 @@
+//! gRPC connection health and metrics
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::solana_sdk::clock::Slot;
+
+/// gRPC connection state change callback
+pub type OnStateChangeFn = dyn Fn(&GrpcStatus, GrpcConnectionState) + Send + Sync + 'static;
+
+/// Minimum window for computing update rates
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Connection state of a gRPC subscription
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum GrpcConnectionState {
+    /// initial connection in progress
+    Connecting = 0,
+    /// subscribed and receiving updates
+    Connected = 1,
+    /// stream dropped, reconnect in progress
+    Reconnecting = 2,
+    /// retry policy exhausted, no further updates will be received
+    Failed = 3,
+    /// unsubscribed by the user
+    Disconnected = 4,
+}
+
+impl GrpcConnectionState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Connecting,
+            1 => Self::Connected,
+            2 => Self::Reconnecting,
+            3 => Self::Failed,
+            _ => Self::Disconnected,
+        }
+    }
+}
+
+/// Kinds of gRPC updates, one per subscription filter type
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GrpcUpdateKind {
+    Account,
+    Transaction,
+    Slot,
+    BlockMeta,
+}
+
+/// Update rates per `GrpcUpdateKind`
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct UpdateRates {
+    pub account: f64,
+    pub transaction: f64,
+    pub slot: f64,
+    pub block_meta: f64,
+}
+
+impl UpdateRates {
+    /// Updates per second for the given `kind`
+    pub fn get(&self, kind: GrpcUpdateKind) -> f64 {
+        match kind {
+            GrpcUpdateKind::Account => self.account,
+            GrpcUpdateKind::Transaction => self.transaction,
+            GrpcUpdateKind::Slot => self.slot,
+            GrpcUpdateKind::BlockMeta => self.block_meta,
+        }
+    }
+    /// Total updates per second
+    pub fn total(&self) -> f64 {
+        self.account + self.transaction + self.slot + self.block_meta
+    }
+}
+
+/// Point-in-time view of a gRPC connection's health
+#[derive(Debug, Clone)]
+pub struct GrpcStatusSnapshot {
+    /// endpoint of the connection
+    pub endpoint: String,
+    pub state: GrpcConnectionState,
+    /// latest slot reported by the slot stream
+    pub current_slot: Slot,
+    /// slot of the latest account or transaction update
+    pub last_received_slot: Slot,
+    /// time since any update was received
+    pub since_last_update: Option<Duration>,
+    /// updates per second, by kind
+    pub rates: UpdateRates,
+    /// number of reconnect attempts since subscribing
+    pub reconnects: u64,
+    /// most recent connection error, if any
+    pub last_error: Option<String>,
+}
+
+impl GrpcStatusSnapshot {
+    /// Slots between the slot stream and the latest account/transaction update
+    pub fn slot_lag(&self) -> u64 {
+        self.current_slot.saturating_sub(self.last_received_slot)
+    }
+}
+
+/// Update rates over windows of at least `RATE_WINDOW`, computed from the update counters
+#[derive(Debug)]
+struct RateWindow {
+    window_start: Instant,
+    /// update counters at `window_start`
+    start_counts: [u64; 4],
+    rates: UpdateRates,
+}
+
+impl RateWindow {
+    fn new(now: Instant) -> Self {
+        Self {
+            window_start: now,
+            start_counts: Default::default(),
+            rates: Default::default(),
+        }
+    }
+
+    /// Close the current window if it has elapsed, publishing its rates
+    fn roll(&mut self, now: Instant, counts: [u64; 4]) {
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed < RATE_WINDOW {
+            return;
+        }
+        let secs = elapsed.as_secs_f64();
+        let rate = |kind: GrpcUpdateKind| {
+            counts[kind as usize].saturating_sub(self.start_counts[kind as usize]) as f64 / secs
+        };
+        self.rates = UpdateRates {
+            account: rate(GrpcUpdateKind::Account),
+            transaction: rate(GrpcUpdateKind::Transaction),
+            slot: rate(GrpcUpdateKind::Slot),
+            block_meta: rate(GrpcUpdateKind::BlockMeta),
+        };
+        self.start_counts = counts;
+        self.window_start = now;
+    }
+}
+
+struct StatusInner {
+    endpoint: String,
+    state: AtomicU8,
+    current_slot: AtomicU64,
+    last_received_slot: AtomicU64,
+    reconnects: AtomicU64,
+    last_error: Mutex<Option<String>>,
+    /// total updates received, by kind
+    counts: [AtomicU64; 4],
+    /// reference for `last_update`
+    created: Instant,
+    /// µs since `created` of the latest update + 1, 0 if none
+    last_update: AtomicU64,
+    /// only touched by `snapshot`, keeps updates lock-free
+    rate_window: Mutex<RateWindow>,
+    on_state_change: RwLock<Option<Arc<OnStateChangeFn>>>,
+}
+
+/// Shared handle to the status of a gRPC connection
+///
+/// Cheaply cloneable, updated by the gRPC task as the subscription runs
+///
+/// ```example(no_run)
+///  let status = grpc.status();
+///  if !status.is_healthy() {
+///     // stop quoting
+///  }
+///  println!("{:?}", status.snapshot());
+/// ```
+#[derive(Clone)]
+pub struct GrpcStatus {
+    inner: Arc<StatusInner>,
+}
+
+impl std::fmt::Debug for GrpcStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.snapshot().fmt(f)
+    }
+}
+
+impl GrpcStatus {
+    /// Create a new status handle for `endpoint`
+    pub fn new(endpoint: &str) -> Self {
+        let now = Instant::now();
+        Self {
+            inner: Arc::new(StatusInner {
+                endpoint: endpoint.to_string(),
+                state: AtomicU8::new(GrpcConnectionState::Connecting as u8),
+                current_slot: AtomicU64::default(),
+                last_received_slot: AtomicU64::default(),
+                reconnects: AtomicU64::default(),
+                last_error: Mutex::default(),
+                counts: Default::default(),
+                created: now,
+                last_update: AtomicU64::default(),
+                rate_window: Mutex::new(RateWindow::new(now)),
+                on_state_change: RwLock::default(),
+            }),
+        }
+    }
+    /// Endpoint of the connection
+    pub fn endpoint(&self) -> &str {
+        self.inner.endpoint.as_str()
+    }
+    /// Current connection state
+    pub fn state(&self) -> GrpcConnectionState {
+        GrpcConnectionState::from_u8(self.inner.state.load(Ordering::Relaxed))
+    }
+    /// True if the connection is subscribed and receiving updates
+    pub fn is_healthy(&self) -> bool {
+        self.state() == GrpcConnectionState::Connected
+    }
+    /// Latest slot reported by the slot stream
+    pub fn current_slot(&self) -> Slot {
+        self.inner.current_slot.load(Ordering::Relaxed)
+    }
+    /// Slot of the latest account or transaction update
+    pub fn last_received_slot(&self) -> Slot {
+        self.inner.last_received_slot.load(Ordering::Relaxed)
+    }
+    /// Number of reconnect attempts since subscribing
+    pub fn reconnects(&self) -> u64 {
+        self.inner.reconnects.load(Ordering::Relaxed)
+    }
+    /// Most recent connection error, if any
+    pub fn last_error(&self) -> Option<String> {
+        self.inner.last_error.lock().unwrap().clone()
+    }
+    /// Take a consistent snapshot of all metrics
+    pub fn snapshot(&self) -> GrpcStatusSnapshot {
+        let now = Instant::now();
+        let counts: [u64; 4] =
+            std::array::from_fn(|kind| self.inner.counts[kind].load(Ordering::Relaxed));
+        let since_last_update =
+            match self.inner.last_update.load(Ordering::Relaxed) {
+                0 => None,
+                micros => Some(now.saturating_duration_since(
+                    self.inner.created + Duration::from_micros(micros - 1),
+                )),
+            };
+        let rates = {
+            let mut rate_window = self.inner.rate_window.lock().unwrap();
+            rate_window.roll(now, counts);
+            // no updates in the last window means the published rates are stale
+            match since_last_update {
+                Some(since) if since < 2 * RATE_WINDOW => rate_window.rates,
+                _ => UpdateRates::default(),
+            }
+        };
+        GrpcStatusSnapshot {
+            endpoint: self.inner.endpoint.clone(),
+            state: self.state(),
+            current_slot: self.current_slot(),
+            last_received_slot: self.last_received_slot(),
+            since_last_update,
+            rates,
+            reconnects: self.reconnects(),
+            last_error: self.last_error(),
+        }
+    }
+    /// Set a callback to invoke on connection state transitions
+    ///
+    /// ! `on_state_change` must not block the gRPC task
+    pub fn on_state_change(
+        &self,
+        on_state_change: impl Fn(&GrpcStatus, GrpcConnectionState) + Send + Sync + 'static,
+    ) {
+        self.set_on_state_change(Arc::new(on_state_change));
+    }
+
+    pub(crate) fn set_on_state_change(&self, on_state_change: Arc<OnStateChangeFn>) {
+        let mut guard = self.inner.on_state_change.write().unwrap();
+        let _ = guard.insert(on_state_change);
+    }
+
+    pub(crate) fn set_state(&self, state: GrpcConnectionState) {
+        let prev = self.inner.state.swap(state as u8, Ordering::Relaxed);
+        if prev != state as u8 {
+            log::debug!(target: "grpc", "{} state: {:?}", self.inner.endpoint, state);
+            // release the lock before calling out, the callback may set a new one
+            let on_state_change = self.inner.on_state_change.read().unwrap().clone();
+            if let Some(f) = on_state_change {
+                f(self, state);
+            }
+        }
+    }
+
+    pub(crate) fn set_error(&self, err: String) {
+        let _ = self.inner.last_error.lock().unwrap().insert(err);
+    }
+
+    pub(crate) fn inc_reconnects(&self) {
+        self.inner.reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_update(&self, kind: GrpcUpdateKind, slot: Slot) {
+        match kind {
+            GrpcUpdateKind::Slot => {
+                self.inner.current_slot.fetch_max(slot, Ordering::Relaxed);
+            }
+            GrpcUpdateKind::Account | GrpcUpdateKind::Transaction => {
+                self.inner
+                    .last_received_slot
+                    .fetch_max(slot, Ordering::Relaxed);
+            }
+            GrpcUpdateKind::BlockMeta => (),
+        }
+        self.inner.counts[kind as usize].fetch_add(1, Ordering::Relaxed);
+        let since_created = self.inner.created.elapsed().as_micros() as u64;
+        self.inner
+            .last_update
+            .fetch_max(since_created + 1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    #[test]
+    fn state_change_callback_fires_on_transition_only() {
+        let status = GrpcStatus::new("https://grpc.example.com");
+        let calls = Arc::new(AtomicUsize::default());
+        status.on_state_change({
+            let calls = Arc::clone(&calls);
+            move |_status, _state| {
+                calls.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+
+        status.set_state(GrpcConnectionState::Connected);
+        status.set_state(GrpcConnectionState::Connected);
+        status.set_state(GrpcConnectionState::Reconnecting);
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+        assert_eq!(status.state(), GrpcConnectionState::Reconnecting);
+        assert!(!status.is_healthy());
+    }
+
+    #[test]
+    fn records_slots_and_errors() {
+        let status = GrpcStatus::new("https://grpc.example.com");
+        status.record_update(GrpcUpdateKind::Slot, 105);
+        status.record_update(GrpcUpdateKind::Slot, 104);
+        status.record_update(GrpcUpdateKind::Account, 100);
+        status.inc_reconnects();
+        status.set_error("stream err".into());
+
+        let snapshot = status.snapshot();
+        assert_eq!(snapshot.current_slot, 105);
+        assert_eq!(snapshot.last_received_slot, 100);
+        assert_eq!(snapshot.slot_lag(), 5);
+        assert_eq!(snapshot.reconnects, 1);
+        assert_eq!(snapshot.last_error.as_deref(), Some("stream err"));
+        assert!(snapshot.since_last_update.is_some());
+    }
+
+    #[test]
+    fn rate_windows() {
+        let start = Instant::now();
+        let mut window = RateWindow::new(start);
+        // window still open
+        window.roll(start, [10, 0, 1, 0]);
+        assert_eq!(window.rates, UpdateRates::default());
+
+        window.roll(start + Duration::from_secs(2), [10, 0, 1, 0]);
+        assert_eq!(window.rates.get(GrpcUpdateKind::Account), 5.0);
+        assert_eq!(window.rates.slot, 0.5);
+        assert_eq!(window.rates.total(), 5.5);
+        assert_eq!(window.start_counts, [10, 0, 1, 0]);
+
+        // rates only count updates within the window
+        window.roll(start + Duration::from_secs(4), [14, 0, 1, 0]);
+        assert_eq!(window.rates.account, 2.0);
+        assert_eq!(window.rates.slot, 0.0);
+    }
+}
@@ -19,7 +19,7 @@ use solana_rpc_client_api::config::RpcSendTransactionConfig;
 use crate::{
     accounts::User,
     build_accounts,
-    constants::{self, derive_revenue_share_escrow, state_account, JIT_PROXY_ID},
+    constants::{self, derive_revenue_share_escrow, state_account, ProgramData, JIT_PROXY_ID},
     drift_idl,
     swift_order_subscriber::SignedOrderInfo,
     types::PositionDirection,
@@ -213,25 +213,7 @@ impl JitProxyClient {
             data: instruction::Jit { params: jit_params }.data(),
         };
 
-        let mut ixs = Vec::with_capacity(3);
-        if let Some(cu_params) = self.cu_params {
-            let cu_limit_ix =
-                ComputeBudgetInstruction::set_compute_unit_price(cu_params.microlamports_per_cu());
-            let cu_price_ix =
-                ComputeBudgetInstruction::set_compute_unit_limit(cu_params.cu_limit());
-
-            ixs.push(cu_limit_ix);
-            ixs.push(cu_price_ix);
-        }
-        ixs.push(ix);
-
-        let luts = program_data.lookup_tables;
-
-        let message =
-            v0::Message::try_compile(&maker_authority, ixs.as_slice(), luts, Default::default())
-                .expect("failed to compile message");
-
-        Ok(VersionedMessage::V0(message))
+        Ok(self.compile_message(&maker_authority, ix))
     }
 
     /// Build a swift fill tx against a taker order given by `taker_params`
@@ -333,6 +315,105 @@ impl JitProxyClient {
         Ok(message)
     }
 
+    /// Build an `arb_perp` tx, atomically taking against crossing makers and the AMM
+    ///
+    /// The tx fails unless the arb ends in profit
+    ///
+    /// `market_index` perp market to arb
+    /// `makers` crossing makers to take against e.g. from the DLOB
+    /// `referrer_info` referrer of the arber, if any
+    /// `arber_params` tuple (pubkey, data) of the arber's sub-account
+    pub fn build_arb_perp_tx(
+        &self,
+        market_index: u16,
+        makers: &[ArbMaker],
+        referrer_info: Option<ReferrerInfo>,
+        arber_params: (&Pubkey, &User),
+    ) -> SdkResult<VersionedMessage> {
+        if self
+            .drift_client
+            .program_data()
+            .perp_market_config_by_index(market_index)
+            .is_none()
+        {
+            return Err(SdkError::InvalidAccount);
+        }
+        let ix = arb_perp_ix(
+            self.drift_client.program_data(),
+            market_index,
+            makers,
+            referrer_info,
+            arber_params,
+        );
+        Ok(self.compile_message(&arber_params.1.authority, ix))
+    }
+
+    /// Send an `arb_perp` tx with given params
+    ///
+    /// `market_index` perp market to arb
+    /// `makers` crossing makers to take against e.g. from the DLOB
+    /// `referrer_info` referrer of the arber, if any
+    /// `arber_authority` the arber's authority key
+    /// `sub_account_id` the arber's sub-account for the arb
+    pub async fn arb_perp(
+        &self,
+        market_index: u16,
+        makers: &[ArbMaker],
+        referrer_info: Option<ReferrerInfo>,
+        arber_authority: &Pubkey,
+        sub_account_id: Option<u16>,
+    ) -> SdkResult<Signature> {
+        let sub_account =
+            Wallet::derive_user_account(arber_authority, sub_account_id.unwrap_or_default());
+        let sub_account_data = self.drift_client.get_user_account(&sub_account).await?;
+        let tx = self.build_arb_perp_tx(
+            market_index,
+            makers,
+            referrer_info,
+            (&sub_account, &sub_account_data),
+        )?;
+        self.drift_client
+            .sign_and_send_with_config(tx, None, self.config)
+            .await
+    }
+
+    /// Build a `check_order_constraints` ix
+    ///
+    /// The ix fails if any position of the sub-account, including open orders, is outside its
+    /// constraint bounds. Add it after order placement ixs to guard a maker's inventory
+    ///
+    /// `user_params` tuple (pubkey, data) of the constrained sub-account
+    /// `constraints` position bounds per market
+    pub fn build_check_order_constraints_ix(
+        &self,
+        user_params: (&Pubkey, &User),
+        constraints: &[OrderConstraint],
+    ) -> Instruction {
+        check_order_constraints_ix(self.drift_client.program_data(), user_params, constraints)
+    }
+
+    /// Compile `ix` into a tx with the configured compute budget
+    fn compile_message(&self, payer: &Pubkey, ix: Instruction) -> VersionedMessage {
+        let mut ixs = Vec::with_capacity(3);
+        if let Some(cu_params) = self.cu_params {
+            let cu_limit_ix =
+                ComputeBudgetInstruction::set_compute_unit_price(cu_params.microlamports_per_cu());
+            let cu_price_ix =
+                ComputeBudgetInstruction::set_compute_unit_limit(cu_params.cu_limit());
+
+            ixs.push(cu_limit_ix);
+            ixs.push(cu_price_ix);
+        }
+        ixs.push(ix);
+
+        let luts = self.drift_client.program_data().lookup_tables;
+
+        let message = v0::Message::try_compile(payer, ixs.as_slice(), luts, Default::default())
+            .expect("failed to compile message");
+
+        VersionedMessage::V0(message)
+    }
+
     /// Send a jit tx with given params
     ///
     /// `taker_order_id` Id of the order to take against
@@ -397,6 +478,115 @@ impl JitProxyClient {
     }
 }
 
+/// A maker to take against in `arb_perp`
+#[derive(Clone, Copy)]
+pub struct ArbMaker {
+    maker: Pubkey,
+    maker_stats: Pubkey,
+    maker_account: User,
+}
+
+impl ArbMaker {
+    /// * `maker` - the maker's sub-account
+    /// * `maker_account` - the maker's sub-account data
+    pub fn new(maker: Pubkey, maker_account: User) -> Self {
+        Self {
+            maker,
+            maker_stats: Wallet::derive_stats_account(&maker_account.authority),
+            maker_account,
+        }
+    }
+}
+
+/// Position bounds of a market, enforced by `check_order_constraints`
+#[derive(Debug, Clone, Copy, AnchorSerialize, AnchorDeserialize, PartialEq)]
+pub struct OrderConstraint {
+    pub max_position: i64,
+    pub min_position: i64,
+    pub market_index: u16,
+    pub market_type: MarketType,
+}
+
+impl OrderConstraint {
+    pub fn new(market: MarketId, min_position: i64, max_position: i64) -> Self {
+        Self {
+            max_position,
+            min_position,
+            market_index: market.index(),
+            market_type: market.kind(),
+        }
+    }
+}
+
+fn arb_perp_ix(
+    program_data: &ProgramData,
+    market_index: u16,
+    makers: &[ArbMaker],
+    referrer_info: Option<ReferrerInfo>,
+    arber_params: (&Pubkey, &User),
+) -> Instruction {
+    let arber_authority = arber_params.1.authority;
+    let writable_markets = [MarketId::perp(market_index)];
+    let mut accounts = build_accounts(
+        program_data,
+        self::accounts::ArbPerp {
+            state: *state_account(),
+            user: *arber_params.0,
+            user_stats: Wallet::derive_stats_account(&arber_authority),
+            authority: arber_authority,
+            drift_program: constants::PROGRAM_ID,
+        },
+        std::iter::once(arber_params.1).chain(makers.iter().map(|m| &m.maker_account)),
+        std::iter::empty(),
+        writable_markets.iter(),
+    );
+
+    for maker in makers {
+        accounts.push(AccountMeta::new(maker.maker, false));
+        accounts.push(AccountMeta::new(maker.maker_stats, false));
+    }
+
+    if let Some(referrer_info) = referrer_info {
+        accounts.push(AccountMeta::new(referrer_info.referrer(), false));
+        accounts.push(AccountMeta::new(referrer_info.referrer_stats(), false));
+    }
+
+    Instruction {
+        program_id: JIT_PROXY_ID,
+        accounts,
+        data: instruction::ArbPerp { market_index }.data(),
+    }
+}
+
+fn check_order_constraints_ix(
+    program_data: &ProgramData,
+    user_params: (&Pubkey, &User),
+    constraints: &[OrderConstraint],
+) -> Instruction {
+    let markets: Vec<MarketId> = constraints
+        .iter()
+        .map(|c| MarketId::new(c.market_index, c.market_type))
+        .collect();
+    let accounts = build_accounts(
+        program_data,
+        self::accounts::CheckOrderConstraints {
+            user: *user_params.0,
+        },
+        std::iter::once(user_params.1),
+        markets.iter(),
+        std::iter::empty(),
+    );
+
+    Instruction {
+        program_id: JIT_PROXY_ID,
+        accounts,
+        data: instruction::CheckOrderConstraints {
+            constraints: constraints.to_vec(),
+        }
+        .data(),
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct ComputeBudgetParams {
     microlamports_per_cu: u64,
@@ -498,6 +688,24 @@ pub mod instruction {
     }
     impl anchor_lang::InstructionData for JitSignedMsg {}
 
+    #[derive(AnchorSerialize, AnchorDeserialize)]
+    pub struct ArbPerp {
+        pub market_index: u16,
+    }
+    impl anchor_lang::Discriminator for ArbPerp {
+        const DISCRIMINATOR: &[u8] = &[116, 105, 138, 99, 28, 171, 39, 225];
+    }
+    impl anchor_lang::InstructionData for ArbPerp {}
+
+    #[derive(AnchorSerialize, AnchorDeserialize)]
+    pub struct CheckOrderConstraints {
+        pub constraints: Vec<OrderConstraint>,
+    }
+    impl anchor_lang::Discriminator for CheckOrderConstraints {
+        const DISCRIMINATOR: &[u8] = &[183, 174, 142, 245, 5, 29, 207, 2];
+    }
+    impl anchor_lang::InstructionData for CheckOrderConstraints {}
+
     #[derive(Debug, Clone, Copy, AnchorSerialize, AnchorDeserialize)]
     pub struct JitSignedMsgParams {
         pub signed_order_info_uuid: [u8; 8],
@@ -569,4 +777,121 @@ pub mod accounts {
             ]
         }
     }
+
+    pub struct ArbPerp {
+        pub state: Pubkey,
+        pub user: Pubkey,
+        pub user_stats: Pubkey,
+        pub authority: Pubkey,
+        pub drift_program: Pubkey,
+    }
+    #[automatically_derived]
+    impl ToAccountMetas for ArbPerp {
+        fn to_account_metas(&self) -> Vec<AccountMeta> {
+            vec![
+                AccountMeta::new_readonly(self.state, false),
+                AccountMeta::new(self.user, false),
+                AccountMeta::new(self.user_stats, false),
+                AccountMeta::new_readonly(self.authority, true),
+                AccountMeta::new_readonly(self.drift_program, false),
+            ]
+        }
+    }
+
+    pub struct CheckOrderConstraints {
+        pub user: Pubkey,
+    }
+    #[automatically_derived]
+    impl ToAccountMetas for CheckOrderConstraints {
+        fn to_account_metas(&self) -> Vec<AccountMeta> {
+            vec![AccountMeta::new_readonly(self.user, false)]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::Discriminator;
+
+    use super::*;
+    use crate::types::accounts::{PerpMarket, SpotMarket, State};
+
+    fn program_data() -> ProgramData {
+        ProgramData::new(
+            vec![SpotMarket::default()],
+            vec![PerpMarket::default()],
+            vec![],
+            State::default(),
+        )
+    }
+
+    #[test]
+    fn arb_perp_ix_accounts() {
+        let program_data = program_data();
+        let arber = Pubkey::new_unique();
+        let arber_account = User {
+            authority: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        let maker = ArbMaker::new(
+            Pubkey::new_unique(),
+            User {
+                authority: Pubkey::new_unique(),
+                ..Default::default()
+            },
+        );
+        let referrer = ReferrerInfo::new(Pubkey::new_unique(), Pubkey::new_unique());
+
+        let ix = arb_perp_ix(
+            &program_data,
+            0,
+            &[maker],
+            Some(referrer),
+            (&arber, &arber_account),
+        );
+        assert_eq!(ix.program_id, JIT_PROXY_ID);
+        assert_eq!(ix.data[..8], instruction::ArbPerp::DISCRIMINATOR[..]);
+        assert_eq!(ix.data[8..], 0_u16.to_le_bytes());
+
+        assert_eq!(ix.accounts[1], AccountMeta::new(arber, false));
+        assert_eq!(
+            ix.accounts[3],
+            AccountMeta::new_readonly(arber_account.authority, true)
+        );
+        // perp market is writable
+        let perp_market = program_data.perp_market_config_by_index(0).unwrap().pubkey;
+        assert!(ix
+            .accounts
+            .iter()
+            .any(|a| a.pubkey == perp_market && a.is_writable));
+        // makers then referrer trail the market accounts
+        let n = ix.accounts.len();
+        assert_eq!(
+            ix.accounts[n - 4..],
+            [
+                AccountMeta::new(maker.maker, false),
+                AccountMeta::new(maker.maker_stats, false),
+                AccountMeta::new(referrer.referrer(), false),
+                AccountMeta::new(referrer.referrer_stats(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_order_constraints_ix_data() {
+        let program_data = program_data();
+        let user = Pubkey::new_unique();
+        let constraints = [
+            OrderConstraint::new(MarketId::perp(0), -1_000, 1_000),
+            OrderConstraint::new(MarketId::spot(0), 0, 5_000),
+        ];
+
+        let ix = check_order_constraints_ix(&program_data, (&user, &User::default()), &constraints);
+        assert_eq!(ix.accounts[0], AccountMeta::new_readonly(user, false));
+        assert!(ix.accounts[1..].iter().all(|a| !a.is_writable));
+
+        let mut data = &ix.data[8..];
+        let decoded = instruction::CheckOrderConstraints::deserialize(&mut data).unwrap();
+        assert_eq!(decoded.constraints, constraints);
+    }
 }
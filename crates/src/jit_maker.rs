@@ -0,0 +1,473 @@
+//! JIT maker auction engine
+//!
+//! Watches taker auctions streamed by `AuctionSubscriber`, prices new auction orders with a
+//! user supplied `JitStrategy` and fills them via `JitProxyClient` once the auction price crosses
+//! the maker's quote
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+
+use crate::{
+    auction_subscriber::AuctionSubscriber,
+    dlob::util::{compare_user_orders, OrderDelta},
+    ffi::OraclePriceData,
+    jit_client::{JitIxParams, JitProxyClient, JitTakerParams, PriceType},
+    math::auction::{get_auction_price, is_auction_complete},
+    solana_sdk::{clock::Slot, pubkey::Pubkey},
+    types::{accounts::User, MarketId, Order, OrderStatus, PositionDirection, ReferrerInfo},
+    websocket_program_account_subscriber::ProgramAccountUpdate,
+    DriftClient, Wallet,
+};
+
+const LOG_TARGET: &str = "jit";
+
+/// Slots between sweeps for takers whose auctions ended without a further account update
+const TAKER_PRUNE_SLOTS: Slot = 150;
+
+/// A taker order in auction
+#[derive(Clone, Debug)]
+pub struct TakerOrder {
+    /// taker sub-account
+    pub taker: Pubkey,
+    /// taker sub-account data
+    pub taker_account: User,
+    pub order: Order,
+    /// slot of the taker account update
+    pub slot: Slot,
+}
+
+/// Prices taker auctions for a `JitMaker`
+pub trait JitStrategy: Send + Sync + 'static {
+    /// Return fill bounds for `order` or `None` to skip it
+    ///
+    /// * `oracle` - latest oracle price of the order's market
+    fn quote(&self, order: &TakerOrder, oracle: &OraclePriceData) -> Option<JitIxParams>;
+}
+
+impl<F> JitStrategy for F
+where
+    F: Fn(&TakerOrder, &OraclePriceData) -> Option<JitIxParams> + Send + Sync + 'static,
+{
+    fn quote(&self, order: &TakerOrder, oracle: &OraclePriceData) -> Option<JitIxParams> {
+        self(order, oracle)
+    }
+}
+
+/// `JitMaker` configuration
+#[derive(Clone, Debug)]
+pub struct JitMakerConfig {
+    /// the maker's authority
+    pub maker_authority: Pubkey,
+    /// the maker's sub-account for fills
+    pub sub_account_id: u16,
+    /// expected slot duration, used to schedule fills
+    pub slot_time: Duration,
+}
+
+impl JitMakerConfig {
+    pub fn new(maker_authority: Pubkey, sub_account_id: u16) -> Self {
+        Self {
+            maker_authority,
+            sub_account_id,
+            slot_time: Duration::from_millis(400),
+        }
+    }
+}
+
+struct JitMakerInner<S> {
+    drift: DriftClient,
+    jit_client: JitProxyClient,
+    strategy: S,
+    config: JitMakerConfig,
+    /// takers with auctions in progress
+    takers: DashMap<Pubkey, TakerState, ahash::RandomState>,
+    /// latest slot the takers were swept at
+    pruned_slot: AtomicU64,
+}
+
+/// Last seen state of a taker with auctions in progress
+#[derive(Default)]
+struct TakerState {
+    account: User,
+    slot: Slot,
+    /// ids of open orders with a scheduled fill
+    attempted: Vec<u32>,
+}
+
+impl TakerState {
+    /// Replace the taker account, forgetting attempted orders that are no longer open
+    fn update(&mut self, account: User, slot: Slot) {
+        self.attempted.retain(|order_id| {
+            account
+                .orders
+                .iter()
+                .any(|o| o.order_id == *order_id && o.status == OrderStatus::Open)
+        });
+        self.account = account;
+        self.slot = slot;
+    }
+}
+
+/// Fills taker auctions with JIT maker orders
+///
+/// Auction orders are quoted once, on the taker update they first appear in, and
+/// filled at most once at the first slot their auction price crosses the strategy's quote
+pub struct JitMaker<S: JitStrategy> {
+    inner: Arc<JitMakerInner<S>>,
+}
+
+impl<S: JitStrategy> Clone for JitMaker<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<S: JitStrategy> JitMaker<S> {
+    /// Create a new `JitMaker`
+    ///
+    /// * `drift` - client providing oracle prices and taker stats
+    /// * `jit_client` - client sending the JIT fills
+    /// * `strategy` - prices taker auction orders
+    pub fn new(
+        drift: DriftClient,
+        jit_client: JitProxyClient,
+        strategy: S,
+        config: JitMakerConfig,
+    ) -> Self {
+        Self {
+            inner: Arc::new(JitMakerInner {
+                drift,
+                jit_client,
+                strategy,
+                config,
+                takers: DashMap::default(),
+                pruned_slot: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Start handling taker auctions from `auction_subscriber`
+    ///
+    /// requires oracles of the traded markets are subscribed
+    pub fn subscribe(&self, auction_subscriber: &AuctionSubscriber) {
+        let maker = self.clone();
+        auction_subscriber.subscribe(move |update| maker.on_update(update));
+    }
+
+    /// Handle a taker account update, scheduling fills for new auction orders
+    pub fn on_update(&self, update: &ProgramAccountUpdate<User>) {
+        let Ok(taker) = Pubkey::from_str(&update.pubkey) else {
+            log::warn!(target: LOG_TARGET, "invalid taker pubkey: {}", update.pubkey);
+            return;
+        };
+        let slot = update.data_and_slot.slot;
+        let taker_account = update.data_and_slot.data;
+
+        let orders = {
+            let mut state = self.inner.takers.entry(taker).or_default();
+            if slot < state.slot {
+                return;
+            }
+            let orders = new_auction_orders(taker, &state, &taker_account, slot);
+            state.update(taker_account, slot);
+            orders
+        };
+
+        for order in orders {
+            let taker_order = TakerOrder {
+                taker,
+                taker_account,
+                order,
+                slot,
+            };
+            if self.try_fill(taker_order, update.now) {
+                if let Some(mut state) = self.inner.takers.get_mut(&taker) {
+                    state.attempted.push(order.order_id);
+                }
+            }
+        }
+
+        self.inner.takers.remove_if(&taker, |_, state| {
+            !has_auction_in_progress(&state.account, slot)
+        });
+        self.inner.prune_takers(slot);
+    }
+
+    /// Quote `taker_order` and schedule a fill if the auction crosses the quote
+    ///
+    /// Returns true if a fill was scheduled
+    fn try_fill(&self, taker_order: TakerOrder, received: Instant) -> bool {
+        let order = &taker_order.order;
+        let market = MarketId::new(order.market_index, order.market_type);
+        let Some(oracle) = self.inner.drift.try_get_oracle_price_data_and_slot(market) else {
+            log::warn!(target: LOG_TARGET, "no oracle price for market: {market:?}");
+            return false;
+        };
+        let Some(params) = self.inner.strategy.quote(&taker_order, &oracle.data) else {
+            return false;
+        };
+        let Some(fire_slot) = fire_slot(order, taker_order.slot, oracle.data.price, &params) else {
+            log::debug!(
+                target: LOG_TARGET,
+                "auction never crosses quote: {}/{}",
+                taker_order.taker,
+                order.order_id
+            );
+            return false;
+        };
+
+        let fire_at = received
+            + self.inner.config.slot_time * fire_slot.saturating_sub(taker_order.slot) as u32;
+        let inner = Arc::clone(&self.inner);
+        tokio::spawn(async move { inner.fill(taker_order, params, fire_at).await });
+        true
+    }
+}
+
+impl<S: JitStrategy> JitMakerInner<S> {
+    /// Forget takers without auctions in progress, once per `TAKER_PRUNE_SLOTS`
+    fn prune_takers(&self, slot: Slot) {
+        let pruned_slot = self.pruned_slot.load(Ordering::Relaxed);
+        if slot < pruned_slot + TAKER_PRUNE_SLOTS
+            || self
+                .pruned_slot
+                .compare_exchange(pruned_slot, slot, Ordering::Relaxed, Ordering::Relaxed)
+                .is_err()
+        {
+            return;
+        }
+        self.takers
+            .retain(|_, state| has_auction_in_progress(&state.account, slot));
+    }
+
+    async fn fill(&self, taker_order: TakerOrder, params: JitIxParams, fire_at: Instant) {
+        let TakerOrder {
+            taker,
+            taker_account,
+            order,
+            ..
+        } = taker_order;
+        let referrer_info = self
+            .drift
+            .get_user_stats(&taker_account.authority)
+            .await
+            .ok()
+            .and_then(ReferrerInfo::get_referrer_info);
+        let taker_params = JitTakerParams::new(
+            taker,
+            Wallet::derive_stats_account(&taker_account.authority),
+            taker_account,
+            referrer_info,
+        );
+
+        tokio::time::sleep_until(fire_at.into()).await;
+        match self
+            .jit_client
+            .jit(
+                order.order_id,
+                &taker_params,
+                params,
+                &self.config.maker_authority,
+                Some(self.config.sub_account_id),
+            )
+            .await
+        {
+            Ok(signature) => {
+                log::info!(target: LOG_TARGET, "jit sent: {taker}/{}: {signature}", order.order_id)
+            }
+            Err(err) => {
+                log::warn!(target: LOG_TARGET, "jit failed: {taker}/{}: {err:?}", order.order_id)
+            }
+        }
+    }
+}
+
+/// Orders of `new` with an auction in progress at `slot` that were not open in the taker's
+/// last seen account, nor attempted
+///
+/// the `compare_user_orders` deltas also include orders that remain open, these are filtered out
+fn new_auction_orders(taker: Pubkey, state: &TakerState, new: &User, slot: Slot) -> Vec<Order> {
+    let (_, deltas) = compare_user_orders(taker, &state.account, new);
+    let removed: Vec<u32> = deltas
+        .iter()
+        .filter_map(|delta| match delta {
+            OrderDelta::Remove { order } => Some(order.order_id),
+            _ => None,
+        })
+        .collect();
+
+    deltas
+        .into_iter()
+        .filter_map(|delta| match delta {
+            OrderDelta::Create { order } => Some(order),
+            _ => None,
+        })
+        .filter(|order| {
+            let is_new = removed.contains(&order.order_id)
+                || !state
+                    .account
+                    .orders
+                    .iter()
+                    .any(|o| o.order_id == order.order_id && o.status == OrderStatus::Open);
+            is_new && !state.attempted.contains(&order.order_id) && in_auction(order, slot)
+        })
+        .collect()
+}
+
+/// True if `order` is open with an auction in progress at `slot`
+fn in_auction(order: &Order, slot: Slot) -> bool {
+    order.status == OrderStatus::Open
+        && order.auction_duration > 0
+        && !is_auction_complete(order, slot)
+}
+
+/// True if any order of `taker_account` has an auction in progress at `slot`
+fn has_auction_in_progress(taker_account: &User, slot: Slot) -> bool {
+    taker_account.orders.iter().any(|o| in_auction(o, slot))
+}
+
+/// First slot from `current_slot` at which the auction price of `order` crosses the maker's quote
+///
+/// A long taker is filled on the maker's ask, a short taker on the maker's bid.
+/// Returns `None` if the quote is not crossed before the auction ends
+pub fn fire_slot(
+    order: &Order,
+    current_slot: Slot,
+    oracle_price: i64,
+    params: &JitIxParams,
+) -> Option<Slot> {
+    let auction_end = order.slot + order.auction_duration as u64;
+    (current_slot.max(order.slot)..=auction_end).find(|slot| {
+        let mut price = get_auction_price(order, *slot, oracle_price);
+        if params.price_type == PriceType::Oracle {
+            price -= oracle_price as i128;
+        }
+        match order.direction {
+            PositionDirection::Long => price >= params.ask as i128,
+            PositionDirection::Short => price <= params.bid as i128,
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{MarketType, OrderType};
+
+    fn auction_order(order_id: u32, direction: PositionDirection) -> Order {
+        Order {
+            order_id,
+            slot: 100,
+            status: OrderStatus::Open,
+            order_type: OrderType::Oracle,
+            market_type: MarketType::Perp,
+            direction,
+            base_asset_amount: 1_000,
+            auction_duration: 10,
+            auction_start_price: -100,
+            auction_end_price: 100,
+            ..Default::default()
+        }
+    }
+
+    fn quote(bid: i64, ask: i64) -> JitIxParams {
+        JitIxParams::new(1_000, -1_000, bid, ask, PriceType::Oracle, None)
+    }
+
+    #[test]
+    fn fire_slot_crosses_quote() {
+        let long = auction_order(1, PositionDirection::Long);
+        let oracle_price = 1_000_000;
+        // auction start offset already crosses
+        assert_eq!(
+            fire_slot(&long, 100, oracle_price, &quote(-200, -200)),
+            Some(100)
+        );
+        // never crosses
+        assert_eq!(fire_slot(&long, 100, oracle_price, &quote(-200, 500)), None);
+        // auction over
+        assert_eq!(
+            fire_slot(&long, 200, oracle_price, &quote(-200, -200)),
+            None
+        );
+
+        let fired = fire_slot(&long, 100, oracle_price, &quote(-200, 50)).unwrap();
+        assert!(fired > 100 && fired <= 110);
+        assert!(get_auction_price(&long, fired, oracle_price) - oracle_price as i128 >= 50);
+        assert!(get_auction_price(&long, fired - 1, oracle_price) - (oracle_price as i128) < 50);
+
+        // short taker fills on the maker's bid
+        let short = auction_order(2, PositionDirection::Short);
+        assert_eq!(
+            fire_slot(&short, 100, oracle_price, &quote(i64::MAX, i64::MAX)),
+            Some(100)
+        );
+        assert_eq!(
+            fire_slot(&short, 100, oracle_price, &quote(i64::MIN, i64::MAX)),
+            None
+        );
+    }
+
+    #[test]
+    fn only_new_auction_orders() {
+        let taker = Pubkey::new_unique();
+        let mut state = TakerState::default();
+        state.account.orders[0] = auction_order(1, PositionDirection::Long);
+
+        let mut new = state.account;
+        new.orders[1] = auction_order(2, PositionDirection::Short);
+        new.orders[2] = Order {
+            auction_duration: 0,
+            ..auction_order(3, PositionDirection::Short)
+        };
+        new.orders[3] = Order {
+            status: OrderStatus::Filled,
+            ..auction_order(4, PositionDirection::Short)
+        };
+
+        let orders = new_auction_orders(taker, &state, &new, 105);
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].order_id, 2);
+
+        // auction ended
+        assert!(new_auction_orders(taker, &state, &new, 200).is_empty());
+        // all orders new for unseen takers
+        assert_eq!(
+            new_auction_orders(taker, &TakerState::default(), &new, 105).len(),
+            2
+        );
+        // attempted orders are skipped
+        let attempted = TakerState {
+            attempted: vec![1],
+            ..TakerState::default()
+        };
+        let orders = new_auction_orders(taker, &attempted, &new, 105);
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].order_id, 2);
+    }
+
+    #[test]
+    fn taker_state_forgets_closed_orders() {
+        let mut state = TakerState::default();
+        state.account.orders[0] = auction_order(1, PositionDirection::Long);
+        state.account.orders[1] = auction_order(2, PositionDirection::Short);
+        state.attempted = vec![1, 2];
+        assert!(has_auction_in_progress(&state.account, 105));
+
+        let mut new = state.account;
+        new.orders[1].status = OrderStatus::Filled;
+        state.update(new, 106);
+        assert_eq!(state.attempted, vec![1]);
+        assert_eq!(state.slot, 106);
+        assert!(!has_auction_in_progress(&state.account, 200));
+    }
+}
@@ -0,0 +1,141 @@
+//! Keeper scheduling
+//!
+//! Config, on-chain clock and crank loop shared by `MarketKeeper` and `LpPoolKeeper`
+use std::{future::Future, time::Duration};
+
+use crate::{
+    constants::SYSVAR_CLOCK_PUBKEY,
+    solana_sdk::clock::Slot,
+    types::{SdkError, SdkResult, UnsubHandle},
+    DriftClient,
+};
+
+/// CU limit headroom over the summed crank estimates (percent)
+const COMPUTE_UNIT_HEADROOM_PCT: u32 = 20;
+
+/// CU limit of a tx sending cranks estimated at `compute_units`, including headroom
+pub fn compute_unit_limit(compute_units: u32) -> u32 {
+    compute_units.saturating_mul(100 + COMPUTE_UNIT_HEADROOM_PCT) / 100
+}
+
+/// Options shared by all keepers
+#[derive(Clone, Debug)]
+pub struct KeeperOpts {
+    /// interval between checks when spawned
+    pub interval: Duration,
+    /// priority fee in µ-lamports per CU
+    pub priority_fee: Option<u64>,
+}
+
+impl KeeperOpts {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            priority_fee: None,
+        }
+    }
+}
+
+/// Builder methods shared by keeper configs
+pub trait KeeperConfig: Sized {
+    /// The config's `KeeperOpts`
+    fn opts_mut(&mut self) -> &mut KeeperOpts;
+
+    /// Set the interval between checks when spawned
+    fn with_interval(mut self, interval: Duration) -> Self {
+        self.opts_mut().interval = interval;
+        self
+    }
+    /// Set the tx priority fee
+    fn with_priority_fee(mut self, microlamports_per_cu: u64) -> Self {
+        self.opts_mut().priority_fee = Some(microlamports_per_cu);
+        self
+    }
+}
+
+/// On-chain clock that cranks are scheduled against
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChainClock {
+    pub slot: Slot,
+    /// unix timestamp of `slot`, as seen by the program
+    pub unix_timestamp: i64,
+}
+
+impl ChainClock {
+    /// Fetch the clock sysvar
+    pub async fn fetch(drift: &DriftClient) -> SdkResult<Self> {
+        let (account, _slot) = drift
+            .backend
+            .get_account_with_slot_raw(&SYSVAR_CLOCK_PUBKEY)
+            .await?;
+        Self::from_sysvar_data(&account.data)
+    }
+
+    /// Decode the clock sysvar `data`
+    fn from_sysvar_data(data: &[u8]) -> SdkResult<Self> {
+        // slot, epoch_start_timestamp, epoch, leader_schedule_epoch, unix_timestamp
+        let field = |offset: usize| -> SdkResult<[u8; 8]> {
+            data.get(offset..offset + 8)
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or(SdkError::Deserializing)
+        };
+        Ok(Self {
+            slot: u64::from_le_bytes(field(0)?),
+            unix_timestamp: i64::from_le_bytes(field(32)?),
+        })
+    }
+}
+
+/// Run `crank` every `interval` until the returned handle is sent to or dropped
+///
+/// * `log_target` - log target of the keeper
+pub(crate) fn spawn_keeper<F, Fut, T>(
+    log_target: &'static str,
+    interval: Duration,
+    crank: F,
+) -> UnsubHandle
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = SdkResult<T>> + Send + 'static,
+{
+    let (unsub_tx, mut unsub_rx) = tokio::sync::oneshot::channel::<()>();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                biased;
+                _ = &mut unsub_rx => break,
+                _ = interval.tick() => {
+                    if let Err(err) = crank().await {
+                        log::warn!(target: log_target, "crank failed: {err:?}");
+                    }
+                }
+            }
+        }
+        log::debug!(target: log_target, "keeper stopped");
+    });
+
+    unsub_tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_clock_from_sysvar() {
+        let mut data = vec![];
+        for field in [1_000_u64, 5, 2, 3, 1_700_000_000] {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+        assert_eq!(
+            ChainClock::from_sysvar_data(&data).unwrap(),
+            ChainClock {
+                slot: 1_000,
+                unix_timestamp: 1_700_000_000,
+            }
+        );
+        assert!(ChainClock::from_sysvar_data(&data[..39]).is_err());
+    }
+}
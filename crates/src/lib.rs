@@ -21,23 +21,48 @@ pub use crate::solana_sdk::{message::AddressLookupTableAccount, pubkey::Pubkey};
 use crate::titan::TitanSwapInfo;
 use crate::{
     account_map::AccountMap,
+    async_utils::retry_policy::TaskRetryPolicy,
     blockhash_subscriber::BlockhashSubscriber,
     constants::{
-        derive_perp_market_account, derive_revenue_share_escrow, derive_spot_market_account,
-        state_account, MarketExt, ProgramData, DEFAULT_PUBKEY, PYTH_LAZER_STORAGE_ACCOUNT_KEY,
-        SYSVAR_INSTRUCTIONS_PUBKEY, SYSVAR_RENT_PUBKEY,
+        derive_amm_cache, derive_amm_constituent_mapping, derive_constituent,
+        derive_constituent_target_base, derive_constituent_vault, derive_lp_pool,
+        derive_lp_pool_token_vault, derive_perp_market_account, derive_revenue_share,
+        derive_revenue_share_escrow, derive_spot_market_account, state_account, MarketExt,
+        ProgramData, DEFAULT_PUBKEY, PYTH_LAZER_STORAGE_ACCOUNT_KEY, SYSVAR_INSTRUCTIONS_PUBKEY,
+        SYSVAR_RENT_PUBKEY,
     },
     drift_idl::traits::ToAccountMetas,
     ffi::OraclePriceData,
-    grpc::grpc_subscriber::{AccountFilter, DriftGrpcClient, GeyserSubscribeOpts},
+    grpc::{
+        dedup::{subscribe_redundant, GrpcDedup},
+        grpc_subscriber::{AccountFilter, DriftGrpcClient, GeyserSubscribeOpts},
+        status::{GrpcStatus, OnStateChangeFn},
+        OnAccountFn, OnBlockMetaFn, OnOracleFn, OnResyncFn, OnSlotFn, OnTransactionFn,
+    },
     jupiter::JupiterSwapInfo,
+    lp_pool_keeper::{LpPoolKeeper, LpPoolKeeperConfig},
+    market_keeper::{MarketKeeper, MarketKeeperConfig},
     marketmap::MarketMap,
+    math::lp_pool::{
+        calculate_target_weight, ConstituentState, LpMintQuote, LpRedeemQuote, LpSwapQuote,
+    },
     oraclemap::{Oracle, OracleMap},
-    swift_order_subscriber::{SignedOrderInfo, SwiftOrderStream},
+    slot_subscriber::SlotSubscriber,
+    snapshot::{AccountSnapshot, SnapshotAccount},
+    staleness::{AccountKind, StalenessConfig},
+    swap_router::SwapRoute,
+    swift_order_subscriber::{
+        ResilientSwiftOrderStream, SignedOrderInfo, SwiftOrderStream, SwiftSubscribeOpts,
+    },
     types::{
-        accounts::{PerpMarket, SpotMarket, State, User, UserStats},
+        accounts::{
+            AmmCache, Constituent, ConstituentTargetBase, LPPool, PerpMarket, SpotMarket, State,
+            User, UserStats,
+        },
         AccountUpdate, DataAndSlot, MarketType, *,
     },
+    update_stream::UpdateStream,
+    user_stats_map::UserStatsMap,
     utils::{get_http_url, get_ws_url},
 };
 pub use crate::{grpc::GrpcSubscribeOpts, types::Context, wallet::Wallet};
@@ -50,11 +75,12 @@ use constants::{
 pub use drift_pubsub_client::PubsubClient;
 use futures_util::TryFutureExt;
 use log::debug;
+use solana_account_decoder_client_types::UiAccountEncoding;
 pub use solana_rpc_client::nonblocking::rpc_client::RpcClient;
 use solana_rpc_client_api::{
-    config::RpcSimulateTransactionConfig,
+    config::{RpcAccountInfoConfig, RpcProgramAccountsConfig, RpcSimulateTransactionConfig},
     filter::RpcFilterType,
-    response::{Response, RpcSimulateTransactionResult},
+    response::RpcSimulateTransactionResult,
 };
 
 // utils
@@ -62,6 +88,8 @@ pub mod async_utils;
 pub mod ffi;
 pub mod jupiter;
 pub mod market_state;
+pub mod spot_liquidator;
+pub mod swap_router;
 pub mod titan;
 pub use market_state::MarketState;
 pub mod math;
@@ -86,15 +114,26 @@ pub mod auction_subscriber;
 pub mod blockhash_subscriber;
 pub mod event_subscriber;
 pub mod priority_fee_subscriber;
+pub mod swift_order_filter;
 pub mod swift_order_subscriber;
+pub mod swift_taker;
 
 pub mod jit_client;
+pub mod jit_maker;
 
+pub mod account_fetcher;
 pub mod account_map;
+pub mod keeper;
+pub mod lp_pool_keeper;
+pub mod market_keeper;
 pub mod marketmap;
 pub mod oraclemap;
+pub mod snapshot;
+pub mod staleness;
+pub mod update_stream;
 
 pub mod slot_subscriber;
+pub mod user_stats_map;
 pub mod usermap;
 
 pub mod dlob;
@@ -220,6 +259,17 @@ impl DriftClient {
             .await
     }
 
+    /// Start monitoring Ws subscribed markets, oracles and accounts for staleness
+    ///
+    /// Cached slots are compared to the network slot every `config.check_interval`.
+    /// Accounts exceeding their max age are resynced over RPC and have their Ws subscription restarted
+    /// if it missed updates. Enables the `try_get_*_fresh` family of reads
+    ///
+    /// Calling again updates the config
+    pub fn subscribe_staleness_monitor(&self, config: StalenessConfig) -> SdkResult<()> {
+        self.backend.subscribe_staleness_monitor(config)
+    }
+
     /// Subscribe to all spot and perp markets
     ///
     /// This is a no-op if already subscribed
@@ -367,6 +417,9 @@ impl DriftClient {
     /// - 'deposit+trade' orders require fillers to send an attached, preceding deposit tx
     ///   before the swift order
     ///
+    /// - a `Wallet::delegated` client authenticates as a swift Ws delegate of its authority,
+    ///   see `TransactionBuilder::initialize_swift_ws_delegates`
+    ///
     /// Returns a stream of swift orders
     pub async fn subscribe_swift_orders(
         &self,
@@ -385,6 +438,23 @@ impl DriftClient {
         .await
     }
 
+    /// Subscribe to swift order feed(s) for given `markets`, reconnecting automatically
+    ///
+    /// * `markets` - list of markets to watch for swift orders
+    /// * `opts` - subscription options e.g. sanitized/deposit+trade order flow, heartbeat timeout
+    /// * `retry_policy` - reconnect policy
+    ///
+    /// Returns a stream of deduplicated swift orders and recoverable errors
+    pub async fn subscribe_swift_orders_resilient(
+        &self,
+        markets: &[MarketId],
+        opts: SwiftSubscribeOpts,
+        retry_policy: impl TaskRetryPolicy,
+    ) -> SdkResult<ResilientSwiftOrderStream> {
+        swift_order_subscriber::subscribe_swift_orders_resilient(self, markets, opts, retry_policy)
+            .await
+    }
+
     /// Returns the MarketIds for all active spot markets (ignores de-listed and settled markets)
     ///
     /// Useful for iterating over all spot markets
@@ -641,6 +711,382 @@ impl DriftClient {
         self.backend.get_latest_blockhash().await
     }
 
+    /// Fetch `account` as `T`, returns `None` if it does not exist
+    async fn get_optional_account<T: AccountDeserialize>(
+        &self,
+        account: &Pubkey,
+    ) -> SdkResult<Option<T>> {
+        let (account, _slot) = self
+            .backend
+            .account_map
+            .fetcher()
+            .get_account(account)
+            .await?;
+        account
+            .map(|account| {
+                T::try_deserialize(&mut account.data.as_slice())
+                    .map_err(|err| SdkError::Anchor(Box::new(err)))
+            })
+            .transpose()
+    }
+
+    /// Fetch variable length `account` as `T`
+    ///
+    /// uses latest cached if subscribed, otherwise falls back to network query
+    async fn get_account_deserialized<T: AccountDeserialize>(
+        &self,
+        account: &Pubkey,
+    ) -> SdkResult<T> {
+        match self.backend.account_map.account_data_deserialized(account) {
+            Some(data) => data.map(|x| x.data),
+            None => self
+                .get_optional_account(account)
+                .await?
+                .ok_or(SdkError::NoAccountData(*account)),
+        }
+    }
+
+    /// Get the keys authorized to authenticate to the swift Ws server on behalf of `authority`
+    ///
+    /// Returns an empty list if `authority` has no delegates account
+    pub async fn get_swift_ws_delegates(&self, authority: &Pubkey) -> SdkResult<Vec<Pubkey>> {
+        self.get_optional_account::<types::accounts::SignedMsgWsDelegates>(
+            &Wallet::derive_swift_ws_delegates_account(authority),
+        )
+        .await
+        .map(|x| x.map(|x| x.delegates).unwrap_or_default())
+    }
+
+    /// Returns true if `delegate` may authenticate to the swift Ws server on behalf of `authority`
+    pub async fn is_swift_ws_delegate(
+        &self,
+        authority: &Pubkey,
+        delegate: &Pubkey,
+    ) -> SdkResult<bool> {
+        self.get_swift_ws_delegates(authority)
+            .await
+            .map(|delegates| delegates.contains(delegate))
+    }
+
+    /// Get the builder `RevenueShare` account of `authority`, if initialized
+    pub async fn get_revenue_share(
+        &self,
+        authority: &Pubkey,
+    ) -> SdkResult<Option<types::accounts::RevenueShare>> {
+        self.get_optional_account(&derive_revenue_share(authority))
+            .await
+    }
+
+    /// Get the `RevenueShareEscrow` account of `authority`, if initialized
+    ///
+    /// It tracks the authority's approved builders and builder fees owed by its open orders
+    pub async fn get_revenue_share_escrow(
+        &self,
+        authority: &Pubkey,
+    ) -> SdkResult<Option<types::accounts::RevenueShareEscrow>> {
+        self.get_optional_account(&derive_revenue_share_escrow(authority))
+            .await
+    }
+
+    /// Get the revenue of `builder` from builder codes
+    ///
+    /// Pending builder fees are summed over all `RevenueShareEscrow` accounts that approved `builder`.
+    /// The builder is not at a fixed offset of the escrow so all escrows are fetched and filtered locally
+    ///
+    /// * `builder` - builder authority
+    pub async fn get_builder_revenue(&self, builder: &Pubkey) -> SdkResult<BuilderRevenue> {
+        let mut revenue = BuilderRevenue::default();
+        if let Some(revenue_share) = self.get_revenue_share(builder).await? {
+            revenue.settled = revenue_share.total_builder_rewards;
+            revenue.settled_referrer_rewards = revenue_share.total_referrer_rewards;
+        }
+
+        let escrows = self
+            .rpc()
+            .get_program_ui_accounts_with_config(
+                &PROGRAM_ID,
+                RpcProgramAccountsConfig {
+                    filters: Some(vec![memcmp::get_revenue_share_escrow_filter()]),
+                    account_config: RpcAccountInfoConfig {
+                        encoding: Some(UiAccountEncoding::Base64Zstd),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )
+            .await?;
+        revenue.accrued = escrows
+            .iter()
+            .filter_map(|(_, account)| {
+                let data = account.data.decode()?;
+                types::accounts::RevenueShareEscrow::try_deserialize(&mut data.as_slice()).ok()
+            })
+            .map(|escrow| escrow.accrued_builder_fees(builder))
+            .sum();
+
+        Ok(revenue)
+    }
+
+    /// Subscribe to LP pool `lp_pool_id` and its constituents
+    ///
+    /// Subscribes the `LPPool`, its `ConstituentTargetBase` and the `Constituent`s of `constituent_markets`.
+    /// Spot markets and oracles of the constituents should be subscribed separately e.g. with `subscribe_markets`
+    ///
+    /// * `constituent_markets` - spot market indexes of the pool's constituents
+    pub async fn subscribe_lp_pool(
+        &self,
+        lp_pool_id: u8,
+        constituent_markets: &[u16],
+    ) -> SdkResult<()> {
+        let accounts = lp_pool_accounts(lp_pool_id, constituent_markets);
+        futures_util::future::try_join_all(
+            accounts
+                .iter()
+                .map(|account| self.backend.account_map.subscribe_account(account)),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Unsubscribe from LP pool `lp_pool_id` and its constituents
+    ///
+    /// Releases the subscriptions of `subscribe_lp_pool` with the same `constituent_markets`
+    pub fn unsubscribe_lp_pool(&self, lp_pool_id: u8, constituent_markets: &[u16]) {
+        for account in lp_pool_accounts(lp_pool_id, constituent_markets) {
+            self.backend.account_map.unsubscribe_account(&account);
+        }
+    }
+
+    /// Get the `LPPool` account of `lp_pool_id`
+    ///
+    /// uses latest cached if subscribed, otherwise falls back to network query
+    pub async fn get_lp_pool(&self, lp_pool_id: u8) -> SdkResult<LPPool> {
+        self.backend.get_account(&derive_lp_pool(lp_pool_id)).await
+    }
+
+    /// Try get the `LPPool` account of `lp_pool_id` from cache
+    ///
+    /// requires `subscribe_lp_pool` first
+    pub fn try_get_lp_pool(&self, lp_pool_id: u8) -> SdkResult<LPPool> {
+        self.backend.try_get_account(&derive_lp_pool(lp_pool_id))
+    }
+
+    /// Try get the `Constituent` of LP pool `lp_pool_id` for `spot_market_index` from cache
+    ///
+    /// requires `subscribe_lp_pool` first
+    pub fn try_get_constituent(
+        &self,
+        lp_pool_id: u8,
+        spot_market_index: u16,
+    ) -> SdkResult<Constituent> {
+        self.backend.try_get_account(&derive_constituent(
+            &derive_lp_pool(lp_pool_id),
+            spot_market_index,
+        ))
+    }
+
+    /// Try get the `ConstituentTargetBase` of LP pool `lp_pool_id` from cache
+    ///
+    /// requires `subscribe_lp_pool` first
+    pub fn try_get_constituent_target_base(
+        &self,
+        lp_pool_id: u8,
+    ) -> SdkResult<ConstituentTargetBase> {
+        let account = derive_constituent_target_base(&derive_lp_pool(lp_pool_id));
+        self.backend
+            .account_map
+            .account_data_deserialized(&account)
+            .ok_or(SdkError::NoAccountData(account))?
+            .map(|x| x.data)
+    }
+
+    /// Get the `ConstituentTargetBase` of LP pool `lp_pool_id`
+    ///
+    /// uses latest cached if subscribed, otherwise falls back to network query
+    pub async fn get_constituent_target_base(
+        &self,
+        lp_pool_id: u8,
+    ) -> SdkResult<ConstituentTargetBase> {
+        self.get_account_deserialized(&derive_constituent_target_base(&derive_lp_pool(lp_pool_id)))
+            .await
+    }
+
+    /// Get the perp market `AmmCache` shared by LP pools
+    ///
+    /// uses latest cached if subscribed, otherwise falls back to network query
+    pub async fn get_amm_cache(&self) -> SdkResult<AmmCache> {
+        self.get_account_deserialized(&derive_amm_cache()).await
+    }
+
+    /// Try get the state of LP pool `lp_pool_id`'s constituent for `spot_market_index` from cache
+    ///
+    /// The result provides the constituent's target weight for `quote_lp_pool_swap`
+    ///
+    /// requires `subscribe_lp_pool` and the constituent's spot market and oracle are subscribed
+    pub fn try_get_constituent_state(
+        &self,
+        lp_pool_id: u8,
+        spot_market_index: u16,
+    ) -> SdkResult<ConstituentState> {
+        let lp_pool = self.try_get_lp_pool(lp_pool_id)?;
+        let constituent = self.try_get_constituent(lp_pool_id, spot_market_index)?;
+        let spot_market = self.try_get_spot_market_account(spot_market_index)?;
+        let price = self
+            .try_get_oracle_price_data_and_slot(MarketId::spot(spot_market_index))
+            .ok_or(SdkError::InvalidOracle)?
+            .data
+            .price;
+        let target_base = self
+            .try_get_constituent_target_base(lp_pool_id)?
+            .targets
+            .get(constituent.constituent_index as usize)
+            .map(|target| target.target_base)
+            .ok_or_else(|| {
+                SdkError::Generic(format!(
+                    "no target for constituent: {}",
+                    constituent.constituent_index
+                ))
+            })?;
+
+        let spot_balance = constituent.spot_balance;
+        let spot_token_amount = ffi::get_token_amount(
+            spot_balance.scaled_balance,
+            &spot_market,
+            spot_balance.balance_type,
+        )? as i128;
+        let spot_token_amount = match spot_balance.balance_type {
+            SpotBalanceType::Deposit => spot_token_amount,
+            SpotBalanceType::Borrow => -spot_token_amount,
+        };
+
+        Ok(ConstituentState {
+            constituent,
+            token_amount: constituent.vault_token_balance as i128 + spot_token_amount,
+            price,
+            target_weight: calculate_target_weight(
+                target_base,
+                price,
+                constituent.decimals as u32,
+                lp_pool.last_aum,
+            ),
+        })
+    }
+
+    /// Quote LP tokens minted for depositing `in_amount` of spot market `in_market_index` into `lp_pool`
+    ///
+    /// Simulates the program's `ViewLpPoolAddLiquidityFees` ix
+    pub async fn quote_lp_pool_add_liquidity(
+        &self,
+        lp_pool: &LPPool,
+        in_market_index: u16,
+        in_amount: u128,
+    ) -> SdkResult<LpMintQuote> {
+        let tx = self
+            .view_tx()
+            .view_lp_pool_add_liquidity_fees(lp_pool, in_market_index, in_amount)
+            .build();
+        LpMintQuote::from_view_logs(&self.simulate_view_tx(tx).await?)
+    }
+
+    /// Quote constituent tokens of spot market `out_market_index` returned for burning `lp_amount`
+    /// LP tokens of `lp_pool`
+    ///
+    /// Simulates the program's `ViewLpPoolRemoveLiquidityFees` ix
+    pub async fn quote_lp_pool_remove_liquidity(
+        &self,
+        lp_pool: &LPPool,
+        out_market_index: u16,
+        lp_amount: u64,
+    ) -> SdkResult<LpRedeemQuote> {
+        let tx = self
+            .view_tx()
+            .view_lp_pool_remove_liquidity_fees(lp_pool, out_market_index, lp_amount)
+            .build();
+        LpRedeemQuote::from_view_logs(&self.simulate_view_tx(tx).await?)
+    }
+
+    /// Quote constituent tokens of `out_market_index` returned for swapping `in_amount` of
+    /// `in_market_index` in LP pool `lp_pool_id`
+    ///
+    /// Simulates the program's `ViewLpPoolSwapFees` ix at the constituents' current target weights
+    ///
+    /// requires `subscribe_lp_pool` and the constituents' spot markets and oracles are subscribed
+    pub async fn quote_lp_pool_swap(
+        &self,
+        lp_pool_id: u8,
+        in_market_index: u16,
+        out_market_index: u16,
+        in_amount: u64,
+    ) -> SdkResult<LpSwapQuote> {
+        let lp_pool = self.try_get_lp_pool(lp_pool_id)?;
+        let in_constituent = self.try_get_constituent_state(lp_pool_id, in_market_index)?;
+        let out_constituent = self.try_get_constituent_state(lp_pool_id, out_market_index)?;
+        let tx = self
+            .view_tx()
+            .view_lp_pool_swap_fees(
+                &lp_pool,
+                in_market_index,
+                out_market_index,
+                in_amount,
+                in_constituent.target_weight,
+                out_constituent.target_weight,
+            )
+            .build();
+        LpSwapQuote::from_view_logs(&self.simulate_view_tx(tx).await?)
+    }
+
+    /// `TransactionBuilder` for view ixs signed by this client's wallet
+    fn view_tx(&self) -> TransactionBuilder<'_> {
+        let wallet = self.wallet();
+        TransactionBuilder::new(
+            self.program_data(),
+            wallet.default_sub_account(),
+            Cow::Owned(User {
+                authority: *wallet.authority(),
+                ..Default::default()
+            }),
+            false,
+        )
+    }
+
+    /// Simulate a view ix `tx`, returning its logs
+    async fn simulate_view_tx(&self, tx: VersionedMessage) -> SdkResult<Vec<String>> {
+        let result = self.simulate_tx(tx).await?;
+        if let Some(err) = result.err {
+            return Err(SdkError::Generic(format!(
+                "view ix simulation failed: {err:?}"
+            )));
+        }
+        Ok(result.logs.unwrap_or_default())
+    }
+
+    /// Spawn an `LpPoolKeeper` sending the cranks of LP pool `config.lp_pool_id` as they fall due
+    ///
+    /// * `keeper_sub_account` - keeper's drift sub-account, authority is this client's wallet
+    ///
+    /// Returns a handle that stops the keeper when sent to or dropped
+    pub fn spawn_lp_pool_keeper(
+        &self,
+        keeper_sub_account: Pubkey,
+        config: LpPoolKeeperConfig,
+    ) -> UnsubHandle {
+        LpPoolKeeper::new(self.clone(), keeper_sub_account, config).spawn()
+    }
+
+    /// Spawn a `MarketKeeper` sending the funding, AMM and interest cranks of `config`'s markets
+    /// as they fall due
+    ///
+    /// * `keeper_sub_account` - keeper's drift sub-account, authority is this client's wallet
+    ///
+    /// Returns a handle that stops the keeper when sent to or dropped
+    pub fn spawn_market_keeper(
+        &self,
+        keeper_sub_account: Pubkey,
+        config: MarketKeeperConfig,
+    ) -> UnsubHandle {
+        MarketKeeper::new(self.clone(), keeper_sub_account, config).spawn()
+    }
+
     /// Get some account value deserialized as T
     /// Uses cached value if subscribed, falls back to network query
     ///
@@ -944,6 +1390,64 @@ impl DriftClient {
         self.backend.try_get_oracle_price_data_and_slot(market)
     }
 
+    /// Same as `try_get_oracle_price_data_and_slot` but errors if the oracle exceeds its max age
+    ///
+    /// Requires `subscribe_staleness_monitor`, otherwise data is never considered stale
+    pub fn try_get_oracle_price_data_and_slot_fresh(&self, market: MarketId) -> SdkResult<Oracle> {
+        self.backend
+            .try_get_oracle_price_data_and_slot_fresh(market)
+    }
+
+    /// Same as `try_get_perp_market_account_and_slot` but errors if the market exceeds its max age
+    ///
+    /// Requires `subscribe_staleness_monitor`, otherwise data is never considered stale
+    pub fn try_get_perp_market_account_and_slot_fresh(
+        &self,
+        market_index: u16,
+    ) -> SdkResult<DataAndSlot<PerpMarket>> {
+        self.backend
+            .try_get_perp_market_account_and_slot_fresh(market_index)
+    }
+
+    /// Same as `try_get_spot_market_account_and_slot` but errors if the market exceeds its max age
+    ///
+    /// Requires `subscribe_staleness_monitor`, otherwise data is never considered stale
+    pub fn try_get_spot_market_account_and_slot_fresh(
+        &self,
+        market_index: u16,
+    ) -> SdkResult<DataAndSlot<SpotMarket>> {
+        self.backend
+            .try_get_spot_market_account_and_slot_fresh(market_index)
+    }
+
+    /// Try get a subscribed `User` account and slot from cache, erroring if it exceeds its max age
+    ///
+    /// Requires `subscribe_staleness_monitor`, otherwise data is never considered stale
+    pub fn try_get_user_account_and_slot_fresh(
+        &self,
+        account: &Pubkey,
+    ) -> SdkResult<DataAndSlot<User>> {
+        self.backend.try_get_user_account_and_slot_fresh(account)
+    }
+
+    /// Try get a slot-consistent snapshot of `user` and its markets & oracles
+    ///
+    /// Use this for margin and liquidation decisions to avoid acting on state mixed from different slots
+    ///
+    /// * `user` - pubkey of the user account, must be subscribed
+    /// * `min_slot` - every account in the snapshot must be at or above this slot
+    /// * `force_markets` - additional markets to include regardless of user positions
+    ///
+    /// Returns `SdkError::StaleSnapshot` listing all accounts that are missing or older than `min_slot`
+    pub fn try_get_snapshot(
+        &self,
+        user: &Pubkey,
+        min_slot: Slot,
+        force_markets: &[MarketId],
+    ) -> SdkResult<AccountSnapshot> {
+        self.backend.try_get_snapshot(user, min_slot, force_markets)
+    }
+
     /// Get the AMM `OraclePriceData` if valid, otherwise return the conventional `OraclePriceData`
     ///
     /// ## Params
@@ -997,7 +1501,67 @@ impl DriftClient {
             .await
     }
 
+    /// Query referrals, 30d volumes and fee tiers of cached `UserStats` accounts
+    ///
+    /// Stats accounts must be cached first, e.g. with `GrpcSubscribeOpts::statsmap_on` or `UserStatsMap::sync`
+    pub fn user_stats_map(&self) -> UserStatsMap<'static> {
+        UserStatsMap::new(&self.backend.account_map)
+    }
+
+    /// Stream of `User` account updates matching `filter`
+    ///
+    /// Yields updates of subscribed users (Ws, polled or gRPC) with their pubkey.
+    /// Updates are buffered per consumer, a consumer too far behind skips the oldest ones (see `UpdateStream::lagged`)
+    /// ```example(no_run)
+    /// let mut updates = client.user_updates(|_pubkey, user| user.authority == authority);
+    /// while let Some((pubkey, user)) = updates.next().await {
+    ///     println!("{pubkey}: {} @ {}", user.data.sub_account_id, user.slot);
+    /// }
+    /// ```
+    pub fn user_updates<F>(&self, filter: F) -> UpdateStream<(Pubkey, DataAndSlot<User>)>
+    where
+        F: Fn(&Pubkey, &User) -> bool + Send + 'static,
+    {
+        self.backend.account_map.account_updates(filter)
+    }
+
+    /// Stream of perp market account updates
+    ///
+    /// Yields updates of subscribed perp markets (Ws or gRPC)
+    pub fn perp_market_updates(&self) -> UpdateStream<DataAndSlot<PerpMarket>> {
+        self.backend.perp_market_map.updates()
+    }
+
+    /// Stream of spot market account updates
+    ///
+    /// Yields updates of subscribed spot markets (Ws or gRPC)
+    pub fn spot_market_updates(&self) -> UpdateStream<DataAndSlot<SpotMarket>> {
+        self.backend.spot_market_map.updates()
+    }
+
+    /// Stream of oracle updates for `market`
+    ///
+    /// Yields updates while the market oracle is subscribed (Ws or gRPC)
+    pub fn oracle_updates(&self, market: MarketId) -> SdkResult<UpdateStream<Oracle>> {
+        self.backend.oracle_map.updates(&market)
+    }
+
+    /// Same as `subscribe_account` but returns a handle that unsubscribes `account` on drop
+    ///
+    /// The account remains subscribed while other subscribers hold it
+    pub async fn subscribe_account_handle(
+        &self,
+        account: &Pubkey,
+    ) -> SdkResult<SubscriptionHandle> {
+        self.backend
+            .account_map
+            .subscribe_account_handle(account)
+            .await
+    }
+
     /// Unsubscribe from updates for `account`
+    ///
+    /// Subscriptions are refcounted, the account remains subscribed while other subscribers hold it
     pub fn unsubscribe_account(&self, account: &Pubkey) -> SdkResult<()> {
         self.backend.account_map.unsubscribe_account(account);
         Ok(())
@@ -1070,6 +1634,13 @@ impl DriftClient {
         self.backend.grpc_unsubscribe();
     }
 
+    /// Returns status handles of the gRPC connections (program accounts, oracles)
+    ///
+    /// Empty if not subscribed via gRPC
+    pub fn grpc_status(&self) -> Vec<GrpcStatus> {
+        self.backend.grpc_status()
+    }
+
     pub async fn get_slot(&self) -> Option<u64> {
         self.backend.client().get_slot().await.ok()
     }
@@ -1081,6 +1652,27 @@ impl DriftClient {
     }
 }
 
+/// Log the outcome of a staleness resync
+fn log_resync<T: std::fmt::Debug>(name: &str, result: SdkResult<Vec<T>>) {
+    match result {
+        Ok(stale) if !stale.is_empty() => {
+            debug!(target: "staleness", "resynced stale {name}: {stale:?}")
+        }
+        Ok(_) => (),
+        Err(err) => log::warn!(target: "staleness", "{name} resync failed: {err:?}"),
+    }
+}
+
+/// Status handles of the streams redundant to the one at `idx`
+fn redundant_status(status: &[GrpcStatus], idx: usize) -> Vec<GrpcStatus> {
+    status
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != idx)
+        .map(|(_, s)| s.clone())
+        .collect()
+}
+
 /// Provides the heavy-lifting and network facing features of the SDK
 /// It is intended to be a singleton
 pub struct DriftClientBackend {
@@ -1092,7 +1684,11 @@ pub struct DriftClientBackend {
     perp_market_map: MarketMap<PerpMarket>,
     spot_market_map: MarketMap<SpotMarket>,
     oracle_map: OracleMap,
-    grpc_unsub: RwLock<Option<(UnsubHandle, UnsubHandle)>>,
+    grpc_unsub: RwLock<Vec<UnsubHandle>>,
+    grpc_status: RwLock<Vec<GrpcStatus>>,
+    slot_subscriber: SlotSubscriber,
+    staleness: RwLock<StalenessConfig>,
+    staleness_unsub: RwLock<Option<UnsubHandle>>,
 }
 impl DriftClientBackend {
     /// Initialize a new `DriftClientBackend`
@@ -1122,8 +1718,8 @@ impl DriftClientBackend {
         )?;
 
         let (_, _, lut_accounts, state_account_data) = tokio::try_join!(
-            perp_market_map.sync(&rpc_client),
-            spot_market_map.sync(&rpc_client),
+            perp_market_map.sync(account_map.fetcher()),
+            spot_market_map.sync(account_map.fetcher()),
             rpc_client
                 .get_multiple_accounts(lut_pubkeys)
                 .map_err(Into::into),
@@ -1162,6 +1758,7 @@ impl DriftClientBackend {
 
         Ok(Self {
             rpc_client: Arc::clone(&rpc_client),
+            slot_subscriber: SlotSubscriber::new(Arc::clone(&pubsub_client)),
             pubsub_client,
             blockhash_subscriber: BlockhashSubscriber::new(Duration::from_secs(2), rpc_client),
             program_data: ProgramData::new(
@@ -1175,6 +1772,9 @@ impl DriftClientBackend {
             spot_market_map,
             oracle_map,
             grpc_unsub: RwLock::default(),
+            grpc_status: RwLock::default(),
+            staleness: RwLock::default(),
+            staleness_unsub: RwLock::default(),
         })
     }
 
@@ -1210,8 +1810,8 @@ impl DriftClientBackend {
         )?;
 
         let (_, _, lut_accounts, state_account_data) = tokio::try_join!(
-            perp_market_map.sync(&rpc_client),
-            spot_market_map.sync(&rpc_client),
+            perp_market_map.sync(account_map.fetcher()),
+            spot_market_map.sync(account_map.fetcher()),
             rpc_client
                 .get_multiple_accounts(lut_pubkeys)
                 .map_err(Into::into),
@@ -1248,6 +1848,7 @@ impl DriftClientBackend {
 
         Ok(Self {
             rpc_client: Arc::clone(&rpc_client),
+            slot_subscriber: SlotSubscriber::new(Arc::clone(&pubsub_client)),
             pubsub_client,
             blockhash_subscriber: BlockhashSubscriber::new(Duration::from_secs(2), rpc_client),
             program_data: ProgramData::new(
@@ -1261,13 +1862,16 @@ impl DriftClientBackend {
             spot_market_map,
             oracle_map,
             grpc_unsub: RwLock::default(),
+            grpc_status: RwLock::default(),
+            staleness: RwLock::default(),
+            staleness_unsub: RwLock::default(),
         })
     }
 
     /// Returns true if `DriftClientBackend` is subscribed via gRPC
     pub fn is_grpc_subscribed(&self) -> bool {
         let unsub = self.grpc_unsub.read().unwrap();
-        unsub.is_some()
+        !unsub.is_empty()
     }
 
     /// Start subscription for latest block hashes
@@ -1348,125 +1952,195 @@ impl DriftClientBackend {
 
     /// Subscribe to all: markets, oracles, and slot updates over gRPC
     async fn grpc_subscribe(
-        &self,
+        &'static self,
         endpoint: String,
         x_token: String,
         opts: GrpcSubscribeOpts,
         sync: bool,
     ) -> SdkResult<()> {
         log::debug!(target: "grpc", "subscribing to grpc with config: commitment: {:?}, interslot updates: {:?}", opts.commitment, opts.interslot_updates);
-        let mut grpc = DriftGrpcClient::new(endpoint.clone(), x_token.clone())
-            .grpc_connection_opts(opts.connection_opts.clone());
 
         if sync {
             // the DriftClientBackend syncs marketmaps by default
             if self.perp_market_map.len() == 0 {
-                self.perp_market_map.sync(&self.rpc_client).await?;
+                self.perp_market_map
+                    .sync(self.account_map.fetcher())
+                    .await?;
             }
             if self.spot_market_map.len() == 0 {
-                self.spot_market_map.sync(&self.rpc_client).await?;
+                self.spot_market_map
+                    .sync(self.account_map.fetcher())
+                    .await?;
             }
-            let spot_markets = self
-                .spot_market_map
-                .marketmap
-                .iter()
-                .map(|i| MarketId::spot(*i.key()));
-            let perp_markets = self
-                .perp_market_map
-                .marketmap
-                .iter()
-                .map(|i| MarketId::perp(*i.key()));
-            let all_markets: Vec<MarketId> = spot_markets.chain(perp_markets).collect();
-
-            self.oracle_map
-                .sync(all_markets.as_ref(), &self.rpc_client)
-                .await?;
-        }
-
-        grpc.on_account(
-            AccountFilter::partial().with_discriminator(SpotMarket::DISCRIMINATOR),
-            self.spot_market_map.on_account_fn(),
-        );
-        grpc.on_account(
-            AccountFilter::partial().with_discriminator(PerpMarket::DISCRIMINATOR),
-            self.perp_market_map.on_account_fn(),
-        );
-
-        if opts.user_stats_map {
-            grpc.on_account(
-                AccountFilter::partial().with_discriminator(UserStats::DISCRIMINATOR),
-                self.account_map.on_account_fn(),
-            );
+            self.resync_oracles().await?;
         }
 
-        let transactions_accounts_include = opts
-            .transaction_include_accounts
-            .iter()
-            .map(|a| a.to_string())
+        let endpoints: Vec<(String, String)> = std::iter::once((endpoint, x_token))
+            .chain(opts.redundant_endpoints)
             .collect();
-        if let Some(f) = opts.on_transaction {
-            grpc.on_transaction(f);
-        }
+        // redundant streams are merged, forwarding the first arrival of each update
+        let (program_dedup, oracle_dedup) = if endpoints.len() > 1 {
+            (
+                Some(Arc::new(GrpcDedup::default())),
+                Some(Arc::new(GrpcDedup::default())),
+            )
+        } else {
+            (None, None)
+        };
 
-        // set custom callbacks
-        if let Some(callbacks) = opts.on_account {
-            for (filter, on_account) in callbacks {
-                grpc.on_account(filter, on_account)
+        let on_state_change: Option<Arc<OnStateChangeFn>> = opts.on_state_change.map(Arc::from);
+        let new_client = |endpoint: &str, x_token: &str, dedup: &Option<Arc<GrpcDedup>>| {
+            let mut grpc = DriftGrpcClient::new(endpoint.to_string(), x_token.to_string())
+                .grpc_connection_opts(opts.connection_opts.clone())
+                .retry_policy((opts.retry_policy)())
+                .from_slot_replay(opts.from_slot_replay);
+            if let Some(dedup) = dedup {
+                grpc = grpc.dedup(Arc::clone(dedup));
             }
-        }
-
-        if let Some(f) = opts.on_slot {
-            grpc.on_slot(f);
-        }
+            if let Some(ref f) = on_state_change {
+                grpc.status().set_on_state_change(Arc::clone(f));
+            }
+            grpc
+        };
 
-        if let Some(f) = opts.on_block_meta {
-            grpc.on_block_meta(f);
-        }
+        // custom callbacks are shared by all endpoints
+        let on_transaction: Option<Arc<OnTransactionFn>> = opts.on_transaction.map(Arc::from);
+        let on_slot: Option<Arc<OnSlotFn>> = opts.on_slot.map(Arc::from);
+        let on_block_meta: Option<Arc<OnBlockMetaFn>> = opts.on_block_meta.map(Arc::from);
+        let on_oracle_update: Option<Arc<OnOracleFn>> = opts.on_oracle_update.map(Arc::from);
+        let on_account: Vec<(AccountFilter, Arc<OnAccountFn>)> = opts
+            .on_account
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(filter, f)| (filter, Arc::from(f)))
+            .collect();
 
-        if opts.usermap {
-            grpc.on_account(
-                AccountFilter::partial().with_discriminator(User::DISCRIMINATOR),
-                self.account_map.on_account_fn(),
-            );
+        let user_filter = if opts.usermap {
+            AccountFilter::partial().with_discriminator(User::DISCRIMINATOR)
         } else {
             // when usermap is on, the custom accounts are already included
             // usermap off: subscribe to custom `User` accounts
+            AccountFilter::full()
+                .with_discriminator(User::DISCRIMINATOR)
+                .with_accounts(opts.user_accounts.into_iter())
+        };
+
+        let mut program_clients = Vec::with_capacity(endpoints.len());
+        let mut oracle_clients = Vec::with_capacity(endpoints.len());
+        for (endpoint, x_token) in endpoints.iter() {
+            let mut grpc = new_client(endpoint, x_token, &program_dedup);
             grpc.on_account(
-                AccountFilter::full()
-                    .with_discriminator(User::DISCRIMINATOR)
-                    .with_accounts(opts.user_accounts.into_iter()),
-                self.account_map.on_account_fn(),
+                AccountFilter::partial().with_discriminator(SpotMarket::DISCRIMINATOR),
+                self.spot_market_map.on_account_fn(),
             );
-        }
-
-        if opts.user_stats_map {
             grpc.on_account(
-                AccountFilter::partial().with_discriminator(UserStats::DISCRIMINATOR),
-                self.account_map.on_account_fn(),
+                AccountFilter::partial().with_discriminator(PerpMarket::DISCRIMINATOR),
+                self.perp_market_map.on_account_fn(),
             );
+            if let Some(ref f) = on_transaction {
+                let f = Arc::clone(f);
+                grpc.on_transaction(move |update| f(update));
+            }
+            for (filter, f) in on_account.iter() {
+                let f = Arc::clone(f);
+                grpc.on_account(filter.clone(), move |update| f(update));
+            }
+            if let Some(ref f) = on_slot {
+                let f = Arc::clone(f);
+                grpc.on_slot(move |slot| f(slot));
+            }
+            if let Some(ref f) = on_block_meta {
+                let f = Arc::clone(f);
+                grpc.on_block_meta(move |meta| f(meta));
+            }
+            grpc.on_account(user_filter.clone(), self.account_map.on_account_fn());
+            if opts.user_stats_map {
+                grpc.on_account(
+                    AccountFilter::partial().with_discriminator(UserStats::DISCRIMINATOR),
+                    self.account_map.on_account_fn(),
+                );
+            }
+            program_clients.push(grpc);
+
+            // oracle pubkeys are subscribed individually
+            // due to ownership differences
+            let mut oracles_grpc = new_client(endpoint, x_token, &oracle_dedup);
+            if let Some(ref f) = on_oracle_update {
+                let f = Arc::clone(f);
+                oracles_grpc.on_account(AccountFilter::firehose(), move |update| f(update));
+            }
+            if opts.oraclemap {
+                oracles_grpc.on_account(AccountFilter::firehose(), self.oracle_map.on_account_fn());
+            }
+            oracle_clients.push(oracles_grpc);
+        }
+
+        // re-fetch cached accounts over RPC when a stream reconnects without replay
+        // unnecessary while a redundant stream stayed healthy
+        let handle = tokio::runtime::Handle::current();
+        let on_resync: Option<Arc<OnResyncFn>> = opts.on_resync.map(Arc::from);
+        let program_status: Vec<GrpcStatus> = program_clients.iter().map(|c| c.status()).collect();
+        let oracle_status: Vec<GrpcStatus> = oracle_clients.iter().map(|c| c.status()).collect();
+        for (idx, grpc) in program_clients.iter_mut().enumerate() {
+            let handle = handle.clone();
+            let on_resync = on_resync.clone();
+            let others = redundant_status(&program_status, idx);
+            grpc.on_resync(move |slot| {
+                if others.iter().any(GrpcStatus::is_healthy) {
+                    log::debug!(target: "grpc", "redundant stream healthy, skip resync");
+                    return;
+                }
+                if let Some(ref f) = on_resync {
+                    f(slot);
+                }
+                handle.spawn(async move {
+                    if let Err(err) = self.resync_program_accounts().await {
+                        log::error!(target: "grpc", "resync from slot {slot} failed: {err:?}");
+                    }
+                });
+            });
+        }
+        for (idx, oracles_grpc) in oracle_clients.iter_mut().enumerate() {
+            let handle = handle.clone();
+            let on_resync = on_resync.clone();
+            let others = redundant_status(&oracle_status, idx);
+            oracles_grpc.on_resync(move |slot| {
+                if others.iter().any(GrpcStatus::is_healthy) {
+                    log::debug!(target: "grpc", "redundant stream healthy, skip oracle resync");
+                    return;
+                }
+                if let Some(ref f) = on_resync {
+                    f(slot);
+                }
+                handle.spawn(async move {
+                    if let Err(err) = self.resync_oracles().await {
+                        log::error!(target: "grpc", "oracle resync from slot {slot} failed: {err:?}");
+                    }
+                });
+            });
         }
 
         // start subscription
         let commitment = opts.commitment.unwrap_or(CommitmentLevel::Confirmed);
-        let grpc_unsub = grpc
-            .subscribe(
-                commitment,
-                GeyserSubscribeOpts {
-                    accounts_owners: vec![PROGRAM_ID.to_string()],
-                    interslot_updates: Some(opts.interslot_updates),
-                    transactions_accounts_include,
-                    blocks_meta: opts.subscribe_block_meta_updates,
-                    slot_updates: opts.subscribe_slot_updates,
-                    ..Default::default()
-                },
-            )
-            .await
-            .map_err(|err| SdkError::Grpc(Box::new(err)))?;
-
-        // oracle pubkeys are subscribed individually
-        // due to ownership differences
-        let mut oracles_grpc =
-            DriftGrpcClient::new(endpoint, x_token).grpc_connection_opts(opts.connection_opts);
+        let transactions_accounts_include = opts
+            .transaction_include_accounts
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        let mut grpc_unsub = subscribe_redundant(
+            program_clients,
+            commitment,
+            GeyserSubscribeOpts {
+                accounts_owners: vec![PROGRAM_ID.to_string()],
+                interslot_updates: Some(opts.interslot_updates),
+                transactions_accounts_include,
+                blocks_meta: opts.subscribe_block_meta_updates,
+                slot_updates: opts.subscribe_slot_updates,
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(|err| SdkError::Grpc(Box::new(err)))?;
 
         let oracle_pubkeys: Vec<String> = self
             .oracle_map
@@ -1474,49 +2148,203 @@ impl DriftClientBackend {
             .iter()
             .map(|(_, (pubkey, _))| pubkey.to_string())
             .collect();
+        let oracles_grpc_unsub = subscribe_redundant(
+            oracle_clients,
+            commitment,
+            GeyserSubscribeOpts {
+                accounts_pubkeys: oracle_pubkeys,
+                interslot_updates: Some(opts.interslot_updates),
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(|err| SdkError::Grpc(Box::new(err)))?;
+        grpc_unsub.extend(oracles_grpc_unsub);
+
+        let mut unsub = self.grpc_unsub.write().unwrap();
+        *unsub = grpc_unsub;
+        *self.grpc_status.write().unwrap() =
+            program_status.into_iter().chain(oracle_status).collect();
+
+        Ok(())
+    }
+
+    /// Re-fetch market and gRPC cached accounts over RPC
+    async fn resync_program_accounts(&self) -> SdkResult<()> {
+        tokio::try_join!(
+            self.perp_market_map.sync(self.account_map.fetcher()),
+            self.spot_market_map.sync(self.account_map.fetcher()),
+            self.account_map.resync_grpc_accounts(),
+        )?;
+        Ok(())
+    }
+
+    /// Re-fetch oracle accounts of all known markets over RPC
+    async fn resync_oracles(&self) -> SdkResult<()> {
+        let spot_markets = self
+            .spot_market_map
+            .marketmap
+            .iter()
+            .map(|i| MarketId::spot(*i.key()));
+        let perp_markets = self
+            .perp_market_map
+            .marketmap
+            .iter()
+            .map(|i| MarketId::perp(*i.key()));
+        let all_markets: Vec<MarketId> = spot_markets.chain(perp_markets).collect();
+
+        self.oracle_map
+            .sync(all_markets.as_ref(), self.account_map.fetcher())
+            .await
+    }
 
-        if let Some(on_oracle) = opts.on_oracle_update {
-            oracles_grpc.on_account(AccountFilter::firehose(), on_oracle);
+    /// Start the staleness monitor for Ws subscribed accounts
+    ///
+    /// Updates the config if already running
+    fn subscribe_staleness_monitor(&'static self, config: StalenessConfig) -> SdkResult<()> {
+        *self.staleness.write().unwrap() = config;
+        self.slot_subscriber.subscribe(|_| {})?;
+
+        let mut guard = self.staleness_unsub.write().unwrap();
+        if guard.is_some() {
+            return Ok(());
         }
+        let (unsub_tx, mut unsub_rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(async move {
+            loop {
+                // re-read so config updates apply from the next check
+                let check_interval = self.staleness.read().unwrap().check_interval;
+                tokio::select! {
+                    biased;
+                    _ = &mut unsub_rx => break,
+                    _ = tokio::time::sleep(check_interval) => self.resync_stale_accounts().await,
+                }
+            }
+            debug!(target: "staleness", "monitor stopped");
+        });
+        *guard = Some(unsub_tx);
+
+        Ok(())
+    }
 
-        if opts.oraclemap {
-            oracles_grpc.on_account(AccountFilter::firehose(), self.oracle_map.on_account_fn());
+    /// Stop the staleness monitor, if running
+    async fn unsubscribe_staleness_monitor(&self) -> SdkResult<()> {
+        if let Some(unsub) = self.staleness_unsub.write().unwrap().take() {
+            let _ = unsub.send(());
         }
+        self.slot_subscriber.unsubscribe().await
+    }
 
-        let oracles_grpc_unsub = oracles_grpc
-            .subscribe(
-                commitment,
-                GeyserSubscribeOpts {
-                    accounts_pubkeys: oracle_pubkeys,
-                    interslot_updates: Some(opts.interslot_updates),
-                    ..Default::default()
-                },
-            )
-            .await
-            .map_err(|err| SdkError::Grpc(Box::new(err)))?;
+    /// Resync all Ws subscribed accounts exceeding their max age
+    async fn resync_stale_accounts(&self) {
+        let current_slot = self.slot_subscriber.current_slot();
+        if current_slot == 0 {
+            return;
+        }
+        let config = *self.staleness.read().unwrap();
+        let fetcher = self.account_map.fetcher();
+
+        let (perp_markets, spot_markets, oracles, users) = tokio::join!(
+            async {
+                match config.max_age(AccountKind::Market) {
+                    Some(max_age) => {
+                        self.perp_market_map
+                            .resync_stale(fetcher, current_slot, max_age)
+                            .await
+                    }
+                    None => Ok(vec![]),
+                }
+            },
+            async {
+                match config.max_age(AccountKind::Market) {
+                    Some(max_age) => {
+                        self.spot_market_map
+                            .resync_stale(fetcher, current_slot, max_age)
+                            .await
+                    }
+                    None => Ok(vec![]),
+                }
+            },
+            async {
+                match config.max_age(AccountKind::Oracle) {
+                    Some(max_age) => {
+                        self.oracle_map
+                            .resync_stale(fetcher, current_slot, max_age)
+                            .await
+                    }
+                    None => Ok(vec![]),
+                }
+            },
+            async {
+                match config.max_age(AccountKind::User) {
+                    Some(max_age) => {
+                        self.account_map
+                            .resync_stale_ws_accounts(current_slot, max_age)
+                            .await
+                    }
+                    None => Ok(vec![]),
+                }
+            },
+        );
 
-        let mut unsub = self.grpc_unsub.write().unwrap();
-        let _ = unsub.insert((grpc_unsub, oracles_grpc_unsub));
+        log_resync("perp markets", perp_markets);
+        log_resync("spot markets", spot_markets);
+        log_resync("oracles", oracles);
+        log_resync("accounts", users);
+    }
 
+    /// Returns `SdkError::StaleAccountData` if `account` at `slot` exceeds the max age for `kind`
+    fn check_fresh(
+        &self,
+        account: SnapshotAccount,
+        kind: AccountKind,
+        slot: Slot,
+    ) -> SdkResult<()> {
+        let current_slot = self.slot_subscriber.current_slot();
+        if self
+            .staleness
+            .read()
+            .unwrap()
+            .is_stale(kind, slot, current_slot)
+        {
+            return Err(SdkError::StaleAccountData {
+                account,
+                slot,
+                current_slot,
+            });
+        }
         Ok(())
     }
 
+    /// Status handles of the active gRPC connections
+    fn grpc_status(&self) -> Vec<GrpcStatus> {
+        self.grpc_status.read().unwrap().clone()
+    }
+
     /// Unsubscribe the gRPC connections
     fn grpc_unsubscribe(&self) {
         let mut guard = self.grpc_unsub.write().unwrap();
-        if let Some((a, b)) = guard.take() {
-            let _ = a.send(());
-            let _ = b.send(());
+        for unsub in guard.drain(..) {
+            let _ = unsub.send(());
         }
+        self.grpc_status.write().unwrap().clear();
     }
 
     /// End subscriptions to live program data
+    ///
+    /// Tears down every subscription before returning the first error, if any
     async fn unsubscribe(&self) -> SdkResult<()> {
         self.blockhash_subscriber.unsubscribe();
-        self.perp_market_map.unsubscribe_all()?;
-        self.spot_market_map.unsubscribe_all()?;
+        let staleness_monitor = self.unsubscribe_staleness_monitor().await;
+        let perp_markets = self.perp_market_map.unsubscribe_all();
+        let spot_markets = self.spot_market_map.unsubscribe_all();
         self.account_map.unsubscribe_account(state_account());
-        self.oracle_map.unsubscribe_all()
+        let oracles = self.oracle_map.unsubscribe_all();
+
+        staleness_monitor
+            .and(perp_markets)
+            .and(spot_markets)
+            .and(oracles)
     }
 
     pub fn try_get_perp_market_account_and_slot(
@@ -1561,6 +2389,92 @@ impl DriftClientBackend {
         self.try_get_oracle_price_data_and_slot(market)
     }
 
+    /// Same as `try_get_perp_market_account_and_slot` but errors if the data exceeds its max age
+    pub fn try_get_perp_market_account_and_slot_fresh(
+        &self,
+        market_index: u16,
+    ) -> SdkResult<DataAndSlot<PerpMarket>> {
+        let market = self
+            .try_get_perp_market_account_and_slot(market_index)
+            .ok_or(SdkError::NoMarketData(MarketId::perp(market_index)))?;
+        self.check_fresh(
+            SnapshotAccount::PerpMarket(market_index),
+            AccountKind::Market,
+            market.slot,
+        )?;
+        Ok(market)
+    }
+
+    /// Same as `try_get_spot_market_account_and_slot` but errors if the data exceeds its max age
+    pub fn try_get_spot_market_account_and_slot_fresh(
+        &self,
+        market_index: u16,
+    ) -> SdkResult<DataAndSlot<SpotMarket>> {
+        let market = self
+            .try_get_spot_market_account_and_slot(market_index)
+            .ok_or(SdkError::NoMarketData(MarketId::spot(market_index)))?;
+        self.check_fresh(
+            SnapshotAccount::SpotMarket(market_index),
+            AccountKind::Market,
+            market.slot,
+        )?;
+        Ok(market)
+    }
+
+    /// Same as `try_get_oracle_price_data_and_slot` but errors if the data exceeds its max age
+    pub fn try_get_oracle_price_data_and_slot_fresh(&self, market: MarketId) -> SdkResult<Oracle> {
+        let oracle = self
+            .try_get_oracle_price_data_and_slot(market)
+            .ok_or(SdkError::NoMarketData(market))?;
+        self.check_fresh(
+            SnapshotAccount::Oracle(market),
+            AccountKind::Oracle,
+            oracle.slot,
+        )?;
+        Ok(oracle)
+    }
+
+    /// Try get the cached `User` account and slot, erroring if the data exceeds its max age
+    pub fn try_get_user_account_and_slot_fresh(
+        &self,
+        account: &Pubkey,
+    ) -> SdkResult<DataAndSlot<User>> {
+        let user = self
+            .account_map
+            .account_data_and_slot::<User>(account)
+            .ok_or(SdkError::NoAccountData(*account))?;
+        self.check_fresh(
+            SnapshotAccount::User(*account),
+            AccountKind::User,
+            user.slot,
+        )?;
+        Ok(user)
+    }
+
+    /// Read a consistent snapshot of `user` and its markets & oracles from the local caches
+    ///
+    /// * `user` - pubkey of the user account, must be subscribed
+    /// * `min_slot` - every account in the snapshot must be at or above this slot
+    /// * `force_markets` - additional markets to include regardless of user positions
+    ///
+    /// Returns `SdkError::StaleSnapshot` listing all accounts that are missing or older than `min_slot`
+    pub fn try_get_snapshot(
+        &self,
+        user: &Pubkey,
+        min_slot: Slot,
+        force_markets: &[MarketId],
+    ) -> SdkResult<AccountSnapshot> {
+        snapshot::build_snapshot(
+            user,
+            self.account_map.account_data_and_slot::<User>(user),
+            min_slot,
+            force_markets,
+            |idx| self.try_get_perp_market_account_and_slot(idx),
+            |idx| self.try_get_spot_market_account_and_slot(idx),
+            |market| self.try_get_oracle_price_data_and_slot(market),
+        )
+    }
+
     /// Return a handle to the inner RPC client
     fn client(&self) -> Arc<RpcClient> {
         Arc::clone(&self.rpc_client)
@@ -1613,7 +2527,8 @@ impl DriftClientBackend {
         if let Some(value) = self.account_map.account_data(account) {
             Ok(value)
         } else {
-            let account_data = self.rpc_client.get_account_data(account).await?;
+            let (account_data, _slot) = self.account_map.fetcher().get_account(account).await?;
+            let account_data = account_data.map(|a| a.data).unwrap_or_default();
             if account_data.is_empty() {
                 return Err(SdkError::NoAccountData(*account));
             }
@@ -1761,21 +2676,12 @@ impl DriftClientBackend {
     }
 
     /// Get account via rpc along with retrieved slot number
+    ///
+    /// Fetched at the account map commitment, which the client sets to `rpc_client.commitment()`
     async fn get_account_with_slot_raw(&self, pubkey: &Pubkey) -> SdkResult<(Account, Slot)> {
-        match self
-            .rpc_client
-            .get_account_with_commitment(pubkey, self.rpc_client.commitment())
-            .await
-        {
-            Ok(Response {
-                context,
-                value: Some(account),
-            }) => Ok((account, context.slot)),
-            Ok(Response {
-                context: _,
-                value: None,
-            }) => Err(SdkError::InvalidAccount),
-            Err(err) => Err(err.into()),
+        match self.account_map.fetcher().get_account(pubkey).await? {
+            (Some(account), slot) => Ok((account, slot)),
+            (None, _) => Err(SdkError::InvalidAccount),
         }
     }
 
@@ -1948,8 +2854,14 @@ impl<'a> TransactionBuilder<'a> {
         self
     }
     /// Extend the tx lookup tables (always includes the defacto drift LUTs)
+    ///
+    /// tables already included are ignored
     pub fn lookup_tables(mut self, lookup_tables: &[AddressLookupTableAccount]) -> Self {
-        self.lookup_tables.extend_from_slice(lookup_tables);
+        for lut in lookup_tables {
+            if !self.lookup_tables.iter().any(|x| x.key == lut.key) {
+                self.lookup_tables.push(lut.clone());
+            }
+        }
 
         self
     }
@@ -3214,6 +4126,132 @@ impl<'a> TransactionBuilder<'a> {
         self.lookup_tables(&luts)
     }
 
+    /// Token account creation ixs required by `route`
+    fn swap_route_account_creation_ixs(
+        &self,
+        route: &SwapRoute,
+        in_market: &SpotMarket,
+        out_market: &SpotMarket,
+        in_token_account: &Pubkey,
+        out_token_account: &Pubkey,
+    ) -> Vec<Instruction> {
+        if !route.create_token_accounts {
+            return vec![];
+        }
+        vec![
+            Self::create_token_account_instructions(
+                &self.authority,
+                in_token_account,
+                &in_market.mint,
+                &in_market.token_program(),
+            ),
+            Self::create_token_account_instructions(
+                &self.authority,
+                out_token_account,
+                &out_market.mint,
+                &out_market.token_program(),
+            ),
+        ]
+    }
+
+    /// Add a token swap to the tx from any `SwapRouter` venue
+    ///
+    /// The route's swap ixs are wrapped with drift `begin_swap` and `end_swap` ixs
+    ///
+    /// # Arguments
+    /// * `route` - swap route and instructions, see `SwapAggregator`
+    /// * `in_market` - Spot market of the input token
+    /// * `out_market` - Spot market of the output token
+    /// * `in_token_account` - Input token account pubkey
+    /// * `out_token_account` - Output token account pubkey
+    /// * `limit_price` - Set a limit price
+    /// * `reduce_only` - Set a reduce only order
+    pub fn swap_route(
+        mut self,
+        route: SwapRoute,
+        in_market: &SpotMarket,
+        out_market: &SpotMarket,
+        in_token_account: &Pubkey,
+        out_token_account: &Pubkey,
+        limit_price: Option<u64>,
+        reduce_only: Option<SwapReduceOnly>,
+    ) -> Self {
+        let account_creation_instructions = self.swap_route_account_creation_ixs(
+            &route,
+            in_market,
+            out_market,
+            in_token_account,
+            out_token_account,
+        );
+        self.ixs.extend(account_creation_instructions);
+
+        self = self.begin_swap(
+            route.in_amount,
+            in_market,
+            out_market,
+            in_token_account,
+            out_token_account,
+        );
+        self.ixs.extend(route.swap_ixs);
+        self = self.end_swap(
+            in_market,
+            out_market,
+            in_token_account,
+            out_token_account,
+            limit_price,
+            reduce_only,
+        );
+        self.lookup_tables(&route.luts)
+    }
+
+    /// Add a token swap to the tx for liquidation from any `SwapRouter` venue
+    ///
+    /// This wraps the route's swap ixs with `liquidate_spot_with_swap_begin` and `liquidate_spot_with_swap_end`
+    ///
+    /// # Arguments
+    /// * `route` - swap route and instructions, see `SwapAggregator`
+    /// * `in_market` - Spot market of the input token (asset market)
+    /// * `out_market` - Spot market of the output token (liability market)
+    /// * `in_token_account` - Input token account pubkey (for account creation if needed)
+    /// * `out_token_account` - Output token account pubkey (for account creation if needed)
+    /// * `asset_market_index` - Market index of the asset (collateral)
+    /// * `liability_market_index` - Market index of the liability (borrow)
+    /// * `user_account` - The user account being liquidated
+    pub fn swap_route_liquidate(
+        mut self,
+        route: SwapRoute,
+        in_market: &SpotMarket,
+        out_market: &SpotMarket,
+        in_token_account: &Pubkey,
+        out_token_account: &Pubkey,
+        asset_market_index: u16,
+        liability_market_index: u16,
+        user_account: &User,
+    ) -> Self {
+        let account_creation_instructions = self.swap_route_account_creation_ixs(
+            &route,
+            in_market,
+            out_market,
+            in_token_account,
+            out_token_account,
+        );
+        self.ixs.extend(account_creation_instructions);
+        self = self.liquidate_spot_with_swap_begin(
+            asset_market_index,
+            liability_market_index,
+            route.in_amount,
+            user_account,
+        );
+        self.ixs.extend(route.swap_ixs);
+        self = self.liquidate_spot_with_swap_end(
+            asset_market_index,
+            liability_market_index,
+            user_account,
+        );
+
+        self.lookup_tables(&route.luts)
+    }
+
     /// Settle perp PnL for some user account and market
     ///
     /// * `market_index` market to settle position for
@@ -3479,18 +4517,994 @@ impl<'a> TransactionBuilder<'a> {
         self
     }
 
-    /// Initialize a new user account (subaccount) for the authority/wallet.
+    /// Resize the Swift (signed message) order account of the sub-account's authority
     ///
-    /// Optionally set a custom name and referrer.
-    /// For `sub_account_id = 0`, also initializes the user stats account.
+    /// * `num_orders` - number of signed message orders the account can track
+    pub fn resize_swift_account(mut self, num_orders: u16) -> Self {
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::ResizeSignedMsgUserOrders {
+                signed_msg_user_orders: Wallet::derive_swift_order_account(&self.owner()),
+                authority: self.owner(),
+                user: self.sub_account,
+                payer: self.authority,
+                system_program: SYSTEM_PROGRAM_ID,
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::ResizeSignedMsgUserOrders {
+                num_orders,
+            }),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Delete the Swift (signed message) order account of the authority/wallet, reclaiming its rent
+    pub fn delete_swift_account(mut self) -> Self {
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::DeleteSignedMsgUserOrders {
+                signed_msg_user_orders: Wallet::derive_swift_order_account(&self.authority),
+                state: *state_account(),
+                authority: self.authority,
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::DeleteSignedMsgUserOrders {}),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Initialize the swift Ws delegates account of the authority/wallet
     ///
-    /// # Parameters
-    /// - `sub_account_id`: The subaccount index to initialize (0 for main account).
-    /// - `name`: Optional custom name for the account. If `None`, a default name is used.
-    /// - `referrer`: Optional referrer pubkey for the account.
+    /// Delegates may authenticate to the swift Ws server on behalf of the authority
+    /// e.g. a maker's hot key subscribing to swift orders
     ///
-    /// # Example
-    /// ```
+    /// * `delegates` - initial delegate keys
+    pub fn initialize_swift_ws_delegates(mut self, delegates: Vec<Pubkey>) -> Self {
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::InitializeSignedMsgWsDelegates {
+                signed_msg_ws_delegates: Wallet::derive_swift_ws_delegates_account(&self.authority),
+                authority: self.authority,
+                rent: SYSVAR_RENT_PUBKEY,
+                system_program: SYSTEM_PROGRAM_ID,
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::InitializeSignedMsgWsDelegates {
+                delegates,
+            }),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Add or remove a swift Ws delegate of the authority/wallet
+    ///
+    /// * `delegate` - delegate key
+    /// * `add` - true to authorize `delegate`, false to revoke it
+    pub fn change_swift_ws_delegate_status(mut self, delegate: Pubkey, add: bool) -> Self {
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::ChangeSignedMsgWsDelegateStatus {
+                signed_msg_ws_delegates: Wallet::derive_swift_ws_delegates_account(&self.authority),
+                authority: self.authority,
+                system_program: SYSTEM_PROGRAM_ID,
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(
+                &drift_idl::instructions::ChangeSignedMsgWsDelegateStatus { delegate, add },
+            ),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Initialize a `RevenueShare` account for the authority/wallet
+    ///
+    /// Required to receive builder fees and referral rewards as a builder
+    pub fn initialize_revenue_share(mut self) -> Self {
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::InitializeRevenueShare {
+                revenue_share: derive_revenue_share(&self.authority),
+                authority: self.authority,
+                payer: self.authority,
+                rent: SYSVAR_RENT_PUBKEY,
+                system_program: SYSTEM_PROGRAM_ID,
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::InitializeRevenueShare {}),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Initialize a `RevenueShareEscrow` account for the authority/wallet
+    ///
+    /// Required to place orders with a builder fee
+    ///
+    /// * `num_orders` - number of builder orders the escrow can track
+    pub fn initialize_revenue_share_escrow(mut self, num_orders: u16) -> Self {
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::InitializeRevenueShareEscrow {
+                escrow: derive_revenue_share_escrow(&self.authority),
+                authority: self.authority,
+                user_stats: Wallet::derive_stats_account(&self.authority),
+                state: *state_account(),
+                payer: self.authority,
+                rent: SYSVAR_RENT_PUBKEY,
+                system_program: SYSTEM_PROGRAM_ID,
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::InitializeRevenueShareEscrow {
+                num_orders,
+            }),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Resize the authority/wallet's `RevenueShareEscrow` account
+    ///
+    /// * `num_orders` - number of builder orders the escrow can track
+    pub fn resize_revenue_share_escrow_orders(mut self, num_orders: u16) -> Self {
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::ResizeRevenueShareEscrowOrders {
+                escrow: derive_revenue_share_escrow(&self.authority),
+                authority: self.authority,
+                payer: self.authority,
+                system_program: SYSTEM_PROGRAM_ID,
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::ResizeRevenueShareEscrowOrders {
+                num_orders,
+            }),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Approve or revoke a builder of the authority/wallet's orders
+    ///
+    /// * `builder` - builder authority
+    /// * `max_fee_tenth_bps` - max fee the builder may charge per order, in tenths of a bps
+    /// * `add` - true to approve `builder`, false to revoke it
+    pub fn change_approved_builder(
+        mut self,
+        builder: Pubkey,
+        max_fee_tenth_bps: u16,
+        add: bool,
+    ) -> Self {
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::ChangeApprovedBuilder {
+                escrow: derive_revenue_share_escrow(&self.authority),
+                authority: self.authority,
+                payer: self.authority,
+                system_program: SYSTEM_PROGRAM_ID,
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::ChangeApprovedBuilder {
+                builder,
+                max_fee_bps: max_fee_tenth_bps,
+                add,
+            }),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Deposit a constituent token into an LP pool, minting LP tokens to the authority/wallet
+    ///
+    /// Creates the authority's LP token account if required
+    ///
+    /// * `lp_pool` - the LP pool account
+    /// * `in_market_index` - spot market index of the deposited constituent
+    /// * `in_amount` - amount of constituent tokens to deposit
+    /// * `min_mint_amount` - min. LP tokens to receive (see `DriftClient::quote_lp_pool_add_liquidity`)
+    pub fn lp_pool_add_liquidity(
+        mut self,
+        lp_pool: &LPPool,
+        in_market_index: u16,
+        in_amount: u128,
+        min_mint_amount: u64,
+    ) -> Self {
+        let in_market = self
+            .program_data
+            .spot_market_config_by_index(in_market_index)
+            .expect("spot markets syncd");
+        let user_lp_token_account = Wallet::derive_lp_token_account(&self.authority, lp_pool);
+        self.ixs.push(Self::create_token_account_instructions(
+            &self.authority,
+            &user_lp_token_account,
+            &lp_pool.mint,
+            &TOKEN_PROGRAM_ID,
+        ));
+
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::LpPoolAddLiquidity {
+                state: *state_account(),
+                lp_pool: lp_pool.pubkey,
+                authority: self.authority,
+                in_market_mint: in_market.mint,
+                in_constituent: derive_constituent(&lp_pool.pubkey, in_market_index),
+                user_in_token_account: Wallet::derive_associated_token_address(
+                    &self.authority,
+                    in_market,
+                ),
+                constituent_in_token_account: derive_constituent_vault(
+                    &lp_pool.pubkey,
+                    in_market_index,
+                ),
+                user_lp_token_account,
+                lp_mint: lp_pool.mint,
+                constituent_target_base: lp_pool.constituent_target_base,
+                lp_pool_token_vault: derive_lp_pool_token_vault(&lp_pool.pubkey),
+                token_program: in_market.token_program(),
+            },
+            std::iter::empty(),
+            [MarketId::spot(in_market_index)].iter(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::LpPoolAddLiquidity {
+                in_market_index,
+                in_amount,
+                min_mint_amount,
+            }),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Burn LP tokens of the authority/wallet, withdrawing a constituent token from the LP pool
+    ///
+    /// Creates the authority's constituent token account if required
+    ///
+    /// * `lp_pool` - the LP pool account
+    /// * `out_market_index` - spot market index of the withdrawn constituent
+    /// * `lp_amount` - amount of LP tokens to burn
+    /// * `min_out_amount` - min. constituent tokens to receive (see `DriftClient::quote_lp_pool_remove_liquidity`)
+    pub fn lp_pool_remove_liquidity(
+        mut self,
+        lp_pool: &LPPool,
+        out_market_index: u16,
+        lp_amount: u64,
+        min_out_amount: u128,
+    ) -> Self {
+        let out_market = self
+            .program_data
+            .spot_market_config_by_index(out_market_index)
+            .expect("spot markets syncd");
+        let user_out_token_account =
+            Wallet::derive_associated_token_address(&self.authority, out_market);
+        self.ixs.push(Self::create_token_account_instructions(
+            &self.authority,
+            &user_out_token_account,
+            &out_market.mint,
+            &out_market.token_program(),
+        ));
+
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::LpPoolRemoveLiquidity {
+                state: *state_account(),
+                drift_signer: constants::derive_drift_signer(),
+                lp_pool: lp_pool.pubkey,
+                authority: self.authority,
+                out_market_mint: out_market.mint,
+                out_constituent: derive_constituent(&lp_pool.pubkey, out_market_index),
+                user_out_token_account,
+                constituent_out_token_account: derive_constituent_vault(
+                    &lp_pool.pubkey,
+                    out_market_index,
+                ),
+                user_lp_token_account: Wallet::derive_lp_token_account(&self.authority, lp_pool),
+                spot_market_token_account: out_market.vault,
+                lp_mint: lp_pool.mint,
+                constituent_target_base: lp_pool.constituent_target_base,
+                lp_pool_token_vault: derive_lp_pool_token_vault(&lp_pool.pubkey),
+                token_program: out_market.token_program(),
+                amm_cache: derive_amm_cache(),
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            [MarketId::spot(out_market_index)].iter(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::LpPoolRemoveLiquidity {
+                in_market_index: out_market_index,
+                in_amount: lp_amount,
+                min_out_amount,
+            }),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Swap between constituent tokens of an LP pool
+    ///
+    /// Creates the authority's out token account if required
+    ///
+    /// * `lp_pool` - the LP pool account
+    /// * `in_market_index` - spot market index of the constituent sold
+    /// * `out_market_index` - spot market index of the constituent bought
+    /// * `in_amount` - amount of in tokens to sell
+    /// * `min_out_amount` - min. out tokens to receive (see `DriftClient::quote_lp_pool_swap`)
+    pub fn lp_pool_swap(
+        mut self,
+        lp_pool: &LPPool,
+        in_market_index: u16,
+        out_market_index: u16,
+        in_amount: u64,
+        min_out_amount: u64,
+    ) -> Self {
+        let in_market = self
+            .program_data
+            .spot_market_config_by_index(in_market_index)
+            .expect("spot markets syncd");
+        let out_market = self
+            .program_data
+            .spot_market_config_by_index(out_market_index)
+            .expect("spot markets syncd");
+        let in_token_program = in_market.token_program();
+        let out_token_program = out_market.token_program();
+        let user_out_token_account =
+            Wallet::derive_associated_token_address(&self.authority, out_market);
+        self.ixs.push(Self::create_token_account_instructions(
+            &self.authority,
+            &user_out_token_account,
+            &out_market.mint,
+            &out_token_program,
+        ));
+
+        let mut accounts = build_accounts(
+            self.program_data,
+            types::accounts::LpPoolSwap {
+                state: *state_account(),
+                lp_pool: lp_pool.pubkey,
+                constituent_target_base: lp_pool.constituent_target_base,
+                constituent_correlations: lp_pool.constituent_correlations,
+                constituent_in_token_account: derive_constituent_vault(
+                    &lp_pool.pubkey,
+                    in_market_index,
+                ),
+                constituent_out_token_account: derive_constituent_vault(
+                    &lp_pool.pubkey,
+                    out_market_index,
+                ),
+                user_in_token_account: Wallet::derive_associated_token_address(
+                    &self.authority,
+                    in_market,
+                ),
+                user_out_token_account,
+                in_constituent: derive_constituent(&lp_pool.pubkey, in_market_index),
+                out_constituent: derive_constituent(&lp_pool.pubkey, out_market_index),
+                in_market_mint: in_market.mint,
+                out_market_mint: out_market.mint,
+                authority: self.authority,
+                token_program: in_token_program,
+            },
+            std::iter::empty(),
+            [
+                MarketId::spot(in_market_index),
+                MarketId::spot(out_market_index),
+            ]
+            .iter(),
+            std::iter::empty(),
+        );
+
+        if out_token_program != in_token_program {
+            accounts.push(AccountMeta::new_readonly(out_token_program, false));
+        }
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::LpPoolSwap {
+                in_market_index,
+                out_market_index,
+                in_amount,
+                min_out_amount,
+            }),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Quote the fees of an LP pool swap, logged by the program
+    ///
+    /// Only useful in a simulated tx (see `DriftClient::quote_lp_pool_swap`)
+    ///
+    /// * `lp_pool` - the LP pool account
+    /// * `in_market_index` - spot market index of the constituent sold
+    /// * `out_market_index` - spot market index of the constituent bought
+    /// * `in_amount` - amount of in tokens to sell
+    /// * `in_target_weight`/`out_target_weight` - target weights of the constituents (PERCENTAGE_PRECISION)
+    pub fn view_lp_pool_swap_fees(
+        mut self,
+        lp_pool: &LPPool,
+        in_market_index: u16,
+        out_market_index: u16,
+        in_amount: u64,
+        in_target_weight: i64,
+        out_target_weight: i64,
+    ) -> Self {
+        let in_market = self
+            .program_data
+            .spot_market_config_by_index(in_market_index)
+            .expect("spot markets syncd");
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::ViewLpPoolSwapFees {
+                drift_signer: constants::derive_drift_signer(),
+                state: *state_account(),
+                lp_pool: lp_pool.pubkey,
+                constituent_target_base: lp_pool.constituent_target_base,
+                constituent_correlations: lp_pool.constituent_correlations,
+                constituent_in_token_account: derive_constituent_vault(
+                    &lp_pool.pubkey,
+                    in_market_index,
+                ),
+                constituent_out_token_account: derive_constituent_vault(
+                    &lp_pool.pubkey,
+                    out_market_index,
+                ),
+                in_constituent: derive_constituent(&lp_pool.pubkey, in_market_index),
+                out_constituent: derive_constituent(&lp_pool.pubkey, out_market_index),
+                authority: self.authority,
+                token_program: in_market.token_program(),
+            },
+            std::iter::empty(),
+            [
+                MarketId::spot(in_market_index),
+                MarketId::spot(out_market_index),
+            ]
+            .iter(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::ViewLpPoolSwapFees {
+                in_market_index,
+                out_market_index,
+                in_amount,
+                in_target_weight,
+                out_target_weight,
+            }),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Quote the fees of adding liquidity to an LP pool, logged by the program
+    ///
+    /// Only useful in a simulated tx (see `DriftClient::quote_lp_pool_add_liquidity`)
+    ///
+    /// * `lp_pool` - the LP pool account
+    /// * `in_market_index` - spot market index of the deposited constituent
+    /// * `in_amount` - amount of constituent tokens to deposit
+    pub fn view_lp_pool_add_liquidity_fees(
+        mut self,
+        lp_pool: &LPPool,
+        in_market_index: u16,
+        in_amount: u128,
+    ) -> Self {
+        let in_market = self
+            .program_data
+            .spot_market_config_by_index(in_market_index)
+            .expect("spot markets syncd");
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::ViewLpPoolAddLiquidityFees {
+                state: *state_account(),
+                lp_pool: lp_pool.pubkey,
+                authority: self.authority,
+                in_market_mint: in_market.mint,
+                in_constituent: derive_constituent(&lp_pool.pubkey, in_market_index),
+                lp_mint: lp_pool.mint,
+                constituent_target_base: lp_pool.constituent_target_base,
+            },
+            std::iter::empty(),
+            [MarketId::spot(in_market_index)].iter(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::ViewLpPoolAddLiquidityFees {
+                in_market_index,
+                in_amount,
+            }),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Quote the fees of removing liquidity from an LP pool, logged by the program
+    ///
+    /// Only useful in a simulated tx (see `DriftClient::quote_lp_pool_remove_liquidity`)
+    ///
+    /// * `lp_pool` - the LP pool account
+    /// * `out_market_index` - spot market index of the withdrawn constituent
+    /// * `lp_amount` - amount of LP tokens to burn
+    pub fn view_lp_pool_remove_liquidity_fees(
+        mut self,
+        lp_pool: &LPPool,
+        out_market_index: u16,
+        lp_amount: u64,
+    ) -> Self {
+        let out_market = self
+            .program_data
+            .spot_market_config_by_index(out_market_index)
+            .expect("spot markets syncd");
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::ViewLpPoolRemoveLiquidityFees {
+                state: *state_account(),
+                lp_pool: lp_pool.pubkey,
+                authority: self.authority,
+                out_market_mint: out_market.mint,
+                out_constituent: derive_constituent(&lp_pool.pubkey, out_market_index),
+                lp_mint: lp_pool.mint,
+                constituent_target_base: lp_pool.constituent_target_base,
+            },
+            std::iter::empty(),
+            [MarketId::spot(out_market_index)].iter(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::ViewLpPoolRemoveLiquidityFees {
+                in_market_index: out_market_index,
+                in_amount: lp_amount,
+            }),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Refresh the perp market AMM cache used by LP pools
+    ///
+    /// * `perp_markets` - perp market indexes to refresh
+    pub fn update_amm_cache(mut self, perp_markets: &[u16]) -> Self {
+        let markets: Vec<MarketId> = perp_markets.iter().map(|i| MarketId::perp(*i)).collect();
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::UpdateAmmCache {
+                keeper: self.authority,
+                state: *state_account(),
+                amm_cache: derive_amm_cache(),
+                quote_market: derive_spot_market_account(MarketId::QUOTE_SPOT.index()),
+            },
+            std::iter::empty(),
+            markets.iter(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::UpdateAmmCache {}),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Settle perp market pnl owed to/from an LP pool's quote constituent
+    ///
+    /// * `lp_pool` - the LP pool account
+    /// * `perp_markets` - perp market indexes to settle
+    pub fn settle_perp_to_lp_pool(mut self, lp_pool: &LPPool, perp_markets: &[u16]) -> Self {
+        let quote_market = self
+            .program_data
+            .spot_market_config_by_index(MarketId::QUOTE_SPOT.index())
+            .expect("spot markets syncd");
+        let markets: Vec<MarketId> = perp_markets.iter().map(|i| MarketId::perp(*i)).collect();
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::SettlePerpToLpPool {
+                state: *state_account(),
+                lp_pool: lp_pool.pubkey,
+                keeper: self.authority,
+                amm_cache: derive_amm_cache(),
+                quote_market: quote_market.pubkey,
+                constituent: derive_constituent(&lp_pool.pubkey, quote_market.market_index),
+                constituent_quote_token_account: derive_constituent_vault(
+                    &lp_pool.pubkey,
+                    quote_market.market_index,
+                ),
+                quote_token_vault: quote_market.vault,
+                token_program: quote_market.token_program(),
+                drift_signer: constants::derive_drift_signer(),
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            markets.iter(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::SettlePerpToLpPool {}),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Refresh the cached oracle price of an LP pool constituent
+    ///
+    /// * `lp_pool` - the LP pool account
+    /// * `spot_market_index` - spot market index of the constituent
+    pub fn update_constituent_oracle_info(
+        mut self,
+        lp_pool: &LPPool,
+        spot_market_index: u16,
+    ) -> Self {
+        let spot_market = self
+            .program_data
+            .spot_market_config_by_index(spot_market_index)
+            .expect("spot markets syncd");
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::UpdateConstituentOracleInfo {
+                state: *state_account(),
+                keeper: self.authority,
+                constituent: derive_constituent(&lp_pool.pubkey, spot_market_index),
+                spot_market: spot_market.pubkey,
+                oracle: spot_market.oracle,
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::UpdateConstituentOracleInfo {}),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Recompute the target base of an LP pool's constituents from the AMM cache
+    ///
+    /// * `lp_pool` - the LP pool account
+    /// * `constituent_markets` - spot market indexes of all the pool's constituents
+    pub fn update_lp_constituent_target_base(
+        mut self,
+        lp_pool: &LPPool,
+        constituent_markets: &[u16],
+    ) -> Self {
+        let mut accounts = build_accounts(
+            self.program_data,
+            types::accounts::UpdateLpConstituentTargetBase {
+                state: *state_account(),
+                keeper: self.authority,
+                amm_constituent_mapping: derive_amm_constituent_mapping(&lp_pool.pubkey),
+                constituent_target_base: lp_pool.constituent_target_base,
+                amm_cache: derive_amm_cache(),
+                lp_pool: lp_pool.pubkey,
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            std::iter::empty(),
+        );
+        accounts.extend(constituent_markets.iter().map(|market_index| {
+            AccountMeta::new_readonly(derive_constituent(&lp_pool.pubkey, *market_index), false)
+        }));
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::UpdateLpConstituentTargetBase {}),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Recompute the AUM of an LP pool from its constituents and the AMM cache
+    ///
+    /// * `lp_pool` - the LP pool account
+    /// * `constituent_markets` - spot market indexes of all the pool's constituents
+    pub fn update_lp_pool_aum(mut self, lp_pool: &LPPool, constituent_markets: &[u16]) -> Self {
+        let markets: Vec<MarketId> = constituent_markets
+            .iter()
+            .map(|i| MarketId::spot(*i))
+            .collect();
+        // the constituents follow the oracles and spot markets
+        let mut accounts = build_accounts(
+            self.program_data,
+            types::accounts::UpdateLpPoolAum {
+                state: *state_account(),
+                keeper: self.authority,
+                lp_pool: lp_pool.pubkey,
+                constituent_target_base: lp_pool.constituent_target_base,
+                amm_cache: derive_amm_cache(),
+            },
+            std::iter::empty(),
+            markets.iter(),
+            std::iter::empty(),
+        );
+        accounts.extend(constituent_markets.iter().map(|market_index| {
+            AccountMeta::new(derive_constituent(&lp_pool.pubkey, *market_index), false)
+        }));
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::UpdateLpPoolAum {}),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Update the funding rate of a perp market
+    ///
+    /// Succeeds once per funding period (see `math::funding::time_until_next_funding_update`)
+    pub fn update_funding_rate(mut self, market_index: u16) -> Self {
+        let perp_market = self
+            .program_data
+            .perp_market_config_by_index(market_index)
+            .expect("perp markets syncd");
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::UpdateFundingRate {
+                state: *state_account(),
+                perp_market: perp_market.pubkey,
+                oracle: perp_market.amm.oracle,
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::UpdateFundingRate {
+                market_index,
+            }),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Update the AMMs of perp markets with their latest oracle prices
+    ///
+    /// * `market_indexes` - perp market indexes to update
+    pub fn update_amms(mut self, market_indexes: &[u16]) -> Self {
+        let markets: Vec<MarketId> = market_indexes.iter().map(|i| MarketId::perp(*i)).collect();
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::UpdateAmms {
+                state: *state_account(),
+                authority: self.authority,
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            markets.iter(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::UpdateAmms {
+                market_indexes: market_indexes.to_vec(),
+            }),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Update the bid/ask TWAPs of a perp market
+    ///
+    /// * `market_index` - perp market index
+    /// * `makers` - (sub-account, user stats) of the market's top makers, their orders are
+    ///   included in the bid/ask, may be empty
+    pub fn update_perp_bid_ask_twap(
+        mut self,
+        market_index: u16,
+        makers: &[(Pubkey, Pubkey)],
+    ) -> Self {
+        let perp_market = self
+            .program_data
+            .perp_market_config_by_index(market_index)
+            .expect("perp markets syncd");
+        let mut accounts = build_accounts(
+            self.program_data,
+            types::accounts::UpdatePerpBidAskTwap {
+                state: *state_account(),
+                perp_market: perp_market.pubkey,
+                oracle: perp_market.amm.oracle,
+                keeper_stats: Wallet::derive_stats_account(&self.authority),
+                authority: self.authority,
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            std::iter::empty(),
+        );
+        for (maker, maker_stats) in makers {
+            accounts.push(AccountMeta::new_readonly(*maker, false));
+            accounts.push(AccountMeta::new_readonly(*maker_stats, false));
+        }
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::UpdatePerpBidAskTwap {}),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Accrue interest of a spot market
+    pub fn update_spot_market_cumulative_interest(mut self, market_index: u16) -> Self {
+        let spot_market = self
+            .program_data
+            .spot_market_config_by_index(market_index)
+            .expect("spot markets syncd");
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::UpdateSpotMarketCumulativeInterest {
+                state: *state_account(),
+                spot_market: spot_market.pubkey,
+                oracle: spot_market.oracle,
+                spot_market_vault: spot_market.vault,
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(
+                &drift_idl::instructions::UpdateSpotMarketCumulativeInterest {},
+            ),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Update the prelaunch oracle of a perp market from its mark price
+    ///
+    /// The perp market's oracle source must be `OracleSource::Prelaunch`
+    pub fn update_prelaunch_oracle(mut self, market_index: u16) -> Self {
+        let perp_market = self
+            .program_data
+            .perp_market_config_by_index(market_index)
+            .expect("perp markets syncd");
+        let accounts = build_accounts(
+            self.program_data,
+            types::accounts::UpdatePrelaunchOracle {
+                state: *state_account(),
+                perp_market: perp_market.pubkey,
+                oracle: perp_market.amm.oracle,
+            },
+            std::iter::empty(),
+            std::iter::empty(),
+            std::iter::empty(),
+        );
+
+        let ix = Instruction {
+            program_id: constants::PROGRAM_ID,
+            accounts,
+            data: InstructionData::data(&drift_idl::instructions::UpdatePrelaunchOracle {}),
+        };
+        self.ixs.push(ix);
+
+        self
+    }
+
+    /// Initialize a new user account (subaccount) for the authority/wallet.
+    ///
+    /// Optionally set a custom name and referrer.
+    /// For `sub_account_id = 0`, also initializes the user stats account.
+    ///
+    /// # Parameters
+    /// - `sub_account_id`: The subaccount index to initialize (0 for main account).
+    /// - `name`: Optional custom name for the account. If `None`, a default name is used.
+    /// - `referrer`: Optional referrer pubkey for the account.
+    ///
+    /// # Example
+    /// ```
     /// use drift_rs::{TransactionBuilder, Wallet};
     /// use solana_pubkey::Pubkey;
     ///
@@ -4137,6 +6151,18 @@ impl<'a> TransactionBuilder<'a> {
     }
 }
 
+/// Accounts subscribed by `DriftClient::subscribe_lp_pool`
+fn lp_pool_accounts(lp_pool_id: u8, constituent_markets: &[u16]) -> Vec<Pubkey> {
+    let lp_pool = derive_lp_pool(lp_pool_id);
+    let mut accounts = vec![lp_pool, derive_constituent_target_base(&lp_pool)];
+    accounts.extend(
+        constituent_markets
+            .iter()
+            .map(|market_index| derive_constituent(&lp_pool, *market_index)),
+    );
+    accounts
+}
+
 /// Builds a set of required accounts from a user's open positions and additional given accounts
 ///
 /// * `base_accounts` - base anchor accounts
@@ -4222,7 +6248,7 @@ mod tests {
     use std::str::FromStr;
 
     use crate::solana_sdk::keypair::Keypair;
-    use anchor_lang::prelude::system_instruction;
+    use anchor_lang::{prelude::system_instruction, AnchorDeserialize};
     use serde_json::json;
     use solana_account_decoder_client_types::{UiAccount, UiAccountData, UiAccountEncoding};
     use solana_rpc_client::rpc_client::Mocks;
@@ -4273,6 +6299,10 @@ mod tests {
                 CommitmentConfig::processed(),
             ),
             grpc_unsub: Default::default(),
+            grpc_status: Default::default(),
+            slot_subscriber: SlotSubscriber::new(Arc::clone(&pubsub_client)),
+            staleness: Default::default(),
+            staleness_unsub: Default::default(),
         };
 
         DriftClient {
@@ -4292,6 +6322,17 @@ mod tests {
         });
     }
 
+    #[tokio::test]
+    async fn grpc_unsubscribe_clears_status() {
+        let client = setup(Mocks::default(), Keypair::new()).await;
+        *client.backend.grpc_status.write().unwrap() =
+            vec![GrpcStatus::new("https://grpc.example")];
+        assert_eq!(client.grpc_status().len(), 1);
+
+        client.grpc_unsubscribe();
+        assert!(client.grpc_status().is_empty());
+    }
+
     #[tokio::test]
     #[cfg(feature = "rpc_tests")]
     async fn test_marketmap_subscribe() {
@@ -4332,7 +6373,7 @@ mod tests {
         let mut account_mocks = Mocks::default();
         let account_response = json!(Response {
             context: RpcResponseContext::new(12_345),
-            value: Some(UiAccount {
+            value: vec![Some(UiAccount {
                 data: UiAccountData::Binary(
                     bs58::encode(account_data).into_string(),
                     UiAccountEncoding::Base58
@@ -4342,9 +6383,9 @@ mod tests {
                 lamports: 0,
                 rent_epoch: 0,
                 space: None,
-            })
+            })]
         });
-        account_mocks.insert(RpcRequest::GetAccountInfo, account_response.clone());
+        account_mocks.insert(RpcRequest::GetMultipleAccounts, account_response.clone());
 
         let client = setup(account_mocks, Keypair::new()).await;
 
@@ -4360,7 +6401,7 @@ mod tests {
         let mut account_mocks = Mocks::default();
         let account_response = json!(Response {
             context: RpcResponseContext::new(12_345),
-            value: Some(UiAccount {
+            value: vec![Some(UiAccount {
                 data: UiAccountData::Binary(
                     bs58::encode(account_data).into_string(),
                     UiAccountEncoding::Base58
@@ -4370,9 +6411,9 @@ mod tests {
                 lamports: 0,
                 rent_epoch: 0,
                 space: None,
-            })
+            })]
         });
-        account_mocks.insert(RpcRequest::GetAccountInfo, account_response.clone());
+        account_mocks.insert(RpcRequest::GetMultipleAccounts, account_response.clone());
         let client = setup(account_mocks, Keypair::new()).await;
 
         let (spot, perp) = client.all_positions(&user).await.unwrap();
@@ -4434,4 +6475,241 @@ mod tests {
         // Check that high leverage mode account is included
         assert!(tx.static_account_keys().contains(&high_leverage_account));
     }
+    fn swift_test_user() -> User {
+        User {
+            authority: Pubkey::new_unique(),
+            delegate: Pubkey::new_unique(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn resize_swift_account_ix() {
+        let program_data = ProgramData::uninitialized();
+        let user = swift_test_user();
+        let sub_account = Pubkey::new_unique();
+
+        // delegate pays for the resize of the owner's account
+        let builder =
+            TransactionBuilder::new(&program_data, sub_account, Cow::Borrowed(&user), true)
+                .resize_swift_account(32);
+        let ix = &builder.ixs()[0];
+
+        assert_eq!(ix.program_id, constants::PROGRAM_ID);
+        assert_eq!(
+            ix.data[..8],
+            drift_idl::instructions::ResizeSignedMsgUserOrders::DISCRIMINATOR[..]
+        );
+        assert_eq!(ix.data[8..], 32_u16.to_le_bytes());
+        assert_eq!(
+            ix.accounts,
+            vec![
+                AccountMeta::new(Wallet::derive_swift_order_account(&user.authority), false),
+                AccountMeta::new_readonly(user.authority, false),
+                AccountMeta::new_readonly(sub_account, false),
+                AccountMeta::new(user.delegate, true),
+                AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn delete_swift_account_ix() {
+        let program_data = ProgramData::uninitialized();
+        let user = swift_test_user();
+
+        let builder = TransactionBuilder::new(
+            &program_data,
+            Pubkey::new_unique(),
+            Cow::Borrowed(&user),
+            false,
+        )
+        .delete_swift_account();
+        let ix = &builder.ixs()[0];
+
+        assert_eq!(
+            ix.data,
+            drift_idl::instructions::DeleteSignedMsgUserOrders::DISCRIMINATOR
+        );
+        assert_eq!(
+            ix.accounts,
+            vec![
+                AccountMeta::new(Wallet::derive_swift_order_account(&user.authority), false),
+                AccountMeta::new(*state_account(), false),
+                AccountMeta::new_readonly(user.authority, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn swift_ws_delegates_ixs() {
+        let program_data = ProgramData::uninitialized();
+        let user = swift_test_user();
+        let delegates_account = Wallet::derive_swift_ws_delegates_account(&user.authority);
+        let delegate = Pubkey::new_unique();
+
+        let builder = TransactionBuilder::new(
+            &program_data,
+            Pubkey::new_unique(),
+            Cow::Borrowed(&user),
+            false,
+        )
+        .initialize_swift_ws_delegates(vec![delegate])
+        .change_swift_ws_delegate_status(delegate, false);
+        let [init_ix, change_ix] = builder.ixs() else {
+            panic!("expected 2 ixs");
+        };
+
+        let mut data = &init_ix.data[..];
+        assert_eq!(
+            data[..8],
+            drift_idl::instructions::InitializeSignedMsgWsDelegates::DISCRIMINATOR[..]
+        );
+        data = &data[8..];
+        let decoded =
+            drift_idl::instructions::InitializeSignedMsgWsDelegates::deserialize(&mut data)
+                .unwrap();
+        assert_eq!(decoded.delegates, vec![delegate]);
+        assert_eq!(
+            init_ix.accounts,
+            vec![
+                AccountMeta::new(delegates_account, false),
+                AccountMeta::new(user.authority, true),
+                AccountMeta::new_readonly(SYSVAR_RENT_PUBKEY, false),
+                AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+            ]
+        );
+
+        let mut data = &change_ix.data[..];
+        assert_eq!(
+            data[..8],
+            drift_idl::instructions::ChangeSignedMsgWsDelegateStatus::DISCRIMINATOR[..]
+        );
+        data = &data[8..];
+        let decoded =
+            drift_idl::instructions::ChangeSignedMsgWsDelegateStatus::deserialize(&mut data)
+                .unwrap();
+        assert_eq!(decoded.delegate, delegate);
+        assert!(!decoded.add);
+        assert_eq!(
+            change_ix.accounts,
+            vec![
+                AccountMeta::new(delegates_account, false),
+                AccountMeta::new(user.authority, true),
+                AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn lp_pool_swap_out_token_program() {
+        let program_data = ProgramData::new(
+            vec![
+                SpotMarket {
+                    mint: Pubkey::new_unique(),
+                    ..Default::default()
+                },
+                SpotMarket {
+                    market_index: 1,
+                    mint: Pubkey::new_unique(),
+                    token_program_flag: types::TokenProgramFlag::Token2022 as u8,
+                    ..Default::default()
+                },
+            ],
+            vec![],
+            vec![],
+            State::default(),
+        );
+        let user = User::default();
+        let lp_pool = LPPool {
+            pubkey: Pubkey::new_unique(),
+            ..Default::default()
+        };
+
+        let builder = TransactionBuilder::new(
+            &program_data,
+            Pubkey::new_unique(),
+            Cow::Borrowed(&user),
+            false,
+        )
+        .lp_pool_swap(&lp_pool, 0, 1, 1_000, 0);
+        let [create_ata_ix, swap_ix] = builder.ixs() else {
+            panic!("expected 2 ixs");
+        };
+
+        assert!(create_ata_ix
+            .accounts
+            .contains(&AccountMeta::new_readonly(TOKEN_2022_PROGRAM_ID, false)));
+        let token_programs: Vec<&Pubkey> = swap_ix
+            .accounts
+            .iter()
+            .map(|a| &a.pubkey)
+            .filter(|p| **p == TOKEN_PROGRAM_ID || **p == TOKEN_2022_PROGRAM_ID)
+            .collect();
+        assert_eq!(
+            token_programs,
+            vec![&TOKEN_PROGRAM_ID, &TOKEN_2022_PROGRAM_ID]
+        );
+    }
+
+    #[test]
+    fn view_lp_pool_swap_fees_ix() {
+        let program_data = ProgramData::new(
+            vec![
+                SpotMarket::default(),
+                SpotMarket {
+                    market_index: 1,
+                    ..Default::default()
+                },
+            ],
+            vec![],
+            vec![],
+            State::default(),
+        );
+        let user = User {
+            authority: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        let lp_pool = LPPool {
+            pubkey: Pubkey::new_unique(),
+            constituent_correlations: Pubkey::new_unique(),
+            ..Default::default()
+        };
+
+        let builder = TransactionBuilder::new(
+            &program_data,
+            Pubkey::new_unique(),
+            Cow::Borrowed(&user),
+            false,
+        )
+        .view_lp_pool_swap_fees(&lp_pool, 0, 1, 1_000, 400_000, 600_000);
+        let [view_ix] = builder.ixs() else {
+            panic!("expected 1 ix");
+        };
+
+        let mut data = &view_ix.data[..];
+        assert_eq!(
+            data[..8],
+            drift_idl::instructions::ViewLpPoolSwapFees::DISCRIMINATOR[..]
+        );
+        data = &data[8..];
+        let decoded = drift_idl::instructions::ViewLpPoolSwapFees::deserialize(&mut data).unwrap();
+        assert_eq!(
+            (
+                decoded.in_market_index,
+                decoded.out_market_index,
+                decoded.in_amount,
+                decoded.in_target_weight,
+                decoded.out_target_weight
+            ),
+            (0, 1, 1_000, 400_000, 600_000)
+        );
+        assert!(view_ix.accounts.contains(&AccountMeta::new_readonly(
+            lp_pool.constituent_correlations,
+            false
+        )));
+        assert!(view_ix
+            .accounts
+            .contains(&AccountMeta::new_readonly(user.authority, true)));
+    }
 }
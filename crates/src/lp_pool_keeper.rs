@@ -0,0 +1,575 @@
+//! LP pool keeper
+//!
+//! Works out which LP pool cranks are due from the `LPPool`, its constituents and the `AmmCache`,
+//! then sends them in order, batched into txs within a CU and account budget. Run it once with
+//! `LpPoolKeeper::crank` or periodically with `LpPoolKeeper::spawn`
+use std::{borrow::Cow, time::Duration};
+
+use crate::{
+    constants::derive_constituent,
+    keeper::{compute_unit_limit, spawn_keeper, ChainClock, KeeperConfig, KeeperOpts},
+    solana_sdk::{clock::Slot, message::VersionedMessage, pubkey::Pubkey, signature::Signature},
+    types::{
+        accounts::{AmmCache, Constituent, ConstituentTargetBase, LPPool, User},
+        SdkError, SdkResult, UnsubHandle,
+    },
+    DriftClient, TransactionBuilder,
+};
+
+const LOG_TARGET: &str = "lpkeeper";
+
+/// Max. accounts locked by a tx
+const MAX_TX_ACCOUNT_LOCKS: usize = 64;
+
+/// Accounts shared by all cranks of a tx i.e. the keeper and `State`
+const SHARED_CRANK_ACCOUNTS: usize = 2;
+
+/// An LP pool crank
+#[derive(Clone, Debug, PartialEq)]
+pub enum LpPoolCrank {
+    /// refresh the AMM cache of the pool's perp markets
+    UpdateAmmCache(Vec<u16>),
+    /// settle pnl of the pool's perp markets with its quote constituent
+    SettlePerpToLpPool(Vec<u16>),
+    /// refresh the cached oracle price of a constituent, by spot market index
+    UpdateConstituentOracleInfo(u16),
+    /// recompute the target bases of all constituents, by spot market index
+    UpdateLpConstituentTargetBase(Vec<u16>),
+    /// recompute the pool AUM from all constituents, by spot market index
+    UpdateLpPoolAum(Vec<u16>),
+}
+
+impl LpPoolCrank {
+    /// Estimated compute units of the crank
+    pub fn compute_units(&self) -> u32 {
+        match self {
+            Self::UpdateAmmCache(perp_markets) => 20_000 + 20_000 * perp_markets.len() as u32,
+            Self::SettlePerpToLpPool(perp_markets) => 50_000 + 30_000 * perp_markets.len() as u32,
+            Self::UpdateConstituentOracleInfo(_) => 20_000,
+            Self::UpdateLpConstituentTargetBase(constituents) => {
+                40_000 + 25_000 * constituents.len() as u32
+            }
+            Self::UpdateLpPoolAum(constituents) => 40_000 + 30_000 * constituents.len() as u32,
+        }
+    }
+    /// Estimated accounts locked by the crank, excluding the keeper and `State`
+    pub fn accounts(&self) -> usize {
+        match self {
+            // amm cache, quote market + perp market and oracle each
+            Self::UpdateAmmCache(perp_markets) => 2 + 2 * perp_markets.len(),
+            // pool, amm cache, quote market, constituent, vaults, token program, signer
+            // + perp market and oracle each
+            Self::SettlePerpToLpPool(perp_markets) => 8 + 2 * perp_markets.len(),
+            // constituent, spot market, oracle
+            Self::UpdateConstituentOracleInfo(_) => 3,
+            // pool, mapping, target base, amm cache + constituents
+            Self::UpdateLpConstituentTargetBase(constituents) => 4 + constituents.len(),
+            // pool, target base, amm cache + constituent, spot market and oracle each
+            Self::UpdateLpPoolAum(constituents) => 3 + 3 * constituents.len(),
+        }
+    }
+}
+
+/// `LpPoolKeeper` configuration
+#[derive(Clone, Debug)]
+pub struct LpPoolKeeperConfig {
+    pub lp_pool_id: u8,
+    /// spot market indexes of all the pool's constituents
+    pub constituent_markets: Vec<u16>,
+    /// max. slots since an AMM cache entry or constituent oracle was refreshed
+    pub max_oracle_age: u64,
+    /// max. slots since the pool AUM or constituent targets were recomputed
+    pub max_aum_age: u64,
+    /// min. seconds between perp market pnl settles
+    pub settle_interval: i64,
+    /// max. CU limit of a crank tx
+    pub max_tx_compute_units: u32,
+    /// check interval and priority fee
+    pub opts: KeeperOpts,
+}
+
+impl LpPoolKeeperConfig {
+    pub fn new(lp_pool_id: u8, constituent_markets: Vec<u16>) -> Self {
+        Self {
+            lp_pool_id,
+            constituent_markets,
+            max_oracle_age: 10,
+            max_aum_age: 20,
+            settle_interval: 60,
+            max_tx_compute_units: 1_000_000,
+            opts: KeeperOpts::new(Duration::from_secs(2)),
+        }
+    }
+    /// Set the max. slots since an AMM cache entry or constituent oracle was refreshed
+    pub fn with_max_oracle_age(mut self, slots: u64) -> Self {
+        self.max_oracle_age = slots;
+        self
+    }
+    /// Set the max. slots since the pool AUM or constituent targets were recomputed
+    pub fn with_max_aum_age(mut self, slots: u64) -> Self {
+        self.max_aum_age = slots;
+        self
+    }
+    /// Set the min. seconds between perp market pnl settles
+    pub fn with_settle_interval(mut self, seconds: i64) -> Self {
+        self.settle_interval = seconds;
+        self
+    }
+    /// Set the max. CU limit of a crank tx
+    pub fn with_max_tx_compute_units(mut self, compute_units: u32) -> Self {
+        self.max_tx_compute_units = compute_units;
+        self
+    }
+}
+
+impl KeeperConfig for LpPoolKeeperConfig {
+    fn opts_mut(&mut self) -> &mut KeeperOpts {
+        &mut self.opts
+    }
+}
+
+/// On-chain state of an LP pool used to schedule cranks
+#[derive(Clone, Debug)]
+pub struct LpPoolState {
+    pub lp_pool: LPPool,
+    /// constituents in order of `LpPoolKeeperConfig::constituent_markets`
+    pub constituents: Vec<Constituent>,
+    pub target_base: ConstituentTargetBase,
+    pub amm_cache: AmmCache,
+}
+
+impl LpPoolState {
+    /// Perp markets with LP pool exposure
+    pub fn lp_perp_markets(&self) -> Vec<u16> {
+        self.amm_cache
+            .cache
+            .iter()
+            .filter(|info| info.lp_status_for_perp_market != 0)
+            .map(|info| info.market_index)
+            .collect()
+    }
+}
+
+/// Cranks due for `state` at `slot` and unix timestamp `now`, in the order they should land
+///
+/// Cache and oracle refreshes come first, the target base and AUM are recomputed after any of
+/// their inputs are refreshed
+pub fn due_cranks(
+    state: &LpPoolState,
+    config: &LpPoolKeeperConfig,
+    slot: Slot,
+    now: i64,
+) -> Vec<LpPoolCrank> {
+    let is_stale = |last_slot: u64, max_age: u64| slot.saturating_sub(last_slot) > max_age;
+    let lp_markets = || {
+        state
+            .amm_cache
+            .cache
+            .iter()
+            .filter(|info| info.lp_status_for_perp_market != 0)
+    };
+    let constituent_markets = &config.constituent_markets;
+    let mut cranks = vec![];
+
+    let amm_cache_due = lp_markets().any(|info| {
+        is_stale(info.slot, config.max_oracle_age)
+            || is_stale(info.oracle_slot, config.max_oracle_age)
+    });
+    if amm_cache_due {
+        cranks.push(LpPoolCrank::UpdateAmmCache(state.lp_perp_markets()));
+    }
+
+    let settle_due = lp_markets().any(|info| now - info.last_settle_ts >= config.settle_interval);
+    if settle_due {
+        cranks.push(LpPoolCrank::SettlePerpToLpPool(state.lp_perp_markets()));
+    }
+
+    let cranks_before_oracles = cranks.len();
+    cranks.extend(
+        state
+            .constituents
+            .iter()
+            .filter(|c| is_stale(c.last_oracle_slot, config.max_oracle_age))
+            .map(|c| LpPoolCrank::UpdateConstituentOracleInfo(c.spot_market_index)),
+    );
+    let oracles_due = cranks.len() > cranks_before_oracles;
+
+    let target_base_due = amm_cache_due
+        || oracles_due
+        || state.target_base.targets.iter().any(|target| {
+            is_stale(
+                target.last_oracle_slot.min(target.last_position_slot),
+                config.max_aum_age,
+            )
+        });
+    if target_base_due {
+        cranks.push(LpPoolCrank::UpdateLpConstituentTargetBase(
+            constituent_markets.clone(),
+        ));
+    }
+
+    if target_base_due || settle_due || is_stale(state.lp_pool.last_aum_slot, config.max_aum_age) {
+        cranks.push(LpPoolCrank::UpdateLpPoolAum(constituent_markets.clone()));
+    }
+
+    cranks
+}
+
+/// Batch `cranks` into txs with a CU limit of at most `max_compute_units` and at most
+/// `MAX_TX_ACCOUNT_LOCKS` accounts, preserving their order
+///
+/// A crank exceeding the budget on its own is sent alone
+pub fn batch_cranks(cranks: Vec<LpPoolCrank>, max_compute_units: u32) -> Vec<Vec<LpPoolCrank>> {
+    let mut batches: Vec<Vec<LpPoolCrank>> = vec![];
+    let mut batch_compute_units = 0;
+    let mut batch_accounts = 0;
+    for crank in cranks {
+        let compute_units = crank.compute_units();
+        let accounts = crank.accounts();
+        match batches.last_mut() {
+            Some(batch)
+                if compute_unit_limit(batch_compute_units + compute_units) <= max_compute_units
+                    && SHARED_CRANK_ACCOUNTS + batch_accounts + accounts
+                        <= MAX_TX_ACCOUNT_LOCKS =>
+            {
+                batch_compute_units += compute_units;
+                batch_accounts += accounts;
+                batch.push(crank);
+            }
+            _ => {
+                batch_compute_units = compute_units;
+                batch_accounts = accounts;
+                batches.push(vec![crank]);
+            }
+        }
+    }
+
+    batches
+}
+
+/// Keeps an LP pool cranked
+///
+/// Due cranks are sent in order, each tx landing before the next is sent, so the target base
+/// and AUM are recomputed from the AMM cache and constituent oracles refreshed before them
+#[derive(Clone)]
+pub struct LpPoolKeeper {
+    drift: DriftClient,
+    /// keeper's drift sub-account
+    keeper_sub_account: Pubkey,
+    config: LpPoolKeeperConfig,
+}
+
+impl LpPoolKeeper {
+    /// Create a new `LpPoolKeeper`
+    ///
+    /// * `drift` - client signing and sending the cranks
+    /// * `keeper_sub_account` - keeper's drift sub-account
+    pub fn new(drift: DriftClient, keeper_sub_account: Pubkey, config: LpPoolKeeperConfig) -> Self {
+        Self {
+            drift,
+            keeper_sub_account,
+            config,
+        }
+    }
+
+    /// Fetch the LP pool state, uses cached accounts if subscribed
+    ///
+    /// Errors if `config.constituent_markets` does not cover all of the pool's constituents
+    pub async fn fetch_state(&self) -> SdkResult<LpPoolState> {
+        let lp_pool_id = self.config.lp_pool_id;
+        let (lp_pool, target_base, amm_cache) = tokio::try_join!(
+            self.drift.get_lp_pool(lp_pool_id),
+            self.drift.get_constituent_target_base(lp_pool_id),
+            self.drift.get_amm_cache(),
+        )?;
+        validate_constituent_markets(&lp_pool, &self.config.constituent_markets)?;
+        let constituents = futures_util::future::try_join_all(
+            self.config.constituent_markets.iter().map(|market_index| {
+                self.drift
+                    .get_account_value::<Constituent>(&derive_constituent(
+                        &lp_pool.pubkey,
+                        *market_index,
+                    ))
+            }),
+        )
+        .await?;
+
+        Ok(LpPoolState {
+            lp_pool,
+            constituents,
+            target_base,
+            amm_cache,
+        })
+    }
+
+    /// Build a tx sending the `batch` of cranks in order
+    ///
+    /// * `keeper_account` - keeper's drift sub-account data
+    /// * `lp_pool` - the cranked LP pool account
+    pub fn build_batch_tx(
+        &self,
+        keeper_account: &User,
+        lp_pool: &LPPool,
+        batch: &[LpPoolCrank],
+    ) -> VersionedMessage {
+        let compute_units = batch.iter().map(LpPoolCrank::compute_units).sum();
+        let tx = TransactionBuilder::new(
+            self.drift.program_data(),
+            self.keeper_sub_account,
+            Cow::Borrowed(keeper_account),
+            false,
+        )
+        .with_priority_fee(
+            self.config.opts.priority_fee.unwrap_or(0),
+            Some(compute_unit_limit(compute_units)),
+        );
+
+        batch
+            .iter()
+            .fold(tx, |tx, crank| match crank {
+                LpPoolCrank::UpdateAmmCache(perp_markets) => tx.update_amm_cache(perp_markets),
+                LpPoolCrank::SettlePerpToLpPool(perp_markets) => {
+                    tx.settle_perp_to_lp_pool(lp_pool, perp_markets)
+                }
+                LpPoolCrank::UpdateConstituentOracleInfo(market_index) => {
+                    tx.update_constituent_oracle_info(lp_pool, *market_index)
+                }
+                LpPoolCrank::UpdateLpConstituentTargetBase(constituent_markets) => {
+                    tx.update_lp_constituent_target_base(lp_pool, constituent_markets)
+                }
+                LpPoolCrank::UpdateLpPoolAum(constituent_markets) => {
+                    tx.update_lp_pool_aum(lp_pool, constituent_markets)
+                }
+            })
+            .build()
+    }
+
+    /// Send all cranks due now
+    ///
+    /// Batches are sent in order, waiting for each to land before sending the next.
+    /// Returns signatures of the sent txs, stops at the first batch that fails to send or land
+    pub async fn crank(&self) -> SdkResult<Vec<Signature>> {
+        let clock = ChainClock::fetch(&self.drift).await?;
+        let state = self.fetch_state().await?;
+        let cranks = due_cranks(&state, &self.config, clock.slot, clock.unix_timestamp);
+        if cranks.is_empty() {
+            return Ok(vec![]);
+        }
+        let keeper_account = self
+            .drift
+            .get_user_account(&self.keeper_sub_account)
+            .await?;
+
+        let batches = batch_cranks(cranks, self.config.max_tx_compute_units);
+        let mut signatures = Vec::with_capacity(batches.len());
+        for (i, batch) in batches.iter().enumerate() {
+            let tx = self.build_batch_tx(&keeper_account, &state.lp_pool, batch);
+            let signature = self.drift.sign_and_send(tx).await?;
+            log::debug!(target: LOG_TARGET, "sent {batch:?}: {signature}");
+            if i + 1 < batches.len() {
+                self.drift.rpc().poll_for_signature(&signature).await?;
+            }
+            signatures.push(signature);
+        }
+
+        Ok(signatures)
+    }
+
+    /// Run `crank` every `config.opts.interval`
+    ///
+    /// Stops when the returned handle is sent to or dropped
+    pub fn spawn(self) -> UnsubHandle {
+        let interval = self.config.opts.interval;
+        spawn_keeper(LOG_TARGET, interval, move || {
+            let keeper = self.clone();
+            async move { keeper.crank().await }
+        })
+    }
+}
+
+/// Check `constituent_markets` lists each of `lp_pool`'s constituents once
+///
+/// The target base and AUM cranks require all constituents of the pool
+fn validate_constituent_markets(lp_pool: &LPPool, constituent_markets: &[u16]) -> SdkResult<()> {
+    let mut markets = constituent_markets.to_vec();
+    markets.sort_unstable();
+    markets.dedup();
+    if markets.len() != constituent_markets.len() || markets.len() != lp_pool.constituents as usize
+    {
+        return Err(SdkError::Generic(format!(
+            "lp pool {} has {} constituents, configured markets: {constituent_markets:?}",
+            lp_pool.lp_pool_id, lp_pool.constituents
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drift_idl::types::{CacheInfo, TargetsDatum};
+
+    const SLOT: Slot = 1_000;
+    const NOW: i64 = 1_700_000_000;
+
+    fn fresh_state() -> LpPoolState {
+        LpPoolState {
+            lp_pool: LPPool {
+                last_aum_slot: SLOT,
+                ..Default::default()
+            },
+            constituents: vec![
+                Constituent {
+                    spot_market_index: 0,
+                    last_oracle_slot: SLOT,
+                    ..Default::default()
+                },
+                Constituent {
+                    spot_market_index: 1,
+                    last_oracle_slot: SLOT,
+                    ..Default::default()
+                },
+            ],
+            target_base: ConstituentTargetBase {
+                targets: vec![
+                    TargetsDatum {
+                        last_oracle_slot: SLOT,
+                        last_position_slot: SLOT,
+                        ..Default::default()
+                    };
+                    2
+                ],
+                ..Default::default()
+            },
+            amm_cache: AmmCache {
+                cache: vec![
+                    CacheInfo {
+                        market_index: 0,
+                        slot: SLOT,
+                        oracle_slot: SLOT,
+                        last_settle_ts: NOW,
+                        lp_status_for_perp_market: 1,
+                        ..Default::default()
+                    },
+                    // no LP exposure
+                    CacheInfo {
+                        market_index: 1,
+                        lp_status_for_perp_market: 0,
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn lp_pool_constituent_markets_validated() {
+        let lp_pool = LPPool {
+            constituents: 2,
+            ..Default::default()
+        };
+        assert!(validate_constituent_markets(&lp_pool, &[0, 1]).is_ok());
+        assert!(validate_constituent_markets(&lp_pool, &[0]).is_err());
+        assert!(validate_constituent_markets(&lp_pool, &[0, 0]).is_err());
+        assert!(validate_constituent_markets(&lp_pool, &[0, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn lp_pool_due_cranks() {
+        let config = LpPoolKeeperConfig::new(0, vec![0, 1]);
+        let state = fresh_state();
+        assert_eq!(state.lp_perp_markets(), vec![0]);
+        assert!(due_cranks(&state, &config, SLOT, NOW).is_empty());
+
+        // everything stale
+        assert_eq!(
+            due_cranks(&state, &config, SLOT + 21, NOW),
+            vec![
+                LpPoolCrank::UpdateAmmCache(vec![0]),
+                LpPoolCrank::UpdateConstituentOracleInfo(0),
+                LpPoolCrank::UpdateConstituentOracleInfo(1),
+                LpPoolCrank::UpdateLpConstituentTargetBase(vec![0, 1]),
+                LpPoolCrank::UpdateLpPoolAum(vec![0, 1])
+            ]
+        );
+        // stale AUM only
+        let mut state = fresh_state();
+        state.lp_pool.last_aum_slot = SLOT - 21;
+        assert_eq!(
+            due_cranks(&state, &config, SLOT, NOW),
+            vec![LpPoolCrank::UpdateLpPoolAum(vec![0, 1])]
+        );
+
+        // stale constituent oracle refreshes targets and AUM
+        let mut state = fresh_state();
+        state.constituents[1].last_oracle_slot = SLOT - 11;
+        assert_eq!(
+            due_cranks(&state, &config, SLOT, NOW),
+            vec![
+                LpPoolCrank::UpdateConstituentOracleInfo(1),
+                LpPoolCrank::UpdateLpConstituentTargetBase(vec![0, 1]),
+                LpPoolCrank::UpdateLpPoolAum(vec![0, 1]),
+            ]
+        );
+
+        // settle interval elapsed
+        assert_eq!(
+            due_cranks(&fresh_state(), &config, SLOT, NOW + 60),
+            vec![
+                LpPoolCrank::SettlePerpToLpPool(vec![0]),
+                LpPoolCrank::UpdateLpPoolAum(vec![0, 1])
+            ]
+        );
+    }
+
+    #[test]
+    fn lp_pool_batch_cranks() {
+        let constituents: Vec<u16> = (0..10).collect();
+        let cranks = || {
+            let mut cranks = vec![
+                LpPoolCrank::UpdateAmmCache(vec![0, 1, 2]),
+                LpPoolCrank::SettlePerpToLpPool(vec![0, 1, 2]),
+            ];
+            cranks.extend(
+                constituents
+                    .iter()
+                    .map(|i| LpPoolCrank::UpdateConstituentOracleInfo(*i)),
+            );
+            cranks.push(LpPoolCrank::UpdateLpConstituentTargetBase(
+                constituents.clone(),
+            ));
+            cranks.push(LpPoolCrank::UpdateLpPoolAum(constituents.clone()));
+            cranks
+        };
+
+        let batches = batch_cranks(cranks(), 1_000_000);
+        // order is preserved
+        assert_eq!(batches.concat(), cranks());
+        for batch in &batches {
+            let compute_units = batch.iter().map(LpPoolCrank::compute_units).sum();
+            let accounts: usize = batch.iter().map(LpPoolCrank::accounts).sum();
+            assert!(compute_unit_limit(compute_units) <= 1_000_000);
+            assert!(SHARED_CRANK_ACCOUNTS + accounts <= MAX_TX_ACCOUNT_LOCKS);
+        }
+        // target base exceeds the account locks of the cranks before it
+        assert_eq!(batches.len(), 2);
+        assert_eq!(
+            batches[1],
+            vec![
+                LpPoolCrank::UpdateLpConstituentTargetBase(constituents.clone()),
+                LpPoolCrank::UpdateLpPoolAum(constituents.clone())
+            ]
+        );
+
+        // CU bound, oversized cranks are sent alone
+        let batches = batch_cranks(cranks(), 100_000);
+        assert_eq!(batches.concat(), cranks());
+        assert_eq!(batches[0], vec![LpPoolCrank::UpdateAmmCache(vec![0, 1, 2])]);
+        assert_eq!(
+            batches[1],
+            vec![LpPoolCrank::SettlePerpToLpPool(vec![0, 1, 2])]
+        );
+        assert_eq!(batches[2].len(), 4);
+    }
+}
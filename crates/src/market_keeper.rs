@@ -0,0 +1,493 @@
+//! Market keeper
+//!
+//! Works out which perp and spot market cranks are due (funding, AMM and bid/ask TWAP updates,
+//! spot interest accrual and prelaunch oracles) from market account state, then sends them
+//! batched into txs within a CU budget. Run it once with `MarketKeeper::crank` or periodically
+//! with `MarketKeeper::spawn`
+use std::{borrow::Cow, time::Duration};
+
+use crate::{
+    keeper::{compute_unit_limit, spawn_keeper, ChainClock, KeeperConfig, KeeperOpts},
+    math::funding::time_until_next_funding_update,
+    solana_sdk::{clock::Slot, message::VersionedMessage, pubkey::Pubkey, signature::Signature},
+    types::{
+        accounts::{PerpMarket, SpotMarket, User},
+        MarketStatus, OracleSource, SdkResult, UnsubHandle,
+    },
+    DriftClient, TransactionBuilder,
+};
+
+const LOG_TARGET: &str = "marketkeeper";
+
+/// Max. perp markets per `update_amms` ix
+const MAX_UPDATE_AMMS_MARKETS: usize = 5;
+
+/// A market crank
+#[derive(Clone, Debug, PartialEq)]
+pub enum MarketCrank {
+    /// update the prelaunch oracle of a perp market
+    UpdatePrelaunchOracle(u16),
+    /// update the AMMs of perp markets
+    UpdateAmms(Vec<u16>),
+    /// update the bid/ask TWAPs of a perp market
+    UpdatePerpBidAskTwap(u16),
+    /// update the funding rate of a perp market
+    UpdateFundingRate(u16),
+    /// accrue interest of a spot market
+    UpdateSpotMarketCumulativeInterest(u16),
+}
+
+impl MarketCrank {
+    /// Estimated compute units of the crank
+    pub fn compute_units(&self) -> u32 {
+        match self {
+            Self::UpdatePrelaunchOracle(_) => 30_000,
+            Self::UpdateAmms(markets) => 60_000 * markets.len() as u32,
+            Self::UpdatePerpBidAskTwap(_) => 80_000,
+            Self::UpdateFundingRate(_) => 120_000,
+            Self::UpdateSpotMarketCumulativeInterest(_) => 40_000,
+        }
+    }
+    /// True if the crank should be sent in its own tx
+    ///
+    /// Funding updates fail if sent early or the oracle is invalid, which would fail any cranks
+    /// batched with them
+    pub fn is_isolated(&self) -> bool {
+        matches!(self, Self::UpdateFundingRate(_))
+    }
+}
+
+/// `MarketKeeper` configuration
+#[derive(Clone, Debug)]
+pub struct MarketKeeperConfig {
+    /// perp market indexes to crank
+    pub perp_markets: Vec<u16>,
+    /// spot market indexes to crank
+    pub spot_markets: Vec<u16>,
+    /// max. slots since a perp market AMM was updated
+    pub max_amm_age: u64,
+    /// min. seconds between bid/ask TWAP updates
+    pub bid_ask_twap_interval: i64,
+    /// min. seconds between spot market interest updates
+    pub interest_interval: i64,
+    /// min. seconds between prelaunch oracle updates
+    pub prelaunch_interval: i64,
+    /// CU budget of each tx, cranks are batched up to it
+    pub max_tx_compute_units: u32,
+    /// check interval and priority fee
+    pub opts: KeeperOpts,
+}
+
+impl MarketKeeperConfig {
+    pub fn new(perp_markets: Vec<u16>, spot_markets: Vec<u16>) -> Self {
+        Self {
+            perp_markets,
+            spot_markets,
+            max_amm_age: 50,
+            bid_ask_twap_interval: 60,
+            interest_interval: 3_600,
+            prelaunch_interval: 60,
+            max_tx_compute_units: 1_000_000,
+            opts: KeeperOpts::new(Duration::from_secs(5)),
+        }
+    }
+    /// Set the max. slots since a perp market AMM was updated
+    pub fn with_max_amm_age(mut self, slots: u64) -> Self {
+        self.max_amm_age = slots;
+        self
+    }
+    /// Set the min. seconds between bid/ask TWAP updates
+    pub fn with_bid_ask_twap_interval(mut self, seconds: i64) -> Self {
+        self.bid_ask_twap_interval = seconds;
+        self
+    }
+    /// Set the min. seconds between spot market interest updates
+    pub fn with_interest_interval(mut self, seconds: i64) -> Self {
+        self.interest_interval = seconds;
+        self
+    }
+    /// Set the min. seconds between prelaunch oracle updates
+    pub fn with_prelaunch_interval(mut self, seconds: i64) -> Self {
+        self.prelaunch_interval = seconds;
+        self
+    }
+    /// Set the CU budget of each tx
+    pub fn with_max_tx_compute_units(mut self, compute_units: u32) -> Self {
+        self.max_tx_compute_units = compute_units;
+        self
+    }
+}
+
+impl KeeperConfig for MarketKeeperConfig {
+    fn opts_mut(&mut self) -> &mut KeeperOpts {
+        &mut self.opts
+    }
+}
+
+/// Cranks due for `perp_markets` and `spot_markets` at `slot` and unix timestamp `now`
+///
+/// Oracle and AMM updates come before the funding updates reading them
+pub fn due_market_cranks(
+    perp_markets: &[PerpMarket],
+    spot_markets: &[SpotMarket],
+    config: &MarketKeeperConfig,
+    slot: Slot,
+    now: i64,
+) -> Vec<MarketCrank> {
+    let amm_active = |market: &&PerpMarket| {
+        !matches!(
+            market.status,
+            MarketStatus::Initialized
+                | MarketStatus::AmmPaused
+                | MarketStatus::Settlement
+                | MarketStatus::Delisted
+        )
+    };
+    let mut cranks = vec![];
+
+    cranks.extend(
+        perp_markets
+            .iter()
+            .filter(amm_active)
+            .filter(|market| {
+                market.amm.oracle_source == OracleSource::Prelaunch
+                    && now - market.amm.historical_oracle_data.last_oracle_price_twap_ts
+                        >= config.prelaunch_interval
+            })
+            .map(|market| MarketCrank::UpdatePrelaunchOracle(market.market_index)),
+    );
+
+    let stale_amms: Vec<u16> = perp_markets
+        .iter()
+        .filter(amm_active)
+        .filter(|market| slot.saturating_sub(market.amm.last_update_slot) > config.max_amm_age)
+        .map(|market| market.market_index)
+        .collect();
+    cranks.extend(
+        stale_amms
+            .chunks(MAX_UPDATE_AMMS_MARKETS)
+            .map(|markets| MarketCrank::UpdateAmms(markets.to_vec())),
+    );
+
+    cranks.extend(
+        perp_markets
+            .iter()
+            .filter(amm_active)
+            .filter(|market| {
+                now - market.amm.last_mark_price_twap_ts >= config.bid_ask_twap_interval
+            })
+            .map(|market| MarketCrank::UpdatePerpBidAskTwap(market.market_index)),
+    );
+
+    cranks.extend(
+        perp_markets
+            .iter()
+            .filter(|market| {
+                !matches!(
+                    market.status,
+                    MarketStatus::Initialized
+                        | MarketStatus::FundingPaused
+                        | MarketStatus::Settlement
+                        | MarketStatus::Delisted
+                ) && time_until_next_funding_update(
+                    now,
+                    market.amm.last_funding_rate_ts,
+                    market.amm.funding_period,
+                ) == 0
+            })
+            .map(|market| MarketCrank::UpdateFundingRate(market.market_index)),
+    );
+
+    cranks.extend(
+        spot_markets
+            .iter()
+            .filter(|market| now - market.last_interest_ts as i64 >= config.interest_interval)
+            .map(|market| MarketCrank::UpdateSpotMarketCumulativeInterest(market.market_index)),
+    );
+
+    cranks
+}
+
+/// Batch `cranks` into txs with a CU limit of at most `max_compute_units`, preserving their order
+///
+/// Isolated cranks and a crank exceeding the budget on its own are sent alone
+pub fn batch_cranks(cranks: Vec<MarketCrank>, max_compute_units: u32) -> Vec<Vec<MarketCrank>> {
+    let mut batches: Vec<Vec<MarketCrank>> = vec![];
+    let mut batch_compute_units = 0;
+    for crank in cranks {
+        let compute_units = crank.compute_units();
+        match batches.last_mut() {
+            Some(batch)
+                if !crank.is_isolated()
+                    && !batch[0].is_isolated()
+                    && compute_unit_limit(batch_compute_units + compute_units)
+                        <= max_compute_units =>
+            {
+                batch_compute_units += compute_units;
+                batch.push(crank);
+            }
+            _ => {
+                batch_compute_units = compute_units;
+                batches.push(vec![crank]);
+            }
+        }
+    }
+
+    batches
+}
+
+/// Keeps perp and spot markets cranked
+#[derive(Clone)]
+pub struct MarketKeeper {
+    drift: DriftClient,
+    /// keeper's drift sub-account
+    keeper_sub_account: Pubkey,
+    config: MarketKeeperConfig,
+}
+
+impl MarketKeeper {
+    /// Create a new `MarketKeeper`
+    ///
+    /// * `drift` - client signing and sending the cranks
+    /// * `keeper_sub_account` - keeper's drift sub-account
+    pub fn new(drift: DriftClient, keeper_sub_account: Pubkey, config: MarketKeeperConfig) -> Self {
+        Self {
+            drift,
+            keeper_sub_account,
+            config,
+        }
+    }
+
+    /// Fetch the configured markets, uses cached accounts if subscribed
+    pub async fn fetch_markets(&self) -> SdkResult<(Vec<PerpMarket>, Vec<SpotMarket>)> {
+        tokio::try_join!(
+            futures_util::future::try_join_all(
+                self.config
+                    .perp_markets
+                    .iter()
+                    .map(|market_index| self.drift.get_perp_market_account(*market_index)),
+            ),
+            futures_util::future::try_join_all(
+                self.config
+                    .spot_markets
+                    .iter()
+                    .map(|market_index| self.drift.get_spot_market_account(*market_index)),
+            ),
+        )
+    }
+
+    /// Build a tx sending the `batch` of cranks
+    ///
+    /// * `keeper_account` - keeper's drift sub-account data
+    pub fn build_batch_tx(&self, keeper_account: &User, batch: &[MarketCrank]) -> VersionedMessage {
+        let compute_units = batch.iter().map(MarketCrank::compute_units).sum();
+        let tx = TransactionBuilder::new(
+            self.drift.program_data(),
+            self.keeper_sub_account,
+            Cow::Borrowed(keeper_account),
+            false,
+        )
+        .with_priority_fee(
+            self.config.opts.priority_fee.unwrap_or(0),
+            Some(compute_unit_limit(compute_units)),
+        );
+
+        batch
+            .iter()
+            .fold(tx, |tx, crank| match crank {
+                MarketCrank::UpdatePrelaunchOracle(market_index) => {
+                    tx.update_prelaunch_oracle(*market_index)
+                }
+                MarketCrank::UpdateAmms(market_indexes) => tx.update_amms(market_indexes),
+                MarketCrank::UpdatePerpBidAskTwap(market_index) => {
+                    tx.update_perp_bid_ask_twap(*market_index, &[])
+                }
+                MarketCrank::UpdateFundingRate(market_index) => {
+                    tx.update_funding_rate(*market_index)
+                }
+                MarketCrank::UpdateSpotMarketCumulativeInterest(market_index) => {
+                    tx.update_spot_market_cumulative_interest(*market_index)
+                }
+            })
+            .build()
+    }
+
+    /// Send all cranks due now
+    ///
+    /// A failed batch is retried one crank per tx so it doesn't hold back the other markets.
+    /// Returns signatures of the sent txs, failed sends are logged and skipped
+    pub async fn crank(&self) -> SdkResult<Vec<Signature>> {
+        let clock = ChainClock::fetch(&self.drift).await?;
+        let (perp_markets, spot_markets) = self.fetch_markets().await?;
+        let cranks = due_market_cranks(
+            &perp_markets,
+            &spot_markets,
+            &self.config,
+            clock.slot,
+            clock.unix_timestamp,
+        );
+        if cranks.is_empty() {
+            return Ok(vec![]);
+        }
+        let keeper_account = self
+            .drift
+            .get_user_account(&self.keeper_sub_account)
+            .await?;
+
+        let batches = batch_cranks(cranks, self.config.max_tx_compute_units);
+        let mut signatures = Vec::with_capacity(batches.len());
+        for batch in batches {
+            match self.send_batch(&keeper_account, &batch).await {
+                Some(signature) => signatures.push(signature),
+                None if batch.len() > 1 => {
+                    for crank in batch.chunks(1) {
+                        signatures.extend(self.send_batch(&keeper_account, crank).await);
+                    }
+                }
+                None => (),
+            }
+        }
+
+        Ok(signatures)
+    }
+
+    /// Send the `batch` of cranks, failures are logged
+    async fn send_batch(&self, keeper_account: &User, batch: &[MarketCrank]) -> Option<Signature> {
+        let tx = self.build_batch_tx(keeper_account, batch);
+        match self.drift.sign_and_send(tx).await {
+            Ok(signature) => {
+                log::debug!(target: LOG_TARGET, "sent {batch:?}: {signature}");
+                Some(signature)
+            }
+            Err(err) => {
+                log::warn!(target: LOG_TARGET, "{batch:?} failed: {err:?}");
+                None
+            }
+        }
+    }
+
+    /// Run `crank` every `config.opts.interval`
+    ///
+    /// Stops when the returned handle is sent to or dropped
+    pub fn spawn(self) -> UnsubHandle {
+        let interval = self.config.opts.interval;
+        spawn_keeper(LOG_TARGET, interval, move || {
+            let keeper = self.clone();
+            async move { keeper.crank().await }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{HistoricalOracleData, AMM};
+
+    const SLOT: Slot = 1_000;
+    const ONE_HOUR: i64 = 3_600;
+    /// on the hour
+    const NOW: i64 = 1_700_002_800;
+
+    fn perp_market(market_index: u16) -> PerpMarket {
+        PerpMarket {
+            market_index,
+            status: MarketStatus::Active,
+            amm: AMM {
+                last_update_slot: SLOT,
+                last_mark_price_twap_ts: NOW,
+                last_funding_rate_ts: NOW,
+                funding_period: ONE_HOUR,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn spot_market(market_index: u16) -> SpotMarket {
+        SpotMarket {
+            market_index,
+            last_interest_ts: NOW as u64,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn market_due_cranks() {
+        let config = MarketKeeperConfig::new(vec![0, 1], vec![0]);
+        let perps = [perp_market(0), perp_market(1)];
+        let spots = [spot_market(0)];
+        assert!(due_market_cranks(&perps, &spots, &config, SLOT, NOW).is_empty());
+
+        // an hour later everything is due
+        assert_eq!(
+            due_market_cranks(&perps, &spots, &config, SLOT + 51, NOW + ONE_HOUR),
+            vec![
+                MarketCrank::UpdateAmms(vec![0, 1]),
+                MarketCrank::UpdatePerpBidAskTwap(0),
+                MarketCrank::UpdatePerpBidAskTwap(1),
+                MarketCrank::UpdateFundingRate(0),
+                MarketCrank::UpdateFundingRate(1),
+                MarketCrank::UpdateSpotMarketCumulativeInterest(0),
+            ]
+        );
+
+        // paused and prelaunch markets
+        let mut funding_paused = perp_market(0);
+        funding_paused.status = MarketStatus::FundingPaused;
+        let mut prelaunch = perp_market(1);
+        prelaunch.amm.oracle_source = OracleSource::Prelaunch;
+        prelaunch.amm.historical_oracle_data = HistoricalOracleData {
+            last_oracle_price_twap_ts: NOW - 60,
+            ..Default::default()
+        };
+        assert_eq!(
+            due_market_cranks(
+                &[funding_paused, prelaunch],
+                &[],
+                &config,
+                SLOT,
+                NOW + ONE_HOUR
+            ),
+            vec![
+                MarketCrank::UpdatePrelaunchOracle(1),
+                MarketCrank::UpdatePerpBidAskTwap(0),
+                MarketCrank::UpdatePerpBidAskTwap(1),
+                MarketCrank::UpdateFundingRate(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn market_cranks_batched_by_compute_units() {
+        let cranks = vec![
+            MarketCrank::UpdateAmms(vec![0, 1, 2, 3, 4]),
+            MarketCrank::UpdatePerpBidAskTwap(0),
+            MarketCrank::UpdatePerpBidAskTwap(1),
+            MarketCrank::UpdateFundingRate(0),
+            MarketCrank::UpdateSpotMarketCumulativeInterest(0),
+            MarketCrank::UpdateSpotMarketCumulativeInterest(1),
+        ];
+        assert_eq!(compute_unit_limit(100_000), 120_000);
+
+        // funding updates are sent alone
+        assert_eq!(
+            batch_cranks(cranks.clone(), 1_000_000),
+            vec![
+                cranks[..3].to_vec(),
+                vec![cranks[3].clone()],
+                cranks[4..].to_vec(),
+            ]
+        );
+        // budget includes the CU headroom
+        assert_eq!(
+            batch_cranks(cranks.clone(), 500_000),
+            vec![
+                cranks[..2].to_vec(),
+                vec![cranks[2].clone()],
+                vec![cranks[3].clone()],
+                cranks[4..].to_vec(),
+            ]
+        );
+        assert!(batch_cranks(vec![], 300_000).is_empty());
+    }
+}
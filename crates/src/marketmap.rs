@@ -10,28 +10,28 @@ use crate::solana_sdk::{clock::Slot, commitment_config::CommitmentConfig, pubkey
 use anchor_lang::{AccountDeserialize, AnchorDeserialize};
 use dashmap::DashMap;
 use drift_pubsub_client::PubsubClient;
-use futures_util::{
-    stream::{FuturesOrdered, FuturesUnordered},
-    StreamExt,
-};
+use futures_util::{stream::FuturesUnordered, StreamExt};
 use serde_json::json;
 use solana_account_decoder_client_types::UiAccountEncoding;
-use solana_rpc_client::nonblocking::rpc_client::RpcClient;
 use solana_rpc_client_api::{
     config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
     request::RpcRequest,
     response::{OptionalContext, RpcKeyedAccount},
 };
+use tokio::sync::broadcast;
 
 use crate::{
+    account_fetcher::AccountFetcher,
     accounts::State,
     constants::{self, derive_perp_market_account, derive_spot_market_account, state_account},
     drift_idl::types::OracleSource,
     grpc::AccountUpdate,
     memcmp::get_market_filter,
+    staleness::is_stale,
     types::{MapOf, EMPTY_ACCOUNT_CALLBACK},
+    update_stream::{publish, UpdateStream, UPDATE_CHANNEL_CAPACITY},
     websocket_account_subscriber::WebsocketAccountSubscriber,
-    DataAndSlot, MarketId, MarketType, PerpMarket, SdkResult, SpotMarket, UnsubHandle,
+    DataAndSlot, MarketId, MarketType, PerpMarket, SdkError, SdkResult, SpotMarket, UnsubHandle,
 };
 
 const LOG_TARGET: &str = "marketmap";
@@ -80,10 +80,13 @@ impl Market for SpotMarket {
 /// or drive the map by calling `.sync()` periodically
 pub struct MarketMap<T: AnchorDeserialize + Send> {
     pub marketmap: Arc<DashMap<u16, DataAndSlot<T>, ahash::RandomState>>,
-    subscriptions: DashMap<u16, UnsubHandle, ahash::RandomState>,
+    /// Ws subscriptions by market index
+    subscriptions: DashMap<u16, (UnsubHandle, WebsocketAccountSubscriber), ahash::RandomState>,
     latest_slot: Arc<AtomicU64>,
     pubsub: Arc<PubsubClient>,
     commitment: CommitmentConfig,
+    /// applied market updates
+    updates: broadcast::Sender<DataAndSlot<T>>,
 }
 
 impl<T> MarketMap<T>
@@ -99,6 +102,7 @@ where
             latest_slot: Arc::new(AtomicU64::new(0)),
             pubsub,
             commitment,
+            updates: broadcast::channel(UPDATE_CHANNEL_CAPACITY).0,
         }
     }
 
@@ -107,19 +111,26 @@ where
         Arc::clone(&self.marketmap)
     }
 
+    /// Stream of market account updates
+    ///
+    /// Yields Ws and gRPC updates of subscribed markets
+    pub fn updates(&self) -> UpdateStream<DataAndSlot<T>> {
+        UpdateStream::new(self.updates.subscribe(), Some)
+    }
+
     /// Returns a hook for driving the map with new `Account` updates
     pub(crate) fn on_account_fn(&self) -> impl Fn(&AccountUpdate) {
         let marketmap = self.map();
+        let updates = self.updates.clone();
         move |update: &AccountUpdate| {
             let market = T::deserialize(&mut &update.data[8..]).expect("deser market");
             let idx = market.market_index();
-            marketmap.insert(
-                idx,
-                DataAndSlot {
-                    slot: update.slot,
-                    data: market,
-                },
-            );
+            let market = DataAndSlot {
+                slot: update.slot,
+                data: market,
+            };
+            publish(&updates, || market.clone());
+            marketmap.insert(idx, market);
         }
     }
 
@@ -171,6 +182,7 @@ where
         let futs_iter = pending_subscriptions.into_iter().map(|(idx, fut)| {
             let marketmap = Arc::clone(&self.marketmap);
             let latest_slot = self.latest_slot.clone();
+            let updates = self.updates.clone();
             let on_account = on_account.clone();
             async move {
                 let unsub = fut
@@ -179,26 +191,25 @@ where
                             if update.slot > latest_slot.load(Ordering::Relaxed) {
                                 latest_slot.store(update.slot, Ordering::Relaxed);
                             }
-                            marketmap.insert(
-                                idx,
-                                DataAndSlot {
-                                    slot: update.slot,
-                                    data: T::deserialize(&mut &update.data.as_slice()[8..])
-                                        .expect("valid market"),
-                                },
-                            );
+                            let market = DataAndSlot {
+                                slot: update.slot,
+                                data: T::deserialize(&mut &update.data.as_slice()[8..])
+                                    .expect("valid market"),
+                            };
+                            publish(&updates, || market.clone());
+                            marketmap.insert(idx, market);
                             on_account(update);
                         }
                     })
                     .await;
-                (idx, unsub)
+                (idx, unsub, fut)
             }
         });
 
         let mut subscription_futs = FuturesUnordered::from_iter(futs_iter);
-        while let Some((market, unsub)) = subscription_futs.next().await {
+        while let Some((market, unsub, subscriber)) = subscription_futs.next().await {
             log::debug!(target: LOG_TARGET, "subscribed market: {market:?}");
-            self.subscriptions.insert(market, unsub?);
+            self.subscriptions.insert(market, (unsub?, subscriber));
         }
 
         log::debug!(target: LOG_TARGET, "subscribed: {:?}", T::MARKET_TYPE);
@@ -214,7 +225,7 @@ where
     /// Unsubscribe from updates for the given `markets`
     pub fn unsubscribe(&self, markets: &[MarketId]) -> SdkResult<()> {
         for market in markets {
-            if let Some((market, unsub)) = self.subscriptions.remove(&market.index()) {
+            if let Some((market, (unsub, _))) = self.subscriptions.remove(&market.index()) {
                 let _ = unsub.send(());
                 self.marketmap.remove(&market);
             }
@@ -258,13 +269,15 @@ where
     }
 
     /// Sync all market accounts
-    pub async fn sync(&self, rpc: &RpcClient) -> SdkResult<()> {
+    ///
+    /// * `fetcher` - batched RPC fetcher, shared with concurrent syncs
+    pub async fn sync(&self, fetcher: &AccountFetcher) -> SdkResult<()> {
         log::debug!(
             target: LOG_TARGET,
             "syncing marketmap: {:?}",
             T::MARKET_TYPE
         );
-        let (markets, latest_slot) = get_market_accounts_with_fallback::<T>(rpc).await?;
+        let (markets, latest_slot) = get_market_accounts_with_fallback::<T>(fetcher).await?;
         for market in markets {
             self.marketmap.insert(
                 market.market_index(),
@@ -288,34 +301,94 @@ where
     pub fn get_latest_slot(&self) -> u64 {
         self.latest_slot.load(Ordering::Relaxed)
     }
+
+    /// Resync markets not updated within `max_age` slots of `current_slot`
+    ///
+    /// Markets are quiet between cranks and fills, so a market equal to its RPC copy is only
+    /// marked fresh. A changed market means its Ws subscription stalled and it is resubscribed
+    ///
+    /// Returns the indexes of stale markets
+    pub async fn resync_stale(
+        &self,
+        fetcher: &AccountFetcher,
+        current_slot: Slot,
+        max_age: u64,
+    ) -> SdkResult<Vec<u16>>
+    where
+        T: PartialEq,
+    {
+        let stale: Vec<u16> = self
+            .subscriptions
+            .iter()
+            .map(|s| *s.key())
+            .filter(|idx| {
+                self.marketmap
+                    .get(idx)
+                    .is_none_or(|m| is_stale(m.slot, current_slot, max_age))
+            })
+            .collect();
+
+        let pubkeys: Vec<Pubkey> = stale
+            .iter()
+            .map(|idx| match T::MARKET_TYPE {
+                MarketType::Perp => derive_perp_market_account(*idx),
+                MarketType::Spot => derive_spot_market_account(*idx),
+            })
+            .collect();
+        let (accounts, slot) = fetcher.get_multiple_accounts(&pubkeys).await?;
+
+        for (idx, account) in stale.iter().zip(accounts) {
+            let Some(market) = account.and_then(|a| T::deserialize(&mut a.data.get(8..)?).ok())
+            else {
+                log::warn!(target: LOG_TARGET, "resync failed: {:?}/{idx}", T::MARKET_TYPE);
+                continue;
+            };
+            let missed_update = match self.marketmap.get(idx) {
+                // Ws update arrived in the meantime
+                Some(cached) if cached.slot >= slot => continue,
+                Some(cached) => cached.data != market,
+                None => true,
+            };
+            let market = DataAndSlot { slot, data: market };
+            if missed_update {
+                log::warn!(target: LOG_TARGET, "stale subscription: {:?}/{idx}", T::MARKET_TYPE);
+                if let Some(sub) = self.subscriptions.get(idx) {
+                    sub.1.resubscribe();
+                }
+                publish(&self.updates, || market.clone());
+            }
+            self.marketmap.insert(*idx, market);
+        }
+
+        Ok(stale)
+    }
 }
 
-/// Fetch all market (program) accounts with multiple fallbacks
+/// Fetch all market (program) accounts with fallback
 ///
-/// Tries progressively less intensive RPC methods for wider compatibility with RPC providers:
-///     getProgramAccounts, getMultipleAccounts, lastly multiple getAccountInfo
+/// Tries getProgramAccounts first, falling back to batched getMultipleAccounts via `fetcher`
+/// for wider compatibility with RPC providers
 ///
 /// Returns deserialized accounts and retrieved slot
 pub async fn get_market_accounts_with_fallback<T: Market + AnchorDeserialize>(
-    rpc: &RpcClient,
+    fetcher: &AccountFetcher,
 ) -> SdkResult<(Vec<T>, Slot)> {
     let mut markets = Vec::<T>::default();
 
-    let account_config = RpcAccountInfoConfig {
-        commitment: Some(rpc.commitment()),
-        encoding: Some(UiAccountEncoding::Base64Zstd),
-        ..RpcAccountInfoConfig::default()
-    };
-
     let gpa_config = RpcProgramAccountsConfig {
         filters: Some(vec![get_market_filter(T::MARKET_TYPE)]),
-        account_config: account_config.clone(),
+        account_config: RpcAccountInfoConfig {
+            commitment: Some(fetcher.commitment()),
+            encoding: Some(UiAccountEncoding::Base64Zstd),
+            ..RpcAccountInfoConfig::default()
+        },
         with_context: Some(true),
         sort_results: None,
     };
 
     // try 'getProgramAccounts'
-    let response: Result<OptionalContext<Vec<RpcKeyedAccount>>, _> = rpc
+    let response: Result<OptionalContext<Vec<RpcKeyedAccount>>, _> = fetcher
+        .rpc()
         .send(
             RpcRequest::GetProgramAccounts,
             json!([constants::PROGRAM_ID.to_string(), gpa_config]),
@@ -336,16 +409,11 @@ pub async fn get_market_accounts_with_fallback<T: Market + AnchorDeserialize>(
         T::MARKET_TYPE
     );
 
-    let state_response = rpc
-        .get_ui_account_with_config(state_account(), account_config)
-        .await
-        .expect("state account fetch");
-
-    let state_data = state_response.value.expect("state has data").data;
-    let state = State::try_deserialize_unchecked(
-        &mut state_data.decode().expect("deser state account").as_slice(),
-    )
-    .expect("state deserializes");
+    // try 'getMultipleAccounts'
+    let (state_account, _slot) = fetcher.get_account(&state_account()).await?;
+    let state_data = state_account.ok_or(SdkError::InvalidAccount)?.data;
+    let state =
+        State::try_deserialize_unchecked(&mut state_data.as_slice()).expect("state deserializes");
 
     let market_pdas: Vec<Pubkey> = match T::MARKET_TYPE {
         MarketType::Spot => (0..state.number_of_spot_markets)
@@ -356,70 +424,19 @@ pub async fn get_market_accounts_with_fallback<T: Market + AnchorDeserialize>(
             .collect(),
     };
 
-    // try 'getMultipleAccounts'
-    let mut market_requests = FuturesOrdered::new();
-    for market_chunk in market_pdas.chunks(64) {
-        market_requests
-            .push_back(rpc.get_multiple_accounts_with_commitment(market_chunk, rpc.commitment()));
-    }
-
-    while let Some(market_response) = market_requests.next().await {
-        match market_response {
-            Ok(data) => {
-                for market in data.value {
-                    match market {
-                        Some(market) => {
-                            markets.push(
-                                T::deserialize(&mut &market.data.as_slice()[8..])
-                                    .expect("market deserializes"),
-                            );
-                        }
-                        None => {
-                            log::warn!(
-                                target: LOG_TARGET,
-                                "failed to fetch market account (missing)"
-                            );
-                            break;
-                        }
-                    }
-                }
-            }
-            Err(err) => {
-                log::warn!(
-                    target: LOG_TARGET,
-                    "failed to fetch market accounts: {err:?}"
-                );
-                return Err(err)?;
-            }
-        }
-    }
-    if market_pdas.len() == markets.len() {
-        return Ok((markets, state_response.context.slot));
-    }
-    log::debug!(
-        target: LOG_TARGET,
-        "syncing with getMultipleAccounts failed: {:?}",
-        T::MARKET_TYPE
-    );
-
-    // try multiple 'getAccount's
-    let mut market_requests =
-        FuturesOrdered::from_iter(market_pdas.iter().map(|acc| rpc.get_account_data(acc)));
-
-    while let Some(market_response) = market_requests.next().await {
-        match market_response {
-            Ok(data) => {
-                markets
-                    .push(T::deserialize(&mut &data.as_slice()[8..]).expect("market deserializes"));
-            }
-            Err(err) => {
-                log::warn!("failed to fetch market account: {err:?}");
-                return Err(err)?;
-            }
-        }
+    let (accounts, slot) = fetcher.get_multiple_accounts(&market_pdas).await?;
+    for (pubkey, account) in market_pdas.iter().zip(accounts) {
+        let Some(account) = account else {
+            log::warn!(
+                target: LOG_TARGET,
+                "failed to fetch market account (missing): {pubkey:?}"
+            );
+            return Err(SdkError::InvalidAccount);
+        };
+        markets.push(T::deserialize(&mut &account.data[8..]).expect("market deserializes"));
     }
 
-    Ok((markets, state_response.context.slot))
+    Ok((markets, slot))
 }
 
 #[cfg(test)]
@@ -432,6 +449,7 @@ mod tests {
 
     use super::{get_market_accounts_with_fallback, MarketMap};
     use crate::{
+        account_fetcher::AccountFetcher,
         accounts::{PerpMarket, SpotMarket},
         utils::{get_ws_url, test_envs::devnet_endpoint},
         MarketId,
@@ -464,15 +482,16 @@ mod tests {
 
     #[tokio::test]
     async fn get_market_accounts_with_fallback_works() {
+        let fetcher = AccountFetcher::new(
+            Arc::new(RpcClient::new(devnet_endpoint())),
+            CommitmentConfig::confirmed(),
+        );
         let result: Result<(Vec<PerpMarket>, _), _> =
-            get_market_accounts_with_fallback::<PerpMarket>(&RpcClient::new(devnet_endpoint()))
-                .await;
+            get_market_accounts_with_fallback::<PerpMarket>(&fetcher).await;
 
         assert!(result.is_ok_and(|r| r.0.len() > 0 && r.1 > 0));
 
-        let result =
-            get_market_accounts_with_fallback::<SpotMarket>(&RpcClient::new(devnet_endpoint()))
-                .await;
+        let result = get_market_accounts_with_fallback::<SpotMarket>(&fetcher).await;
 
         assert!(result.is_ok_and(|r| r.0.len() > 0 && r.1 > 0));
     }
@@ -6,6 +6,7 @@ use crate::{
     accounts::State,
     constants::{self, oracle_source_to_owner, state_account},
     ffi::{AccountWithKey, AccountsList},
+    snapshot::AccountSnapshot,
     types::accounts::User,
     utils::zero_account_to_bytes,
     DriftClient, MarketId, SdkError, SdkResult,
@@ -235,4 +236,74 @@ impl AccountsListBuilder {
             latest_slot: latest_oracle_slot,
         })
     }
+
+    /// Constructs an accounts list from a slot-consistent `snapshot`
+    ///
+    /// * `client` - drift client instance
+    /// * `snapshot` - user, market, and oracle accounts e.g. from `DriftClient::try_get_snapshot`
+    pub fn build_from_snapshot(
+        &mut self,
+        client: &DriftClient,
+        snapshot: &AccountSnapshot,
+    ) -> SdkResult<AccountsList<'_>> {
+        let drift_state_account = client.try_get_account::<State>(state_account())?;
+
+        for market in snapshot.spot_markets.iter() {
+            self.spot_accounts.push(
+                (
+                    market.data.pubkey,
+                    Account {
+                        data: zero_account_to_bytes(market.data),
+                        owner: constants::PROGRAM_ID,
+                        ..Default::default()
+                    },
+                )
+                    .into(),
+            );
+        }
+
+        for market in snapshot.perp_markets.iter() {
+            self.perp_accounts.push(
+                (
+                    market.data.pubkey,
+                    Account {
+                        data: zero_account_to_bytes(market.data),
+                        owner: constants::PROGRAM_ID,
+                        ..Default::default()
+                    },
+                )
+                    .into(),
+            );
+        }
+
+        // markets may share an oracle
+        let mut oracle_keys = ahash::HashSet::default();
+        let mut latest_oracle_slot = 0;
+        for (_, oracle) in snapshot.oracles.iter() {
+            if !oracle_keys.insert(oracle.pubkey) {
+                continue;
+            }
+            latest_oracle_slot = oracle.slot.max(latest_oracle_slot);
+            let oracle_owner = oracle_source_to_owner(client.context, oracle.source);
+            self.oracle_accounts.push(
+                (
+                    oracle.pubkey,
+                    Account {
+                        data: oracle.raw.clone(),
+                        owner: oracle_owner,
+                        ..Default::default()
+                    },
+                )
+                    .into(),
+            );
+        }
+
+        Ok(AccountsList {
+            perp_markets: self.perp_accounts.as_mut_slice(),
+            spot_markets: self.spot_accounts.as_mut_slice(),
+            oracles: self.oracle_accounts.as_mut_slice(),
+            oracle_guard_rails: Some(drift_state_account.oracle_guard_rails),
+            latest_slot: latest_oracle_slot,
+        })
+    }
 }
@@ -22,6 +22,7 @@ pub const QUOTE_PRECISION_U64: u64 = 1_000_000; // expo = -6
 
 pub const FUNDING_RATE_BUFFER: u128 = 1_000; // expo = -3
 pub const FUNDING_RATE_BUFFER_I128: i128 = FUNDING_RATE_BUFFER as i128; // expo = -3
+pub const FUNDING_RATE_OFFSET_DENOMINATOR: i64 = 5_000; // 1/5000 of oracle twap per day
 
 pub const MARGIN_PRECISION: u32 = 10_000; // expo = -4
 pub const MARGIN_PRECISION_U128: u128 = 10_000; // expo = -4
@@ -81,3 +82,11 @@ pub const AMM_TIMES_PEG_TO_QUOTE_PRECISION_RATIO: u128 =
 pub const AMM_TO_QUOTE_PRECISION_RATIO: u128 = AMM_RESERVE_PRECISION / QUOTE_PRECISION; // expo: 3
 
 pub const LIQUIDATION_FEE_ADJUST_GRACE_PERIOD_SLOTS: u64 = 1_500; // ~10 minutes
+
+pub const FUNDING_RATE_PRECISION: u128 = PRICE_PRECISION * FUNDING_RATE_BUFFER; // expo = -9
+pub const FUNDING_RATE_PRECISION_I128: i128 = FUNDING_RATE_PRECISION as i128; // expo = -9
+pub const FUNDING_RATE_PRECISION_I64: i64 = FUNDING_RATE_PRECISION as i64; // expo = -9
+
+pub const ONE_HOUR: i64 = 3_600;
+pub const ONE_YEAR: u128 = 31_536_000;
+pub const ONE_YEAR_I64: i64 = ONE_YEAR as i64;
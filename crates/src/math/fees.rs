@@ -0,0 +1,189 @@
+//! Fee tier math
+//!
+//! Mirrors the protocol's fee tier selection so takers and makers can price fees in before placing orders
+use crate::{
+    math::constants::QUOTE_PRECISION_U64,
+    types::{accounts::UserStats, FeeStructure, FeeTier, MarketType},
+};
+
+/// Length of the rolling volume window in seconds
+pub const THIRTY_DAYS: i64 = 30 * 24 * 3_600;
+
+/// Min. 30d volume for perp fee tiers 1..=5, expo = -6
+const VOLUME_THRESHOLDS: [u64; 5] = [
+    2_000_000 * QUOTE_PRECISION_U64,
+    10_000_000 * QUOTE_PRECISION_U64,
+    20_000_000 * QUOTE_PRECISION_U64,
+    80_000_000 * QUOTE_PRECISION_U64,
+    200_000_000 * QUOTE_PRECISION_U64,
+];
+
+/// Min. staked gov token amount for stake benefit levels 1..=5, expo = -6
+const STAKE_THRESHOLDS: [u64; 5] = [
+    1_000 * QUOTE_PRECISION_U64 - 1,
+    10_000 * QUOTE_PRECISION_U64 - 1,
+    50_000 * QUOTE_PRECISION_U64 - 1,
+    100_000 * QUOTE_PRECISION_U64 - 1,
+    250_000 * QUOTE_PRECISION_U64 - 5,
+];
+
+/// Taker fee discount and maker rebate boost in percent, by stake benefit level
+const STAKE_BENEFIT_PCT: [u32; 6] = [0, 5, 10, 20, 30, 40];
+
+/// Fee tier a user qualifies for in markets of `market_type`
+///
+/// Perp tiers are selected by 30d maker + taker volume and improved by staked gov tokens,
+/// spot markets and high leverage mode users always use the first tier
+///
+/// * `high_leverage_mode` - `user.margin_mode == MarginMode::HighLeverage`
+pub fn determine_user_fee_tier(
+    user_stats: &UserStats,
+    fee_structure: &FeeStructure,
+    market_type: MarketType,
+    high_leverage_mode: bool,
+) -> FeeTier {
+    match market_type {
+        MarketType::Perp if high_leverage_mode => fee_structure.fee_tiers[0],
+        MarketType::Perp => determine_perp_fee_tier(user_stats, fee_structure),
+        MarketType::Spot => fee_structure.fee_tiers[0],
+    }
+}
+
+fn determine_perp_fee_tier(user_stats: &UserStats, fee_structure: &FeeStructure) -> FeeTier {
+    let volume = total_30d_volume(user_stats);
+    let tier_index = VOLUME_THRESHOLDS
+        .iter()
+        .position(|threshold| volume < *threshold)
+        .unwrap_or(VOLUME_THRESHOLDS.len());
+    let stake_index = STAKE_THRESHOLDS
+        .iter()
+        .position(|threshold| user_stats.if_staked_gov_token_amount < *threshold)
+        .unwrap_or(STAKE_THRESHOLDS.len());
+
+    let mut tier = fee_structure.fee_tiers[tier_index];
+    let stake_benefit = STAKE_BENEFIT_PCT[stake_index];
+    if stake_benefit > 0 {
+        tier.fee_numerator = tier.fee_numerator * (100 - stake_benefit) / 100;
+        tier.maker_rebate_numerator = tier.maker_rebate_numerator * (100 + stake_benefit) / 100;
+    }
+
+    tier
+}
+
+/// Maker plus taker 30d volume as last recorded on-chain, expo = -6
+pub fn total_30d_volume(user_stats: &UserStats) -> u64 {
+    user_stats
+        .maker_volume30d
+        .saturating_add(user_stats.taker_volume30d)
+}
+
+/// `volume` recorded at `last_ts` decayed to `now` over the rolling 30d window
+///
+/// On-chain volumes only decay when the user trades, this estimates their current value
+pub fn decay_30d_volume(volume: u64, last_ts: i64, now: i64) -> u64 {
+    let elapsed = now.saturating_sub(last_ts).clamp(0, THIRTY_DAYS);
+    ((volume as u128 * (THIRTY_DAYS - elapsed) as u128) / THIRTY_DAYS as u128) as u64
+}
+
+/// Taker fee of a fill worth `quote_amount` at `tier`, expo = -6
+///
+/// * `is_referred` - taker receives the referee discount
+pub fn calculate_taker_fee(quote_amount: u64, tier: &FeeTier, is_referred: bool) -> u64 {
+    if tier.fee_denominator == 0 {
+        return 0;
+    }
+    let fee = (quote_amount as u128 * tier.fee_numerator as u128)
+        .div_ceil(tier.fee_denominator as u128) as u64;
+
+    if is_referred && tier.referee_fee_denominator != 0 {
+        let discount = (fee as u128 * tier.referee_fee_numerator as u128
+            / tier.referee_fee_denominator as u128) as u64;
+        fee.saturating_sub(discount)
+    } else {
+        fee
+    }
+}
+
+/// Maker rebate of a fill worth `quote_amount` at `tier`, expo = -6
+pub fn calculate_maker_rebate(quote_amount: u64, tier: &FeeTier) -> u64 {
+    if tier.maker_rebate_denominator == 0 {
+        return 0;
+    }
+    (quote_amount as u128 * tier.maker_rebate_numerator as u128
+        / tier.maker_rebate_denominator as u128) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fee_structure() -> FeeStructure {
+        let mut fee_structure = FeeStructure::default();
+        for (i, tier) in fee_structure.fee_tiers.iter_mut().enumerate() {
+            *tier = FeeTier {
+                fee_numerator: 35 - 2 * i as u32,
+                fee_denominator: 100_000,
+                maker_rebate_numerator: 20,
+                maker_rebate_denominator: 100_000,
+                referee_fee_numerator: 5,
+                referee_fee_denominator: 100,
+                ..Default::default()
+            };
+        }
+        fee_structure
+    }
+
+    #[test]
+    fn fee_tier_by_volume_and_stake() {
+        let fee_structure = fee_structure();
+        let mut stats = UserStats::default();
+        assert_eq!(
+            determine_user_fee_tier(&stats, &fee_structure, MarketType::Perp, false),
+            fee_structure.fee_tiers[0]
+        );
+
+        stats.maker_volume30d = 6_000_000 * QUOTE_PRECISION_U64;
+        stats.taker_volume30d = 4_000_000 * QUOTE_PRECISION_U64;
+        assert_eq!(
+            determine_user_fee_tier(&stats, &fee_structure, MarketType::Perp, false),
+            fee_structure.fee_tiers[2]
+        );
+        // spot ignores volume
+        assert_eq!(
+            determine_user_fee_tier(&stats, &fee_structure, MarketType::Spot, false),
+            fee_structure.fee_tiers[0]
+        );
+        // high leverage mode ignores volume
+        assert_eq!(
+            determine_user_fee_tier(&stats, &fee_structure, MarketType::Perp, true),
+            fee_structure.fee_tiers[0]
+        );
+
+        stats.taker_volume30d = 1_000_000_000 * QUOTE_PRECISION_U64;
+        stats.if_staked_gov_token_amount = 10_000 * QUOTE_PRECISION_U64;
+        let tier = determine_user_fee_tier(&stats, &fee_structure, MarketType::Perp, false);
+        assert_eq!(tier.fee_numerator, 25 * 90 / 100);
+        assert_eq!(tier.maker_rebate_numerator, 20 * 110 / 100);
+    }
+
+    #[test]
+    fn volume_decay() {
+        let volume = 3_000 * QUOTE_PRECISION_U64;
+        assert_eq!(decay_30d_volume(volume, 100, 100), volume);
+        assert_eq!(decay_30d_volume(volume, 0, THIRTY_DAYS / 3), volume * 2 / 3);
+        assert_eq!(decay_30d_volume(volume, 0, THIRTY_DAYS * 2), 0);
+        // clock skew
+        assert_eq!(decay_30d_volume(volume, 100, 0), volume);
+    }
+
+    #[test]
+    fn taker_fee_and_maker_rebate() {
+        let tier = fee_structure().fee_tiers[0];
+        let quote = 10_000 * QUOTE_PRECISION_U64;
+        assert_eq!(calculate_taker_fee(quote, &tier, false), 3_500_000);
+        assert_eq!(calculate_taker_fee(quote, &tier, true), 3_325_000);
+        assert_eq!(calculate_taker_fee(1, &tier, false), 1);
+        assert_eq!(calculate_maker_rebate(quote, &tier), 2_000_000);
+        assert_eq!(calculate_taker_fee(quote, &FeeTier::default(), false), 0);
+    }
+}
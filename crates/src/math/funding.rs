@@ -0,0 +1,564 @@
+//! Funding rate math
+//!
+//! Mirrors the protocol's funding calculations so clients can estimate the next funding rate
+//! of a perp market and the unsettled funding of a position without waiting for an update
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{
+    drift_idl::events::FundingRateRecord,
+    event_subscriber::DriftEvent,
+    math::constants::{
+        AMM_TIMES_PEG_TO_QUOTE_PRECISION_RATIO, BASE_PRECISION_I128, FUNDING_RATE_BUFFER_I128,
+        FUNDING_RATE_OFFSET_DENOMINATOR, ONE_HOUR, ONE_YEAR, PERCENTAGE_PRECISION_I128,
+    },
+    types::{accounts::PerpMarket, ContractTier, PerpPosition, SdkError, SdkResult},
+};
+
+/// Estimate of a market's next funding update
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FundingRateEstimate {
+    /// projected mark price TWAP at update time, expo = -6
+    pub mark_twap: i64,
+    /// projected oracle price TWAP at update time, expo = -6
+    pub oracle_twap: i64,
+    /// quote per base funding rate for one period, expo = -9
+    ///
+    /// positive: longs pay shorts
+    pub funding_rate: i64,
+    /// seconds until the funding rate may be updated
+    pub seconds_until_update: i64,
+}
+
+impl FundingRateEstimate {
+    /// Funding rate as a percentage of the oracle TWAP for one period, expo = -6
+    pub fn rate_pct(&self) -> i64 {
+        calculate_funding_rate_pct(self.funding_rate, self.oracle_twap)
+    }
+    /// Annualized funding rate as a percentage, expo = -6
+    pub fn apr(&self, funding_period: i64) -> i64 {
+        calculate_funding_rate_apr(self.funding_rate, self.oracle_twap, funding_period)
+    }
+}
+
+/// Max spread between mark and oracle TWAP used for funding, by contract tier
+pub fn calculate_max_price_divergence_for_funding_rate(
+    contract_tier: ContractTier,
+    oracle_price_twap: i64,
+) -> i64 {
+    if contract_tier.to_number() <= ContractTier::B.to_number() {
+        oracle_price_twap / 33
+    } else if contract_tier.to_number() <= ContractTier::C.to_number() {
+        oracle_price_twap / 20
+    } else {
+        oracle_price_twap / 10
+    }
+}
+
+/// Calculate the funding rate for one `funding_period` from mark and oracle TWAPs
+///
+/// The spread includes the protocol's funding offset of `oracle_price_twap / 5000` per day
+///
+/// Returns quote per base funding rate, expo = -9
+pub fn calculate_funding_rate(
+    mark_price_twap: i64,
+    oracle_price_twap: i64,
+    funding_period: i64,
+    contract_tier: ContractTier,
+) -> i64 {
+    // funding is paid on a 24hr basis, scaled down to the funding period
+    let period_adjustment = (24 * ONE_HOUR) / funding_period.max(ONE_HOUR);
+    let max_divergence =
+        calculate_max_price_divergence_for_funding_rate(contract_tier, oracle_price_twap);
+    let funding_offset = oracle_price_twap.abs() / FUNDING_RATE_OFFSET_DENOMINATOR;
+    let price_spread = (mark_price_twap - oracle_price_twap + funding_offset)
+        .clamp(-max_divergence, max_divergence);
+
+    ((price_spread as i128 * FUNDING_RATE_BUFFER_I128) / period_adjustment as i128) as i64
+}
+
+/// Funding rate (expo = -9) as a percentage of `oracle_price_twap`, expo = -6
+pub fn calculate_funding_rate_pct(funding_rate: i64, oracle_price_twap: i64) -> i64 {
+    if oracle_price_twap == 0 {
+        return 0;
+    }
+    ((funding_rate as i128 * PERCENTAGE_PRECISION_I128)
+        / (oracle_price_twap as i128 * FUNDING_RATE_BUFFER_I128)) as i64
+}
+
+/// Annualize a per period funding rate (expo = -9) as a percentage of `oracle_price_twap`, expo = -6
+pub fn calculate_funding_rate_apr(
+    funding_rate: i64,
+    oracle_price_twap: i64,
+    funding_period: i64,
+) -> i64 {
+    if oracle_price_twap == 0 {
+        return 0;
+    }
+    let periods_per_year = ONE_YEAR as i128 / funding_period.max(1) as i128;
+    ((funding_rate as i128 * periods_per_year * PERCENTAGE_PRECISION_I128)
+        / (oracle_price_twap as i128 * FUNDING_RATE_BUFFER_I128)) as i64
+}
+
+/// Roll a TWAP forward to `now` with `price`, weighted over `period` seconds
+pub fn calculate_new_twap(
+    price: i64,
+    now: i64,
+    last_twap: i64,
+    last_twap_ts: i64,
+    period: i64,
+) -> i64 {
+    let since_last = (now - last_twap_ts).max(0);
+    let from_start = (period - since_last).max(0);
+    let denominator = since_last + from_start;
+    if denominator == 0 {
+        return price;
+    }
+
+    let weighted = price as i128 * since_last as i128 + last_twap as i128 * from_start as i128;
+    (weighted / denominator as i128) as i64
+}
+
+/// Seconds until the next funding update is allowed
+///
+/// Updates are aligned to the start of each `funding_period` (on the hour for hourly funding)
+pub fn time_until_next_funding_update(now: i64, last_update_ts: i64, funding_period: i64) -> i64 {
+    if funding_period <= 0 {
+        return 0;
+    }
+    let time_since_last_update = (now - last_update_ts).max(0);
+
+    // updates landing late in a period shorten the following wait, bounded to 1/3 of a period
+    let last_update_delay = last_update_ts.rem_euclid(funding_period);
+    let max_delay_for_next_period = funding_period / 3;
+    let next_update_wait = if last_update_delay > max_delay_for_next_period {
+        2 * funding_period - last_update_delay
+    } else {
+        funding_period - last_update_delay
+    };
+
+    (next_update_wait - time_since_last_update).max(0)
+}
+
+/// Estimate the next funding rate of `market`
+///
+/// * `oracle_price` - current oracle price
+/// * `mark_price` - current mark price, defaults to the AMM bid/ask mid
+/// * `now` - current unix timestamp
+pub fn estimate_next_funding_rate(
+    market: &PerpMarket,
+    oracle_price: i64,
+    mark_price: Option<i64>,
+    now: i64,
+) -> FundingRateEstimate {
+    let amm = &market.amm;
+    let mark_price = mark_price.unwrap_or_else(|| {
+        let reserve_price = market.reserve_price();
+        ((market.bid_price(Some(reserve_price)) + market.ask_price(Some(reserve_price))) / 2) as i64
+    });
+
+    let mark_twap = calculate_new_twap(
+        mark_price,
+        now,
+        amm.last_mark_price_twap as i64,
+        amm.last_mark_price_twap_ts,
+        amm.funding_period,
+    );
+    let oracle_twap = calculate_new_twap(
+        oracle_price,
+        now,
+        amm.historical_oracle_data.last_oracle_price_twap,
+        amm.historical_oracle_data.last_oracle_price_twap_ts,
+        amm.funding_period,
+    );
+
+    FundingRateEstimate {
+        mark_twap,
+        oracle_twap,
+        funding_rate: calculate_funding_rate(
+            mark_twap,
+            oracle_twap,
+            amm.funding_period,
+            market.contract_tier,
+        ),
+        seconds_until_update: time_until_next_funding_update(
+            now,
+            amm.last_funding_rate_ts,
+            amm.funding_period,
+        ),
+    }
+}
+
+/// Calculate the funding payment of a position given the market's cumulative funding rate
+///
+/// Returns quote amount, expo = -6. positive: position receives funding
+pub fn calculate_funding_payment(
+    amm_cumulative_funding_rate: i128,
+    position: &PerpPosition,
+) -> SdkResult<i64> {
+    let funding_rate_delta =
+        amm_cumulative_funding_rate - position.last_cumulative_funding_rate as i128;
+    if funding_rate_delta == 0 || position.base_asset_amount == 0 {
+        return Ok(0);
+    }
+
+    let payment = (funding_rate_delta * position.base_asset_amount as i128)
+        / AMM_TIMES_PEG_TO_QUOTE_PRECISION_RATIO as i128
+        / FUNDING_RATE_BUFFER_I128;
+
+    i64::try_from(-payment).map_err(|_| SdkError::MathError("funding payment overflow"))
+}
+
+/// Calculate the unsettled funding of `position` in `market`
+///
+/// Returns quote amount, expo = -6. positive: position receives funding
+pub fn calculate_pending_funding(market: &PerpMarket, position: &PerpPosition) -> SdkResult<i64> {
+    if position.market_index != market.market_index {
+        return Err(SdkError::Generic(format!(
+            "position market {} != market {}",
+            position.market_index, market.market_index
+        )));
+    }
+    let cumulative_funding_rate = if position.base_asset_amount > 0 {
+        market.amm.cumulative_funding_rate_long.as_i128()
+    } else {
+        market.amm.cumulative_funding_rate_short.as_i128()
+    };
+
+    calculate_funding_payment(cumulative_funding_rate, position)
+}
+
+/// Project the funding payment of `position` at the next update given an estimated rate
+///
+/// Returns quote amount, expo = -6. positive: position receives funding
+pub fn calculate_projected_funding_payment(
+    estimate: &FundingRateEstimate,
+    position: &PerpPosition,
+) -> i64 {
+    let payment = (estimate.funding_rate as i128 * position.base_asset_amount as i128)
+        / BASE_PRECISION_I128
+        / FUNDING_RATE_BUFFER_I128;
+    -payment as i64
+}
+
+/// Default number of records retained per market by [`FundingRateHistory`] (1 week of hourly updates)
+pub const DEFAULT_FUNDING_HISTORY_CAPACITY: usize = 24 * 7;
+
+/// Per market history of realized funding rates built from `FundingRateRecord` events
+///
+/// ```example(no_run)
+/// let mut history = FundingRateHistory::default();
+/// while let Some(event) = events.next().await {
+///     history.on_event(&event);
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct FundingRateHistory {
+    capacity: usize,
+    markets: BTreeMap<u16, VecDeque<FundingRateRecord>>,
+}
+
+impl Default for FundingRateHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_FUNDING_HISTORY_CAPACITY)
+    }
+}
+
+impl FundingRateHistory {
+    /// Create a new history retaining up to `capacity` records per market
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            markets: Default::default(),
+        }
+    }
+    /// Add a record to the history
+    ///
+    /// Records are kept ordered by `record_id`, duplicates are ignored
+    pub fn insert(&mut self, record: FundingRateRecord) {
+        let records = self.markets.entry(record.market_index).or_default();
+        match records.binary_search_by_key(&record.record_id, |r| r.record_id) {
+            Ok(_) => return,
+            Err(idx) => records.insert(idx, record),
+        }
+        while records.len() > self.capacity {
+            records.pop_front();
+        }
+    }
+    /// Add the record from a `DriftEvent::FundingRate`, other events are ignored
+    pub fn on_event(&mut self, event: &DriftEvent) {
+        if let DriftEvent::FundingRate { record, .. } = event {
+            self.insert(record.clone());
+        }
+    }
+    /// Latest record for `market_index`
+    pub fn latest(&self, market_index: u16) -> Option<&FundingRateRecord> {
+        self.markets.get(&market_index).and_then(|r| r.back())
+    }
+    /// Records of `market_index` from oldest to newest
+    pub fn records(&self, market_index: u16) -> impl Iterator<Item = &FundingRateRecord> {
+        self.markets
+            .get(&market_index)
+            .into_iter()
+            .flat_map(|r| r.iter())
+    }
+    /// Average funding rate percentage (expo = -6) of the latest `n` records of `market_index`
+    pub fn average_rate_pct(&self, market_index: u16, n: usize) -> Option<i64> {
+        let records = self.markets.get(&market_index)?;
+        let latest = records.iter().rev().take(n);
+        let count = latest.len();
+        if count == 0 {
+            return None;
+        }
+        let total: i64 = latest
+            .map(|r| calculate_funding_rate_pct(r.funding_rate, r.oracle_price_twap))
+            .sum();
+
+        Some(total / count as i64)
+    }
+    /// Funding realized by a position of `base_asset_amount` in `market_index` since `since_ts`
+    ///
+    /// Returns quote amount, expo = -6. positive: position receives funding
+    pub fn realized_funding(
+        &self,
+        market_index: u16,
+        base_asset_amount: i64,
+        since_ts: i64,
+    ) -> i64 {
+        let total_rate: i128 = self
+            .records(market_index)
+            .filter(|r| r.ts >= since_ts)
+            .map(|r| {
+                if base_asset_amount > 0 {
+                    r.funding_rate_long.as_i128()
+                } else {
+                    r.funding_rate_short.as_i128()
+                }
+            })
+            .sum();
+
+        (-(total_rate * base_asset_amount as i128) / BASE_PRECISION_I128 / FUNDING_RATE_BUFFER_I128)
+            as i64
+    }
+    /// Number of hourly funding periods covered by the history of `market_index`
+    pub fn hours_covered(&self, market_index: u16) -> i64 {
+        let mut records = self.records(market_index);
+        match (records.next(), self.latest(market_index)) {
+            (Some(first), Some(last)) => (last.ts - first.ts) / ONE_HOUR,
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::constants::{BASE_PRECISION_I64, PRICE_PRECISION_I64},
+        types::{HistoricalOracleData, AMM},
+    };
+
+    #[test]
+    fn funding_rate_unclamped() {
+        let rate = calculate_funding_rate(
+            101 * PRICE_PRECISION_I64,
+            100 * PRICE_PRECISION_I64,
+            ONE_HOUR,
+            ContractTier::A,
+        );
+        // ($1 spread + $0.02 offset) / 24
+        assert_eq!(rate, 42_500_000);
+        assert_eq!(
+            calculate_funding_rate_pct(rate, 100 * PRICE_PRECISION_I64),
+            425
+        );
+        assert_eq!(
+            calculate_funding_rate_apr(rate, 100 * PRICE_PRECISION_I64, ONE_HOUR),
+            3_723_000
+        );
+
+        let rate = calculate_funding_rate(
+            99 * PRICE_PRECISION_I64,
+            100 * PRICE_PRECISION_I64,
+            ONE_HOUR,
+            ContractTier::A,
+        );
+        assert_eq!(rate, -40_833_333);
+
+        // offset only
+        let rate = calculate_funding_rate(
+            100 * PRICE_PRECISION_I64,
+            100 * PRICE_PRECISION_I64,
+            ONE_HOUR,
+            ContractTier::A,
+        );
+        assert_eq!(rate, 833_333);
+    }
+
+    #[test]
+    fn funding_rate_clamped_by_tier() {
+        let mark = 110 * PRICE_PRECISION_I64;
+        let oracle = 100 * PRICE_PRECISION_I64;
+        assert_eq!(
+            calculate_funding_rate(mark, oracle, ONE_HOUR, ContractTier::A),
+            126_262_625
+        );
+        assert_eq!(
+            calculate_funding_rate(mark, oracle, ONE_HOUR, ContractTier::C),
+            208_333_333
+        );
+        assert_eq!(
+            calculate_funding_rate(mark, oracle, ONE_HOUR, ContractTier::Speculative),
+            416_666_666
+        );
+    }
+
+    #[test]
+    fn new_twap() {
+        // halfway through the period
+        assert_eq!(calculate_new_twap(200, 1_800, 100, 0, ONE_HOUR), 150);
+        // period elapsed
+        assert_eq!(calculate_new_twap(200, 7_200, 100, 0, ONE_HOUR), 200);
+        // no time elapsed
+        assert_eq!(calculate_new_twap(200, 0, 100, 0, ONE_HOUR), 100);
+        assert_eq!(calculate_new_twap(200, 0, 100, 0, 0), 200);
+    }
+
+    #[test]
+    fn next_funding_update() {
+        let on_the_hour = 10 * ONE_HOUR;
+        assert_eq!(
+            time_until_next_funding_update(on_the_hour + 100, on_the_hour, ONE_HOUR),
+            3_500
+        );
+        // late update, next one is on the following hour
+        let late = on_the_hour + 1_300;
+        assert_eq!(
+            time_until_next_funding_update(late + 100, late, ONE_HOUR),
+            5_800
+        );
+        assert_eq!(
+            time_until_next_funding_update(late + ONE_HOUR * 2, late, ONE_HOUR),
+            0
+        );
+    }
+
+    #[test]
+    fn estimate_next_rate() {
+        let market = PerpMarket {
+            contract_tier: ContractTier::A,
+            amm: AMM {
+                funding_period: ONE_HOUR,
+                last_funding_rate_ts: 0,
+                last_mark_price_twap: 101 * PRICE_PRECISION_I64 as u64,
+                historical_oracle_data: HistoricalOracleData {
+                    last_oracle_price_twap: 100 * PRICE_PRECISION_I64,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let estimate = estimate_next_funding_rate(
+            &market,
+            100 * PRICE_PRECISION_I64,
+            Some(101 * PRICE_PRECISION_I64),
+            ONE_HOUR,
+        );
+        assert_eq!(
+            estimate,
+            FundingRateEstimate {
+                mark_twap: 101 * PRICE_PRECISION_I64,
+                oracle_twap: 100 * PRICE_PRECISION_I64,
+                funding_rate: 42_500_000,
+                seconds_until_update: 0,
+            }
+        );
+        assert_eq!(estimate.rate_pct(), 425);
+
+        let long = PerpPosition {
+            base_asset_amount: BASE_PRECISION_I64,
+            ..Default::default()
+        };
+        assert_eq!(
+            calculate_projected_funding_payment(&estimate, &long),
+            -42_500
+        );
+    }
+
+    #[test]
+    fn pending_funding() {
+        let market = PerpMarket {
+            amm: AMM {
+                cumulative_funding_rate_long: 141_666_666_i128.into(),
+                cumulative_funding_rate_short: 141_666_666_i128.into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut position = PerpPosition {
+            base_asset_amount: BASE_PRECISION_I64,
+            last_cumulative_funding_rate: 100_000_000,
+            ..Default::default()
+        };
+        // long pays
+        assert_eq!(
+            calculate_pending_funding(&market, &position).unwrap(),
+            -41_666
+        );
+
+        // short receives
+        position.base_asset_amount = -BASE_PRECISION_I64;
+        assert_eq!(
+            calculate_pending_funding(&market, &position).unwrap(),
+            41_666
+        );
+
+        position.market_index = 1;
+        assert!(calculate_pending_funding(&market, &position).is_err());
+    }
+
+    fn record(record_id: u64, ts: i64, funding_rate: i64) -> FundingRateRecord {
+        FundingRateRecord {
+            ts,
+            record_id,
+            market_index: 0,
+            funding_rate,
+            funding_rate_long: (funding_rate as i128).into(),
+            funding_rate_short: (funding_rate as i128).into(),
+            oracle_price_twap: 100 * PRICE_PRECISION_I64,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn funding_history() {
+        let mut history = FundingRateHistory::new(3);
+        history.insert(record(2, 2 * ONE_HOUR, 41_666_666));
+        history.insert(record(1, ONE_HOUR, 41_666_666));
+        history.insert(record(2, 2 * ONE_HOUR, 41_666_666));
+        history.on_event(&DriftEvent::FundingRate {
+            record: record(3, 3 * ONE_HOUR, -41_666_666),
+            signature: String::new(),
+            tx_idx: 0,
+        });
+
+        assert_eq!(history.records(0).count(), 3);
+        assert_eq!(history.records(1).count(), 0);
+        assert_eq!(history.latest(0).map(|r| r.record_id), Some(3));
+        assert_eq!(history.hours_covered(0), 2);
+        assert_eq!(history.average_rate_pct(0, 2), Some(0));
+        assert_eq!(history.average_rate_pct(0, 10), Some(138));
+        assert_eq!(history.average_rate_pct(1, 10), None);
+        assert_eq!(
+            history.realized_funding(0, BASE_PRECISION_I64, 2 * ONE_HOUR),
+            0
+        );
+        assert_eq!(history.realized_funding(0, BASE_PRECISION_I64, 0), -41_666);
+
+        // oldest record is evicted
+        history.insert(record(4, 4 * ONE_HOUR, 0));
+        assert_eq!(history.records(0).next().map(|r| r.record_id), Some(2));
+    }
+}
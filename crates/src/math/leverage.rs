@@ -1,11 +1,17 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use solana_pubkey::Pubkey;
 
 use super::{
     account_list_builder::AccountsListBuilder,
-    constants::{AMM_RESERVE_PRECISION, BASE_PRECISION, MARGIN_PRECISION, PRICE_PRECISION},
+    constants::{
+        AMM_RESERVE_PRECISION, BASE_PRECISION, MARGIN_PRECISION, PRICE_PRECISION,
+        SPOT_UTILIZATION_PRECISION, SPOT_WEIGHT_PRECISION_U128,
+    },
+    spot_interest::get_market_token_amounts,
 };
 use crate::{
-    accounts::PerpMarket,
+    accounts::{PerpMarket, SpotMarket},
     ffi::{
         calculate_margin_requirement_and_total_collateral_and_liability_info, MarginCalculation,
         MarginContextMode,
@@ -106,6 +112,27 @@ pub trait UserMargin {
     ) -> SdkResult<u128>;
     /// Calculate the user's live margin information
     fn calculate_margin_info(&self, user: &User) -> SdkResult<MarginCalculation>;
+    /// Calculate user's max. withdrawable amount of a spot market token
+    ///
+    /// * `user` - the user account
+    /// * `market_index` - the spot market to withdraw from
+    /// * `reduce_only` - only withdraw existing deposits i.e. do not borrow
+    ///
+    /// Respects initial margin and the market's withdraw guard limits
+    ///
+    /// Returns max token amount (spot market token precision)
+    fn max_withdrawable(
+        &self,
+        user: &Pubkey,
+        market_index: u16,
+        reduce_only: bool,
+    ) -> SdkResult<u64>;
+    /// Calculate user's max. borrowable amount of a spot market token
+    ///
+    /// i.e. the amount that may be withdrawn in excess of existing deposits
+    ///
+    /// Returns max token amount (spot market token precision)
+    fn max_borrowable(&self, user: &Pubkey, market_index: u16) -> SdkResult<u64>;
 }
 
 impl UserMargin for DriftClient {
@@ -162,6 +189,28 @@ impl UserMargin for DriftClient {
             Err(SdkError::Generic("spot market unimplemented".to_string()))
         }
     }
+    fn max_withdrawable(
+        &self,
+        user: &Pubkey,
+        market_index: u16,
+        reduce_only: bool,
+    ) -> SdkResult<u64> {
+        let max_withdraw = self.calculate_max_spot_withdraw(user, market_index)?;
+        let amount = if reduce_only {
+            max_withdraw.withdrawable
+        } else {
+            max_withdraw
+                .withdrawable
+                .checked_add(max_withdraw.borrowable)
+                .ok_or(SdkError::MathError("max withdrawable overflow"))?
+        };
+        u64::try_from(amount).map_err(|_| SdkError::MathError("max withdrawable overflow"))
+    }
+    fn max_borrowable(&self, user: &Pubkey, market_index: u16) -> SdkResult<u64> {
+        let max_withdraw = self.calculate_max_spot_withdraw(user, market_index)?;
+        u64::try_from(max_withdraw.borrowable)
+            .map_err(|_| SdkError::MathError("max borrowable overflow"))
+    }
     /// Calculate buying power = free collateral / initial margin ratio
     ///
     /// Returns buying power in `QUOTE_PRECISION` units
@@ -198,6 +247,219 @@ impl UserMargin for DriftClient {
     }
 }
 
+impl DriftClient {
+    fn calculate_max_spot_withdraw(
+        &self,
+        user: &Pubkey,
+        market_index: u16,
+    ) -> SdkResult<MaxSpotWithdraw> {
+        let market = MarketId::spot(market_index);
+        let oracle = self
+            .try_get_oracle_price_data_and_slot(market)
+            .ok_or(SdkError::NoMarketData(market))?;
+        let user_account = self.try_get_account::<User>(user)?;
+        let spot_market = self.try_get_spot_market_account(market_index)?;
+
+        let mut builder = AccountsListBuilder::default();
+        let mut accounts = builder.try_build(self, &user_account, &[])?;
+        let margin_info = calculate_margin_requirement_and_total_collateral_and_liability_info(
+            &user_account,
+            &mut accounts,
+            MarginContextMode::StandardInitial,
+        )?;
+
+        // no position in the market means nothing to withdraw, only borrow
+        let deposit_amount = match user_account.get_spot_position(market_index) {
+            Ok(position) => position.get_signed_token_amount(&spot_market)?.max(0) as u128,
+            Err(_) => 0,
+        };
+        // withdraw guard twaps are rolled forward to now, as the program does on withdraw
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(spot_market.last_twap_ts as i64);
+        let mut limits = calculate_withdraw_limits(&spot_market, now)?;
+        if can_bypass_withdraw_limits(&user_account, &spot_market, deposit_amount) {
+            limits.withdraw_limit = limits.withdraw_limit.max(deposit_amount);
+        }
+
+        calculate_max_spot_withdraw(
+            &spot_market,
+            limits,
+            deposit_amount,
+            oracle.data.price,
+            margin_info.get_free_collateral(),
+            margin_info.margin_requirement,
+        )
+    }
+}
+
+/// Market-wide limits on withdraws and borrows of a spot market
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SpotWithdrawLimits {
+    /// token amount that may be withdrawn from the market now
+    pub withdraw_limit: u128,
+    /// token amount that may be borrowed from the market now
+    pub borrow_limit: u128,
+}
+
+/// Calculate the withdraw and borrow limits imposed by `spot_market`'s withdraw guard
+///
+/// * `now` - current unix timestamp
+pub fn calculate_withdraw_limits(
+    spot_market: &SpotMarket,
+    now: i64,
+) -> SdkResult<SpotWithdrawLimits> {
+    let (deposits, borrows) = get_market_token_amounts(spot_market)?;
+    Ok(calculate_withdraw_limits_inner(
+        spot_market,
+        deposits,
+        borrows,
+        now,
+    ))
+}
+
+fn calculate_withdraw_limits_inner(
+    spot_market: &SpotMarket,
+    deposit_token_amount: u128,
+    borrow_token_amount: u128,
+    now: i64,
+) -> SpotWithdrawLimits {
+    const TWENTY_FOUR_HOURS: i64 = 86_400;
+    let withdraw_guard_threshold = spot_market.withdraw_guard_threshold as u128;
+
+    // roll the 24hr token twaps forward with current amounts
+    let since_last = now.saturating_sub(spot_market.last_twap_ts as i64).max(0) as u128;
+    let since_start = (TWENTY_FOUR_HOURS as u128).saturating_sub(since_last);
+    let twap_live = |twap: u64, current: u128| {
+        (twap as u128 * since_start + current * since_last) / (since_start + since_last).max(1)
+    };
+    let deposit_token_twap = twap_live(spot_market.deposit_token_twap, deposit_token_amount);
+    let borrow_token_twap = twap_live(spot_market.borrow_token_twap, borrow_token_amount);
+
+    // between ~15-80% utilization with friction on twap
+    let lesser_deposit_amount = deposit_token_amount.min(deposit_token_twap);
+    let max_borrow_tokens_twap = withdraw_guard_threshold.max(
+        (deposit_token_amount / 6)
+            .max(borrow_token_twap + lesser_deposit_amount / 10)
+            .min(deposit_token_amount.saturating_sub(lesser_deposit_amount / 5)),
+    );
+    let min_deposit_tokens_twap = deposit_token_twap.saturating_sub(
+        (deposit_token_twap / 4).max(withdraw_guard_threshold.min(deposit_token_twap)),
+    );
+
+    // immediate utilization limits
+    let utilization_twap = spot_market.utilization_twap as u128;
+    let max_utilization = (spot_market.optimal_utilization as u128)
+        .max(utilization_twap + SPOT_UTILIZATION_PRECISION.saturating_sub(utilization_twap) / 2);
+    let min_deposit_tokens_for_utilization = (borrow_token_amount * SPOT_UTILIZATION_PRECISION
+        / max_utilization.max(1))
+    .min(deposit_token_amount.saturating_sub(withdraw_guard_threshold));
+    let max_borrow_tokens_for_utilization =
+        max_utilization * deposit_token_amount / SPOT_UTILIZATION_PRECISION;
+
+    let min_deposit_tokens = min_deposit_tokens_for_utilization.max(min_deposit_tokens_twap);
+    let max_borrow_tokens = max_borrow_tokens_twap.min(max_borrow_tokens_for_utilization);
+
+    let withdraw_limit = deposit_token_amount.saturating_sub(min_deposit_tokens);
+    let mut borrow_limit = max_borrow_tokens
+        .saturating_sub(borrow_token_amount)
+        .min(deposit_token_amount.saturating_sub(borrow_token_amount));
+
+    if spot_market.max_token_borrows_fraction > 0 {
+        let max_token_borrows = spot_market.max_token_deposits as u128
+            * spot_market.max_token_borrows_fraction as u128
+            / 10_000;
+        borrow_limit = borrow_limit.min(max_token_borrows.saturating_sub(borrow_token_amount));
+    }
+
+    if withdraw_limit == 0 {
+        borrow_limit = 0;
+    }
+
+    SpotWithdrawLimits {
+        withdraw_limit,
+        borrow_limit,
+    }
+}
+
+/// Small deposits of users without net withdraws are not subject to market withdraw limits
+fn can_bypass_withdraw_limits(user: &User, spot_market: &SpotMarket, deposit_amount: u128) -> bool {
+    deposit_amount > 0
+        && deposit_amount <= spot_market.withdraw_guard_threshold as u128 / 10
+        && user.total_deposits >= user.total_withdraws
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct MaxSpotWithdraw {
+    /// token amount of deposits that may be withdrawn
+    withdrawable: u128,
+    /// token amount that may be borrowed after withdrawing all deposits
+    borrowable: u128,
+}
+
+fn calculate_max_spot_withdraw(
+    spot_market: &SpotMarket,
+    limits: SpotWithdrawLimits,
+    deposit_amount: u128,
+    oracle_price: i64,
+    free_collateral: u128,
+    margin_requirement: u128,
+) -> SdkResult<MaxSpotWithdraw> {
+    if oracle_price <= 0 {
+        return Err(SdkError::InvalidOracle);
+    }
+    let oracle_price = oracle_price as u128;
+    let token_precision = 10_u128.pow(spot_market.decimals);
+
+    let asset_weight = spot_market.get_asset_weight(
+        deposit_amount,
+        oracle_price as i64,
+        MarginRequirementType::Initial,
+    )? as u128;
+    let amount_withdrawable = if asset_weight == 0 || margin_requirement == 0 {
+        deposit_amount
+    } else {
+        // deposit amount that frees up all the user's free collateral
+        (free_collateral * SPOT_WEIGHT_PRECISION_U128)
+            .div_ceil(asset_weight)
+            .saturating_mul(token_precision)
+            .div_ceil(oracle_price)
+    };
+    let withdrawable = amount_withdrawable
+        .min(deposit_amount)
+        .min(limits.withdraw_limit);
+
+    // free collateral remaining once the deposit is fully withdrawn
+    let weighted_asset_value =
+        deposit_amount * oracle_price / token_precision * asset_weight / SPOT_WEIGHT_PRECISION_U128;
+    let free_collateral_after_withdraw = free_collateral.saturating_sub(weighted_asset_value);
+    if withdrawable < deposit_amount || free_collateral_after_withdraw == 0 {
+        return Ok(MaxSpotWithdraw {
+            withdrawable,
+            borrowable: 0,
+        });
+    }
+
+    let max_liability = |liability_weight: u128| {
+        free_collateral_after_withdraw * SPOT_WEIGHT_PRECISION_U128 / liability_weight.max(1)
+            * token_precision
+            / oracle_price
+    };
+    // liability weight scales with size (IMF), re-evaluate at the estimated borrow size
+    let base_liability_weight =
+        spot_market.get_liability_weight(0, MarginRequirementType::Initial)? as u128;
+    let estimated_liability = max_liability(base_liability_weight);
+    let liability_weight = spot_market
+        .get_liability_weight(estimated_liability, MarginRequirementType::Initial)?
+        as u128;
+
+    Ok(MaxSpotWithdraw {
+        withdrawable,
+        borrowable: max_liability(liability_weight).min(limits.borrow_limit),
+    })
+}
+
 #[inline]
 pub fn calculate_perp_liability_value(
     base_asset_amount: i64,
@@ -212,7 +474,106 @@ pub fn calculate_perp_liability_value(
 
 #[cfg(test)]
 mod tests {
-    use super::calculate_perp_liability_value;
+    use super::{
+        calculate_max_spot_withdraw, calculate_perp_liability_value,
+        calculate_withdraw_limits_inner, MaxSpotWithdraw, SpotWithdrawLimits,
+    };
+    use crate::accounts::SpotMarket;
+
+    fn usdc_market() -> SpotMarket {
+        SpotMarket {
+            decimals: 6,
+            initial_asset_weight: 10_000,
+            maintenance_asset_weight: 10_000,
+            initial_liability_weight: 10_000,
+            maintenance_liability_weight: 10_000,
+            optimal_utilization: 800_000,
+            utilization_twap: 400_000,
+            deposit_token_twap: 1_000_000_000,
+            borrow_token_twap: 400_000_000,
+            withdraw_guard_threshold: 10_000_000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn calculate_withdraw_limits_works() {
+        let market = usdc_market();
+        assert_eq!(
+            calculate_withdraw_limits_inner(&market, 1_000_000_000, 400_000_000, 0),
+            SpotWithdrawLimits {
+                withdraw_limit: 250_000_000,
+                borrow_limit: 100_000_000,
+            }
+        );
+
+        let market = SpotMarket {
+            max_token_deposits: 1_000_000_000,
+            max_token_borrows_fraction: 4_500, // 45%
+            ..usdc_market()
+        };
+        assert_eq!(
+            calculate_withdraw_limits_inner(&market, 1_000_000_000, 400_000_000, 0),
+            SpotWithdrawLimits {
+                withdraw_limit: 250_000_000,
+                borrow_limit: 50_000_000,
+            }
+        );
+    }
+
+    #[test]
+    fn calculate_max_spot_withdraw_works() {
+        let market = usdc_market();
+        let limits = SpotWithdrawLimits {
+            withdraw_limit: u64::MAX as u128,
+            borrow_limit: u64::MAX as u128,
+        };
+        let price = 1_000_000;
+
+        // free collateral limits the withdraw
+        assert_eq!(
+            calculate_max_spot_withdraw(&market, limits, 100_000_000, price, 50_000_000, 1)
+                .unwrap(),
+            MaxSpotWithdraw {
+                withdrawable: 50_000_000,
+                borrowable: 0,
+            }
+        );
+        // withdraw all, borrow the remaining free collateral
+        assert_eq!(
+            calculate_max_spot_withdraw(&market, limits, 100_000_000, price, 150_000_000, 1)
+                .unwrap(),
+            MaxSpotWithdraw {
+                withdrawable: 100_000_000,
+                borrowable: 50_000_000,
+            }
+        );
+        // market limits
+        let limits = SpotWithdrawLimits {
+            withdraw_limit: 100_000_000,
+            borrow_limit: 10_000_000,
+        };
+        assert_eq!(
+            calculate_max_spot_withdraw(&market, limits, 100_000_000, price, 150_000_000, 1)
+                .unwrap(),
+            MaxSpotWithdraw {
+                withdrawable: 100_000_000,
+                borrowable: 10_000_000,
+            }
+        );
+        // no margin requirement, whole deposit withdrawable
+        let limits = SpotWithdrawLimits {
+            withdraw_limit: 30_000_000,
+            borrow_limit: 0,
+        };
+        assert_eq!(
+            calculate_max_spot_withdraw(&market, limits, 100_000_000, price, 0, 0).unwrap(),
+            MaxSpotWithdraw {
+                withdrawable: 30_000_000,
+                borrowable: 0,
+            }
+        );
+    }
 
     #[test]
     fn calculate_perp_liability_value_works() {
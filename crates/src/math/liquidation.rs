@@ -13,9 +13,9 @@ use crate::{
     math::{
         account_list_builder::AccountsListBuilder,
         constants::{
-            AMM_RESERVE_PRECISION_I128, BASE_PRECISION_I128, LIQUIDATION_PCT_PRECISION,
-            MARGIN_PRECISION, QUOTE_PRECISION, QUOTE_PRECISION_I128, QUOTE_PRECISION_I64,
-            SPOT_WEIGHT_PRECISION,
+            AMM_RESERVE_PRECISION_I128, BASE_PRECISION_I128, LIQUIDATION_FEE_PRECISION,
+            LIQUIDATION_FEE_PRECISION_U128, LIQUIDATION_PCT_PRECISION, MARGIN_PRECISION,
+            QUOTE_PRECISION, QUOTE_PRECISION_I128, QUOTE_PRECISION_I64, SPOT_WEIGHT_PRECISION,
         },
     },
     types::{
@@ -359,6 +359,130 @@ pub fn calculate_max_pct_to_liquidate(
     Ok((margin_freeable * LIQUIDATION_PCT_PRECISION) / margin_shortage)
 }
 
+/// Amounts transferable in a spot liquidation
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SpotLiquidationSize {
+    /// asset tokens paid to the liquidator
+    pub asset_amount: u64,
+    /// liability tokens repaid by the liquidator
+    pub liability_amount: u64,
+    /// asset value multiplier, 1 + asset market liquidator fee (LIQUIDATION_FEE_PRECISION)
+    pub asset_liquidation_multiplier: u32,
+    /// liability value multiplier, 1 - liability market liquidator fee (LIQUIDATION_FEE_PRECISION)
+    pub liability_liquidation_multiplier: u32,
+}
+
+/// Calculate the max asset and liability amounts transferable when liquidating a user's
+/// spot liability for a spot asset
+///
+/// The repaid liability covers `max_pct_to_liquidate` of the margin shortage, bounded by the user's
+/// liability and asset balances. As in the program, the liquidator receives the asset at a premium
+/// of the asset market's liquidator fee and repays the liability at a discount of the liability
+/// market's liquidator fee.
+///
+/// * `margin_shortage` - user's maintenance margin shortage (QUOTE_PRECISION)
+/// * `max_pct_to_liquidate` - see `calculate_max_pct_to_liquidate` (LIQUIDATION_PCT_PRECISION)
+/// * `asset_price`, `liability_price` - oracle prices (PRICE_PRECISION)
+/// * `asset_token_amount`, `liability_token_amount` - user's deposit and borrow token amounts
+pub fn calculate_spot_liquidation_size(
+    margin_shortage: u128,
+    max_pct_to_liquidate: u128,
+    asset_market: &SpotMarket,
+    asset_price: i64,
+    asset_token_amount: u128,
+    liability_market: &SpotMarket,
+    liability_price: i64,
+    liability_token_amount: u128,
+) -> SdkResult<SpotLiquidationSize> {
+    if asset_price <= 0 || liability_price <= 0 {
+        return Err(SdkError::MathError("invalid oracle price"));
+    }
+    if liability_market.liquidator_fee >= LIQUIDATION_FEE_PRECISION {
+        return Err(SdkError::MathError("invalid liquidator fee"));
+    }
+    let asset_liquidation_multiplier = LIQUIDATION_FEE_PRECISION + asset_market.liquidator_fee;
+    let liability_liquidation_multiplier =
+        LIQUIDATION_FEE_PRECISION - liability_market.liquidator_fee;
+    let (asset_multiplier, liability_multiplier) = (
+        asset_liquidation_multiplier as u128,
+        liability_liquidation_multiplier as u128,
+    );
+
+    // margin freed per unit of liability value repaid
+    let freed_weight = (liability_market.maintenance_liability_weight as u128
+        * LIQUIDATION_FEE_PRECISION_U128
+            .saturating_sub(liability_market.if_liquidation_fee as u128))
+    .saturating_sub(
+        asset_market.maintenance_asset_weight as u128
+            * LIQUIDATION_FEE_PRECISION_U128
+            * asset_multiplier
+            / liability_multiplier,
+    );
+
+    let margin_to_free = margin_shortage * max_pct_to_liquidate.min(LIQUIDATION_PCT_PRECISION)
+        / LIQUIDATION_PCT_PRECISION;
+    let liability_value = if freed_weight == 0 {
+        u128::MAX
+    } else {
+        margin_to_free
+            .saturating_mul(SPOT_WEIGHT_PRECISION as u128 * LIQUIDATION_FEE_PRECISION_U128)
+            / freed_weight
+    };
+
+    let mut liability_amount =
+        token_amount_from_value(liability_value, liability_market.decimals, liability_price)
+            .min(liability_token_amount);
+    let mut asset_amount = token_amount_from_value(
+        token_value(liability_amount, liability_market.decimals, liability_price)
+            .saturating_mul(asset_multiplier)
+            / liability_multiplier,
+        asset_market.decimals,
+        asset_price,
+    );
+
+    if asset_amount > asset_token_amount {
+        asset_amount = asset_token_amount;
+        liability_amount = token_amount_from_value(
+            token_value(asset_amount, asset_market.decimals, asset_price)
+                .saturating_mul(liability_multiplier)
+                / asset_multiplier,
+            liability_market.decimals,
+            liability_price,
+        );
+    }
+
+    Ok(SpotLiquidationSize {
+        asset_amount: asset_amount.min(u64::MAX as u128) as u64,
+        liability_amount: liability_amount.min(u64::MAX as u128) as u64,
+        asset_liquidation_multiplier,
+        liability_liquidation_multiplier,
+    })
+}
+
+/// Calculate the liquidator's profit from a spot liquidation with swap (QUOTE_PRECISION)
+///
+/// The liquidator swaps the received `size.asset_amount` for at least `min_swap_out` liability
+/// tokens, profit is the value of the liability tokens left after repaying `size.liability_amount`
+pub fn calculate_spot_liquidation_swap_profit(
+    size: &SpotLiquidationSize,
+    min_swap_out: u64,
+    liability_market: &SpotMarket,
+    liability_price: i64,
+) -> i128 {
+    (min_swap_out as i128 - size.liability_amount as i128) * liability_price as i128
+        / 10_i128.pow(liability_market.decimals)
+}
+
+/// Value of `token_amount` (QUOTE_PRECISION)
+fn token_value(token_amount: u128, decimals: u32, price: i64) -> u128 {
+    token_amount.saturating_mul(price as u128) / 10_u128.pow(decimals)
+}
+
+/// Token amount worth `value` (QUOTE_PRECISION)
+fn token_amount_from_value(value: u128, decimals: u32, price: i64) -> u128 {
+    value.saturating_mul(10_u128.pow(decimals)) / price as u128
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct CollateralInfo {
     /// total collateral (QUOTE_PRECISION)
@@ -409,9 +533,9 @@ mod tests {
         },
         drift_idl::types::{HistoricalOracleData, MarketStatus, OracleSource, SpotPosition, AMM},
         math::constants::{
-            AMM_RESERVE_PRECISION, BASE_PRECISION_I64, LIQUIDATION_FEE_PRECISION, PEG_PRECISION,
-            PRICE_PRECISION_I64, SPOT_BALANCE_PRECISION, SPOT_BALANCE_PRECISION_U64,
-            SPOT_CUMULATIVE_INTEREST_PRECISION,
+            AMM_RESERVE_PRECISION, BASE_PRECISION_I64, LAMPORTS_PER_SOL_I64,
+            LIQUIDATION_FEE_PRECISION, PEG_PRECISION, PRICE_PRECISION_I64, SPOT_BALANCE_PRECISION,
+            SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION,
         },
         utils::test_utils::*,
         MarketId,
@@ -931,4 +1055,122 @@ mod tests {
         // entry at $80, upnl at $100
         assert_eq!(unrealized_pnl, 20_i128 * QUOTE_PRECISION_I64 as i128);
     }
+
+    #[test]
+    fn spot_liquidation_size() {
+        let sol = sol_spot_market();
+        let usdc = SpotMarket {
+            maintenance_liability_weight: SPOT_WEIGHT_PRECISION,
+            ..usdc_spot_market()
+        };
+        let sol_price = 100 * PRICE_PRECISION_I64;
+        let usdc_price = PRICE_PRECISION_I64;
+        let shortage = 100 * QUOTE_PRECISION;
+
+        // repay enough liability to cover the shortage
+        let size = calculate_spot_liquidation_size(
+            shortage,
+            LIQUIDATION_PCT_PRECISION,
+            &sol,
+            sol_price,
+            (20 * LAMPORTS_PER_SOL_I64) as u128,
+            &usdc,
+            usdc_price,
+            2_000 * QUOTE_PRECISION,
+        )
+        .unwrap();
+        assert_eq!(
+            size,
+            SpotLiquidationSize {
+                asset_amount: 10_100_908_160,
+                liability_amount: 1_009_081_735,
+                asset_liquidation_multiplier: LIQUIDATION_FEE_PRECISION + 1_000,
+                liability_liquidation_multiplier: LIQUIDATION_FEE_PRECISION,
+            }
+        );
+
+        // liability fee discounts the repaid liability, the asset fee is a premium on the asset
+        let usdc_with_fee = SpotMarket {
+            liquidator_fee: 2 * LIQUIDATION_FEE_PRECISION / 1000,
+            ..usdc
+        };
+        let size = calculate_spot_liquidation_size(
+            shortage,
+            LIQUIDATION_PCT_PRECISION,
+            &sol,
+            sol_price,
+            (20 * LAMPORTS_PER_SOL_I64) as u128,
+            &usdc_with_fee,
+            usdc_price,
+            2_000 * QUOTE_PRECISION,
+        )
+        .unwrap();
+        assert_eq!(size.asset_amount, 10_308_959_820);
+        assert_eq!(size.liability_amount, 1_027_806_384);
+        assert_eq!(size.liability_liquidation_multiplier, 998_000);
+        let size = calculate_spot_liquidation_size(
+            shortage,
+            LIQUIDATION_PCT_PRECISION,
+            &sol,
+            sol_price,
+            (5 * LAMPORTS_PER_SOL_I64) as u128,
+            &usdc_with_fee,
+            usdc_price,
+            2_000 * QUOTE_PRECISION,
+        )
+        .unwrap();
+        assert_eq!(size.asset_amount, 5_000_000_000);
+        assert_eq!(size.liability_amount, 498_501_498);
+
+        // half the shortage
+        let half = calculate_spot_liquidation_size(
+            shortage,
+            LIQUIDATION_PCT_PRECISION / 2,
+            &sol,
+            sol_price,
+            (20 * LAMPORTS_PER_SOL_I64) as u128,
+            &usdc,
+            usdc_price,
+            2_000 * QUOTE_PRECISION,
+        )
+        .unwrap();
+        assert_eq!(half.liability_amount, 504_540_867);
+
+        // bounded by liability
+        let size = calculate_spot_liquidation_size(
+            shortage,
+            LIQUIDATION_PCT_PRECISION,
+            &sol,
+            sol_price,
+            (20 * LAMPORTS_PER_SOL_I64) as u128,
+            &usdc,
+            usdc_price,
+            300 * QUOTE_PRECISION,
+        )
+        .unwrap();
+        assert_eq!(size.liability_amount, 300_000_000);
+        assert_eq!(size.asset_amount, 3_003_000_000);
+
+        // bounded by asset
+        let size = calculate_spot_liquidation_size(
+            shortage,
+            LIQUIDATION_PCT_PRECISION,
+            &sol,
+            sol_price,
+            (5 * LAMPORTS_PER_SOL_I64) as u128,
+            &usdc,
+            usdc_price,
+            2_000 * QUOTE_PRECISION,
+        )
+        .unwrap();
+        assert_eq!(size.asset_amount, 5_000_000_000);
+        assert_eq!(size.liability_amount, 499_500_499);
+
+        // swap output covering the repaid liability is profitable
+        assert_eq!(
+            calculate_spot_liquidation_swap_profit(&size, 500_500_499, &usdc, usdc_price),
+            QUOTE_PRECISION as i128
+        );
+        assert!(calculate_spot_liquidation_swap_profit(&size, 499_000_000, &usdc, usdc_price) < 0);
+    }
 }
@@ -0,0 +1,276 @@
+//! LP pool (constituents) math
+//!
+//! Constituent weights, LP token price and the fee quotes of the `ViewLpPool*Fees` ixs.
+//! Mint, redeem and swap fees depend on the pool's correlations, volatility and target delays,
+//! quotes are taken from the program by simulating the view ixs
+//! (see `DriftClient::quote_lp_pool_swap`)
+use crate::{
+    math::constants::{PERCENTAGE_PRECISION_I128, PRICE_PRECISION},
+    types::{
+        accounts::{Constituent, LPPool},
+        SdkError, SdkResult,
+    },
+};
+
+const PROGRAM_LOG: &str = "Program log: ";
+
+/// Constituent state required to price LP pool trades
+#[derive(Clone, Copy, Debug)]
+pub struct ConstituentState {
+    pub constituent: Constituent,
+    /// net token balance held by the pool i.e. vault + spot balance (constituent decimals)
+    pub token_amount: i128,
+    /// oracle price of the constituent token (PRICE_PRECISION)
+    pub price: i64,
+    /// target weight of the constituent (PERCENTAGE_PRECISION)
+    pub target_weight: i64,
+}
+
+impl ConstituentState {
+    /// Weight of the constituent in a pool worth `aum` after a token balance change of `delta`
+    /// (PERCENTAGE_PRECISION)
+    pub fn weight(&self, delta: i128, aum: u128) -> i64 {
+        if aum == 0 {
+            return 0;
+        }
+        (self.value(self.token_amount + delta) * PERCENTAGE_PRECISION_I128 / aum as i128) as i64
+    }
+
+    /// Value of `token_amount` constituent tokens (QUOTE_PRECISION)
+    fn value(&self, token_amount: i128) -> i128 {
+        token_amount * self.price as i128 / 10_i128.pow(self.constituent.decimals as u32)
+    }
+}
+
+/// Target weight of a constituent with `target_base` tokens in a pool worth `aum`
+/// (PERCENTAGE_PRECISION)
+///
+/// * `target_base` - target token balance from `ConstituentTargetBase` (constituent decimals)
+/// * `price` - constituent token price (PRICE_PRECISION)
+/// * `decimals` - constituent token decimals
+pub fn calculate_target_weight(target_base: i64, price: i64, decimals: u32, aum: u128) -> i64 {
+    if aum == 0 {
+        return 0;
+    }
+    (target_base as i128 * price as i128 / 10_i128.pow(decimals) * PERCENTAGE_PRECISION_I128
+        / aum as i128) as i64
+}
+
+/// LP tokens minted by adding liquidity, as quoted by `ViewLpPoolAddLiquidityFees`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LpMintQuote {
+    /// LP tokens received, after fees
+    pub lp_amount: u64,
+    /// constituent tokens deposited (constituent decimals)
+    pub in_amount: u128,
+    /// LP tokens charged as mint fee
+    pub lp_fee_amount: i64,
+    /// constituent tokens charged as fee (constituent decimals)
+    pub in_fee_amount: i128,
+}
+
+impl LpMintQuote {
+    /// Parse the quote from the logs of a simulated `ViewLpPoolAddLiquidityFees` ix
+    pub fn from_view_logs(logs: &[String]) -> SdkResult<Self> {
+        let [lp_amount, in_amount, lp_fee_amount, in_fee_amount] = parse_view_logs(
+            logs,
+            ["lp_amount", "in_amount", "lp_fee_amount", "in_fee_amount"],
+        )?;
+        Ok(Self {
+            lp_amount: narrow(lp_amount)?,
+            in_amount: narrow(in_amount)?,
+            lp_fee_amount: narrow(lp_fee_amount)?,
+            in_fee_amount,
+        })
+    }
+}
+
+/// Constituent tokens returned by removing liquidity, as quoted by `ViewLpPoolRemoveLiquidityFees`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LpRedeemQuote {
+    /// LP tokens burned
+    pub lp_burn_amount: u64,
+    /// constituent tokens received, after fees (constituent decimals)
+    pub out_amount: u128,
+    /// LP tokens charged as burn fee
+    pub lp_fee_amount: i64,
+    /// constituent tokens charged as fee (constituent decimals)
+    pub out_fee_amount: i128,
+}
+
+impl LpRedeemQuote {
+    /// Parse the quote from the logs of a simulated `ViewLpPoolRemoveLiquidityFees` ix
+    pub fn from_view_logs(logs: &[String]) -> SdkResult<Self> {
+        let [lp_burn_amount, out_amount, lp_fee_amount, out_fee_amount] = parse_view_logs(
+            logs,
+            [
+                "lp_burn_amount",
+                "out_amount",
+                "lp_fee_amount",
+                "out_fee_amount",
+            ],
+        )?;
+        Ok(Self {
+            lp_burn_amount: narrow(lp_burn_amount)?,
+            out_amount: narrow(out_amount)?,
+            lp_fee_amount: narrow(lp_fee_amount)?,
+            out_fee_amount,
+        })
+    }
+}
+
+/// Constituent tokens returned by an LP pool swap, as quoted by `ViewLpPoolSwapFees`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LpSwapQuote {
+    /// in constituent tokens sold (in constituent decimals)
+    pub in_amount: u64,
+    /// out constituent tokens received, after fees (out constituent decimals)
+    pub out_amount: u64,
+    /// in constituent tokens charged as fee (in constituent decimals)
+    pub in_fee_amount: i128,
+    /// out constituent tokens charged as fee (out constituent decimals)
+    pub out_fee_amount: i128,
+}
+
+impl LpSwapQuote {
+    /// Parse the quote from the logs of a simulated `ViewLpPoolSwapFees` ix
+    pub fn from_view_logs(logs: &[String]) -> SdkResult<Self> {
+        let [in_amount, out_amount, in_fee_amount, out_fee_amount] =
+            parse_view_logs(logs, ["in_amount", "out_amount", "in_fee", "out_fee"])?;
+        Ok(Self {
+            in_amount: narrow(in_amount)?,
+            out_amount: narrow(out_amount)?,
+            in_fee_amount,
+            out_fee_amount,
+        })
+    }
+}
+
+/// Values of `keys` from the last program log of the form `key: value, key: value, ..`
+fn parse_view_logs<const N: usize>(logs: &[String], keys: [&str; N]) -> SdkResult<[i128; N]> {
+    logs.iter()
+        .rev()
+        .filter_map(|log| log.strip_prefix(PROGRAM_LOG))
+        .find_map(|log| {
+            let fields: Vec<(&str, &str)> = log
+                .split(", ")
+                .filter_map(|field| field.split_once(": "))
+                .collect();
+            let mut values = [0_i128; N];
+            for (value, key) in values.iter_mut().zip(keys) {
+                let (_, field) = fields.iter().find(|(name, _)| name.trim() == key)?;
+                *value = field.trim().parse().ok()?;
+            }
+            Some(values)
+        })
+        .ok_or_else(|| SdkError::Generic(format!("view ix logs missing quote: {keys:?}")))
+}
+
+/// Narrow a quoted amount to its field type
+fn narrow<T: TryFrom<i128>>(value: i128) -> SdkResult<T> {
+    T::try_from(value).map_err(|_| SdkError::MathError("lp pool quote overflow"))
+}
+
+/// Value of one LP token (PRICE_PRECISION)
+pub fn calculate_lp_token_price(lp_pool: &LPPool) -> u128 {
+    if lp_pool.token_supply == 0 {
+        return 0;
+    }
+    lp_pool.last_aum * PRICE_PRECISION / lp_pool.token_supply as u128
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::constants::{LAMPORTS_PER_SOL_I64, QUOTE_PRECISION_I64};
+
+    fn logs(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn lp_pool_weights() {
+        let lp_pool = LPPool {
+            last_aum: 1_000_000 * QUOTE_PRECISION_I64 as u128,
+            token_supply: 1_000_000 * QUOTE_PRECISION_I64 as u64,
+            ..Default::default()
+        };
+        let usdc = ConstituentState {
+            constituent: Constituent {
+                decimals: 6,
+                ..Default::default()
+            },
+            token_amount: 500_000 * QUOTE_PRECISION_I64 as i128,
+            price: 1_000_000,
+            target_weight: 500_000,
+        };
+        assert_eq!(usdc.weight(0, lp_pool.last_aum), 500_000);
+        assert_eq!(
+            usdc.weight(100_000 * QUOTE_PRECISION_I64 as i128, lp_pool.last_aum),
+            600_000
+        );
+        assert_eq!(
+            calculate_target_weight(
+                2_000 * LAMPORTS_PER_SOL_I64,
+                150_000_000,
+                9,
+                lp_pool.last_aum
+            ),
+            300_000
+        );
+        assert_eq!(calculate_lp_token_price(&lp_pool), 1_000_000);
+    }
+
+    #[test]
+    fn lp_pool_quotes_from_view_logs() {
+        let quote = LpSwapQuote::from_view_logs(&logs(&[
+            "Program dRiftyHA39MWEi3m9aunc5MzRF1JYuBsbn6VPcn33UH invoke [1]",
+            "Program log: Instruction: ViewLpPoolSwapFees",
+            "Program log: in_amount: 10000000000, out_amount: 1497815223, in_fee: 3150000, out_fee: -41872",
+            "Program dRiftyHA39MWEi3m9aunc5MzRF1JYuBsbn6VPcn33UH success",
+        ]))
+        .unwrap();
+        assert_eq!(
+            quote,
+            LpSwapQuote {
+                in_amount: 10_000_000_000,
+                out_amount: 1_497_815_223,
+                in_fee_amount: 3_150_000,
+                out_fee_amount: -41_872,
+            }
+        );
+
+        let quote = LpMintQuote::from_view_logs(&logs(&[
+            "Program log: Instruction: ViewLpPoolAddLiquidityFees",
+            "Program log: lp_amount: 99871344016, in_amount: 100000000000, lp_fee_amount: 9987134, in_fee_amount: 118654",
+        ]))
+        .unwrap();
+        assert_eq!(
+            quote,
+            LpMintQuote {
+                lp_amount: 99_871_344_016,
+                in_amount: 100_000_000_000,
+                lp_fee_amount: 9_987_134,
+                in_fee_amount: 118_654,
+            }
+        );
+
+        let quote = LpRedeemQuote::from_view_logs(&logs(&[
+            "Program log: Instruction: ViewLpPoolRemoveLiquidityFees",
+            "Program log: lp_burn_amount: 1000000000, out_amount: 999762411, lp_fee_amount: 0, out_fee_amount: 237589",
+        ]))
+        .unwrap();
+        assert_eq!(quote.out_amount, 999_762_411);
+        assert_eq!(quote.out_fee_amount, 237_589);
+
+        // missing or malformed quote
+        assert!(LpSwapQuote::from_view_logs(&logs(&[
+            "Program log: Instruction: ViewLpPoolSwapFees"
+        ]))
+        .is_err());
+        assert!(LpSwapQuote::from_view_logs(&logs(&[
+            "Program log: in_amount: 1, out_amount: -1, in_fee: 0, out_fee: 0"
+        ]))
+        .is_err());
+    }
+}
@@ -15,9 +15,13 @@ use crate::{
 pub mod account_list_builder;
 pub mod auction;
 pub mod constants;
+pub mod fees;
+pub mod funding;
 pub mod leverage;
 pub mod liquidation;
+pub mod lp_pool;
 pub mod order;
+pub mod spot_interest;
 pub mod tiers;
 
 #[derive(Clone, Copy, Debug)]
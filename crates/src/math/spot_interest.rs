@@ -0,0 +1,343 @@
+//! Spot market interest rate model
+//!
+//! Mirrors the program's borrow rate curve and interest accrual so deposit/borrow rates can be
+//! quoted (and projected for a hypothetical deposit/borrow) from a `SpotMarket` account
+use crate::{
+    ffi,
+    math::constants::{
+        ONE_YEAR, PERCENTAGE_PRECISION, SPOT_RATE_PRECISION, SPOT_UTILIZATION_PRECISION,
+    },
+    types::{accounts::SpotMarket, SdkError, SdkResult, SpotBalanceType, SpotPosition},
+};
+
+/// Snapshot of a spot market's interest rates
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SpotInterestRates {
+    /// total borrows / total deposits, expo = -6
+    pub utilization: u128,
+    /// annual borrow rate, expo = -6
+    pub borrow_rate: u128,
+    /// annual deposit rate net of insurance fund share, expo = -6
+    pub deposit_rate: u128,
+}
+
+impl SpotInterestRates {
+    /// Borrow APY assuming continuous compounding, expo = -6
+    pub fn borrow_apy(&self) -> u128 {
+        calculate_apy(self.borrow_rate)
+    }
+    /// Deposit APY assuming continuous compounding, expo = -6
+    pub fn deposit_apy(&self) -> u128 {
+        calculate_apy(self.deposit_rate)
+    }
+}
+
+/// Calculate utilization from deposit and borrow token amounts, expo = -6
+pub fn calculate_utilization(deposit_token_amount: u128, borrow_token_amount: u128) -> u128 {
+    if borrow_token_amount == 0 {
+        0
+    } else if deposit_token_amount == 0 {
+        SPOT_UTILIZATION_PRECISION
+    } else {
+        borrow_token_amount * SPOT_UTILIZATION_PRECISION / deposit_token_amount
+    }
+}
+
+/// Minimum annual borrow rate of `spot_market`, expo = -6
+///
+/// `min_borrow_rate` is stored in 0.5% increments
+pub fn calculate_min_borrow_rate(spot_market: &SpotMarket) -> u128 {
+    spot_market.min_borrow_rate as u128 * (PERCENTAGE_PRECISION / 200)
+}
+
+/// Calculate the annual borrow rate of `spot_market` at `utilization`, expo = -6
+///
+/// Rate increases linearly to `optimal_borrow_rate` at `optimal_utilization` then linearly to
+/// `max_borrow_rate` at 100% utilization
+pub fn calculate_borrow_rate(spot_market: &SpotMarket, utilization: u128) -> u128 {
+    let optimal_utilization = spot_market.optimal_utilization as u128;
+    let optimal_borrow_rate = spot_market.optimal_borrow_rate as u128;
+    let max_borrow_rate = spot_market.max_borrow_rate as u128;
+
+    let borrow_rate = if utilization > optimal_utilization {
+        let surplus_utilization = utilization - optimal_utilization;
+        let borrow_rate_slope = max_borrow_rate.saturating_sub(optimal_borrow_rate)
+            * SPOT_UTILIZATION_PRECISION
+            / (SPOT_UTILIZATION_PRECISION.saturating_sub(optimal_utilization)).max(1);
+        optimal_borrow_rate + surplus_utilization * borrow_rate_slope / SPOT_UTILIZATION_PRECISION
+    } else {
+        let borrow_rate_slope =
+            optimal_borrow_rate * SPOT_UTILIZATION_PRECISION / optimal_utilization.max(1);
+        utilization * borrow_rate_slope / SPOT_UTILIZATION_PRECISION
+    };
+
+    borrow_rate.max(calculate_min_borrow_rate(spot_market))
+}
+
+/// Calculate the annual deposit rate of `spot_market` given `utilization` and `borrow_rate`, expo = -6
+///
+/// Interest paid by borrowers is shared by depositors less the insurance fund's `total_factor`
+pub fn calculate_deposit_rate(
+    spot_market: &SpotMarket,
+    utilization: u128,
+    borrow_rate: u128,
+) -> u128 {
+    let depositor_share =
+        PERCENTAGE_PRECISION.saturating_sub(spot_market.insurance_fund.total_factor as u128);
+    borrow_rate * utilization / SPOT_UTILIZATION_PRECISION * depositor_share / PERCENTAGE_PRECISION
+}
+
+/// Calculate rates from deposit and borrow token amounts
+pub fn calculate_interest_rates_for_token_amounts(
+    spot_market: &SpotMarket,
+    deposit_token_amount: u128,
+    borrow_token_amount: u128,
+) -> SpotInterestRates {
+    let utilization = calculate_utilization(deposit_token_amount, borrow_token_amount);
+    let borrow_rate = calculate_borrow_rate(spot_market, utilization);
+    SpotInterestRates {
+        utilization,
+        borrow_rate,
+        deposit_rate: calculate_deposit_rate(spot_market, utilization, borrow_rate),
+    }
+}
+
+/// Return the (deposit, borrow) token amounts of `spot_market`
+pub fn get_market_token_amounts(spot_market: &SpotMarket) -> SdkResult<(u128, u128)> {
+    let deposits = ffi::get_token_amount(
+        spot_market.deposit_balance.as_u128(),
+        spot_market,
+        SpotBalanceType::Deposit,
+    )?;
+    let borrows = ffi::get_token_amount(
+        spot_market.borrow_balance.as_u128(),
+        spot_market,
+        SpotBalanceType::Borrow,
+    )?;
+
+    Ok((deposits, borrows))
+}
+
+/// Calculate current rates of `spot_market`
+pub fn calculate_interest_rates(spot_market: &SpotMarket) -> SdkResult<SpotInterestRates> {
+    calculate_interest_rates_with_delta(spot_market, 0, 0)
+}
+
+/// Calculate rates of `spot_market` after a hypothetical change in deposits and/or borrows
+///
+/// * `deposit_delta` - change in deposit token amount, positive: deposit, negative: withdraw
+/// * `borrow_delta` - change in borrow token amount, positive: borrow, negative: repay
+///
+/// e.g. borrowing 100 tokens from an account with no deposit is `(0, 100)`
+pub fn calculate_interest_rates_with_delta(
+    spot_market: &SpotMarket,
+    deposit_delta: i128,
+    borrow_delta: i128,
+) -> SdkResult<SpotInterestRates> {
+    let (deposits, borrows) = get_market_token_amounts(spot_market)?;
+    let deposits = deposits
+        .checked_add_signed(deposit_delta)
+        .ok_or(SdkError::MathError("deposit delta exceeds deposits"))?;
+    let borrows = borrows
+        .checked_add_signed(borrow_delta)
+        .ok_or(SdkError::MathError("borrow delta exceeds borrows"))?;
+
+    Ok(calculate_interest_rates_for_token_amounts(
+        spot_market,
+        deposits,
+        borrows,
+    ))
+}
+
+/// Convert an annual rate to APY assuming continuous compounding, expo = -6
+pub fn calculate_apy(apr: u128) -> u128 {
+    let apr = apr as f64 / PERCENTAGE_PRECISION as f64;
+    (apr.exp_m1() * PERCENTAGE_PRECISION as f64) as u128
+}
+
+/// Calculate the (deposit, borrow) cumulative interest of `spot_market` accrued up to `now`
+///
+/// i.e. the values the program would write on its next interest update, deposit interest is net of
+/// the insurance fund's share, expo = -10
+pub fn calculate_accumulated_interest(
+    spot_market: &SpotMarket,
+    now: i64,
+) -> SdkResult<(u128, u128)> {
+    let cumulative_deposit_interest = spot_market.cumulative_deposit_interest.as_u128();
+    let cumulative_borrow_interest = spot_market.cumulative_borrow_interest.as_u128();
+    let time_since_last_update = now.saturating_sub(spot_market.last_interest_ts as i64);
+    if time_since_last_update <= 0 {
+        return Ok((cumulative_deposit_interest, cumulative_borrow_interest));
+    }
+
+    let rates = calculate_interest_rates(spot_market)?;
+    let modified_borrow_rate = rates.borrow_rate * time_since_last_update as u128;
+    let modified_deposit_rate =
+        modified_borrow_rate * rates.utilization / SPOT_UTILIZATION_PRECISION;
+
+    let borrow_interest =
+        cumulative_borrow_interest * modified_borrow_rate / ONE_YEAR / SPOT_RATE_PRECISION + 1;
+    let deposit_interest =
+        cumulative_deposit_interest * modified_deposit_rate / ONE_YEAR / SPOT_RATE_PRECISION;
+    let deposit_interest_for_stakers =
+        deposit_interest * spot_market.insurance_fund.total_factor as u128 / PERCENTAGE_PRECISION;
+    let deposit_interest = deposit_interest - deposit_interest_for_stakers;
+
+    Ok((
+        cumulative_deposit_interest + deposit_interest,
+        cumulative_borrow_interest + borrow_interest,
+    ))
+}
+
+/// Project interest accrued by a `token_amount` over `seconds` at current `rates` (simple interest)
+///
+/// * `token_amount` - signed token amount, positive: deposit, negative: borrow
+///
+/// Returns signed token amount of interest, positive: earned, negative: owed
+pub fn project_interest(rates: &SpotInterestRates, token_amount: i128, seconds: i64) -> i128 {
+    if seconds <= 0 || token_amount == 0 {
+        return 0;
+    }
+    let rate = if token_amount > 0 {
+        rates.deposit_rate
+    } else {
+        rates.borrow_rate
+    };
+
+    token_amount * rate as i128 * seconds as i128 / ONE_YEAR as i128 / SPOT_RATE_PRECISION as i128
+}
+
+/// Project interest accrued by `position` in `spot_market` over `seconds` at current rates
+///
+/// Returns signed token amount of interest, positive: earned, negative: owed
+pub fn project_position_interest(
+    spot_market: &SpotMarket,
+    position: &SpotPosition,
+    seconds: i64,
+) -> SdkResult<i128> {
+    if position.market_index != spot_market.market_index {
+        return Err(SdkError::Generic(format!(
+            "position market {} != market {}",
+            position.market_index, spot_market.market_index
+        )));
+    }
+    let token_amount = position.get_signed_token_amount(spot_market)?;
+    let rates = calculate_interest_rates(spot_market)?;
+
+    Ok(project_interest(&rates, token_amount, seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::constants::{SPOT_BALANCE_PRECISION, SPOT_CUMULATIVE_INTEREST_PRECISION},
+        types::InsuranceFund,
+    };
+
+    fn usdc_market() -> SpotMarket {
+        SpotMarket {
+            decimals: 6,
+            optimal_utilization: 800_000, // 80%
+            optimal_borrow_rate: 100_000, // 10%
+            max_borrow_rate: 1_000_000,   // 100%
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION.into(),
+            cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION.into(),
+            // 1_000 deposited, 400 borrowed
+            deposit_balance: (1_000 * SPOT_BALANCE_PRECISION).into(),
+            borrow_balance: (400 * SPOT_BALANCE_PRECISION).into(),
+            insurance_fund: InsuranceFund {
+                total_factor: 100_000, // 10%
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn utilization() {
+        assert_eq!(calculate_utilization(0, 0), 0);
+        assert_eq!(calculate_utilization(100, 0), 0);
+        assert_eq!(calculate_utilization(0, 100), SPOT_UTILIZATION_PRECISION);
+        assert_eq!(calculate_utilization(100, 50), 500_000);
+    }
+
+    #[test]
+    fn borrow_rate_curve() {
+        let market = usdc_market();
+        assert_eq!(calculate_borrow_rate(&market, 0), 0);
+        assert_eq!(calculate_borrow_rate(&market, 400_000), 50_000);
+        assert_eq!(calculate_borrow_rate(&market, 800_000), 100_000);
+        assert_eq!(calculate_borrow_rate(&market, 900_000), 550_000);
+        assert_eq!(calculate_borrow_rate(&market, 1_000_000), 1_000_000);
+
+        let market = SpotMarket {
+            min_borrow_rate: 2, // 1%
+            ..usdc_market()
+        };
+        assert_eq!(calculate_borrow_rate(&market, 0), 10_000);
+        assert_eq!(calculate_borrow_rate(&market, 400_000), 50_000);
+    }
+
+    #[test]
+    fn market_rates() {
+        let market = usdc_market();
+        let rates = calculate_interest_rates(&market).unwrap();
+        assert_eq!(
+            rates,
+            SpotInterestRates {
+                utilization: 400_000,
+                borrow_rate: 50_000,
+                // 5% * 40% * 90%
+                deposit_rate: 18_000,
+            }
+        );
+        assert_eq!(rates.borrow_apy(), 51_271);
+
+        // borrow another 400, utilization 80%
+        let rates = calculate_interest_rates_with_delta(&market, 0, 400_000_000).unwrap();
+        assert_eq!(rates.utilization, 800_000);
+        assert_eq!(rates.borrow_rate, 100_000);
+
+        // deposit 1_000, utilization 20%
+        let rates = calculate_interest_rates_with_delta(&market, 1_000_000_000, 0).unwrap();
+        assert_eq!(rates.utilization, 200_000);
+
+        assert!(calculate_interest_rates_with_delta(&market, 0, -500_000_000).is_err());
+    }
+
+    #[test]
+    fn accumulated_interest() {
+        let market = usdc_market();
+        assert_eq!(
+            calculate_accumulated_interest(&market, 0).unwrap(),
+            (
+                SPOT_CUMULATIVE_INTEREST_PRECISION,
+                SPOT_CUMULATIVE_INTEREST_PRECISION
+            )
+        );
+        let (deposit, borrow) = calculate_accumulated_interest(&market, ONE_YEAR as i64).unwrap();
+        // 5% borrow, 1.8% net deposit over 1 year
+        assert_eq!(borrow, 10_500_000_001);
+        assert_eq!(deposit, 10_180_000_000);
+    }
+
+    #[test]
+    fn projected_interest() {
+        let rates = SpotInterestRates {
+            utilization: 400_000,
+            borrow_rate: 50_000,
+            deposit_rate: 18_000,
+        };
+        let one_year = ONE_YEAR as i64;
+        assert_eq!(
+            project_interest(&rates, 1_000_000_000, one_year),
+            18_000_000
+        );
+        assert_eq!(
+            project_interest(&rates, -1_000_000_000, one_year),
+            -50_000_000
+        );
+        assert_eq!(project_interest(&rates, 1_000_000_000, 0), 0);
+    }
+}
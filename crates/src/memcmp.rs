@@ -2,7 +2,7 @@ use anchor_lang::Discriminator;
 use solana_rpc_client_api::filter::{Memcmp, RpcFilterType};
 
 use crate::types::{
-    accounts::{PerpMarket, SpotMarket, User, UserStats},
+    accounts::{PerpMarket, RevenueShareEscrow, SpotMarket, User, UserStats},
     MarketType,
 };
 
@@ -38,6 +38,13 @@ pub fn get_user_stats_is_referred_or_referrer_filter() -> RpcFilterType {
     RpcFilterType::Memcmp(Memcmp::new_raw_bytes(188, vec![3]))
 }
 
+pub fn get_revenue_share_escrow_filter() -> RpcFilterType {
+    RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+        0,
+        RevenueShareEscrow::DISCRIMINATOR.to_vec(),
+    ))
+}
+
 pub fn get_market_filter(market_type: MarketType) -> RpcFilterType {
     match market_type {
         MarketType::Spot => {
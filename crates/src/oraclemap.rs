@@ -8,20 +8,20 @@ use crate::solana_sdk::{
     account::Account, clock::Slot, commitment_config::CommitmentConfig, pubkey::Pubkey,
 };
 use ahash::HashSet;
-use dashmap::{DashMap, ReadOnlyView};
+use dashmap::{mapref::entry::Entry, DashMap, ReadOnlyView};
 use drift_pubsub_client::PubsubClient;
-use futures_util::{
-    stream::{FuturesOrdered, FuturesUnordered},
-    StreamExt,
-};
+use futures_util::{stream::FuturesUnordered, StreamExt};
 use log::warn;
-use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+use tokio::sync::broadcast;
 
 use crate::{
+    account_fetcher::AccountFetcher,
     drift_idl::types::OracleSource,
     ffi::{get_oracle_price, OraclePriceData},
     grpc::AccountUpdate as GrpcAccountUpdate,
-    types::{AccountUpdate, MapOf, EMPTY_ACCOUNT_CALLBACK},
+    staleness::is_stale,
+    types::{AccountUpdate, MapOf, SubscriptionHandle, EMPTY_ACCOUNT_CALLBACK},
+    update_stream::{publish, UpdateStream, UPDATE_CHANNEL_CAPACITY},
     websocket_account_subscriber::WebsocketAccountSubscriber,
     MarketId, SdkError, SdkResult, UnsubHandle,
 };
@@ -47,15 +47,28 @@ pub struct Oracle {
     pub raw: Vec<u8>,
 }
 
+/// Ws subscription to an oracle account
+struct OracleSub {
+    unsub: UnsubHandle,
+    subscriber: WebsocketAccountSubscriber,
+    /// number of market subscriptions using the oracle
+    refs: usize,
+}
+
+type OracleSubscriptions = DashMap<Pubkey, OracleSub, ahash::RandomState>;
+
 /// Dynamic map of Drift market oracle data
 ///
 /// Caller can subscribe to some subset of markets for Ws backed updates
 /// Alternatively, the caller may drive the map by calling `sync` periodically
+///
+/// Oracle subscriptions are refcounted per market, an oracle shared by several markets
+/// stays subscribed until all of them are unsubscribed
 pub struct OracleMap {
     /// Oracle data keyed by pubkey and source
     pub oraclemap: Arc<DashMap<(Pubkey, u8), Oracle, ahash::RandomState>>,
     /// Oracle subscription handles by pubkey
-    subscriptions: DashMap<Pubkey, UnsubHandle, ahash::RandomState>,
+    subscriptions: Arc<OracleSubscriptions>,
     /// Oracle (pubkey, source) by MarketId (immutable)
     pub oracle_by_market: ReadOnlyView<MarketId, (Pubkey, OracleSource), ahash::RandomState>,
     /// map from oracle to consuming markets/source types
@@ -63,6 +76,8 @@ pub struct OracleMap {
     latest_slot: Arc<AtomicU64>,
     commitment: CommitmentConfig,
     pubsub: Arc<PubsubClient>,
+    /// applied oracle updates
+    updates: broadcast::Sender<Oracle>,
 }
 
 impl OracleMap {
@@ -119,6 +134,7 @@ impl OracleMap {
             latest_slot: Arc::new(AtomicU64::new(0)),
             commitment,
             pubsub: pubsub_client,
+            updates: broadcast::channel(UPDATE_CHANNEL_CAPACITY).0,
         }
     }
 
@@ -127,6 +143,26 @@ impl OracleMap {
         self.subscribe_inner(markets, EMPTY_ACCOUNT_CALLBACK).await
     }
 
+    /// Subscribe to oracle updates for given `markets`, returning a handle that unsubscribes them on drop
+    pub async fn subscribe_handle(&self, markets: &[MarketId]) -> SdkResult<SubscriptionHandle> {
+        self.subscribe_inner(markets, EMPTY_ACCOUNT_CALLBACK)
+            .await?;
+
+        let oracles: Vec<(Pubkey, Vec<OracleSource>)> = self
+            .market_oracles(markets)
+            .filter_map(|(_market, pubkey)| pubkey)
+            .map(|pubkey| (*pubkey, self.sources(pubkey)))
+            .collect();
+        let subscriptions = Arc::clone(&self.subscriptions);
+        let oraclemap = Arc::clone(&self.oraclemap);
+
+        Ok(SubscriptionHandle::new(move || {
+            for (pubkey, sources) in oracles {
+                release(&subscriptions, &oraclemap, &pubkey, &sources);
+            }
+        }))
+    }
+
     pub async fn subscribe_with_callback<F>(
         &self,
         markets: &[MarketId],
@@ -149,26 +185,28 @@ impl OracleMap {
     where
         F: Fn(&crate::AccountUpdate) + Send + Sync + 'static + Clone,
     {
-        let markets = HashSet::from_iter(markets);
         log::debug!(target: LOG_TARGET, "subscribe market oracles: {markets:?}");
 
         let mut pending_subscriptions =
-            Vec::<WebsocketAccountSubscriber>::with_capacity(markets.len());
+            Vec::<(WebsocketAccountSubscriber, usize)>::with_capacity(markets.len());
 
-        for market in markets {
-            let (oracle_pubkey, _oracle_source) =
-                self.oracle_by_market.get(market).expect("oracle exists");
+        for (market, oracle_pubkey) in self.market_oracles(markets) {
+            let oracle_pubkey = oracle_pubkey.expect("oracle exists");
 
             // markets can share oracle pubkeys, only want one sub per oracle pubkey
-            if self.subscriptions.contains_key(oracle_pubkey)
-                || pending_subscriptions
-                    .iter()
-                    .any(|sub| &sub.pubkey == oracle_pubkey)
-            {
+            if let Some(mut sub) = self.subscriptions.get_mut(oracle_pubkey) {
                 log::debug!(
                     target: LOG_TARGET,
                     "subscription exists: {market:?}/{oracle_pubkey:?}"
                 );
+                sub.refs += 1;
+                continue;
+            }
+            if let Some((_, refs)) = pending_subscriptions
+                .iter_mut()
+                .find(|(sub, _)| &sub.pubkey == oracle_pubkey)
+            {
+                *refs += 1;
                 continue;
             }
 
@@ -178,10 +216,10 @@ impl OracleMap {
                 self.commitment,
             );
 
-            pending_subscriptions.push(oracle_subscriber);
+            pending_subscriptions.push((oracle_subscriber, 1));
         }
 
-        let futs_iter = pending_subscriptions.into_iter().map(|sub_fut| {
+        let futs_iter = pending_subscriptions.into_iter().map(|(sub_fut, refs)| {
             let oraclemap = Arc::clone(&self.oraclemap);
             let oracle_shared_mode = self
                 .shared_oracles
@@ -189,35 +227,52 @@ impl OracleMap {
                 .expect("oracle exists")
                 .clone();
             let oracle_shared_mode_ref = oracle_shared_mode.clone();
+            let updates = self.updates.clone();
             let on_account = on_account.clone();
             async move {
                 let unsub = sub_fut
                     .subscribe(Self::SUBSCRIPTION_ID, true, move |update| {
                         match &oracle_shared_mode_ref {
                             OracleShareMode::Normal { source } => {
-                                update_handler(update, *source, &oraclemap)
+                                update_handler(update, *source, &oraclemap, &updates)
                             }
                             OracleShareMode::Mixed { sources } => {
                                 for source in sources {
-                                    update_handler(update, *source, &oraclemap);
+                                    update_handler(update, *source, &oraclemap, &updates);
                                 }
                             }
                         }
                         on_account(update);
                     })
                     .await;
-                ((sub_fut.pubkey, oracle_shared_mode), unsub)
+                ((sub_fut.pubkey, oracle_shared_mode), unsub, sub_fut, refs)
             }
         });
 
         let mut subscription_futs = FuturesUnordered::from_iter(futs_iter);
 
-        while let Some(((pubkey, oracle_share_mode), unsub)) = subscription_futs.next().await {
+        while let Some(((pubkey, oracle_share_mode), unsub, subscriber, refs)) =
+            subscription_futs.next().await
+        {
             log::debug!(
                 target: LOG_TARGET,
                 "subscribed market oracle: {oracle_share_mode:?}"
             );
-            self.subscriptions.insert(pubkey, unsub?);
+            let unsub = unsub?;
+            match self.subscriptions.entry(pubkey) {
+                // subscribed concurrently
+                Entry::Occupied(mut existing) => {
+                    existing.get_mut().refs += refs;
+                    let _ = unsub.send(());
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(OracleSub {
+                        unsub,
+                        subscriber,
+                        refs,
+                    });
+                }
+            }
         }
 
         log::debug!(target: LOG_TARGET, "subscribed");
@@ -225,14 +280,17 @@ impl OracleMap {
     }
 
     /// Unsubscribe from oracle updates for the given `markets`
+    ///
+    /// Shared oracles remain subscribed while other markets still use them
     pub fn unsubscribe(&self, markets: &[MarketId]) -> SdkResult<()> {
-        for market in markets {
-            if let Some((oracle_pubkey, oracle_source)) = self.oracle_by_market.get(market) {
-                if let Some((_, unsub)) = self.subscriptions.remove(oracle_pubkey) {
-                    let _ = unsub.send(());
-                    self.oraclemap
-                        .remove(&(*oracle_pubkey, *oracle_source as u8));
-                }
+        for (_market, oracle_pubkey) in self.market_oracles(markets) {
+            if let Some(oracle_pubkey) = oracle_pubkey {
+                release(
+                    &self.subscriptions,
+                    &self.oraclemap,
+                    oracle_pubkey,
+                    &self.sources(oracle_pubkey),
+                );
             }
         }
         log::debug!(target: LOG_TARGET, "unsubscribed markets: {markets:?}");
@@ -240,16 +298,28 @@ impl OracleMap {
         Ok(())
     }
 
-    /// Unsubscribe from all oracle updates
+    /// Unsubscribe from all oracle updates, regardless of outstanding references
     pub fn unsubscribe_all(&self) -> SdkResult<()> {
-        let all_markets: Vec<MarketId> = self.oracle_by_market.keys().copied().collect();
-        self.unsubscribe(&all_markets)
+        let all_oracles: Vec<Pubkey> = self.subscriptions.iter().map(|s| *s.key()).collect();
+        for pubkey in all_oracles {
+            if let Some((_, sub)) = self.subscriptions.remove(&pubkey) {
+                let _ = sub.unsub.send(());
+                for source in self.sources(&pubkey) {
+                    self.oraclemap.remove(&(pubkey, source as u8));
+                }
+            }
+        }
+        log::debug!(target: LOG_TARGET, "unsubscribed all");
+
+        Ok(())
     }
 
     /// Fetches account data for each market oracle set by `markets`
     ///
     /// This may be invoked manually to resync oracle data for some set of markets
-    pub async fn sync(&self, markets: &[MarketId], rpc: &RpcClient) -> SdkResult<()> {
+    ///
+    /// * `fetcher` - batched RPC fetcher, shared with concurrent syncs
+    pub async fn sync(&self, markets: &[MarketId], fetcher: &AccountFetcher) -> SdkResult<()> {
         let markets = HashSet::<MarketId>::from_iter(markets.iter().copied());
         log::debug!(target: LOG_TARGET, "sync oracles for: {markets:?}");
 
@@ -265,19 +335,23 @@ impl OracleMap {
             oracle_sources.push(*source);
         }
 
-        let (synced_oracles, latest_slot) =
-            match get_multi_account_data_with_fallback(rpc, &oracle_pubkeys).await {
-                Ok(result) => result,
-                Err(err) => {
-                    warn!(target: LOG_TARGET, "failed to sync oracle accounts");
-                    return Err(err);
-                }
-            };
+        let (accounts, latest_slot) = match fetcher.get_multiple_accounts(&oracle_pubkeys).await {
+            Ok(result) => result,
+            Err(err) => {
+                warn!(target: LOG_TARGET, "failed to sync oracle accounts");
+                return Err(err);
+            }
+        };
 
-        if synced_oracles.len() != oracle_pubkeys.len() {
+        let Some(synced_oracles) = oracle_pubkeys
+            .iter()
+            .zip(accounts)
+            .map(|(pubkey, account)| Some((*pubkey, account?)))
+            .collect::<Option<Vec<(Pubkey, Account)>>>()
+        else {
             warn!(target: LOG_TARGET, "failed to sync all oracle accounts");
             return Err(SdkError::InvalidOracle);
-        }
+        };
 
         for ((oracle_pubkey, oracle_account), oracle_source) in
             synced_oracles.iter().zip(oracle_sources)
@@ -387,20 +461,141 @@ impl OracleMap {
         Arc::clone(&self.oraclemap)
     }
 
+    /// Stream of oracle updates for `market`
+    ///
+    /// Yields Ws and gRPC updates while the market oracle is subscribed
+    pub fn updates(&self, market: &MarketId) -> SdkResult<UpdateStream<Oracle>> {
+        let (pubkey, source) = *self
+            .oracle_by_market
+            .get(market)
+            .ok_or(SdkError::InvalidOracle)?;
+        Ok(UpdateStream::new(
+            self.updates.subscribe(),
+            move |oracle: Oracle| {
+                (oracle.pubkey == pubkey && oracle.source == source).then_some(oracle)
+            },
+        ))
+    }
+
     /// Returns a hook for driving the map with new `Account` updates
     pub(crate) fn on_account_fn(&self) -> impl Fn(&GrpcAccountUpdate) {
         let oraclemap = self.map();
         let oracle_lookup = self.shared_oracles.clone();
+        let updates = self.updates.clone();
 
         move |update: &GrpcAccountUpdate| match oracle_lookup.get(&update.pubkey).unwrap() {
             OracleShareMode::Normal { source } => {
-                update_handler_grpc(update, *source, &oraclemap);
+                update_handler_grpc(update, *source, &oraclemap, &updates);
             }
             OracleShareMode::Mixed { sources } => {
                 for source in sources {
-                    update_handler_grpc(update, *source, &oraclemap);
+                    update_handler_grpc(update, *source, &oraclemap, &updates);
+                }
+            }
+        }
+    }
+
+    /// Resync oracles not updated within `max_age` slots of `current_slot`
+    ///
+    /// The RPC copy is decoded for every source sharing the oracle account. Oracles update most
+    /// slots so any difference from the cache restarts the Ws subscription
+    ///
+    /// Returns the pubkeys of stale oracles
+    pub async fn resync_stale(
+        &self,
+        fetcher: &AccountFetcher,
+        current_slot: Slot,
+        max_age: u64,
+    ) -> SdkResult<Vec<Pubkey>> {
+        let stale: Vec<Pubkey> = self
+            .subscriptions
+            .iter()
+            .map(|s| *s.key())
+            .filter(|pubkey| {
+                self.sources(pubkey).iter().any(|source| {
+                    self.oraclemap
+                        .get(&(*pubkey, *source as u8))
+                        .is_none_or(|o| is_stale(o.slot, current_slot, max_age))
+                })
+            })
+            .collect();
+
+        let (accounts, slot) = fetcher.get_multiple_accounts(&stale).await?;
+
+        for (pubkey, account) in stale.iter().zip(accounts) {
+            let sources = self.sources(pubkey);
+            let (Some(account), Some(source)) = (account, sources.first()) else {
+                warn!(target: LOG_TARGET, "resync failed: {pubkey:?}");
+                continue;
+            };
+            let missed_update = match self.oraclemap.get(&(*pubkey, *source as u8)) {
+                // Ws update arrived in the meantime
+                Some(cached) if cached.slot >= slot => continue,
+                Some(cached) => cached.raw != account.data,
+                None => true,
+            };
+            if missed_update {
+                warn!(target: LOG_TARGET, "stale subscription: {pubkey:?}");
+                if let Some(sub) = self.subscriptions.get(pubkey) {
+                    sub.subscriber.resubscribe();
                 }
             }
+            let update = AccountUpdate {
+                pubkey: *pubkey,
+                owner: account.owner,
+                lamports: account.lamports,
+                data: account.data,
+                slot,
+            };
+            for source in sources {
+                update_handler(&update, source, &self.oraclemap, &self.updates);
+            }
+        }
+
+        Ok(stale)
+    }
+
+    /// Oracle pubkey of each distinct market in `markets`, `None` if the market is unknown
+    ///
+    /// Each market holds one reference to its oracle subscription, subscribe and unsubscribe both
+    /// count references from this so duplicate markets are ignored consistently
+    fn market_oracles<'a>(
+        &'a self,
+        markets: &'a [MarketId],
+    ) -> impl Iterator<Item = (&'a MarketId, Option<&'a Pubkey>)> + 'a {
+        HashSet::<&MarketId>::from_iter(markets)
+            .into_iter()
+            .map(|market| {
+                let pubkey = self.oracle_by_market.get(market).map(|(pubkey, _)| pubkey);
+                (market, pubkey)
+            })
+    }
+
+    /// Oracle sources of the markets using `pubkey`
+    fn sources(&self, pubkey: &Pubkey) -> Vec<OracleSource> {
+        match self.shared_oracles.get(pubkey) {
+            Some(OracleShareMode::Normal { source }) => vec![*source],
+            Some(OracleShareMode::Mixed { sources }) => sources.clone(),
+            None => vec![],
+        }
+    }
+}
+
+/// Release one market reference to the oracle `pubkey`, unsubscribing on the last
+fn release(
+    subscriptions: &OracleSubscriptions,
+    oraclemap: &MapOf<(Pubkey, u8), Oracle>,
+    pubkey: &Pubkey,
+    sources: &[OracleSource],
+) {
+    let removed = subscriptions.remove_if_mut(pubkey, |_, sub| {
+        sub.refs = sub.refs.saturating_sub(1);
+        sub.refs == 0
+    });
+    if let Some((_, sub)) = removed {
+        let _ = sub.unsub.send(());
+        for source in sources {
+            oraclemap.remove(&(*pubkey, *source as u8));
         }
     }
 }
@@ -411,6 +606,7 @@ fn update_handler_grpc(
     update: &GrpcAccountUpdate,
     oracle_source: OracleSource,
     oracle_map: &DashMap<(Pubkey, u8), Oracle, ahash::RandomState>,
+    updates: &broadcast::Sender<Oracle>,
 ) {
     let lamports = update.lamports;
     let slot = update.slot;
@@ -429,7 +625,7 @@ fn update_handler_grpc(
         slot,
     ) {
         Ok(price_data) => {
-            oracle_map
+            let oracle = oracle_map
                 .entry((update.pubkey, oracle_source as u8))
                 .and_modify(|o| {
                     o.data = price_data;
@@ -444,6 +640,7 @@ fn update_handler_grpc(
                     slot,
                     raw: update.data.to_vec(),
                 });
+            publish(updates, || oracle.clone());
         }
         Err(err) => {
             log::error!("Failed to get oracle price: {err:?}, {:?}", update.pubkey)
@@ -456,6 +653,7 @@ fn update_handler(
     update: &AccountUpdate,
     oracle_source: OracleSource,
     oracle_map: &DashMap<(Pubkey, u8), Oracle, ahash::RandomState>,
+    updates: &broadcast::Sender<Oracle>,
 ) {
     let oracle_pubkey = update.pubkey;
     let lamports = update.lamports;
@@ -473,7 +671,7 @@ fn update_handler(
         update.slot,
     ) {
         Ok(price_data) => {
-            oracle_map
+            let oracle = oracle_map
                 .entry((oracle_pubkey, oracle_source as u8))
                 .and_modify(|o| {
                     o.data = price_data;
@@ -487,6 +685,7 @@ fn update_handler(
                     slot: update.slot,
                     raw: update.data.to_vec(),
                 });
+            publish(updates, || oracle.clone());
         }
         Err(err) => {
             log::error!("Failed to get oracle price: {err:?}, {oracle_pubkey:?}")
@@ -494,98 +693,10 @@ fn update_handler(
     }
 }
 
-/// Fetch all accounts with multiple fallbacks
-///
-/// Tries progressively less intensive RPC methods for wider compatibility with RPC providers:
-///    getMultipleAccounts, lastly multiple getAccountInfo
-///
-/// Returns deserialized accounts and retrieved slot
-async fn get_multi_account_data_with_fallback(
-    rpc: &RpcClient,
-    pubkeys: &[Pubkey],
-) -> SdkResult<(Vec<(Pubkey, Account)>, Slot)> {
-    let mut account_data = Vec::with_capacity(pubkeys.len());
-
-    // try 'getMultipleAccounts'
-    let mut gma_requests = FuturesOrdered::new();
-    for keys in pubkeys.chunks(64) {
-        gma_requests.push_back(async move {
-            let response = rpc
-                .get_multiple_accounts_with_commitment(keys, rpc.commitment())
-                .await;
-            (response, keys)
-        });
-    }
-
-    let mut gma_slot = 0;
-    while let Some((gma_response, keys)) = gma_requests.next().await {
-        match gma_response {
-            Ok(response) => {
-                gma_slot = response.context.slot;
-                for (oracle, pubkey) in response.value.into_iter().zip(keys) {
-                    match oracle {
-                        Some(oracle) => {
-                            account_data.push((*pubkey, oracle));
-                        }
-                        None => {
-                            log::warn!(
-                                target: LOG_TARGET,
-                                "failed to fetch oracle account (missing)"
-                            );
-                            break;
-                        }
-                    }
-                }
-            }
-            Err(err) => {
-                log::warn!(
-                    target: LOG_TARGET,
-                    "failed to fetch oracle accounts: {err:?}"
-                );
-                return Err(err)?;
-            }
-        }
-    }
-
-    if account_data.len() == pubkeys.len() {
-        return Ok((account_data, gma_slot));
-    }
-    log::debug!(
-        target: LOG_TARGET,
-        "syncing with getMultipleAccounts failed"
-    );
-
-    // try multiple 'getAccount's
-    let mut account_requests = FuturesOrdered::from_iter(pubkeys.iter().map(|p| async move {
-        (
-            p,
-            rpc.get_account_with_commitment(p, rpc.commitment()).await,
-        )
-    }));
-
-    let mut latest_slot = 0;
-    while let Some((pubkey, response)) = account_requests.next().await {
-        match response {
-            Ok(response) => {
-                let account = response.value.ok_or({
-                    log::warn!("failed to fetch oracle account");
-                    SdkError::InvalidOracle
-                })?;
-                latest_slot = latest_slot.max(response.context.slot);
-                account_data.push((*pubkey, account));
-            }
-            Err(err) => {
-                log::warn!("failed to fetch oracle account: {err:?}");
-                return Err(err)?;
-            }
-        }
-    }
-
-    Ok((account_data, latest_slot))
-}
-
 #[cfg(test)]
 mod tests {
+    use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+
     use super::*;
     use crate::utils::{
         get_ws_url,
@@ -627,7 +738,12 @@ mod tests {
             MarketId::perp(1),
             MarketId::spot(1),
         ];
-        map.sync(&markets, &rpc).await.expect("subd");
+        map.sync(
+            &markets,
+            &AccountFetcher::new(Arc::clone(&rpc), rpc.commitment()),
+        )
+        .await
+        .expect("subd");
     }
 
     #[tokio::test]
@@ -706,9 +822,31 @@ mod tests {
         assert!(map.is_subscribed(&MarketId::perp(0)));
         assert!(map.is_subscribed(&MarketId::perp(1)));
 
-        // check unsub ok
+        // shared oracle is kept until all its market subscriptions are released
         assert!(map.unsubscribe(&[MarketId::perp(0)]).is_ok());
+        assert!(map.is_subscribed(&MarketId::perp(0)));
+        assert!(map
+            .unsubscribe(&[MarketId::perp(0), MarketId::spot(1)])
+            .is_ok());
+        assert!(map.unsubscribe(&[MarketId::spot(1)]).is_ok());
         assert!(!map.is_subscribed(&MarketId::perp(0)));
+
+        // handle holds a reference until dropped
+        let handle = map
+            .subscribe_handle(&[MarketId::perp(1)])
+            .await
+            .expect("subd");
+        assert!(map.unsubscribe(&[MarketId::perp(1)]).is_ok());
+        assert!(map.is_subscribed(&MarketId::perp(1)));
+        drop(handle);
+        assert!(!map.is_subscribed(&MarketId::perp(1)));
+
+        // duplicate markets hold a single reference
+        map.subscribe(&[MarketId::perp(1), MarketId::perp(1)])
+            .await
+            .expect("subd");
+        assert!(map.unsubscribe(&[MarketId::perp(1)]).is_ok());
+        assert!(!map.is_subscribed(&MarketId::perp(1)));
     }
 
     #[tokio::test]
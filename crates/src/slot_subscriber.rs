@@ -6,7 +6,10 @@ use futures_util::StreamExt;
 use log::{debug, error, warn};
 use tokio::sync::oneshot;
 
-use crate::types::{SdkError, SdkResult};
+use crate::{
+    types::{SdkError, SdkResult},
+    websocket_account_subscriber::resubscribe_delay,
+};
 
 const LOG_TARGET: &str = "slotsub";
 
@@ -69,7 +72,7 @@ impl SlotSubscriber {
     ///
     /// * `on_slot` - callback invoked on new slot updates
     ///
-    pub fn subscribe<F>(&mut self, on_slot: F) -> SdkResult<()>
+    pub fn subscribe<F>(&self, on_slot: F) -> SdkResult<()>
     where
         F: 'static + Send + Fn(SlotUpdate),
     {
@@ -80,13 +83,13 @@ impl SlotSubscriber {
         self.subscribe_ws(on_slot)
     }
 
-    fn subscribe_ws<F>(&mut self, on_slot: F) -> SdkResult<()>
+    fn subscribe_ws<F>(&self, on_slot: F) -> SdkResult<()>
     where
         F: 'static + Send + Fn(SlotUpdate),
     {
         let (unsub_tx, mut unsub_rx) = oneshot::channel::<()>();
         {
-            let mut guard = self.unsub.try_lock().expect("uncontested");
+            let mut guard = self.unsub.lock().expect("acquired");
             *guard = Some(unsub_tx);
         }
 
@@ -95,12 +98,25 @@ impl SlotSubscriber {
 
         tokio::spawn(async move {
             debug!(target: LOG_TARGET, "start slot subscriber");
+            let mut attempt = 0;
             loop {
                 let (mut slot_updates, unsubscriber) = match pubsub.slot_subscribe().await {
-                    Ok(s) => s,
+                    Ok(s) => {
+                        attempt = 0;
+                        s
+                    }
                     Err(err) => {
-                        error!(target: LOG_TARGET, "slot subscribe failed: {err:?}");
-                        continue;
+                        attempt += 1;
+                        let Some(delay) = resubscribe_delay(&pubsub, attempt) else {
+                            error!(target: LOG_TARGET, "Ws client failed, ending slot subscription: {err:?}");
+                            break;
+                        };
+                        error!(target: LOG_TARGET, "slot subscribe failed, retry in {delay:?}: {err:?}");
+                        tokio::select! {
+                            biased;
+                            _ = &mut unsub_rx => break,
+                            _ = tokio::time::sleep(delay) => continue,
+                        }
                     }
                 };
 
@@ -0,0 +1,384 @@
+//! Slot-consistent reads of cached user, market and oracle accounts
+//!
+//! Each cache (`AccountMap`, `MarketMap`, `OracleMap`) is updated independently so reading
+//! them one after another may mix data from different slots. An `AccountSnapshot` checks every
+//! account it contains is no older than some minimum slot, otherwise reporting the stale accounts.
+use crate::{
+    oraclemap::Oracle,
+    solana_sdk::{clock::Slot, pubkey::Pubkey},
+    types::{
+        accounts::{PerpMarket, SpotMarket, User},
+        DataAndSlot, MarketId, SdkError, SdkResult,
+    },
+};
+
+/// Identifies an account within a snapshot
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SnapshotAccount {
+    User(Pubkey),
+    PerpMarket(u16),
+    SpotMarket(u16),
+    /// oracle of the given market
+    Oracle(MarketId),
+}
+
+/// Reason an account failed the snapshot requirements
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Staleness {
+    /// no cached data, the account is not subscribed
+    Missing,
+    /// cached data is older than the minimum slot
+    Behind { slot: Slot },
+    /// cached oracle differs from the oracle configured by its market
+    OracleMismatch,
+}
+
+/// An account that failed the snapshot requirements
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StaleAccount {
+    pub account: SnapshotAccount,
+    pub staleness: Staleness,
+}
+
+/// Consistent view of a user and its associated markets and oracles
+///
+/// All accounts are guaranteed to be at or above the requested minimum slot
+#[derive(Debug, Clone)]
+pub struct AccountSnapshot {
+    /// pubkey of the user account
+    pub pubkey: Pubkey,
+    pub user: DataAndSlot<User>,
+    pub perp_markets: Vec<DataAndSlot<PerpMarket>>,
+    pub spot_markets: Vec<DataAndSlot<SpotMarket>>,
+    /// oracles keyed by market
+    pub oracles: Vec<(MarketId, Oracle)>,
+}
+
+impl AccountSnapshot {
+    /// Oldest slot of any account in the snapshot
+    pub fn slot(&self) -> Slot {
+        self.slots().min().unwrap_or(self.user.slot)
+    }
+    /// Newest slot of any account in the snapshot
+    pub fn max_slot(&self) -> Slot {
+        self.slots().max().unwrap_or(self.user.slot)
+    }
+    /// Slots between the oldest and newest account in the snapshot
+    pub fn slot_spread(&self) -> u64 {
+        self.max_slot() - self.slot()
+    }
+    pub fn perp_market(&self, market_index: u16) -> Option<&PerpMarket> {
+        self.perp_markets
+            .iter()
+            .find(|m| m.data.market_index == market_index)
+            .map(|m| &m.data)
+    }
+    pub fn spot_market(&self, market_index: u16) -> Option<&SpotMarket> {
+        self.spot_markets
+            .iter()
+            .find(|m| m.data.market_index == market_index)
+            .map(|m| &m.data)
+    }
+    pub fn oracle(&self, market: MarketId) -> Option<&Oracle> {
+        self.oracles
+            .iter()
+            .find(|(m, _)| *m == market)
+            .map(|(_, o)| o)
+    }
+
+    fn slots(&self) -> impl Iterator<Item = Slot> + '_ {
+        std::iter::once(self.user.slot)
+            .chain(self.perp_markets.iter().map(|m| m.slot))
+            .chain(self.spot_markets.iter().map(|m| m.slot))
+            .chain(self.oracles.iter().map(|(_, o)| o.slot))
+    }
+}
+
+/// Build a snapshot of `user` and its markets/oracles no older than `min_slot`
+///
+/// * `force_markets` - additional markets to include regardless of user positions
+/// * `get_*` - lookups into the respective account caches
+///
+/// Returns `SdkError::StaleSnapshot` listing every account that is missing or too old
+pub(crate) fn build_snapshot(
+    pubkey: &Pubkey,
+    user: Option<DataAndSlot<User>>,
+    min_slot: Slot,
+    force_markets: &[MarketId],
+    get_perp_market: impl Fn(u16) -> Option<DataAndSlot<PerpMarket>>,
+    get_spot_market: impl Fn(u16) -> Option<DataAndSlot<SpotMarket>>,
+    get_oracle: impl Fn(MarketId) -> Option<Oracle>,
+) -> SdkResult<AccountSnapshot> {
+    let mut stale = Vec::new();
+    let check = |stale: &mut Vec<StaleAccount>, account: SnapshotAccount, slot: Option<Slot>| {
+        let staleness = match slot {
+            None => Staleness::Missing,
+            Some(slot) if slot < min_slot => Staleness::Behind { slot },
+            Some(_) => return,
+        };
+        stale.push(StaleAccount { account, staleness });
+    };
+
+    check(
+        &mut stale,
+        SnapshotAccount::User(*pubkey),
+        user.as_ref().map(|u| u.slot),
+    );
+    let Some(user) = user else {
+        return Err(SdkError::StaleSnapshot(stale));
+    };
+
+    let mut perp_indexes: Vec<u16> = user
+        .data
+        .perp_positions
+        .iter()
+        .filter(|p| !p.is_available())
+        .map(|p| p.market_index)
+        .chain(
+            force_markets
+                .iter()
+                .filter(|m| m.is_perp())
+                .map(|m| m.index()),
+        )
+        .collect();
+    perp_indexes.sort_unstable();
+    perp_indexes.dedup();
+
+    let mut spot_indexes: Vec<u16> = user
+        .data
+        .spot_positions
+        .iter()
+        .filter(|p| !p.is_available())
+        .map(|p| p.market_index)
+        .chain(
+            force_markets
+                .iter()
+                .filter(|m| m.is_spot())
+                .map(|m| m.index()),
+        )
+        .chain(std::iter::once(MarketId::QUOTE_SPOT.index()))
+        .collect();
+    spot_indexes.sort_unstable();
+    spot_indexes.dedup();
+
+    let mut perp_markets = Vec::with_capacity(perp_indexes.len());
+    let mut spot_markets = Vec::with_capacity(spot_indexes.len());
+    // (market, configured oracle)
+    let mut oracle_markets = Vec::with_capacity(perp_indexes.len() + spot_indexes.len());
+
+    for idx in perp_indexes {
+        let market = get_perp_market(idx);
+        check(
+            &mut stale,
+            SnapshotAccount::PerpMarket(idx),
+            market.as_ref().map(|m| m.slot),
+        );
+        if let Some(market) = market {
+            oracle_markets.push((MarketId::perp(idx), market.data.amm.oracle));
+            perp_markets.push(market);
+        }
+    }
+    for idx in spot_indexes {
+        let market = get_spot_market(idx);
+        check(
+            &mut stale,
+            SnapshotAccount::SpotMarket(idx),
+            market.as_ref().map(|m| m.slot),
+        );
+        if let Some(market) = market {
+            oracle_markets.push((MarketId::spot(idx), market.data.oracle));
+            spot_markets.push(market);
+        }
+    }
+
+    let mut oracles = Vec::with_capacity(oracle_markets.len());
+    for (market, configured_oracle) in oracle_markets {
+        let oracle = get_oracle(market);
+        check(
+            &mut stale,
+            SnapshotAccount::Oracle(market),
+            oracle.as_ref().map(|o| o.slot),
+        );
+        if let Some(oracle) = oracle {
+            if oracle.pubkey != configured_oracle {
+                stale.push(StaleAccount {
+                    account: SnapshotAccount::Oracle(market),
+                    staleness: Staleness::OracleMismatch,
+                });
+            }
+            oracles.push((market, oracle));
+        }
+    }
+
+    if !stale.is_empty() {
+        return Err(SdkError::StaleSnapshot(stale));
+    }
+
+    Ok(AccountSnapshot {
+        pubkey: *pubkey,
+        user,
+        perp_markets,
+        spot_markets,
+        oracles,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{PerpPosition, SpotPosition};
+
+    fn oracle(pubkey: Pubkey, slot: Slot) -> Oracle {
+        Oracle {
+            pubkey,
+            slot,
+            ..Default::default()
+        }
+    }
+
+    fn user_with_positions() -> User {
+        let mut user = User::default();
+        user.perp_positions[0] = PerpPosition {
+            market_index: 1,
+            base_asset_amount: 1,
+            ..Default::default()
+        };
+        user.spot_positions[0] = SpotPosition {
+            market_index: 0,
+            scaled_balance: 1,
+            ..Default::default()
+        };
+        user
+    }
+
+    fn perp_market(index: u16, oracle: Pubkey) -> PerpMarket {
+        let mut market = PerpMarket {
+            market_index: index,
+            ..Default::default()
+        };
+        market.amm.oracle = oracle;
+        market
+    }
+
+    fn spot_market(index: u16, oracle: Pubkey) -> SpotMarket {
+        SpotMarket {
+            market_index: index,
+            oracle,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn snapshot_consistent() {
+        let pubkey = Pubkey::new_unique();
+        let perp_oracle = Pubkey::new_unique();
+        let spot_oracle = Pubkey::new_unique();
+        let snapshot = build_snapshot(
+            &pubkey,
+            Some(DataAndSlot {
+                slot: 100,
+                data: user_with_positions(),
+            }),
+            100,
+            &[],
+            |idx| {
+                Some(DataAndSlot {
+                    slot: 101,
+                    data: perp_market(idx, perp_oracle),
+                })
+            },
+            |idx| {
+                Some(DataAndSlot {
+                    slot: 102,
+                    data: spot_market(idx, spot_oracle),
+                })
+            },
+            |market| {
+                Some(oracle(
+                    if market.is_perp() {
+                        perp_oracle
+                    } else {
+                        spot_oracle
+                    },
+                    105,
+                ))
+            },
+        )
+        .unwrap();
+
+        assert_eq!(snapshot.perp_markets.len(), 1);
+        assert_eq!(snapshot.spot_markets.len(), 1);
+        assert_eq!(snapshot.oracles.len(), 2);
+        assert!(snapshot.perp_market(1).is_some());
+        assert!(snapshot.spot_market(0).is_some());
+        assert!(snapshot.oracle(MarketId::perp(1)).is_some());
+        assert_eq!(snapshot.slot(), 100);
+        assert_eq!(snapshot.max_slot(), 105);
+        assert_eq!(snapshot.slot_spread(), 5);
+    }
+
+    #[test]
+    fn snapshot_reports_stale_accounts() {
+        let pubkey = Pubkey::new_unique();
+        let perp_oracle = Pubkey::new_unique();
+        let result = build_snapshot(
+            &pubkey,
+            Some(DataAndSlot {
+                slot: 100,
+                data: user_with_positions(),
+            }),
+            100,
+            &[MarketId::perp(2)],
+            |idx| {
+                (idx == 1).then(|| DataAndSlot {
+                    slot: 99,
+                    data: perp_market(idx, perp_oracle),
+                })
+            },
+            |idx| {
+                Some(DataAndSlot {
+                    slot: 100,
+                    data: spot_market(idx, Pubkey::new_unique()),
+                })
+            },
+            |market| Some(oracle(perp_oracle, 100)).filter(|_| market.is_perp()),
+        );
+
+        let Err(SdkError::StaleSnapshot(stale)) = result else {
+            panic!("expected stale snapshot");
+        };
+        assert_eq!(
+            stale,
+            vec![
+                StaleAccount {
+                    account: SnapshotAccount::PerpMarket(1),
+                    staleness: Staleness::Behind { slot: 99 },
+                },
+                StaleAccount {
+                    account: SnapshotAccount::PerpMarket(2),
+                    staleness: Staleness::Missing,
+                },
+                StaleAccount {
+                    account: SnapshotAccount::Oracle(MarketId::spot(0)),
+                    staleness: Staleness::Missing,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn snapshot_missing_user() {
+        let pubkey = Pubkey::new_unique();
+        let result = build_snapshot(&pubkey, None, 0, &[], |_| None, |_| None, |_| None);
+        let Err(SdkError::StaleSnapshot(stale)) = result else {
+            panic!("expected stale snapshot");
+        };
+        assert_eq!(
+            stale,
+            vec![StaleAccount {
+                account: SnapshotAccount::User(pubkey),
+                staleness: Staleness::Missing,
+            }]
+        );
+    }
+}
@@ -0,0 +1,425 @@
+//! Spot liquidation with swap
+//!
+//! Sizes a spot liquidation of a user's liability, routes the seized asset through a `SwapRouter`
+//! and assembles the `liquidate_spot_with_swap_begin`/`end` wrapped tx
+use std::borrow::Cow;
+
+use crate::{
+    constants::ProgramData,
+    ffi::{
+        calculate_margin_requirement_and_total_collateral_and_liability_info, MarginContextMode,
+    },
+    math::{
+        account_list_builder::AccountsListBuilder,
+        constants::MARGIN_PRECISION,
+        liquidation::{
+            calculate_max_pct_to_liquidate, calculate_spot_liquidation_size,
+            calculate_spot_liquidation_swap_profit, SpotLiquidationSize,
+        },
+    },
+    solana_sdk::{message::VersionedMessage, pubkey::Pubkey},
+    swap_router::{SwapQuoteRequest, SwapRoute, SwapRouter, DEFAULT_SWAP_COMPUTE_UNITS},
+    types::{
+        accounts::{SpotMarket, State, User},
+        SdkError, SdkResult, SpotBalanceType,
+    },
+    DriftClient, MarketId, TransactionBuilder, Wallet,
+};
+
+/// Compute units reserved for the drift liquidation ixs
+const LIQUIDATION_COMPUTE_UNITS: u32 = 300_000;
+
+/// Spot liquidation with swap parameters
+#[derive(Clone, Debug)]
+pub struct SpotLiquidationParams {
+    /// spot market index of the user's asset (collateral)
+    pub asset_market_index: u16,
+    /// spot market index of the user's liability (borrow)
+    pub liability_market_index: u16,
+    /// maximum allowed swap slippage in basis points
+    pub slippage_bps: u16,
+    /// minimum profit after slippage to liquidate (QUOTE_PRECISION)
+    pub min_profit: i64,
+    /// priority fee in µ-lamports per CU
+    pub priority_fee: Option<u64>,
+}
+
+impl SpotLiquidationParams {
+    pub fn new(asset_market_index: u16, liability_market_index: u16) -> Self {
+        Self {
+            asset_market_index,
+            liability_market_index,
+            slippage_bps: 50,
+            min_profit: 0,
+            priority_fee: None,
+        }
+    }
+    /// Set the maximum allowed swap slippage
+    pub fn with_slippage_bps(mut self, slippage_bps: u16) -> Self {
+        self.slippage_bps = slippage_bps;
+        self
+    }
+    /// Set the minimum profit after slippage (QUOTE_PRECISION)
+    pub fn with_min_profit(mut self, min_profit: i64) -> Self {
+        self.min_profit = min_profit;
+        self
+    }
+    /// Set the tx priority fee, the CU limit is derived from the swap route
+    pub fn with_priority_fee(mut self, microlamports_per_cu: u64) -> Self {
+        self.priority_fee = Some(microlamports_per_cu);
+        self
+    }
+}
+
+/// A sized and routed spot liquidation, ready to send
+#[derive(Clone, Debug)]
+pub struct SpotLiquidation {
+    /// liquidation amounts
+    pub size: SpotLiquidationSize,
+    /// swap of the seized asset into the liability
+    pub route: SwapRoute,
+    /// expected profit after slippage (QUOTE_PRECISION)
+    pub expected_profit: i128,
+    /// the liquidation tx
+    pub message: VersionedMessage,
+}
+
+/// Build a spot liquidation with swap of `user`
+///
+/// The liquidation is sized by the user's margin shortage and `calculate_max_pct_to_liquidate`,
+/// the seized asset is swapped via `router` for the liability. Returns error if `user` is not
+/// liquidatable or the liquidation is unprofitable after slippage.
+///
+/// requires spot markets and oracles of the user's positions are subscribed
+///
+/// * `router` - swap venue e.g. `JupiterRouter` or `SwapAggregator`
+/// * `liquidator_sub_account` - liquidator's drift sub-account address
+/// * `liquidator_account` - liquidator's drift sub-account data
+/// * `user` - the user account to liquidate
+pub async fn build_spot_liquidation_with_swap(
+    drift: &DriftClient,
+    router: &dyn SwapRouter,
+    liquidator_sub_account: Pubkey,
+    liquidator_account: &User,
+    user: &User,
+    params: SpotLiquidationParams,
+) -> SdkResult<SpotLiquidation> {
+    let state = drift.state_account()?;
+    let asset_market = drift.try_get_spot_market_account(params.asset_market_index)?;
+    let liability_market = drift.try_get_spot_market_account(params.liability_market_index)?;
+    let asset_oracle = drift
+        .try_get_oracle_price_data_and_slot(MarketId::spot(params.asset_market_index))
+        .ok_or(SdkError::InvalidOracle)?;
+    let liability_oracle = drift
+        .try_get_oracle_price_data_and_slot(MarketId::spot(params.liability_market_index))
+        .ok_or(SdkError::InvalidOracle)?;
+
+    let snapshot = LiquidationSnapshot {
+        state: &state,
+        asset_market: &asset_market,
+        asset_price: asset_oracle.data.price,
+        liability_market: &liability_market,
+        liability_price: liability_oracle.data.price,
+        slot: asset_oracle.slot.max(liability_oracle.slot),
+        margin_shortage: liquidation_margin_shortage(drift, user, &state)?,
+    };
+    build_liquidation(
+        drift.program_data(),
+        router,
+        (liquidator_sub_account, liquidator_account),
+        user,
+        params,
+        snapshot,
+    )
+    .await
+}
+
+/// State, markets and prices a spot liquidation is sized from
+struct LiquidationSnapshot<'a> {
+    state: &'a State,
+    asset_market: &'a SpotMarket,
+    asset_price: i64,
+    liability_market: &'a SpotMarket,
+    liability_price: i64,
+    /// latest oracle slot
+    slot: u64,
+    /// see `liquidation_margin_shortage`
+    margin_shortage: u128,
+}
+
+/// Size, route and build the spot liquidation of `user` from `snapshot`
+async fn build_liquidation(
+    program_data: &ProgramData,
+    router: &dyn SwapRouter,
+    (liquidator_sub_account, liquidator_account): (Pubkey, &User),
+    user: &User,
+    params: SpotLiquidationParams,
+    snapshot: LiquidationSnapshot<'_>,
+) -> SdkResult<SpotLiquidation> {
+    let SpotLiquidationParams {
+        asset_market_index,
+        liability_market_index,
+        slippage_bps,
+        min_profit,
+        priority_fee,
+    } = params;
+    let LiquidationSnapshot {
+        state,
+        asset_market,
+        asset_price,
+        liability_market,
+        liability_price,
+        slot,
+        margin_shortage,
+    } = snapshot;
+
+    if margin_shortage == 0 {
+        return Err(SdkError::Generic("user is not liquidatable".into()));
+    }
+    let max_pct_to_liquidate = calculate_max_pct_to_liquidate(
+        user,
+        margin_shortage,
+        slot,
+        state.initial_pct_to_liquidate as u128,
+        state.liquidation_duration as u128,
+    )?;
+
+    let size = calculate_spot_liquidation_size(
+        margin_shortage,
+        max_pct_to_liquidate,
+        asset_market,
+        asset_price,
+        spot_token_amount(user, asset_market, SpotBalanceType::Deposit)?,
+        liability_market,
+        liability_price,
+        spot_token_amount(user, liability_market, SpotBalanceType::Borrow)?,
+    )?;
+    if size.asset_amount == 0 || size.liability_amount == 0 {
+        return Err(SdkError::Generic("nothing to liquidate".into()));
+    }
+
+    let request = SwapQuoteRequest::new(
+        liquidator_account.authority,
+        asset_market_index,
+        liability_market_index,
+        size.asset_amount,
+        slippage_bps,
+    );
+    let route = router.route(&request).await?;
+    let min_swap_out = (route.out_amount as u128 * 10_000_u128.saturating_sub(slippage_bps as u128)
+        / 10_000) as u64;
+    let expected_profit = calculate_spot_liquidation_swap_profit(
+        &size,
+        min_swap_out,
+        liability_market,
+        liability_price,
+    );
+    if expected_profit < min_profit as i128 {
+        return Err(SdkError::Generic(format!(
+            "liquidation unprofitable: {expected_profit}, via: {}",
+            route.venue
+        )));
+    }
+
+    let mut builder = TransactionBuilder::new(
+        program_data,
+        liquidator_sub_account,
+        Cow::Borrowed(liquidator_account),
+        false,
+    );
+    if let Some(priority_fee) = priority_fee {
+        let cu_limit =
+            route.compute_units.unwrap_or(DEFAULT_SWAP_COMPUTE_UNITS) + LIQUIDATION_COMPUTE_UNITS;
+        builder = builder.with_priority_fee(priority_fee, Some(cu_limit));
+    }
+    let message = builder
+        .swap_route_liquidate(
+            route.clone(),
+            asset_market,
+            liability_market,
+            &Wallet::derive_associated_token_address(&liquidator_account.authority, asset_market),
+            &Wallet::derive_associated_token_address(
+                &liquidator_account.authority,
+                liability_market,
+            ),
+            asset_market_index,
+            liability_market_index,
+            user,
+        )
+        .build();
+
+    Ok(SpotLiquidation {
+        size,
+        route,
+        expected_profit,
+        message,
+    })
+}
+
+/// Margin shortage of `user` at maintenance, including the liquidation buffer once the user is
+/// being liquidated (QUOTE_PRECISION)
+fn liquidation_margin_shortage(drift: &DriftClient, user: &User, state: &State) -> SdkResult<u128> {
+    let margin = calculate_margin_requirement_and_total_collateral_and_liability_info(
+        user,
+        &mut AccountsListBuilder::default().try_build(drift, user, &[])?,
+        MarginContextMode::StandardMaintenance,
+    )?;
+
+    let buffer = if user.is_being_liquidated() {
+        (margin.total_spot_liability_value + margin.total_perp_liability_value)
+            * state.liquidation_margin_buffer_ratio as u128
+            / MARGIN_PRECISION as u128
+    } else {
+        0
+    };
+
+    Ok(((margin.margin_requirement + buffer) as i128 - margin.total_collateral).max(0) as u128)
+}
+
+/// Token amount of `user`'s position in `market` with `balance_type`
+fn spot_token_amount(
+    user: &User,
+    market: &SpotMarket,
+    balance_type: SpotBalanceType,
+) -> SdkResult<u128> {
+    let position = user
+        .spot_positions
+        .iter()
+        .find(|p| p.market_index == market.market_index && !p.is_available())
+        .filter(|p| p.balance_type == balance_type)
+        .ok_or_else(|| {
+            SdkError::Generic(format!(
+                "user has no {balance_type:?} in spot market: {}",
+                market.market_index
+            ))
+        })?;
+
+    position.get_token_amount(market)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        math::constants::{
+            LIQUIDATION_FEE_PRECISION, LIQUIDATION_PCT_PRECISION, PRICE_PRECISION_I64,
+            QUOTE_PRECISION, SPOT_BALANCE_PRECISION_U64, SPOT_CUMULATIVE_INTEREST_PRECISION,
+            SPOT_WEIGHT_PRECISION,
+        },
+        swap_router::MockSwapRouter,
+        types::SpotPosition,
+    };
+
+    fn spot_market(market_index: u16, decimals: u32) -> SpotMarket {
+        SpotMarket {
+            market_index,
+            decimals,
+            cumulative_deposit_interest: SPOT_CUMULATIVE_INTEREST_PRECISION.into(),
+            cumulative_borrow_interest: SPOT_CUMULATIVE_INTEREST_PRECISION.into(),
+            maintenance_asset_weight: SPOT_WEIGHT_PRECISION,
+            maintenance_liability_weight: SPOT_WEIGHT_PRECISION,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn liquidation_with_swap() {
+        let usdc = spot_market(0, 6);
+        let sol = SpotMarket {
+            maintenance_asset_weight: 9 * SPOT_WEIGHT_PRECISION / 10,
+            maintenance_liability_weight: 11 * SPOT_WEIGHT_PRECISION / 10,
+            liquidator_fee: LIQUIDATION_FEE_PRECISION / 1000,
+            ..spot_market(1, 9)
+        };
+        let program_data = ProgramData::new(vec![usdc, sol], vec![], vec![], State::default());
+        let state = State::default();
+
+        // 20 SOL deposit, 2_000 USDC borrow
+        let mut user = User::default();
+        user.spot_positions[0] = SpotPosition {
+            market_index: 1,
+            scaled_balance: 20 * SPOT_BALANCE_PRECISION_U64,
+            ..Default::default()
+        };
+        user.spot_positions[1] = SpotPosition {
+            market_index: 0,
+            scaled_balance: 2_000 * SPOT_BALANCE_PRECISION_U64,
+            balance_type: SpotBalanceType::Borrow,
+            ..Default::default()
+        };
+        let liquidator = User {
+            authority: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        let snapshot = || LiquidationSnapshot {
+            state: &state,
+            asset_market: &sol,
+            asset_price: 100 * PRICE_PRECISION_I64,
+            liability_market: &usdc,
+            liability_price: PRICE_PRECISION_I64,
+            slot: 100,
+            margin_shortage: 100 * QUOTE_PRECISION,
+        };
+        // swaps SOL at $102
+        let router = MockSwapRouter::new("mock", 102, 1_000);
+
+        let liquidation = build_liquidation(
+            &program_data,
+            &router,
+            (Pubkey::new_unique(), &liquidator),
+            &user,
+            SpotLiquidationParams::new(1, 0),
+            snapshot(),
+        )
+        .await
+        .unwrap();
+
+        let expected_size = calculate_spot_liquidation_size(
+            100 * QUOTE_PRECISION,
+            LIQUIDATION_PCT_PRECISION,
+            &sol,
+            100 * PRICE_PRECISION_I64,
+            20 * SPOT_BALANCE_PRECISION_U64 as u128,
+            &usdc,
+            PRICE_PRECISION_I64,
+            2_000 * QUOTE_PRECISION,
+        )
+        .unwrap();
+        assert_eq!(liquidation.size, expected_size);
+        assert_eq!(liquidation.route.in_amount, expected_size.asset_amount);
+        assert_eq!(liquidation.route.out_amount, 1_030_292_632);
+        // min swap out after 50bps slippage less the repaid liability
+        assert_eq!(liquidation.expected_profit, 16_059_433);
+        assert!(liquidation
+            .message
+            .static_account_keys()
+            .contains(&router.program_id()));
+
+        // unprofitable after slippage
+        let result = build_liquidation(
+            &program_data,
+            &router,
+            (Pubkey::new_unique(), &liquidator),
+            &user,
+            SpotLiquidationParams::new(1, 0).with_min_profit(20 * QUOTE_PRECISION as i64),
+            snapshot(),
+        )
+        .await;
+        assert!(result.is_err());
+
+        // not liquidatable
+        let result = build_liquidation(
+            &program_data,
+            &router,
+            (Pubkey::new_unique(), &liquidator),
+            &user,
+            SpotLiquidationParams::new(1, 0),
+            LiquidationSnapshot {
+                margin_shortage: 0,
+                ..snapshot()
+            },
+        )
+        .await;
+        assert!(result.is_err());
+    }
+}
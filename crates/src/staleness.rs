@@ -0,0 +1,93 @@
+//! Staleness detection for Ws subscribed accounts
+//!
+//! A Ws subscription can stall without its stream ending, leaving the last value cached forever.
+//! Cached slots are compared against the network slot (`SlotSubscriber::current_slot`) and accounts
+//! older than the configured max age are resynced over RPC, restarting their subscription if it missed updates.
+use std::time::Duration;
+
+use crate::solana_sdk::clock::Slot;
+
+/// Kinds of cached accounts with independent staleness limits
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AccountKind {
+    /// perp and spot market accounts
+    Market,
+    Oracle,
+    /// user and other `AccountMap` accounts
+    User,
+}
+
+/// Staleness limits of Ws subscribed accounts
+///
+/// Markets and users only update when modified so quiet accounts are expected to age,
+/// the monitor refreshes their slot whenever RPC confirms the cached data is unchanged
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StalenessConfig {
+    /// max age of market accounts in slots, `None` disables the check
+    pub market_max_age: Option<u64>,
+    /// max age of oracle accounts in slots, `None` disables the check
+    pub oracle_max_age: Option<u64>,
+    /// max age of user accounts in slots, `None` disables the check
+    pub user_max_age: Option<u64>,
+    /// interval between staleness checks
+    pub check_interval: Duration,
+}
+
+impl Default for StalenessConfig {
+    fn default() -> Self {
+        Self {
+            market_max_age: Some(150),
+            oracle_max_age: Some(25),
+            user_max_age: Some(300),
+            check_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+impl StalenessConfig {
+    /// Max age in slots for accounts of `kind`
+    pub fn max_age(&self, kind: AccountKind) -> Option<u64> {
+        match kind {
+            AccountKind::Market => self.market_max_age,
+            AccountKind::Oracle => self.oracle_max_age,
+            AccountKind::User => self.user_max_age,
+        }
+    }
+    /// True if an account of `kind` at `slot` exceeds its max age
+    ///
+    /// Always false while `current_slot` is unknown (0)
+    pub fn is_stale(&self, kind: AccountKind, slot: Slot, current_slot: Slot) -> bool {
+        self.max_age(kind)
+            .is_some_and(|max_age| is_stale(slot, current_slot, max_age))
+    }
+}
+
+/// True if data at `slot` is more than `max_age` slots behind `current_slot`
+pub fn is_stale(slot: Slot, current_slot: Slot, max_age: u64) -> bool {
+    current_slot.saturating_sub(slot) > max_age
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn staleness_by_kind() {
+        let config = StalenessConfig {
+            market_max_age: Some(100),
+            oracle_max_age: Some(10),
+            user_max_age: None,
+            ..Default::default()
+        };
+
+        assert!(!config.is_stale(AccountKind::Oracle, 990, 1_000));
+        assert!(config.is_stale(AccountKind::Oracle, 989, 1_000));
+        assert!(!config.is_stale(AccountKind::Market, 989, 1_000));
+        assert!(config.is_stale(AccountKind::Market, 899, 1_000));
+        assert!(!config.is_stale(AccountKind::User, 0, 1_000));
+        // current slot unknown
+        assert!(!config.is_stale(AccountKind::Oracle, 500, 0));
+        // data ahead of the slot subscriber
+        assert!(!config.is_stale(AccountKind::Oracle, 1_005, 1_000));
+    }
+}
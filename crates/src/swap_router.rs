@@ -0,0 +1,610 @@
+//! Venue agnostic token swap routing
+//!
+//! `SwapRouter` unifies the Jupiter and Titan swap APIs (and any user provided venue) behind
+//! a single quote interface. `SwapAggregator` queries a set of routers for the same swap and
+//! selects the best route after fees and compute costs, ready for
+//! `TransactionBuilder::swap_route`
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use futures_util::{
+    future::{join_all, BoxFuture},
+    FutureExt,
+};
+
+pub use crate::jupiter::SwapMode;
+#[cfg(feature = "titan")]
+use crate::{
+    constants::{ASSOCIATED_TOKEN_PROGRAM_ID, DEFAULT_PUBKEY},
+    titan::{Provider, TitanSwapApi, TitanSwapInfo},
+};
+use crate::{
+    constants::{TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID},
+    jupiter::{JupiterSwapApi, JupiterSwapInfo, TransactionConfig},
+    solana_sdk::{
+        instruction::{AccountMeta, Instruction},
+        message::AddressLookupTableAccount,
+        pubkey::Pubkey,
+    },
+    types::{SdkError, SdkResult},
+    DriftClient,
+};
+
+const LOG_TARGET: &str = "swap";
+
+/// Compute units assumed for routes that do not report an estimate
+pub const DEFAULT_SWAP_COMPUTE_UNITS: u32 = 400_000;
+
+/// Parameters of a swap between two drift spot markets
+#[derive(Clone, Debug)]
+pub struct SwapQuoteRequest {
+    /// wallet executing the swap
+    pub user_authority: Pubkey,
+    /// spot market index of the token to swap from
+    pub in_market: u16,
+    /// spot market index of the token to swap to
+    pub out_market: u16,
+    /// swap amount in native units, input token for `ExactIn` and output token for `ExactOut`
+    pub amount: u64,
+    pub swap_mode: SwapMode,
+    /// maximum allowed slippage in basis points
+    pub slippage_bps: u16,
+    /// only consider direct swap routes between the tokens
+    pub only_direct_routes: Option<bool>,
+    /// comma-separated DEX names to exclude from routing
+    pub excluded_dexes: Option<String>,
+}
+
+impl SwapQuoteRequest {
+    /// Create an `ExactIn` swap request of `amount` from `in_market` to `out_market`
+    pub fn new(
+        user_authority: Pubkey,
+        in_market: u16,
+        out_market: u16,
+        amount: u64,
+        slippage_bps: u16,
+    ) -> Self {
+        Self {
+            user_authority,
+            in_market,
+            out_market,
+            amount,
+            swap_mode: SwapMode::ExactIn,
+            slippage_bps,
+            only_direct_routes: None,
+            excluded_dexes: None,
+        }
+    }
+    /// Set the swap mode
+    pub fn swap_mode(mut self, swap_mode: SwapMode) -> Self {
+        self.swap_mode = swap_mode;
+        self
+    }
+    /// Only consider direct swap routes
+    pub fn only_direct_routes(mut self, only_direct_routes: bool) -> Self {
+        self.only_direct_routes = Some(only_direct_routes);
+        self
+    }
+    /// Exclude DEXes from routing (comma-separated names)
+    pub fn excluded_dexes(mut self, excluded_dexes: impl Into<String>) -> Self {
+        self.excluded_dexes = Some(excluded_dexes.into());
+        self
+    }
+}
+
+/// A quoted swap route with instructions ready to be wrapped in drift swap ixs
+#[derive(Clone, Debug)]
+pub struct SwapRoute {
+    /// name of the venue providing the route
+    pub venue: String,
+    /// amount of input token swapped in
+    pub in_amount: u64,
+    /// quoted amount of output token
+    pub out_amount: u64,
+    /// venue fees not included in the quoted amounts
+    ///
+    /// denominated in the output token for `ExactIn` swaps and the input token for `ExactOut`
+    pub fee_amount: u64,
+    /// compute units used by `swap_ixs`, if reported by the venue
+    pub compute_units: Option<u32>,
+    /// the user's token accounts should be created before swapping
+    pub create_token_accounts: bool,
+    /// venue swap ixs
+    pub swap_ixs: Vec<Instruction>,
+    /// lookup tables used by `swap_ixs`
+    pub luts: Vec<AddressLookupTableAccount>,
+}
+
+impl SwapRoute {
+    /// Build a route from a Jupiter swap query
+    ///
+    /// Returns error if the route requires unsupported ixs (i.e. Jito tips)
+    pub fn from_jupiter(info: JupiterSwapInfo) -> SdkResult<Self> {
+        let JupiterSwapInfo { quote, ixs, luts } = info;
+        if !ixs.other_instructions.is_empty() {
+            return Err(SdkError::Generic(
+                "jupiter swap unsupported ix: Jito tip".into(),
+            ));
+        }
+
+        let mut swap_ixs = vec![ixs.swap_instruction];
+        // support SOL unwrap ixs, ignore account delete/reclaim ixs
+        swap_ixs.extend(ixs.cleanup_instruction.filter(|ix| {
+            ix.program_id != TOKEN_PROGRAM_ID && ix.program_id != TOKEN_2022_PROGRAM_ID
+        }));
+
+        Ok(Self {
+            venue: JupiterRouter::NAME.into(),
+            in_amount: quote.in_amount,
+            out_amount: quote.out_amount,
+            // platform fee is already deducted from the quoted amounts
+            fee_amount: 0,
+            compute_units: Some(ixs.compute_unit_limit),
+            create_token_accounts: !ixs.setup_instructions.is_empty(),
+            swap_ixs,
+            luts,
+        })
+    }
+
+    /// Build a route from a Titan swap query
+    #[cfg(feature = "titan")]
+    pub fn from_titan(info: TitanSwapInfo) -> Self {
+        let TitanSwapInfo { quote, ixs, luts } = info;
+        let swap_ixs = ixs
+            .instructions
+            .into_iter()
+            .filter(|ix| {
+                ix.program_id != TOKEN_PROGRAM_ID
+                    && ix.program_id != TOKEN_2022_PROGRAM_ID
+                    && ix.program_id != ASSOCIATED_TOKEN_PROGRAM_ID
+                    && ix.program_id != DEFAULT_PUBKEY
+            })
+            .collect();
+
+        Self {
+            venue: TitanRouter::NAME.into(),
+            in_amount: quote.in_amount,
+            out_amount: quote.out_amount,
+            fee_amount: 0,
+            compute_units: None,
+            create_token_accounts: true,
+            swap_ixs,
+            luts,
+        }
+    }
+
+    /// Score of the route for `swap_mode`, higher is better
+    ///
+    /// * `compute_unit_cost` - cost of 1M CUs denominated in the swap's variable token
+    ///   (output token for `ExactIn`, input token for `ExactOut`)
+    pub fn score(&self, swap_mode: &SwapMode, compute_unit_cost: u64) -> i128 {
+        let compute_units = self.compute_units.unwrap_or(DEFAULT_SWAP_COMPUTE_UNITS) as i128;
+        let compute_cost = compute_units * compute_unit_cost as i128 / 1_000_000;
+        match swap_mode {
+            SwapMode::ExactIn => self.out_amount as i128 - self.fee_amount as i128 - compute_cost,
+            SwapMode::ExactOut => {
+                -(self.in_amount as i128 + self.fee_amount as i128 + compute_cost)
+            }
+        }
+    }
+}
+
+/// A venue providing token swap routes
+pub trait SwapRouter: Send + Sync {
+    /// Name of the venue
+    fn name(&self) -> &str;
+    /// Quote `request` and return a route for it
+    fn route<'a>(&'a self, request: &'a SwapQuoteRequest) -> BoxFuture<'a, SdkResult<SwapRoute>>;
+}
+
+/// Routes swaps via the Jupiter API
+#[derive(Clone)]
+pub struct JupiterRouter {
+    drift: DriftClient,
+    transaction_config: Option<TransactionConfig>,
+}
+
+impl JupiterRouter {
+    pub const NAME: &'static str = "jupiter";
+
+    /// Create a new `JupiterRouter`
+    ///
+    /// see `JupiterSwapApi` for API url and key configuration
+    pub fn new(drift: DriftClient) -> Self {
+        Self {
+            drift,
+            transaction_config: None,
+        }
+    }
+    /// Set the Jupiter swap transaction config
+    pub fn with_transaction_config(mut self, transaction_config: TransactionConfig) -> Self {
+        self.transaction_config = Some(transaction_config);
+        self
+    }
+}
+
+impl SwapRouter for JupiterRouter {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+    fn route<'a>(&'a self, request: &'a SwapQuoteRequest) -> BoxFuture<'a, SdkResult<SwapRoute>> {
+        async move {
+            let info = self
+                .drift
+                .jupiter_swap_query(
+                    &request.user_authority,
+                    request.amount,
+                    request.swap_mode.clone(),
+                    request.slippage_bps,
+                    request.in_market,
+                    request.out_market,
+                    request.only_direct_routes,
+                    request.excluded_dexes.clone(),
+                    self.transaction_config.clone(),
+                )
+                .await?;
+            SwapRoute::from_jupiter(info)
+        }
+        .boxed()
+    }
+}
+
+/// Routes swaps via the Titan API
+#[cfg(feature = "titan")]
+#[derive(Clone)]
+pub struct TitanRouter {
+    drift: DriftClient,
+    max_accounts: Option<usize>,
+    providers: Option<Provider>,
+}
+
+#[cfg(feature = "titan")]
+impl TitanRouter {
+    pub const NAME: &'static str = "titan";
+
+    /// Create a new `TitanRouter`
+    ///
+    /// see `TitanSwapApi` for API url and auth configuration
+    pub fn new(drift: DriftClient) -> Self {
+        Self {
+            drift,
+            max_accounts: None,
+            providers: None,
+        }
+    }
+    /// Limit the number of accounts used by routes
+    pub fn with_max_accounts(mut self, max_accounts: usize) -> Self {
+        self.max_accounts = Some(max_accounts);
+        self
+    }
+    /// Restrict routing to `providers`
+    pub fn with_providers(mut self, providers: Provider) -> Self {
+        self.providers = Some(providers);
+        self
+    }
+}
+
+#[cfg(feature = "titan")]
+impl SwapRouter for TitanRouter {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+    fn route<'a>(&'a self, request: &'a SwapQuoteRequest) -> BoxFuture<'a, SdkResult<SwapRoute>> {
+        async move {
+            let swap_mode = match request.swap_mode {
+                SwapMode::ExactIn => crate::titan::SwapMode::ExactIn,
+                SwapMode::ExactOut => crate::titan::SwapMode::ExactOut,
+            };
+            let info = self
+                .drift
+                .titan_swap_query(
+                    &request.user_authority,
+                    request.amount,
+                    self.max_accounts,
+                    swap_mode,
+                    request.slippage_bps,
+                    request.in_market,
+                    request.out_market,
+                    request.only_direct_routes,
+                    request.excluded_dexes.clone(),
+                    self.providers.clone(),
+                )
+                .await?;
+            Ok(SwapRoute::from_titan(info))
+        }
+        .boxed()
+    }
+}
+
+/// Selects the best swap route across multiple `SwapRouter`s
+///
+/// ```example(no_run)
+/// let aggregator = SwapAggregator::new()
+///     .with_router(JupiterRouter::new(drift.clone()))
+///     .with_router(my_venue);
+/// let route = aggregator
+///     .best_route(&SwapQuoteRequest::new(authority, 0, 1, 1_000_000, 50))
+///     .await?;
+/// let tx = builder
+///     .swap_route(route, &in_market, &out_market, &in_ata, &out_ata, None, None)
+///     .build();
+/// ```
+#[derive(Default)]
+pub struct SwapAggregator {
+    routers: Vec<Box<dyn SwapRouter>>,
+    compute_unit_cost: u64,
+}
+
+impl SwapAggregator {
+    /// Create a new `SwapAggregator` with no routers
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add a swap venue
+    pub fn with_router(mut self, router: impl SwapRouter + 'static) -> Self {
+        self.routers.push(Box::new(router));
+        self
+    }
+    /// Set the cost of 1M CUs used to compare routes
+    ///
+    /// denominated in the output token for `ExactIn` swaps and the input token for `ExactOut`.
+    /// With the default of 0 compute units only break ties between routes
+    pub fn with_compute_unit_cost(mut self, compute_unit_cost: u64) -> Self {
+        self.compute_unit_cost = compute_unit_cost;
+        self
+    }
+    /// Query all routers concurrently for `request`
+    ///
+    /// Returns (venue, route result) for each router
+    pub async fn quotes(&self, request: &SwapQuoteRequest) -> Vec<(String, SdkResult<SwapRoute>)> {
+        let routes = join_all(self.routers.iter().map(|router| router.route(request))).await;
+        self.routers
+            .iter()
+            .map(|router| router.name().to_string())
+            .zip(routes)
+            .collect()
+    }
+    /// Query all routers for `request` and return the best route
+    ///
+    /// Failing routers are skipped, returns error if no router provides a route
+    pub async fn best_route(&self, request: &SwapQuoteRequest) -> SdkResult<SwapRoute> {
+        let mut best: Option<(i128, SwapRoute)> = None;
+        for (venue, route) in self.quotes(request).await {
+            let route = match route {
+                Ok(route) => route,
+                Err(err) => {
+                    log::warn!(target: LOG_TARGET, "{venue} route failed: {err:?}");
+                    continue;
+                }
+            };
+            let score = route.score(&request.swap_mode, self.compute_unit_cost);
+            log::debug!(
+                target: LOG_TARGET,
+                "{venue} route: in: {}, out: {}, score: {score}",
+                route.in_amount,
+                route.out_amount
+            );
+            if best
+                .as_ref()
+                .is_none_or(|(best_score, _)| score > *best_score)
+            {
+                best = Some((score, route));
+            }
+        }
+
+        best.map(|(_, route)| route)
+            .ok_or_else(|| SdkError::Generic("no swap route available".into()))
+    }
+}
+
+impl SwapRouter for SwapAggregator {
+    fn name(&self) -> &str {
+        "aggregator"
+    }
+    fn route<'a>(&'a self, request: &'a SwapQuoteRequest) -> BoxFuture<'a, SdkResult<SwapRoute>> {
+        self.best_route(request).boxed()
+    }
+}
+
+/// In-process `SwapRouter` quoting at a fixed rate, for tests and simulation
+///
+/// Its swap ix is a no-op ix on `program_id`
+pub struct MockSwapRouter {
+    name: String,
+    /// output token per input token, as (numerator, denominator)
+    rate: (u64, u64),
+    fee_amount: u64,
+    compute_units: Option<u32>,
+    program_id: Pubkey,
+    fail: bool,
+    requests: AtomicU64,
+}
+
+impl MockSwapRouter {
+    /// Create a mock router swapping at `numerator / denominator` output tokens per input token
+    pub fn new(name: impl Into<String>, numerator: u64, denominator: u64) -> Self {
+        Self {
+            name: name.into(),
+            rate: (numerator, denominator.max(1)),
+            fee_amount: 0,
+            compute_units: None,
+            program_id: Pubkey::new_unique(),
+            fail: false,
+            requests: AtomicU64::default(),
+        }
+    }
+    /// Set the fee reported by routes
+    pub fn with_fee(mut self, fee_amount: u64) -> Self {
+        self.fee_amount = fee_amount;
+        self
+    }
+    /// Set the compute units reported by routes
+    pub fn with_compute_units(mut self, compute_units: u32) -> Self {
+        self.compute_units = Some(compute_units);
+        self
+    }
+    /// Fail all route requests
+    pub fn failing(mut self) -> Self {
+        self.fail = true;
+        self
+    }
+    /// Program id of the mock swap ix
+    pub fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+    /// Number of routes requested
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+}
+
+impl SwapRouter for MockSwapRouter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn route<'a>(&'a self, request: &'a SwapQuoteRequest) -> BoxFuture<'a, SdkResult<SwapRoute>> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let route = if self.fail {
+            Err(SdkError::Generic(format!("{}: no route", self.name)))
+        } else {
+            let (numerator, denominator) = self.rate;
+            let (in_amount, out_amount) = match request.swap_mode {
+                SwapMode::ExactIn => (
+                    request.amount,
+                    (request.amount as u128 * numerator as u128 / denominator as u128) as u64,
+                ),
+                SwapMode::ExactOut => (
+                    (request.amount as u128 * denominator as u128)
+                        .div_ceil(numerator.max(1) as u128) as u64,
+                    request.amount,
+                ),
+            };
+            Ok(SwapRoute {
+                venue: self.name.clone(),
+                in_amount,
+                out_amount,
+                fee_amount: self.fee_amount,
+                compute_units: self.compute_units,
+                create_token_accounts: false,
+                swap_ixs: vec![Instruction {
+                    program_id: self.program_id,
+                    accounts: vec![AccountMeta::new_readonly(request.user_authority, true)],
+                    data: vec![],
+                }],
+                luts: vec![],
+            })
+        };
+        std::future::ready(route).boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+
+    use super::*;
+    use crate::{
+        constants::{ProgramData, PROGRAM_ID},
+        types::accounts::{SpotMarket, State, User},
+        TransactionBuilder,
+    };
+
+    fn request() -> SwapQuoteRequest {
+        SwapQuoteRequest::new(Pubkey::new_unique(), 0, 1, 1_000_000, 50)
+    }
+
+    #[tokio::test]
+    async fn best_route_by_output() {
+        let aggregator = SwapAggregator::new()
+            .with_router(MockSwapRouter::new("a", 99, 100))
+            .with_router(MockSwapRouter::new("b", 101, 100))
+            .with_router(MockSwapRouter::new("c", 2, 1).failing());
+
+        let quotes = aggregator.quotes(&request()).await;
+        assert_eq!(quotes.len(), 3);
+        assert!(quotes[2].1.is_err());
+
+        let route = aggregator.best_route(&request()).await.unwrap();
+        assert_eq!(route.venue, "b");
+        assert_eq!(route.in_amount, 1_000_000);
+        assert_eq!(route.out_amount, 1_010_000);
+
+        // exact out picks the smallest input
+        let route = aggregator
+            .best_route(&request().swap_mode(SwapMode::ExactOut))
+            .await
+            .unwrap();
+        assert_eq!(route.venue, "b");
+        assert_eq!(route.out_amount, 1_000_000);
+        assert_eq!(route.in_amount, 990_100);
+
+        let aggregator =
+            SwapAggregator::new().with_router(MockSwapRouter::new("c", 1, 1).failing());
+        assert!(aggregator.best_route(&request()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn best_route_after_fees_and_compute() {
+        let aggregator = SwapAggregator::new()
+            .with_router(MockSwapRouter::new("cheap", 100, 100).with_compute_units(200_000))
+            .with_router(
+                MockSwapRouter::new("fee", 101, 100)
+                    .with_fee(20_000)
+                    .with_compute_units(200_000),
+            );
+        assert_eq!(
+            aggregator.best_route(&request()).await.unwrap().venue,
+            "cheap"
+        );
+
+        // compute heavy route loses once CUs are priced
+        let aggregator = SwapAggregator::new()
+            .with_router(MockSwapRouter::new("heavy", 1_001, 1_000).with_compute_units(1_400_000))
+            .with_router(MockSwapRouter::new("light", 1_000, 1_000).with_compute_units(100_000));
+        assert_eq!(
+            aggregator.best_route(&request()).await.unwrap().venue,
+            "heavy"
+        );
+        let aggregator = aggregator.with_compute_unit_cost(1_000);
+        assert_eq!(
+            aggregator.best_route(&request()).await.unwrap().venue,
+            "light"
+        );
+    }
+
+    #[tokio::test]
+    async fn swap_route_wraps_venue_ixs() {
+        let in_market = SpotMarket::default();
+        let out_market = SpotMarket {
+            market_index: 1,
+            ..Default::default()
+        };
+        let program_data = ProgramData::new(
+            vec![in_market, out_market],
+            vec![],
+            vec![],
+            State::default(),
+        );
+        let router = MockSwapRouter::new("mock", 1, 1);
+        let route = router.route(&request()).await.unwrap();
+
+        let builder = TransactionBuilder::new(
+            &program_data,
+            Pubkey::new_unique(),
+            Cow::Owned(User::default()),
+            false,
+        )
+        .swap_route(
+            route,
+            &in_market,
+            &out_market,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            None,
+            None,
+        );
+
+        let program_ids: Vec<Pubkey> = builder.ixs().iter().map(|ix| ix.program_id).collect();
+        assert_eq!(program_ids, [PROGRAM_ID, router.program_id(), PROGRAM_ID]);
+        assert_eq!(router.requests(), 1);
+    }
+}
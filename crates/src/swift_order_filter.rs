@@ -0,0 +1,577 @@
+//! Swift order flow filtering and risk gating for makers
+//!
+//! ```example(no_run)
+//! let filter = SwiftOrderFilter::default()
+//!     .size_bounds(BASE_PRECISION_U64 / 10, Some(100 * BASE_PRECISION_U64))
+//!     .max_slot_age(30)
+//!     .reject_builder_orders(true)
+//!     .check_taker_health(0);
+//!
+//! let orders = client.subscribe_swift_orders(&markets, None, None, None).await?;
+//! let mut accepted = gate_swift_orders(orders, client.clone(), filter, move || slot_sub.current_slot(), |order, reason| {
+//!     log::debug!("rejected {}: {reason:?}", order.order_uuid_str());
+//! });
+//! while let Some(order) = accepted.next().await {
+//!     // quote
+//! }
+//! ```
+//!
+//! Streams from `subscribe_swift_orders_resilient` can be gated the same way, subscription errors
+//! are logged and dropped
+use std::{collections::HashSet, sync::Arc};
+
+use futures_util::{Stream, StreamExt};
+
+use crate::{
+    math::leverage::UserMargin,
+    swift_order_subscriber::{SignedOrderInfo, SwiftError},
+    types::{accounts::User, OrderParams, OrderType, PositionDirection},
+    DriftClient,
+};
+
+const LOG_TARGET: &str = "swiftfilter";
+
+/// Max. orders evaluated concurrently by `gate_swift_orders` e.g. awaiting taker account fetches
+const MAX_CONCURRENT_EVALUATIONS: usize = 16;
+
+/// Reason a swift order was rejected by a `SwiftOrderFilter`
+#[derive(Clone, Debug, PartialEq)]
+pub enum RejectReason {
+    /// order market is not in the allowed set
+    MarketNotAllowed(u16),
+    /// order size is below the minimum
+    OrderTooSmall { base_asset_amount: u64, min: u64 },
+    /// order size is above the maximum
+    OrderTooLarge { base_asset_amount: u64, max: u64 },
+    /// order was signed too many slots ago
+    StaleSlot {
+        order_slot: u64,
+        current_slot: u64,
+        max_age: u64,
+    },
+    /// order params are likely to be sanitized by the program when placed
+    Sanitized,
+    /// auction params are missing or inconsistent with the order direction/limit price
+    InvalidAuctionParams,
+    /// auction duration exceeds the maximum
+    AuctionTooLong { duration: u8, max: u8 },
+    /// order pays a builder fee
+    BuilderFee,
+    /// order deposits into an isolated position
+    IsolatedDeposit,
+    /// order requires a preceding deposit tx
+    PreDeposit,
+    /// taker account could not be fetched
+    TakerAccountUnavailable,
+    /// taker account is being liquidated or bankrupt
+    TakerLiquidatable,
+    /// taker account free collateral is below the minimum
+    TakerUnhealthy { free_collateral: u128, min: u128 },
+}
+
+/// Configurable checks applied to incoming `SignedOrderInfo`s
+///
+/// All checks are disabled by default
+#[derive(Clone, Debug, Default)]
+pub struct SwiftOrderFilter {
+    markets: Option<HashSet<u16>>,
+    min_base_asset_amount: u64,
+    max_base_asset_amount: Option<u64>,
+    max_slot_age: Option<u64>,
+    max_auction_duration: Option<u8>,
+    reject_sanitized: bool,
+    check_auction_params: bool,
+    reject_builder_orders: bool,
+    reject_isolated_deposits: bool,
+    reject_pre_deposits: bool,
+    min_taker_free_collateral: Option<u128>,
+}
+
+impl SwiftOrderFilter {
+    /// Only accept orders for the given perp `markets`
+    pub fn markets(mut self, markets: &[u16]) -> Self {
+        self.markets = Some(markets.iter().copied().collect());
+        self
+    }
+    /// Only accept orders with base amount within [`min`, `max`] (`BASE_PRECISION`)
+    pub fn size_bounds(mut self, min: u64, max: Option<u64>) -> Self {
+        self.min_base_asset_amount = min;
+        self.max_base_asset_amount = max;
+        self
+    }
+    /// Reject orders signed more than `max_age` slots ago
+    pub fn max_slot_age(mut self, max_age: u64) -> Self {
+        self.max_slot_age = Some(max_age);
+        self
+    }
+    /// Reject orders with auctions longer than `max` slots
+    pub fn max_auction_duration(mut self, max: u8) -> Self {
+        self.max_auction_duration = Some(max);
+        self
+    }
+    /// Reject orders flagged as likely to be sanitized
+    pub fn reject_sanitized(mut self, reject: bool) -> Self {
+        self.reject_sanitized = reject;
+        self
+    }
+    /// Reject orders with missing or inconsistent auction params
+    pub fn check_auction_params(mut self, check: bool) -> Self {
+        self.check_auction_params = check;
+        self
+    }
+    /// Reject orders paying a builder fee
+    pub fn reject_builder_orders(mut self, reject: bool) -> Self {
+        self.reject_builder_orders = reject;
+        self
+    }
+    /// Reject orders depositing into an isolated position
+    pub fn reject_isolated_deposits(mut self, reject: bool) -> Self {
+        self.reject_isolated_deposits = reject;
+        self
+    }
+    /// Reject 'deposit+trade' orders
+    pub fn reject_pre_deposits(mut self, reject: bool) -> Self {
+        self.reject_pre_deposits = reject;
+        self
+    }
+    /// Reject orders from takers that are liquidatable or have less than `min_free_collateral`
+    /// (`QUOTE_PRECISION`)
+    ///
+    /// requires the taker account and its markets/oracles are available to the `DriftClient`
+    pub fn check_taker_health(mut self, min_free_collateral: u128) -> Self {
+        self.min_taker_free_collateral = Some(min_free_collateral);
+        self
+    }
+    /// True if the filter needs the taker account
+    pub fn requires_taker(&self) -> bool {
+        self.min_taker_free_collateral.is_some()
+    }
+    /// Evaluate the order level checks
+    ///
+    /// * `order` - the swift order
+    /// * `current_slot` - the current chain slot
+    pub fn check_order(
+        &self,
+        order: &SignedOrderInfo,
+        current_slot: u64,
+    ) -> Result<(), RejectReason> {
+        let params = order.order_params();
+
+        if let Some(ref markets) = self.markets {
+            if !markets.contains(&params.market_index) {
+                return Err(RejectReason::MarketNotAllowed(params.market_index));
+            }
+        }
+
+        if params.base_asset_amount < self.min_base_asset_amount {
+            return Err(RejectReason::OrderTooSmall {
+                base_asset_amount: params.base_asset_amount,
+                min: self.min_base_asset_amount,
+            });
+        }
+        if let Some(max) = self.max_base_asset_amount {
+            if params.base_asset_amount > max {
+                return Err(RejectReason::OrderTooLarge {
+                    base_asset_amount: params.base_asset_amount,
+                    max,
+                });
+            }
+        }
+
+        if let Some(max_age) = self.max_slot_age {
+            let order_slot = order.slot();
+            if current_slot.saturating_sub(order_slot) > max_age {
+                return Err(RejectReason::StaleSlot {
+                    order_slot,
+                    current_slot,
+                    max_age,
+                });
+            }
+        }
+
+        if self.reject_sanitized && order.will_sanitize {
+            return Err(RejectReason::Sanitized);
+        }
+        if self.check_auction_params && !auction_params_valid(&params) {
+            return Err(RejectReason::InvalidAuctionParams);
+        }
+        if let (Some(max), Some(duration)) = (self.max_auction_duration, params.auction_duration) {
+            if duration > max {
+                return Err(RejectReason::AuctionTooLong { duration, max });
+            }
+        }
+
+        if self.reject_builder_orders && order.has_builder() {
+            return Err(RejectReason::BuilderFee);
+        }
+        if self.reject_isolated_deposits && order.has_isolated_position_deposit() {
+            return Err(RejectReason::IsolatedDeposit);
+        }
+        if self.reject_pre_deposits && order.pre_deposit.is_some() {
+            return Err(RejectReason::PreDeposit);
+        }
+
+        Ok(())
+    }
+    /// Evaluate the taker account checks
+    ///
+    /// * `taker` - the taker sub-account
+    /// * `free_collateral` - taker free collateral (`QUOTE_PRECISION`)
+    pub fn check_taker(&self, taker: &User, free_collateral: u128) -> Result<(), RejectReason> {
+        let Some(min) = self.min_taker_free_collateral else {
+            return Ok(());
+        };
+        if taker.is_being_liquidated() || taker.is_bankrupt() {
+            return Err(RejectReason::TakerLiquidatable);
+        }
+        if free_collateral < min {
+            return Err(RejectReason::TakerUnhealthy {
+                free_collateral,
+                min,
+            });
+        }
+
+        Ok(())
+    }
+    /// Evaluate all checks for `order`
+    ///
+    /// The taker account is read from the client's cache if subscribed, otherwise fetched over RPC
+    pub async fn evaluate(
+        &self,
+        client: &DriftClient,
+        order: &SignedOrderInfo,
+        current_slot: u64,
+    ) -> Result<(), RejectReason> {
+        self.check_order(order, current_slot)?;
+
+        if self.requires_taker() {
+            let taker = client
+                .get_user_account(&order.taker_subaccount())
+                .await
+                .map_err(|_| RejectReason::TakerAccountUnavailable)?;
+            let margin_info = client
+                .calculate_margin_info(&taker)
+                .map_err(|_| RejectReason::TakerAccountUnavailable)?;
+            self.check_taker(&taker, margin_info.get_free_collateral())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns true if the order's auction params are set and consistent with its direction and limit price
+fn auction_params_valid(params: &OrderParams) -> bool {
+    let (Some(duration), Some(start_price), Some(end_price)) = (
+        params.auction_duration,
+        params.auction_start_price,
+        params.auction_end_price,
+    ) else {
+        return false;
+    };
+    if duration == 0 {
+        return false;
+    }
+    // absolute prices must be positive, oracle orders use offsets
+    if params.order_type != OrderType::Oracle && (start_price <= 0 || end_price <= 0) {
+        return false;
+    }
+
+    match params.direction {
+        PositionDirection::Long => {
+            start_price <= end_price
+                && (params.order_type == OrderType::Oracle
+                    || params.price == 0
+                    || end_price <= params.price as i64)
+        }
+        PositionDirection::Short => {
+            start_price >= end_price
+                && (params.order_type == OrderType::Oracle
+                    || params.price == 0
+                    || end_price >= params.price as i64)
+        }
+    }
+}
+
+/// Item of a swift order stream accepted by `gate_swift_orders`
+pub trait SwiftOrderItem {
+    /// Returns the order, if any
+    fn into_order(self) -> Option<SignedOrderInfo>;
+}
+
+impl SwiftOrderItem for SignedOrderInfo {
+    fn into_order(self) -> Option<SignedOrderInfo> {
+        Some(self)
+    }
+}
+
+impl SwiftOrderItem for Result<SignedOrderInfo, SwiftError> {
+    fn into_order(self) -> Option<SignedOrderInfo> {
+        match self {
+            Ok(order) => Some(order),
+            Err(err) => {
+                log::warn!(target: LOG_TARGET, "swift order stream: {err}");
+                None
+            }
+        }
+    }
+}
+
+/// Gate a stream of swift orders with `filter`, yielding only accepted orders
+///
+/// Orders are evaluated concurrently and yielded in arrival order
+///
+/// * `orders` - swift order stream e.g. from `DriftClient::subscribe_swift_orders` or
+///   `DriftClient::subscribe_swift_orders_resilient`
+/// * `client` - drift client used to fetch taker accounts
+/// * `filter` - checks to apply
+/// * `current_slot` - provides the current chain slot e.g. from a `SlotSubscriber`
+/// * `on_reject` - called with each rejected order and the reason
+pub fn gate_swift_orders<S, F, R>(
+    orders: S,
+    client: DriftClient,
+    filter: SwiftOrderFilter,
+    current_slot: F,
+    on_reject: R,
+) -> impl Stream<Item = SignedOrderInfo>
+where
+    S: Stream,
+    S::Item: SwiftOrderItem,
+    F: Fn() -> u64 + Send + Sync + 'static,
+    R: Fn(&SignedOrderInfo, &RejectReason) + Send + Sync + 'static,
+{
+    let filter = Arc::new(filter);
+    let current_slot = Arc::new(current_slot);
+    let on_reject = Arc::new(on_reject);
+    orders
+        .filter_map(|item| futures_util::future::ready(item.into_order()))
+        .map(move |order| {
+            let client = client.clone();
+            let filter = Arc::clone(&filter);
+            let current_slot = Arc::clone(&current_slot);
+            let on_reject = Arc::clone(&on_reject);
+            async move {
+                match filter.evaluate(&client, &order, current_slot()).await {
+                    Ok(()) => Some(order),
+                    Err(reason) => {
+                        on_reject(&order, &reason);
+                        None
+                    }
+                }
+            }
+        })
+        .buffered(MAX_CONCURRENT_EVALUATIONS)
+        .filter_map(futures_util::future::ready)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::constants::{BASE_PRECISION_U64, PRICE_PRECISION_I64, PRICE_PRECISION_U64},
+        solana_sdk::signature::Signature,
+        swift_order_subscriber::SignedOrder,
+        types::MarketType,
+        Pubkey,
+    };
+
+    fn order_params() -> OrderParams {
+        OrderParams {
+            order_type: OrderType::Market,
+            market_type: MarketType::Perp,
+            direction: PositionDirection::Long,
+            base_asset_amount: BASE_PRECISION_U64,
+            market_index: 1,
+            price: 101 * PRICE_PRECISION_U64,
+            auction_duration: Some(10),
+            auction_start_price: Some(99 * PRICE_PRECISION_I64),
+            auction_end_price: Some(100 * PRICE_PRECISION_I64),
+            ..Default::default()
+        }
+    }
+
+    fn signed_order(params: OrderParams, slot: u64) -> SignedOrderInfo {
+        SignedOrderInfo::authority(
+            Pubkey::new_unique(),
+            SignedOrder {
+                signed_msg_order_params: params,
+                slot,
+                uuid: *b"abcdefgh",
+                ..Default::default()
+            },
+            Signature::default(),
+        )
+    }
+
+    #[test]
+    fn default_filter_accepts() {
+        let filter = SwiftOrderFilter::default();
+        assert!(filter
+            .check_order(&signed_order(order_params(), 100), 1_000)
+            .is_ok());
+    }
+
+    #[test]
+    fn filter_order_checks() {
+        let filter = SwiftOrderFilter::default()
+            .markets(&[0, 2])
+            .size_bounds(BASE_PRECISION_U64 / 10, Some(10 * BASE_PRECISION_U64))
+            .max_slot_age(10);
+        assert_eq!(
+            filter.check_order(&signed_order(order_params(), 100), 100),
+            Err(RejectReason::MarketNotAllowed(1))
+        );
+
+        let filter = filter.markets(&[1]);
+        assert!(filter
+            .check_order(&signed_order(order_params(), 100), 110)
+            .is_ok());
+        assert_eq!(
+            filter.check_order(&signed_order(order_params(), 100), 111),
+            Err(RejectReason::StaleSlot {
+                order_slot: 100,
+                current_slot: 111,
+                max_age: 10,
+            })
+        );
+
+        let params = OrderParams {
+            base_asset_amount: BASE_PRECISION_U64 / 100,
+            ..order_params()
+        };
+        assert_eq!(
+            filter.check_order(&signed_order(params, 100), 100),
+            Err(RejectReason::OrderTooSmall {
+                base_asset_amount: BASE_PRECISION_U64 / 100,
+                min: BASE_PRECISION_U64 / 10,
+            })
+        );
+        let params = OrderParams {
+            base_asset_amount: 11 * BASE_PRECISION_U64,
+            ..order_params()
+        };
+        assert_eq!(
+            filter.check_order(&signed_order(params, 100), 100),
+            Err(RejectReason::OrderTooLarge {
+                base_asset_amount: 11 * BASE_PRECISION_U64,
+                max: 10 * BASE_PRECISION_U64,
+            })
+        );
+    }
+
+    #[test]
+    fn filter_auction_params() {
+        let filter = SwiftOrderFilter::default()
+            .check_auction_params(true)
+            .max_auction_duration(20);
+        assert!(filter
+            .check_order(&signed_order(order_params(), 100), 100)
+            .is_ok());
+
+        let params = OrderParams {
+            auction_duration: None,
+            ..order_params()
+        };
+        assert_eq!(
+            filter.check_order(&signed_order(params, 100), 100),
+            Err(RejectReason::InvalidAuctionParams)
+        );
+        // long auction must not decrease price
+        let params = OrderParams {
+            auction_start_price: Some(100 * PRICE_PRECISION_I64),
+            auction_end_price: Some(99 * PRICE_PRECISION_I64),
+            ..order_params()
+        };
+        assert_eq!(
+            filter.check_order(&signed_order(params, 100), 100),
+            Err(RejectReason::InvalidAuctionParams)
+        );
+        // end price beyond limit price
+        let params = OrderParams {
+            auction_end_price: Some(102 * PRICE_PRECISION_I64),
+            ..order_params()
+        };
+        assert_eq!(
+            filter.check_order(&signed_order(params, 100), 100),
+            Err(RejectReason::InvalidAuctionParams)
+        );
+        // oracle offsets may be negative
+        let params = OrderParams {
+            order_type: OrderType::Oracle,
+            direction: PositionDirection::Short,
+            auction_start_price: Some(PRICE_PRECISION_I64 / 10),
+            auction_end_price: Some(-PRICE_PRECISION_I64 / 10),
+            ..order_params()
+        };
+        assert!(filter.check_order(&signed_order(params, 100), 100).is_ok());
+
+        let params = OrderParams {
+            auction_duration: Some(30),
+            ..order_params()
+        };
+        assert_eq!(
+            filter.check_order(&signed_order(params, 100), 100),
+            Err(RejectReason::AuctionTooLong {
+                duration: 30,
+                max: 20
+            })
+        );
+    }
+
+    #[test]
+    fn filter_builder_and_isolated() {
+        let filter = SwiftOrderFilter::default()
+            .reject_builder_orders(true)
+            .reject_isolated_deposits(true);
+        let order = SignedOrderInfo::authority(
+            Pubkey::new_unique(),
+            SignedOrder {
+                signed_msg_order_params: order_params(),
+                builder_idx: Some(0),
+                builder_fee_tenth_bps: Some(10),
+                ..Default::default()
+            },
+            Signature::default(),
+        );
+        assert_eq!(filter.check_order(&order, 0), Err(RejectReason::BuilderFee));
+
+        let order = SignedOrderInfo::authority(
+            Pubkey::new_unique(),
+            SignedOrder {
+                signed_msg_order_params: order_params(),
+                isolated_position_deposit: Some(1_000_000),
+                ..Default::default()
+            },
+            Signature::default(),
+        );
+        assert_eq!(
+            filter.check_order(&order, 0),
+            Err(RejectReason::IsolatedDeposit)
+        );
+    }
+
+    #[test]
+    fn filter_taker_health() {
+        let filter = SwiftOrderFilter::default();
+        assert!(!filter.requires_taker());
+        assert!(filter.check_taker(&User::default(), 0).is_ok());
+
+        let filter = filter.check_taker_health(100);
+        assert!(filter.requires_taker());
+        assert!(filter.check_taker(&User::default(), 100).is_ok());
+        assert_eq!(
+            filter.check_taker(&User::default(), 99),
+            Err(RejectReason::TakerUnhealthy {
+                free_collateral: 99,
+                min: 100
+            })
+        );
+        let liquidated = User {
+            status: User::STATUS_BEING_LIQUIDATED,
+            ..Default::default()
+        };
+        assert_eq!(
+            filter.check_taker(&liquidated, 1_000),
+            Err(RejectReason::TakerLiquidatable)
+        );
+    }
+}
@@ -1,5 +1,8 @@
 //! Swift order subscriber and serialization utilities
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    collections::{HashSet, VecDeque},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use crate::solana_sdk::{clock::Slot, pubkey::Pubkey, signature::Signature};
 use anchor_lang::{AnchorDeserialize, AnchorSerialize, Space};
@@ -7,10 +10,12 @@ use base64::Engine;
 use futures_util::{SinkExt, StreamExt};
 use serde::Deserialize;
 use serde_json::{json, Value};
+use tokio::{net::TcpStream, sync::mpsc::Sender};
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_tungstenite::{
     connect_async,
     tungstenite::{error::Error as WsError, Message},
+    MaybeTlsStream, WebSocketStream,
 };
 
 pub use crate::types::{
@@ -18,6 +23,7 @@ pub use crate::types::{
     SignedMsgOrderParamsMessage as SignedOrder,
 };
 use crate::{
+    async_utils::retry_policy::TaskRetryPolicy,
     constants::MarketExt,
     types::{Context, MarketId, OrderParams, SdkError, SdkResult},
     DriftClient, Wallet,
@@ -362,6 +368,75 @@ impl SignedOrderInfo {
 /// Emits swift orders from the Ws server
 pub type SwiftOrderStream = ReceiverStream<SignedOrderInfo>;
 
+/// Emits swift orders from the Ws server across reconnects
+///
+/// `Err` items report recoverable issues (malformed message, lost connection, etc.), the stream
+/// only ends once the reconnect policy gives up
+pub type ResilientSwiftOrderStream = ReceiverStream<Result<SignedOrderInfo, SwiftError>>;
+
+type SwiftWsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Swift order subscription and submission error
+#[derive(Debug, thiserror::Error)]
+pub enum SwiftError {
+    #[error("swift ws connection err: {0}")]
+    Connection(Box<WsError>),
+    #[error("swift http err: {0}")]
+    Http(Box<reqwest::Error>),
+    #[error("swift auth failed: {0}")]
+    Auth(String),
+    #[error("swift server err: {0}")]
+    Server(String),
+    #[error("malformed swift message: {0}")]
+    MalformedMessage(String),
+    #[error("missed swift heartbeat")]
+    MissedHeartbeat,
+    #[error("swift server closed connection")]
+    ConnectionClosed,
+    #[error("market unsupported by swift: {0:?}")]
+    InvalidMarket(MarketId),
+    #[error("swift reconnect attempts exhausted")]
+    RetryLimitReached,
+}
+
+impl SwiftError {
+    /// Map to the errors returned by `subscribe_swift_orders` before typed swift errors
+    fn into_legacy(self) -> SdkError {
+        match self {
+            Self::Connection(err) => SdkError::WsClient(err),
+            Self::Server(_) | Self::ConnectionClosed => SdkError::WebsocketError,
+            err => SdkError::Swift(Box::new(err)),
+        }
+    }
+}
+
+/// Options for `subscribe_swift_orders_resilient`
+#[derive(Clone, Debug)]
+pub struct SwiftSubscribeOpts {
+    /// set to true to receive *sanitized order flow
+    pub accept_sanitized: bool,
+    /// set to true to receive 'deposit+trade' order flow
+    pub accept_deposit_trades: bool,
+    /// custom swift Ws server endpoint
+    pub swift_ws_override: Option<String>,
+    /// reconnect if no message is received from the server within this duration
+    pub heartbeat_timeout: Duration,
+    /// number of recent order uuids tracked to drop duplicates across reconnects
+    pub dedup_capacity: usize,
+}
+
+impl Default for SwiftSubscribeOpts {
+    fn default() -> Self {
+        Self {
+            accept_sanitized: false,
+            accept_deposit_trades: false,
+            swift_ws_override: None,
+            heartbeat_timeout: Duration::from_secs(30),
+            dedup_capacity: 4_096,
+        }
+    }
+}
+
 /// Subscribe to the Swift WebSocket server, authenticate, and listen to new orders
 ///
 /// * `client` - Drift client instance
@@ -376,7 +451,8 @@ pub type SwiftOrderStream = ReceiverStream<SignedOrderInfo>;
 /// * deposit+trade orders require fillers to send an attached, preceding deposit tx
 ///   before the swift order
 ///
-/// Returns a stream of new Swift order messages
+/// Returns a stream of new Swift order messages, the stream ends on any connection error.
+/// see `subscribe_swift_orders_resilient` for a stream that reconnects automatically
 pub async fn subscribe_swift_orders(
     client: &DriftClient,
     markets: &[MarketId],
@@ -384,124 +460,21 @@ pub async fn subscribe_swift_orders(
     accept_deposit_trades: bool,
     swift_ws_override: Option<String>,
 ) -> SdkResult<SwiftOrderStream> {
-    let base_url = if let Some(custom_base_url) = swift_ws_override {
-        custom_base_url
-    } else if client.context == Context::MainNet {
-        SWIFT_MAINNET_WS_URL.to_string()
-    } else {
-        SWIFT_DEVNET_WS_URL.to_string()
-    };
-
-    let maker_pubkey = client.wallet().authority().to_string();
-    let uri = format!("{base_url}/ws?pubkey={maker_pubkey}");
-    let (ws_stream, _) = connect_async(uri).await.map_err(|err| {
-        log::error!(target: LOG_TARGET, "couldn't connect to server: {err:?}");
-        SdkError::WsClient(Box::new(err))
-    })?;
-
-    let (mut outgoing, mut incoming) = ws_stream.split();
-
-    // handle authentication and subscription
-    while let Some(msg) = incoming.next().await {
-        let msg = msg.map_err(|err| {
-            log::error!(target: LOG_TARGET, "failed reading swift msg: {err:?}");
-            SdkError::WsClient(Box::new(err))
-        })?;
-
-        if let Message::Text(text) = msg {
-            log::debug!(target: LOG_TARGET, "msg: {text}");
-            let message: Value = serde_json::from_str(&text).expect("Failed to parse message");
-
-            if let Some(err) = message.get("error") {
-                log::error!(target: LOG_TARGET, "swift server error: {err:?}");
-                return Err(SdkError::WebsocketError);
-            }
-
-            // authenticate with Ws server
-            if message["channel"] == "auth" && message.get("nonce").is_some() {
-                let nonce = message["nonce"].as_str().expect("got nonce");
-                let signature = client
-                    .wallet()
-                    .sign_message(nonce.as_bytes())
-                    .expect("infallible");
-                let signature_b64 =
-                    base64::engine::general_purpose::STANDARD.encode(signature.as_ref());
-
-                let auth_message = json!({
-                    "pubkey": maker_pubkey,
-                    "signature": signature_b64,
-                })
-                .to_string();
-                outgoing.send(Message::Text(auth_message.into())).await?;
-                continue;
-            }
-
-            // subscribe to markets
-            if message["channel"] == "auth" && message["message"] == "Authenticated" {
-                let subscribe_msgs: Vec<Result<Message, _>> = markets
-                    .iter()
-                    .filter_map(|m| {
-                        assert!(m.is_perp(), "only perp markets");
-                        let market = client
-                            .program_data()
-                            .perp_market_config_by_index(m.index())
-                            .expect("market exists");
-                        if !market.symbol().contains("BET") {
-                            let subscribe_msg = json!({
-                              "action": "subscribe",
-                              "market_type": "perp",
-                              "market_name": market.symbol(),
-                            })
-                            .to_string();
-                            Some(Ok(Message::Text(subscribe_msg.into())))
-                        } else {
-                            // skipping bet market
-                            log::debug!(target: LOG_TARGET, "skip subscribe for bet market: {}", market.market_index);
-                            None
-                        }
-                    })
-                    .collect();
-
-                outgoing
-                    .send_all(&mut futures_util::stream::iter(subscribe_msgs))
-                    .await?;
-                break;
-            }
-        }
-    }
+    let base_url = swift_ws_url(client.context, swift_ws_override);
+    let market_names = swift_market_names(client, markets).map_err(SwiftError::into_legacy)?;
+    let mut ws_stream = connect_swift(client.wallet(), &base_url, &market_names)
+        .await
+        .map_err(SwiftError::into_legacy)?;
 
     let (tx, rx) = tokio::sync::mpsc::channel(256);
 
     // handle swift orders
     tokio::spawn(async move {
-        while let Some(msg) = incoming.next().await {
+        while let Some(msg) = ws_stream.next().await {
             match msg {
                 Ok(Message::Text(ref text)) => {
-                    match serde_json::from_str::<OrderNotification>(text) {
-                        Ok(OrderNotification {
-                            channel: _,
-                            mut order,
-                            deposit,
-                        }) => {
-                            log::debug!(
-                                target: LOG_TARGET,
-                                "uuid: {}, latency: {}ms",
-                                order.uuid,
-                                unix_now_ms().saturating_sub(order.ts)
-                            );
-
-                            if let Some(deposit) = deposit {
-                                if !accept_deposit_trades {
-                                    log::debug!(
-                                        target: LOG_TARGET,
-                                        "skipping deposit+trade order: {}",
-                                        order.uuid
-                                    );
-                                    continue;
-                                }
-                                order.pre_deposit = Some(deposit.to_string());
-                            }
-
+                    match parse_swift_message(text, accept_deposit_trades) {
+                        Ok(SwiftMessage::Order(order)) => {
                             if !accept_sanitized {
                                 log::debug!(
                                     target: LOG_TARGET,
@@ -515,22 +488,13 @@ pub async fn subscribe_swift_orders(
                                 break;
                             }
                         }
+                        Ok(SwiftMessage::Heartbeat | SwiftMessage::Skipped) => continue,
+                        Err(err @ SwiftError::Server(_)) => {
+                            log::error!(target: LOG_TARGET, "{err}");
+                            continue;
+                        }
                         Err(err) => {
-                            if text.contains("heartbeat") {
-                                if let Ok(heartbeat) = serde_json::from_str::<Heartbeat>(text) {
-                                    log::debug!(
-                                        target: LOG_TARGET,
-                                        "heartbeat latency: {}",
-                                        unix_now_ms().saturating_sub(heartbeat.ts)
-                                    );
-                                    continue;
-                                }
-                            }
-                            if let Ok(msg) = serde_json::from_str::<ErrorNotification>(text) {
-                                log::error!(target: LOG_TARGET, "{msg:?}");
-                                continue;
-                            }
-                            log::error!(target: LOG_TARGET, "{text}. invalid json response: {err:?}");
+                            log::error!(target: LOG_TARGET, "{err}");
                             break;
                         }
                     }
@@ -561,6 +525,340 @@ pub async fn subscribe_swift_orders(
     Ok(ReceiverStream::new(rx))
 }
 
+/// Subscribe to the Swift WebSocket server with automatic reconnects
+///
+/// On connection loss or a missed heartbeat the subscriber re-authenticates and resubscribes to
+/// `markets` according to `retry_policy`. Orders are deduplicated by uuid across reconnects.
+///
+/// * `client` - Drift client instance
+/// * `markets` - markets to listen on for new swift orders
+/// * `opts` - subscription options
+/// * `retry_policy` - reconnect policy, attempts reset after each successful reconnect
+///
+/// Returns a stream of new Swift order messages or recoverable errors
+pub async fn subscribe_swift_orders_resilient(
+    client: &DriftClient,
+    markets: &[MarketId],
+    opts: SwiftSubscribeOpts,
+    mut retry_policy: impl TaskRetryPolicy,
+) -> SdkResult<ResilientSwiftOrderStream> {
+    let base_url = swift_ws_url(client.context, opts.swift_ws_override.clone());
+    let market_names = swift_market_names(client, markets)?;
+    // fail fast on the initial connection e.g. bad endpoint or signing disabled
+    let mut ws_stream = connect_swift(client.wallet(), &base_url, &market_names).await?;
+
+    let wallet = client.wallet().clone();
+    let (tx, rx) = tokio::sync::mpsc::channel(256);
+
+    tokio::spawn(async move {
+        let mut seen = SeenOrders::new(opts.dedup_capacity);
+        let mut attempts = 0;
+        loop {
+            match run_swift_session(&mut ws_stream, &tx, &mut seen, &opts).await {
+                Some(err) => {
+                    log::warn!(target: LOG_TARGET, "swift session ended: {err}");
+                    if tx.send(Err(err)).await.is_err() {
+                        return;
+                    }
+                }
+                // receiver dropped
+                None => return,
+            }
+
+            ws_stream = loop {
+                if !retry_policy.check(attempts).await {
+                    log::error!(target: LOG_TARGET, "swift reconnect attempts exhausted");
+                    let _ = tx.send(Err(SwiftError::RetryLimitReached)).await;
+                    return;
+                }
+                attempts += 1;
+                log::info!(target: LOG_TARGET, "reconnecting swift ws, attempt: {attempts}");
+                match connect_swift(&wallet, &base_url, &market_names).await {
+                    Ok(ws_stream) => {
+                        attempts = 0;
+                        break ws_stream;
+                    }
+                    Err(err) => {
+                        log::error!(target: LOG_TARGET, "swift reconnect failed: {err}");
+                        if tx.send(Err(err)).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            };
+        }
+    });
+
+    Ok(ReceiverStream::new(rx))
+}
+
+/// Forward orders from `ws_stream` until the connection fails
+///
+/// Returns the error ending the session or `None` if the receiver was dropped
+async fn run_swift_session(
+    ws_stream: &mut SwiftWsStream,
+    tx: &Sender<Result<SignedOrderInfo, SwiftError>>,
+    seen: &mut SeenOrders,
+    opts: &SwiftSubscribeOpts,
+) -> Option<SwiftError> {
+    loop {
+        let msg = match tokio::time::timeout(opts.heartbeat_timeout, ws_stream.next()).await {
+            Ok(Some(msg)) => msg,
+            Ok(None) => return Some(SwiftError::ConnectionClosed),
+            Err(_) => return Some(SwiftError::MissedHeartbeat),
+        };
+        let item = match msg {
+            Ok(Message::Text(ref text)) => {
+                match parse_swift_message(text, opts.accept_deposit_trades) {
+                    Ok(SwiftMessage::Order(order)) => {
+                        if order.will_sanitize && !opts.accept_sanitized {
+                            log::debug!(
+                                target: LOG_TARGET,
+                                "skipping sanitized order: {}",
+                                order.uuid
+                            );
+                            continue;
+                        }
+                        if !seen.insert(order.order_uuid()) {
+                            log::debug!(target: LOG_TARGET, "duplicate order: {}", order.uuid);
+                            continue;
+                        }
+                        Ok(order)
+                    }
+                    Ok(SwiftMessage::Heartbeat | SwiftMessage::Skipped) => continue,
+                    Err(err) => Err(err),
+                }
+            }
+            Ok(Message::Close(_)) => return Some(SwiftError::ConnectionClosed),
+            Ok(_) => continue,
+            Err(WsError::Utf8(err)) => Err(SwiftError::MalformedMessage(format!(
+                "invalid UTF-8: {err:?}"
+            ))),
+            Err(err) => return Some(SwiftError::Connection(Box::new(err))),
+        };
+        if tx.send(item).await.is_err() {
+            return None;
+        }
+    }
+}
+
+/// Bounded set of recently seen order uuids
+struct SeenOrders {
+    capacity: usize,
+    order: VecDeque<[u8; 8]>,
+    set: HashSet<[u8; 8]>,
+}
+
+impl SeenOrders {
+    fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            order: VecDeque::with_capacity(capacity),
+            set: HashSet::with_capacity(capacity),
+        }
+    }
+    /// Returns true if `uuid` was not seen before
+    fn insert(&mut self, uuid: [u8; 8]) -> bool {
+        if !self.set.insert(uuid) {
+            return false;
+        }
+        self.order.push_back(uuid);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.set.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// Return the swift Ws endpoint for `context`
+fn swift_ws_url(context: Context, swift_ws_override: Option<String>) -> String {
+    if let Some(custom_base_url) = swift_ws_override {
+        custom_base_url
+    } else if context == Context::MainNet {
+        SWIFT_MAINNET_WS_URL.to_string()
+    } else {
+        SWIFT_DEVNET_WS_URL.to_string()
+    }
+}
+
+/// Return swift channel names of `markets`
+fn swift_market_names(
+    client: &DriftClient,
+    markets: &[MarketId],
+) -> Result<Vec<String>, SwiftError> {
+    let mut market_names = Vec::with_capacity(markets.len());
+    for m in markets {
+        if !m.is_perp() {
+            return Err(SwiftError::InvalidMarket(*m));
+        }
+        let market = client
+            .program_data()
+            .perp_market_config_by_index(m.index())
+            .ok_or(SwiftError::InvalidMarket(*m))?;
+        if market.symbol().contains("BET") {
+            // skipping bet market
+            log::debug!(target: LOG_TARGET, "skip subscribe for bet market: {}", market.market_index);
+            continue;
+        }
+        market_names.push(market.symbol().to_string());
+    }
+
+    Ok(market_names)
+}
+
+/// Connect to the swift Ws server, authenticate and subscribe to `market_names`
+///
+/// A delegated `wallet` authenticates with its signer key on behalf of the wallet authority,
+/// the signer must be registered as a swift Ws delegate of the authority
+async fn connect_swift(
+    wallet: &Wallet,
+    base_url: &str,
+    market_names: &[String],
+) -> Result<SwiftWsStream, SwiftError> {
+    let maker_pubkey = wallet.signer().to_string();
+    let uri = format!("{base_url}/ws?pubkey={maker_pubkey}");
+    let (mut ws_stream, _) = connect_async(uri).await.map_err(|err| {
+        log::error!(target: LOG_TARGET, "couldn't connect to server: {err:?}");
+        SwiftError::Connection(Box::new(err))
+    })?;
+
+    // handle authentication and subscription
+    while let Some(msg) = ws_stream.next().await {
+        let msg = msg.map_err(|err| {
+            log::error!(target: LOG_TARGET, "failed reading swift msg: {err:?}");
+            SwiftError::Connection(Box::new(err))
+        })?;
+
+        if let Message::Text(text) = msg {
+            log::debug!(target: LOG_TARGET, "msg: {text}");
+            let message: Value = serde_json::from_str(&text)
+                .map_err(|err| SwiftError::MalformedMessage(format!("{text}: {err}")))?;
+
+            if let Some(err) = message.get("error") {
+                log::error!(target: LOG_TARGET, "swift server error: {err:?}");
+                return Err(SwiftError::Server(err.to_string()));
+            }
+
+            // authenticate with Ws server
+            if message["channel"] == "auth" && message.get("nonce").is_some() {
+                let nonce = message["nonce"].as_str().ok_or_else(|| {
+                    SwiftError::MalformedMessage(format!("invalid nonce: {text}"))
+                })?;
+                let signature = wallet
+                    .sign_message(nonce.as_bytes())
+                    .map_err(|err| SwiftError::Auth(err.to_string()))?;
+                let signature_b64 =
+                    base64::engine::general_purpose::STANDARD.encode(signature.as_ref());
+
+                let auth_message = auth_message(wallet, &maker_pubkey, &signature_b64);
+                ws_stream
+                    .send(Message::Text(auth_message.into()))
+                    .await
+                    .map_err(|err| SwiftError::Connection(Box::new(err)))?;
+                continue;
+            }
+
+            // subscribe to markets
+            if message["channel"] == "auth" && message["message"] == "Authenticated" {
+                let subscribe_msgs = market_names.iter().map(|market_name| {
+                    let subscribe_msg = json!({
+                      "action": "subscribe",
+                      "market_type": "perp",
+                      "market_name": market_name,
+                    })
+                    .to_string();
+                    Ok::<_, WsError>(Message::Text(subscribe_msg.into()))
+                });
+
+                ws_stream
+                    .send_all(&mut futures_util::stream::iter(subscribe_msgs))
+                    .await
+                    .map_err(|err| SwiftError::Connection(Box::new(err)))?;
+                return Ok(ws_stream);
+            }
+        }
+    }
+
+    Err(SwiftError::ConnectionClosed)
+}
+
+/// Build the Ws auth message, naming the authority if signed by a delegate
+fn auth_message(wallet: &Wallet, maker_pubkey: &str, signature_b64: &str) -> String {
+    let mut message = json!({
+        "pubkey": maker_pubkey,
+        "signature": signature_b64,
+    });
+    if wallet.is_delegated() {
+        message["stake_pubkey"] = wallet.authority().to_string().into();
+    }
+    message.to_string()
+}
+
+/// Parsed swift Ws message
+enum SwiftMessage {
+    Order(SignedOrderInfo),
+    Heartbeat,
+    /// order filtered by subscription options
+    Skipped,
+}
+
+/// Parse a swift Ws text message
+fn parse_swift_message(
+    text: &str,
+    accept_deposit_trades: bool,
+) -> Result<SwiftMessage, SwiftError> {
+    match serde_json::from_str::<OrderNotification>(text) {
+        Ok(OrderNotification {
+            channel: _,
+            mut order,
+            deposit,
+        }) => {
+            log::debug!(
+                target: LOG_TARGET,
+                "uuid: {}, latency: {}ms",
+                order.uuid,
+                unix_now_ms().saturating_sub(order.ts)
+            );
+
+            if let Some(deposit) = deposit {
+                if !accept_deposit_trades {
+                    log::debug!(
+                        target: LOG_TARGET,
+                        "skipping deposit+trade order: {}",
+                        order.uuid
+                    );
+                    return Ok(SwiftMessage::Skipped);
+                }
+                order.pre_deposit = Some(deposit.to_string());
+            }
+
+            Ok(SwiftMessage::Order(order))
+        }
+        Err(err) => {
+            if text.contains("heartbeat") {
+                if let Ok(heartbeat) = serde_json::from_str::<Heartbeat>(text) {
+                    log::debug!(
+                        target: LOG_TARGET,
+                        "heartbeat latency: {}",
+                        unix_now_ms().saturating_sub(heartbeat.ts)
+                    );
+                    return Ok(SwiftMessage::Heartbeat);
+                }
+            }
+            if let Ok(msg) = serde_json::from_str::<ErrorNotification>(text) {
+                return Err(SwiftError::Server(format!(
+                    "{}: {}",
+                    msg.channel, msg.error
+                )));
+            }
+            Err(SwiftError::MalformedMessage(format!("{text}: {err}")))
+        }
+    }
+}
+
 fn unix_now_ms() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -573,7 +871,7 @@ where
     D: serde::de::Deserializer<'de>,
 {
     let s: &str = serde::de::Deserialize::deserialize(deserializer)?;
-    Ok(s.parse().expect("base58 pubkey"))
+    s.parse().map_err(serde::de::Error::custom)
 }
 
 fn deser_signature<'de, D>(deserializer: D) -> Result<Signature, D::Error>
@@ -581,7 +879,10 @@ where
     D: serde::de::Deserializer<'de>,
 {
     let s: &str = serde::de::Deserialize::deserialize(deserializer)?;
-    Ok(Signature::try_from(base64::engine::general_purpose::STANDARD.decode(s).unwrap()).unwrap())
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(s)
+        .map_err(serde::de::Error::custom)?;
+    Signature::try_from(bytes).map_err(|_| serde::de::Error::custom("invalid signature length"))
 }
 
 fn deser_int_str<'de, D>(deserializer: D) -> Result<u64, D::Error>
@@ -589,7 +890,7 @@ where
     D: serde::de::Deserializer<'de>,
 {
     let s: &str = serde::de::Deserialize::deserialize(deserializer)?;
-    Ok(s.parse().unwrap())
+    s.parse().map_err(serde::de::Error::custom)
 }
 
 /// Deserialize hex-ified, borsh bytes as a `SignedOrderType`
@@ -634,6 +935,7 @@ mod tests {
     use super::*;
     use crate::{
         drift_idl,
+        solana_sdk::keypair::Keypair,
         types::{MarketType, OrderTriggerCondition, OrderType, PositionDirection, PostOnlyParam},
     };
 
@@ -707,6 +1009,108 @@ mod tests {
         assert_eq!(signed_message.order_params().market_type, MarketType::Perp);
     }
 
+    #[test]
+    fn parse_swift_message_typed_errors() {
+        assert!(matches!(
+            parse_swift_message("not json", true),
+            Err(SwiftError::MalformedMessage(_))
+        ));
+        // invalid pubkey does not panic
+        let msg = r#"{
+            "channel":"signed_orders_perp_1",
+            "order":{
+                "order_message":"b9c165ffdf70594d0001010080841e00000000000000000000000000010000000000000000013201a4e99abc16000000011ab2f982160000000300900f84150000000072753959424c52740000",
+                "order_signature":"FIgxWlW+C0abvtE8esSko7At1YGM8h66T0u5lJpwXirW63CuvEllVWZ68NNVFsaqcj4jqgQInXUnLPjIf/PQDA==",
+                "signing_authority":"not-a-pubkey",
+                "taker_authority":"DxoRJ4f5XRMvXU9SGuM4ZziBFUxbhB3ubur5sVZEvue2",
+                "ts":1739518796400,
+                "uuid":"ru9YBLRt"
+            }
+        }"#;
+        assert!(matches!(
+            parse_swift_message(msg, true),
+            Err(SwiftError::MalformedMessage(_))
+        ));
+        assert!(matches!(
+            parse_swift_message(r#"{"channel":"heartbeat","message":"1739518796400"}"#, true),
+            Ok(SwiftMessage::Heartbeat)
+        ));
+        assert!(matches!(
+            parse_swift_message(
+                r#"{"channel":"signed_orders_perp_1","error":"rate limited"}"#,
+                true
+            ),
+            Err(SwiftError::Server(_))
+        ));
+    }
+
+    #[test]
+    fn parse_swift_message_filters() {
+        let order = serde_json::json!({
+            "order_message":"b9c165ffdf70594d0001010080841e00000000000000000000000000010000000000000000013201a4e99abc16000000011ab2f982160000000300900f84150000000072753959424c52740000",
+            "order_signature":"FIgxWlW+C0abvtE8esSko7At1YGM8h66T0u5lJpwXirW63CuvEllVWZ68NNVFsaqcj4jqgQInXUnLPjIf/PQDA==",
+            "signing_authority":"4rmhwytmKH1XsgGAUyUUH7U64HS5FtT6gM8HGKAfwcFE",
+            "taker_authority":"DxoRJ4f5XRMvXU9SGuM4ZziBFUxbhB3ubur5sVZEvue2",
+            "ts":1739518796400_u64,
+            "uuid":"ru9YBLRt",
+            "will_sanitize": true,
+        });
+        let msg = serde_json::json!({
+            "channel": "signed_orders_perp_1",
+            "order": order,
+        })
+        .to_string();
+        // sanitized orders are filtered by the subscriber
+        assert!(matches!(
+            parse_swift_message(&msg, false),
+            Ok(SwiftMessage::Order(order)) if order.will_sanitize
+        ));
+
+        let msg = serde_json::json!({
+            "channel": "signed_orders_perp_1",
+            "order": order,
+            "deposit": "deposit-tx",
+        })
+        .to_string();
+        assert!(matches!(
+            parse_swift_message(&msg, false),
+            Ok(SwiftMessage::Skipped)
+        ));
+        match parse_swift_message(&msg, true) {
+            Ok(SwiftMessage::Order(order)) => {
+                assert_eq!(order.pre_deposit.as_deref(), Some("deposit-tx"))
+            }
+            _ => panic!("expected order"),
+        }
+    }
+
+    #[test]
+    fn seen_orders_dedup() {
+        let mut seen = SeenOrders::new(2);
+        assert!(seen.insert(*b"aaaaaaaa"));
+        assert!(!seen.insert(*b"aaaaaaaa"));
+        assert!(seen.insert(*b"bbbbbbbb"));
+        assert!(seen.insert(*b"cccccccc"));
+        // oldest evicted
+        assert!(seen.insert(*b"aaaaaaaa"));
+        assert!(!seen.insert(*b"cccccccc"));
+    }
+
+    #[test]
+    fn auth_message_names_authority_for_delegate() {
+        let authority = Wallet::new(Keypair::new());
+        let message = auth_message(&authority, "maker", "sig");
+        let message: Value = serde_json::from_str(&message).unwrap();
+        assert_eq!(message["pubkey"], "maker");
+        assert!(message.get("stake_pubkey").is_none());
+
+        let delegate = Wallet::delegated(Keypair::new(), *authority.authority());
+        let message = auth_message(&delegate, "maker", "sig");
+        let message: Value = serde_json::from_str(&message).unwrap();
+        assert_eq!(message["signature"], "sig");
+        assert_eq!(message["stake_pubkey"], authority.authority().to_string());
+    }
+
     #[test]
     fn test_swift_order_encode_for_signing() {
         let msg = "{\"channel\":\"swift_orders_perp_2\",\"order\":{\"market_index\":2,\"market_type\":\"perp\",\"order_message\":\"c8d5a65e2234f55d0001010080841e0000000000000000000000000002000000000000000001320124c6aa950000000001786b2f94000000000000bb64a9150000000074735730364f6d380000\",\"order_signature\":\"SaOaLJ1i0MqZ2cXdp00jGe2EJFa32eOfiQynFU7mclhT86yhIa4/tWXq7r6l7QPN0Jl6frfsZl0nNOvKZxZpAA==\",\"signing_authority\":\"4rmhwytmKH1XsgGAUyUUH7U64HS5FtT6gM8HGKAfwcFE\",\"taker_authority\":\"4rmhwytmKH1XsgGAUyUUH7U64HS5FtT6gM8HGKAfwcFE\",\"ts\":1740456840770,\"uuid\":\"tsW06Om8\"}}";
@@ -0,0 +1,613 @@
+//! Swift taker client
+//!
+//! Builds and signs swift order messages, submits them to the swift server and tracks their
+//! placement onchain via `SignedMsgOrderRecord` events
+use std::{borrow::Cow, hash::BuildHasher, sync::Arc, time::Duration};
+
+use base64::Engine;
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::ProgramData,
+    event_subscriber::{DriftEvent, EventSubscriber},
+    solana_sdk::{
+        clock::Slot, message::VersionedMessage, pubkey::Pubkey,
+        transaction::versioned::VersionedTransaction,
+    },
+    swift_order_subscriber::{
+        SignedDelegateOrder, SignedOrder, SignedOrderInfo, SignedOrderType, SwiftError,
+    },
+    types::{accounts::User, Context, NewOrder, OrderParams, SdkError, SdkResult},
+    DriftClient, PubsubClient, TransactionBuilder, Wallet,
+};
+
+const LOG_TARGET: &str = "swift";
+
+pub const SWIFT_MAINNET_URL: &str = "https://swift.drift.trade";
+pub const SWIFT_DEVNET_URL: &str = "https://master.swift.drift.trade";
+
+/// Request body of the swift `/orders` endpoint
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SwiftOrderRequest {
+    /// hex-ified, borsh encoded signed order message
+    pub message: String,
+    #[serde(with = "pubkey_str")]
+    pub taker_authority: Pubkey,
+    /// taker sub-account
+    #[serde(with = "pubkey_str")]
+    pub taker_pubkey: Pubkey,
+    /// authority that signed `message`, either the taker authority or a sub-account delegate
+    #[serde(with = "pubkey_str")]
+    pub signing_authority: Pubkey,
+    /// base64 encoded signature over `message`
+    pub signature: String,
+}
+
+/// Request body of the swift `/depositTrade` endpoint
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SwiftDepositTradeRequest {
+    /// base64 encoded, signed deposit tx
+    pub deposit_tx: String,
+    pub swift_order: SwiftOrderRequest,
+}
+
+/// Response of the swift server to an accepted order
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct SwiftOrderResponse {
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Signed swift order, ready for submission
+#[derive(Clone, Debug)]
+pub struct SignedSwiftOrder {
+    info: SignedOrderInfo,
+    request: SwiftOrderRequest,
+}
+
+impl SignedSwiftOrder {
+    /// Signed order info as used for onchain placement e.g. `TransactionBuilder::place_swift_order`
+    pub fn info(&self) -> &SignedOrderInfo {
+        &self.info
+    }
+    /// Request body for the swift server
+    pub fn request(&self) -> &SwiftOrderRequest {
+        &self.request
+    }
+    /// The order's UUID
+    pub fn uuid(&self) -> [u8; 8] {
+        self.info.order_uuid()
+    }
+    /// The taker sub-account placing the order
+    pub fn taker_subaccount(&self) -> Pubkey {
+        self.request.taker_pubkey
+    }
+}
+
+/// Outcome of a submitted swift order
+#[derive(Clone, Debug, PartialEq)]
+pub enum SwiftOrderOutcome {
+    /// The order was placed onchain
+    Placed {
+        /// user order id assigned to the placed order
+        user_order_id: u32,
+        /// hash of the signed message
+        hash: String,
+        /// signature of the placing tx
+        signature: String,
+        ts: u64,
+    },
+    /// The order was not seen onchain before the timeout
+    ///
+    /// it may still be placed until its max slot passes
+    NotPlaced,
+}
+
+/// Submits swift orders on behalf of a taker
+///
+/// ```ignore
+/// let taker = SwiftTakerClient::new(Context::DevNet, wallet);
+/// let message = SwiftTakerClient::order_message(0, order_params, current_slot + 50);
+/// let order = taker.sign_order(message)?;
+/// let outcome = taker.place_and_track(drift.ws(), &order, Duration::from_secs(10)).await?;
+/// ```
+#[derive(Clone)]
+pub struct SwiftTakerClient {
+    http: reqwest::Client,
+    url: String,
+    wallet: Wallet,
+}
+
+impl SwiftTakerClient {
+    /// Create a new `SwiftTakerClient` for the swift server of `context`
+    ///
+    /// * `wallet` - taker authority wallet, or `Wallet::delegated` to sign as a sub-account delegate
+    pub fn new(context: Context, wallet: Wallet) -> Self {
+        let url = if context == Context::MainNet {
+            SWIFT_MAINNET_URL
+        } else {
+            SWIFT_DEVNET_URL
+        };
+        Self {
+            http: reqwest::Client::new(),
+            url: url.to_string(),
+            wallet,
+        }
+    }
+    /// Set a custom swift server base URL
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into().trim_end_matches('/').to_string();
+        self
+    }
+    /// Set the HTTP client used for requests (e.g. to configure timeouts)
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+    /// Swift server base URL
+    pub fn url(&self) -> &str {
+        self.url.as_str()
+    }
+    /// The wallet signing orders
+    pub fn wallet(&self) -> &Wallet {
+        &self.wallet
+    }
+    /// Build an order message for the signer's own sub-account with a fresh UUID
+    ///
+    /// * `sub_account_id` - taker sub-account id
+    /// * `order_params` - taker order params
+    /// * `max_slot` - last slot the message may be placed at
+    pub fn order_message(
+        sub_account_id: u16,
+        order_params: OrderParams,
+        max_slot: Slot,
+    ) -> SignedOrder {
+        SignedOrder {
+            sub_account_id,
+            signed_msg_order_params: order_params,
+            slot: max_slot,
+            uuid: new_uuid(),
+            take_profit_order_params: None,
+            stop_loss_order_params: None,
+            max_margin_ratio: None,
+            builder_idx: None,
+            builder_fee_tenth_bps: None,
+            isolated_position_deposit: None,
+        }
+    }
+    /// Build an order message from `order` for the signer's own sub-account with a fresh UUID
+    ///
+    /// includes the builder fee set via `NewOrder::builder`, if any
+    ///
+    /// * `sub_account_id` - taker sub-account id
+    /// * `max_slot` - last slot the message may be placed at
+    pub fn new_order_message(sub_account_id: u16, order: NewOrder, max_slot: Slot) -> SignedOrder {
+        order.build_signed_msg(sub_account_id, max_slot, new_uuid())
+    }
+    /// Build an order message for signing by a sub-account delegate, with a fresh UUID
+    ///
+    /// * `taker_pubkey` - taker sub-account
+    /// * `order_params` - taker order params
+    /// * `max_slot` - last slot the message may be placed at
+    pub fn delegate_order_message(
+        taker_pubkey: Pubkey,
+        order_params: OrderParams,
+        max_slot: Slot,
+    ) -> SignedDelegateOrder {
+        SignedDelegateOrder {
+            signed_msg_order_params: order_params,
+            taker_pubkey,
+            slot: max_slot,
+            uuid: new_uuid(),
+            take_profit_order_params: None,
+            stop_loss_order_params: None,
+            max_margin_ratio: None,
+            builder_idx: None,
+            builder_fee_tenth_bps: None,
+            isolated_position_deposit: None,
+        }
+    }
+    /// Sign `order` with the wallet as the taker authority
+    pub fn sign_order(&self, order: SignedOrder) -> SdkResult<SignedSwiftOrder> {
+        let authority = *self.wallet.authority();
+        if self.wallet.signer() != authority {
+            return Err(SdkError::Generic(
+                "delegated wallet must sign delegate orders".into(),
+            ));
+        }
+        let message = hex::encode(SignedOrderType::authority(order).to_borsh());
+        let signature = self.wallet.sign_message(message.as_bytes())?;
+
+        Ok(SignedSwiftOrder {
+            info: SignedOrderInfo::authority(authority, order, signature),
+            request: SwiftOrderRequest {
+                message,
+                taker_authority: authority,
+                taker_pubkey: Wallet::derive_user_account(&authority, order.sub_account_id),
+                signing_authority: authority,
+                signature: base64::prelude::BASE64_STANDARD.encode(signature.as_ref()),
+            },
+        })
+    }
+    /// Sign `order` with the wallet's signer as a delegate of the taker sub-account
+    ///
+    /// The wallet authority is used as the taker authority (see `Wallet::delegated`)
+    pub fn sign_delegate_order(&self, order: SignedDelegateOrder) -> SdkResult<SignedSwiftOrder> {
+        let taker_authority = *self.wallet.authority();
+        let signer = self.wallet.signer();
+        let message = hex::encode(SignedOrderType::delegated(order).to_borsh());
+        let signature = self.wallet.sign_message(message.as_bytes())?;
+
+        Ok(SignedSwiftOrder {
+            info: SignedOrderInfo::delegated(taker_authority, signer, order, signature),
+            request: SwiftOrderRequest {
+                message,
+                taker_authority,
+                taker_pubkey: order.taker_pubkey,
+                signing_authority: signer,
+                signature: base64::prelude::BASE64_STANDARD.encode(signature.as_ref()),
+            },
+        })
+    }
+    /// Submit a signed order to the swift server
+    pub async fn submit_order(&self, order: &SignedSwiftOrder) -> SdkResult<SwiftOrderResponse> {
+        log::debug!(target: LOG_TARGET, "submit order: {:?}", order.request);
+        self.post("orders", &order.request).await
+    }
+    /// Build the tx depositing collateral and placing `order` in one go
+    ///
+    /// Returns the unsigned message for the `/depositTrade` endpoint
+    ///
+    /// * `taker_account` - taker sub-account data
+    /// * `amount` - deposit amount in native units
+    /// * `market_index` - spot market index of the deposit
+    pub fn build_deposit_trade_tx(
+        &self,
+        program_data: &ProgramData,
+        taker_account: &User,
+        order: &SignedSwiftOrder,
+        amount: u64,
+        market_index: u16,
+    ) -> SdkResult<VersionedMessage> {
+        if order.info.using_delegate_signing() {
+            return Err(SdkError::Generic(
+                "depositTrade requires an authority signed order".into(),
+            ));
+        }
+        let spot_market = program_data
+            .spot_market_config_by_index(market_index)
+            .ok_or(SdkError::InvalidAccount)?;
+        let authority = self.wallet.authority();
+        let create_ata_ix =
+            spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                authority,
+                authority,
+                &spot_market.mint,
+                &spot_market.token_program(),
+            );
+
+        Ok(TransactionBuilder::new(
+            program_data,
+            order.taker_subaccount(),
+            Cow::Borrowed(taker_account),
+            false,
+        )
+        .add_ix(create_ata_ix)
+        .deposit(amount, market_index, None, None)
+        .place_swift_order(&order.info, taker_account)
+        .build())
+    }
+    /// Submit a signed deposit tx together with `order` to the swift server
+    pub async fn submit_deposit_trade(
+        &self,
+        deposit_tx: &VersionedTransaction,
+        order: &SignedSwiftOrder,
+    ) -> SdkResult<SwiftOrderResponse> {
+        let deposit_tx = bincode::serialize(deposit_tx)
+            .map_err(|err| SdkError::Generic(format!("deposit tx: {err}")))?;
+        let request = SwiftDepositTradeRequest {
+            deposit_tx: base64::prelude::BASE64_STANDARD.encode(deposit_tx),
+            swift_order: order.request.clone(),
+        };
+        log::debug!(target: LOG_TARGET, "submit depositTrade: {request:?}");
+        self.post("depositTrade", &request).await
+    }
+    /// Deposit collateral and submit `order` via the `/depositTrade` endpoint
+    ///
+    /// * `amount` - deposit amount in native units
+    /// * `market_index` - spot market index of the deposit
+    pub async fn deposit_trade(
+        &self,
+        drift: &DriftClient,
+        order: &SignedSwiftOrder,
+        amount: u64,
+        market_index: u16,
+    ) -> SdkResult<SwiftOrderResponse> {
+        let (taker_account, blockhash) = tokio::try_join!(
+            drift.get_user_account(&order.taker_subaccount()),
+            drift.get_latest_blockhash(),
+        )?;
+        let message = self.build_deposit_trade_tx(
+            drift.program_data(),
+            &taker_account,
+            order,
+            amount,
+            market_index,
+        )?;
+        let deposit_tx = self.wallet.sign_tx(message, blockhash)?;
+        self.submit_deposit_trade(&deposit_tx, order).await
+    }
+    /// Submit `order` and wait up to `timeout` for its onchain placement
+    ///
+    /// * `ws` - Ws client for taker events
+    pub async fn place_and_track(
+        &self,
+        ws: Arc<PubsubClient>,
+        order: &SignedSwiftOrder,
+        timeout: Duration,
+    ) -> SdkResult<SwiftOrderOutcome> {
+        // subscribe first so the placement can't be missed
+        let events = EventSubscriber::subscribe(ws, order.taker_subaccount()).await?;
+        self.submit_order(order).await?;
+        Ok(wait_for_placement(events, order, timeout).await)
+    }
+
+    async fn post<T: Serialize>(&self, path: &str, body: &T) -> SdkResult<SwiftOrderResponse> {
+        let response = self
+            .http
+            .post(format!("{}/{path}", self.url))
+            .json(body)
+            .send()
+            .await
+            .map_err(|err| SdkError::Swift(Box::new(SwiftError::Http(Box::new(err)))))?;
+
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|err| SdkError::Swift(Box::new(SwiftError::Http(Box::new(err)))))?;
+        if !status.is_success() {
+            return Err(SdkError::Swift(Box::new(SwiftError::Server(format!(
+                "{status}: {body}"
+            )))));
+        }
+
+        Ok(
+            serde_json::from_str(&body).unwrap_or_else(|_| SwiftOrderResponse {
+                message: Some(body),
+                error: None,
+            }),
+        )
+    }
+}
+
+/// Wait up to `timeout` for the onchain placement of `order`
+///
+/// * `events` - drift events of the taker sub-account e.g. from `EventSubscriber::subscribe`
+pub async fn wait_for_placement(
+    events: impl Stream<Item = DriftEvent> + Unpin,
+    order: &SignedSwiftOrder,
+    timeout: Duration,
+) -> SwiftOrderOutcome {
+    let taker = order.taker_subaccount();
+    let uuid = order.uuid();
+    let mut placements = events.filter_map(|event| {
+        futures_util::future::ready(match event {
+            DriftEvent::SignedMsgOrder {
+                user,
+                uuid: event_uuid,
+                user_order_id,
+                hash,
+                signature,
+                ts,
+                ..
+            } if user == taker && event_uuid == uuid => Some(SwiftOrderOutcome::Placed {
+                user_order_id,
+                hash,
+                signature,
+                ts,
+            }),
+            _ => None,
+        })
+    });
+
+    match tokio::time::timeout(timeout, placements.next()).await {
+        Ok(Some(outcome)) => outcome,
+        _ => SwiftOrderOutcome::NotPlaced,
+    }
+}
+
+/// New random 8 char, alphanumeric order UUID
+fn new_uuid() -> [u8; 8] {
+    const ALPHABET: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let mut bits = ahash::RandomState::new().hash_one(nanos);
+    let mut uuid = [0_u8; 8];
+    for c in uuid.iter_mut() {
+        *c = ALPHABET[(bits % ALPHABET.len() as u64) as usize];
+        bits /= ALPHABET.len() as u64;
+    }
+    uuid
+}
+
+mod pubkey_str {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::solana_sdk::pubkey::Pubkey;
+
+    pub fn serialize<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(pubkey)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+        let s: &str = Deserialize::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::{
+        solana_sdk::keypair::Keypair,
+        types::{MarketType, OrderType, PositionDirection},
+    };
+
+    fn order_params() -> OrderParams {
+        OrderParams {
+            market_index: 0,
+            market_type: MarketType::Perp,
+            order_type: OrderType::Oracle,
+            base_asset_amount: 100_000_000,
+            direction: PositionDirection::Long,
+            auction_start_price: Some(100),
+            auction_end_price: Some(1_000),
+            auction_duration: Some(20),
+            ..Default::default()
+        }
+    }
+
+    /// Serve one HTTP request, returning the raw request
+    async fn serve_once(listener: TcpListener, status: &'static str, body: &'static str) -> String {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = vec![];
+        let mut buf = [0_u8; 4_096];
+        loop {
+            let n = socket.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((head, payload)) = text.split_once("\r\n\r\n") {
+                let len = head
+                    .lines()
+                    .find_map(|l| {
+                        l.to_lowercase()
+                            .strip_prefix("content-length: ")
+                            .map(|v| v.parse::<usize>().unwrap())
+                    })
+                    .unwrap_or(0);
+                if payload.len() >= len {
+                    break;
+                }
+            }
+        }
+        let response = format!(
+            "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        socket.write_all(response.as_bytes()).await.unwrap();
+        String::from_utf8(request).unwrap()
+    }
+
+    #[test]
+    fn uuids_alphanumeric_and_unique() {
+        let a = new_uuid();
+        assert!(a.iter().all(u8::is_ascii_alphanumeric));
+        assert_ne!(a, new_uuid());
+    }
+
+    #[tokio::test]
+    async fn submit_order_to_mock_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = tokio::spawn(serve_once(
+            listener,
+            "200 OK",
+            r#"{"message":"Order processed"}"#,
+        ));
+
+        let wallet = Wallet::new(Keypair::new());
+        let taker = SwiftTakerClient::new(Context::DevNet, wallet.clone()).with_url(url);
+        let order = taker
+            .sign_order(SwiftTakerClient::order_message(1, order_params(), 1_000))
+            .unwrap();
+        assert_eq!(order.taker_subaccount(), wallet.sub_account(1));
+        assert_eq!(order.info().order_params(), order_params());
+
+        let response = taker.submit_order(&order).await.unwrap();
+        assert_eq!(response.message.as_deref(), Some("Order processed"));
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /orders "));
+        let body: SwiftOrderRequest =
+            serde_json::from_str(request.split_once("\r\n\r\n").unwrap().1).unwrap();
+        assert_eq!(&body, order.request());
+        assert_eq!(
+            body.message.as_bytes(),
+            order.info().encode_for_signing().as_slice()
+        );
+    }
+
+    #[tokio::test]
+    async fn submit_order_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(serve_once(
+            listener,
+            "400 Bad Request",
+            r#"{"error":"invalid signature"}"#,
+        ));
+
+        let taker =
+            SwiftTakerClient::new(Context::DevNet, Wallet::new(Keypair::new())).with_url(url);
+        let order = taker
+            .sign_order(SwiftTakerClient::order_message(0, order_params(), 1_000))
+            .unwrap();
+        assert!(matches!(
+            taker.submit_order(&order).await,
+            Err(SdkError::Swift(err)) if matches!(*err, SwiftError::Server(_))
+        ));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn tracks_placement_by_uuid() {
+        let wallet = Wallet::new(Keypair::new());
+        let taker = SwiftTakerClient::new(Context::DevNet, wallet.clone());
+        let order = taker
+            .sign_order(SwiftTakerClient::order_message(0, order_params(), 1_000))
+            .unwrap();
+
+        let placed = |user, uuid| DriftEvent::SignedMsgOrder {
+            user,
+            hash: "hash".into(),
+            order_params: order_params(),
+            user_order_id: 3,
+            max_slot: 1_000,
+            uuid,
+            ts: 1,
+            signature: "sig".into(),
+            tx_idx: 0,
+        };
+        let events = futures_util::stream::iter([
+            placed(order.taker_subaccount(), *b"otheruid"),
+            placed(Pubkey::new_unique(), order.uuid()),
+            placed(order.taker_subaccount(), order.uuid()),
+        ]);
+        assert_eq!(
+            wait_for_placement(events, &order, Duration::from_secs(1)).await,
+            SwiftOrderOutcome::Placed {
+                user_order_id: 3,
+                hash: "hash".into(),
+                signature: "sig".into(),
+                ts: 1,
+            }
+        );
+
+        let events = futures_util::stream::pending();
+        assert_eq!(
+            wait_for_placement(events, &order, Duration::from_millis(10)).await,
+            SwiftOrderOutcome::NotPlaced
+        );
+    }
+}
@@ -3,6 +3,7 @@ use std::{
     cmp::Ordering,
     fmt::Display,
     str::FromStr,
+    sync::Arc,
 };
 
 pub use crate::solana_sdk::{
@@ -32,6 +33,8 @@ use crate::{
     constants::{ids, LUTS_DEVNET, LUTS_MAINNET, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID},
     drift_idl::errors::ErrorCode,
     grpc::grpc_subscriber::GrpcError,
+    snapshot::{SnapshotAccount, StaleAccount},
+    swift_order_subscriber::SwiftError,
     types::accounts::UserStats,
     Wallet,
 };
@@ -62,6 +65,37 @@ pub type MapOf<K, V> = DashMap<K, V, ahash::RandomState>;
 /// Handle for unsubscribing from network updates
 pub type UnsubHandle = oneshot::Sender<()>;
 
+/// Refcounted handle to a shared subscription
+///
+/// Holds one reference to the subscription, clones share it.
+/// The reference is released when the last clone is dropped, the subscription ends once no references remain
+#[derive(Clone)]
+pub struct SubscriptionHandle(Arc<ReleaseOnDrop>);
+
+impl SubscriptionHandle {
+    pub(crate) fn new(release: impl FnOnce() + Send + Sync + 'static) -> Self {
+        Self(Arc::new(ReleaseOnDrop(Some(Box::new(release)))))
+    }
+}
+
+impl std::fmt::Debug for SubscriptionHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SubscriptionHandle")
+            .field("clones", &Arc::strong_count(&self.0))
+            .finish()
+    }
+}
+
+struct ReleaseOnDrop(Option<Box<dyn FnOnce() + Send + Sync>>);
+
+impl Drop for ReleaseOnDrop {
+    fn drop(&mut self) {
+        if let Some(release) = self.0.take() {
+            release();
+        }
+    }
+}
+
 pub type SdkResult<T> = Result<T, SdkError>;
 
 pub fn is_one_of_variant<T: PartialEq>(value: &T, variants: &[T]) -> bool {
@@ -231,6 +265,8 @@ pub struct NewOrder {
     amount: u64,
     price: u64,
     user_order_id: u8,
+    /// (builder_idx, builder_fee_tenth_bps)
+    builder: Option<(u8, u16)>,
 }
 
 impl NewOrder {
@@ -296,6 +332,39 @@ impl NewOrder {
         self.user_order_id = user_order_id;
         self
     }
+    /// Set a builder fee (swift orders only)
+    ///
+    /// * `builder_idx` - index of the builder in the taker's approved builders, see `RevenueShareEscrow::approved_builder_index`
+    /// * `fee_tenth_bps` - builder fee in tenths of a bps, must not exceed the builder's approved max fee
+    pub fn builder(mut self, builder_idx: u8, fee_tenth_bps: u16) -> Self {
+        self.builder = Some((builder_idx, fee_tenth_bps));
+        self
+    }
+    /// Complete building the Order as a swift order message, including any builder fee
+    ///
+    /// * `sub_account_id` - taker sub-account id
+    /// * `max_slot` - last slot the message may be placed at
+    /// * `uuid` - unique order message id
+    pub fn build_signed_msg(
+        self,
+        sub_account_id: u16,
+        max_slot: u64,
+        uuid: [u8; 8],
+    ) -> SignedMsgOrderParamsMessage {
+        let builder = self.builder;
+        SignedMsgOrderParamsMessage {
+            signed_msg_order_params: self.build(),
+            sub_account_id,
+            slot: max_slot,
+            uuid,
+            take_profit_order_params: None,
+            stop_loss_order_params: None,
+            max_margin_ratio: None,
+            builder_idx: builder.map(|(idx, _)| idx),
+            builder_fee_tenth_bps: builder.map(|(_, fee)| fee),
+            isolated_position_deposit: None,
+        }
+    }
     /// Call to complete building the Order
     pub fn build(self) -> OrderParams {
         OrderParams {
@@ -378,6 +447,16 @@ pub enum SdkError {
     WalletSigningDisabled,
     #[error("{0}")]
     Grpc(#[from] Box<GrpcError>),
+    #[error("{0}")]
+    Swift(#[from] Box<SwiftError>),
+    #[error("stale snapshot: {0:?}")]
+    StaleSnapshot(Vec<StaleAccount>),
+    #[error("stale account data: {account:?}, slot: {slot}, current slot: {current_slot}")]
+    StaleAccountData {
+        account: SnapshotAccount,
+        slot: u64,
+        current_slot: u64,
+    },
 }
 
 // Manual From implementations for unboxed error types to avoid breaking changes
@@ -411,6 +490,11 @@ impl From<GrpcError> for SdkError {
         SdkError::Grpc(Box::new(e))
     }
 }
+impl From<SwiftError> for SdkError {
+    fn from(e: SwiftError) -> Self {
+        SdkError::Swift(Box::new(e))
+    }
+}
 
 #[derive(Debug, PartialEq)]
 /// Solana program execution error
@@ -703,9 +787,30 @@ mod tests {
         response::RpcSimulateTransactionResult,
     };
 
-    use super::{RemainingAccount, SdkError};
+    use super::{RemainingAccount, SdkError, SubscriptionHandle};
     use crate::{drift_idl::errors::ErrorCode, types::ProgramError, MarketType};
 
+    #[test]
+    fn subscription_handle_releases_on_last_drop() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        let released = Arc::new(AtomicUsize::new(0));
+        let handle = SubscriptionHandle::new({
+            let released = Arc::clone(&released);
+            move || {
+                released.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        let clone = handle.clone();
+        drop(handle);
+        assert_eq!(released.load(Ordering::Relaxed), 0);
+        drop(clone);
+        assert_eq!(released.load(Ordering::Relaxed), 1);
+    }
+
     #[test]
     fn market_type_str() {
         assert_eq!(MarketType::from_str("PERP").unwrap(), MarketType::Perp,);
@@ -792,6 +897,72 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn builder_fee_orders() {
+        use super::{
+            accounts::RevenueShareEscrow, BuilderInfo, MarketId, NewOrder, RevenueShareOrder,
+        };
+
+        let builder = Pubkey::new_unique();
+        let escrow = RevenueShareEscrow {
+            approved_builders: vec![
+                BuilderInfo {
+                    authority: Pubkey::new_unique(),
+                    max_fee_tenth_bps: 10,
+                    ..Default::default()
+                },
+                BuilderInfo {
+                    authority: builder,
+                    max_fee_tenth_bps: 50,
+                    ..Default::default()
+                },
+            ],
+            orders: vec![
+                RevenueShareOrder {
+                    builder_idx: 1,
+                    fees_accrued: 100,
+                    bit_flags: RevenueShareOrder::OPEN_FLAG,
+                    ..Default::default()
+                },
+                RevenueShareOrder {
+                    builder_idx: 0,
+                    fees_accrued: 200,
+                    bit_flags: RevenueShareOrder::OPEN_FLAG,
+                    ..Default::default()
+                },
+                RevenueShareOrder {
+                    builder_idx: 1,
+                    fees_accrued: 300,
+                    bit_flags: RevenueShareOrder::COMPLETED_FLAG,
+                    ..Default::default()
+                },
+                RevenueShareOrder {
+                    builder_idx: 1,
+                    fees_accrued: 400,
+                    bit_flags: RevenueShareOrder::REFERRAL_FLAG,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(escrow.approved_builder_index(&builder), Some(1));
+        assert_eq!(escrow.approved_builder_max_fee(&builder), Some(50));
+        assert_eq!(escrow.accrued_builder_fees(&builder), 400);
+        assert_eq!(escrow.accrued_builder_fees(&Pubkey::new_unique()), 0);
+
+        let message = NewOrder::market(MarketId::perp(1))
+            .amount(1_000)
+            .builder(1, 25)
+            .build_signed_msg(2, 500, [1; 8]);
+        assert_eq!(message.builder_idx, Some(1));
+        assert_eq!(message.builder_fee_tenth_bps, Some(25));
+        assert_eq!(message.sub_account_id, 2);
+        assert_eq!(message.signed_msg_order_params.market_index, 1);
+
+        let message = NewOrder::market(MarketId::perp(1)).build_signed_msg(0, 500, [1; 8]);
+        assert!(message.builder_idx.is_none() && message.builder_fee_tenth_bps.is_none());
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -812,6 +983,55 @@ pub type OnAccountFn = dyn Fn(&AccountUpdate) + Send + Sync + 'static;
 /// Empty callback function pointer that does nothing - useful as a no-op callback
 pub const EMPTY_ACCOUNT_CALLBACK: fn(&AccountUpdate) = |_: &AccountUpdate| {};
 
+/// A builder's revenue from builder codes (QUOTE_PRECISION)
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct BuilderRevenue {
+    /// builder fees settled to the builder
+    pub settled: u64,
+    /// referral rewards settled to the builder
+    pub settled_referrer_rewards: u64,
+    /// builder fees accrued on taker orders, pending settlement
+    pub accrued: u64,
+}
+
+impl RevenueShareOrder {
+    pub const OPEN_FLAG: u8 = 0b0000_0010;
+    pub const COMPLETED_FLAG: u8 = 0b0000_0100;
+    pub const REFERRAL_FLAG: u8 = 0b0000_1000;
+    /// true if the entry tracks referral rewards rather than a builder order
+    pub fn is_referral(&self) -> bool {
+        (self.bit_flags & Self::REFERRAL_FLAG) != 0
+    }
+}
+
+impl accounts::RevenueShareEscrow {
+    /// Index of `builder` in the approved builders list, for use as an order's `builder_idx`
+    pub fn approved_builder_index(&self, builder: &Pubkey) -> Option<u8> {
+        self.approved_builders
+            .iter()
+            .position(|b| b.authority == *builder)
+            .map(|idx| idx as u8)
+    }
+    /// Max fee `builder` is approved to charge in tenths of a bps, if approved
+    pub fn approved_builder_max_fee(&self, builder: &Pubkey) -> Option<u16> {
+        self.approved_builders
+            .iter()
+            .find(|b| b.authority == *builder)
+            .map(|b| b.max_fee_tenth_bps)
+    }
+    /// Builder fees accrued by `builder` on this escrow's orders, pending settlement
+    pub fn accrued_builder_fees(&self, builder: &Pubkey) -> u64 {
+        let Some(builder_idx) = self.approved_builder_index(builder) else {
+            return 0;
+        };
+        self.orders
+            .iter()
+            .filter(|o| !o.is_referral() && o.builder_idx == builder_idx)
+            .map(|o| o.fees_accrued)
+            .sum()
+    }
+}
+
 impl accounts::State {
     pub const MM_ORACLE_UPDATE_FLAG: u8 = 0b00000001;
     pub const MEDIAN_TRIGGER_PRICE_FLAG: u8 = 0b00000010;
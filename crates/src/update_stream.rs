@@ -0,0 +1,122 @@
+//! Typed account update streams
+//!
+//! Subscribed maps publish each applied Ws or gRPC update to a bounded broadcast channel.
+//! Consumers poll updates as a `Stream` on their own task instead of running callbacks on the subscriber task.
+//! A consumer that falls more than `UPDATE_CHANNEL_CAPACITY` updates behind skips the oldest ones,
+//! skipped updates are logged and counted by `UpdateStream::lagged`
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use futures_util::{stream, Stream};
+use tokio::sync::broadcast;
+
+use crate::solana_sdk::{clock::Slot, pubkey::Pubkey};
+
+const LOG_TARGET: &str = "updates";
+
+/// Number of updates buffered for each consumer
+pub const UPDATE_CHANNEL_CAPACITY: usize = 1_024;
+
+/// Raw account data published by `AccountMap`
+#[derive(Clone, Debug)]
+pub struct RawAccountUpdate {
+    pub pubkey: Pubkey,
+    pub slot: Slot,
+    /// account data including discriminator
+    pub data: Arc<[u8]>,
+}
+
+/// Stream of updates from a broadcast channel
+///
+/// Ends when the publishing map is dropped
+pub struct UpdateStream<T> {
+    inner: Pin<Box<dyn Stream<Item = T> + Send>>,
+    lagged: Arc<AtomicU64>,
+}
+
+impl<T: Send + 'static> UpdateStream<T> {
+    /// Create a stream over `updates`, yielding only those `f` maps to `Some`
+    pub(crate) fn new<U, F>(updates: broadcast::Receiver<U>, f: F) -> Self
+    where
+        U: Clone + Send + 'static,
+        F: FnMut(U) -> Option<T> + Send + 'static,
+    {
+        let lagged = Arc::new(AtomicU64::new(0));
+        let inner = stream::unfold(
+            (updates, f, Arc::clone(&lagged)),
+            |(mut updates, mut f, lagged)| async move {
+                loop {
+                    match updates.recv().await {
+                        Ok(update) => {
+                            if let Some(item) = f(update) {
+                                return Some((item, (updates, f, lagged)));
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            log::warn!(target: LOG_TARGET, "consumer lagged, skipped {skipped} updates");
+                            lagged.fetch_add(skipped, Ordering::Relaxed);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            },
+        );
+
+        Self {
+            inner: Box::pin(inner),
+            lagged,
+        }
+    }
+}
+
+impl<T> UpdateStream<T> {
+    /// Total number of updates skipped because the consumer fell behind
+    pub fn lagged(&self) -> u64 {
+        self.lagged.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Stream for UpdateStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+/// Publish `update` if anyone is listening
+#[inline]
+pub(crate) fn publish<U>(updates: &broadcast::Sender<U>, update: impl FnOnce() -> U) {
+    if updates.receiver_count() > 0 {
+        let _ = updates.send(update());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_util::StreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn stream_filters_and_reports_lag() {
+        let (tx, rx) = broadcast::channel::<u64>(2);
+        let mut updates = UpdateStream::new(rx, |x| (x % 2 == 0).then_some(x));
+
+        for x in 0..5 {
+            tx.send(x).unwrap();
+        }
+        drop(tx);
+
+        // 0..=2 overwritten, 3 filtered
+        assert_eq!(updates.next().await, Some(4));
+        assert_eq!(updates.lagged(), 3);
+        assert_eq!(updates.next().await, None);
+    }
+}
@@ -0,0 +1,178 @@
+//! Query layer over cached `UserStats` accounts
+//!
+//! Stats accounts are cached by `AccountMap`, either streamed with gRPC (`GrpcSubscribeOpts::statsmap_on`)
+//! or loaded via `sync`/`sync_referrals`
+use ahash::HashSet;
+use solana_rpc_client_api::filter::RpcFilterType;
+
+use crate::{
+    account_map::AccountMap,
+    math::fees::{decay_30d_volume, determine_user_fee_tier},
+    memcmp::{get_user_stats_is_referred_filter, get_user_stats_is_referred_or_referrer_filter},
+    solana_sdk::pubkey::Pubkey,
+    types::{
+        accounts::{State, UserStats},
+        DataAndSlot, FeeTier, MarketType, SdkResult,
+    },
+    Wallet,
+};
+
+/// 30d volumes of a user, expo = -6
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Volume30d {
+    pub maker: u64,
+    pub taker: u64,
+    pub filler: u64,
+}
+
+impl Volume30d {
+    /// Maker plus taker volume, as used for fee tiers
+    pub fn total(&self) -> u64 {
+        self.maker.saturating_add(self.taker)
+    }
+}
+
+/// Resolves referrals, volumes and fee tiers of users by authority
+pub struct UserStatsMap<'a> {
+    account_map: &'a AccountMap,
+}
+
+impl<'a> UserStatsMap<'a> {
+    /// Create a new `UserStatsMap` over the stats accounts cached by `account_map`
+    pub fn new(account_map: &'a AccountMap) -> Self {
+        Self { account_map }
+    }
+
+    /// Load all `UserStats` accounts over RPC
+    pub async fn sync(&self) -> SdkResult<()> {
+        self.account_map.sync_stats_accounts().await
+    }
+
+    /// Load `UserStats` accounts of referred users over RPC
+    ///
+    /// Sufficient to resolve referrers of all users
+    pub async fn sync_referrals(&self) -> SdkResult<()> {
+        let [referred, referred_referrers] = referral_filters();
+        let (referred, referred_referrers) = tokio::join!(
+            self.account_map
+                .sync_stats_accounts_with_filters(vec![referred]),
+            self.account_map
+                .sync_stats_accounts_with_filters(vec![referred_referrers]),
+        );
+        referred.and(referred_referrers)
+    }
+
+    /// Return the `UserStats` of `authority`, if cached
+    pub fn get(&self, authority: &Pubkey) -> Option<DataAndSlot<UserStats>> {
+        self.account_map
+            .account_data_and_slot(&Wallet::derive_stats_account(authority))
+    }
+
+    /// Return the referrer authority of `authority`, if it was referred
+    pub fn referrer(&self, authority: &Pubkey) -> Option<Pubkey> {
+        self.get(authority)
+            .map(|stats| stats.data)
+            .filter(|stats| stats.is_referred() && stats.referrer != Pubkey::default())
+            .map(|stats| stats.referrer)
+    }
+
+    /// Return the chain of referrers above `authority`, nearest first
+    ///
+    /// Stops at the first user that was not referred or whose stats are not cached
+    pub fn referrer_chain(&self, authority: &Pubkey) -> Vec<Pubkey> {
+        let mut chain = vec![];
+        let mut seen = HashSet::from_iter([*authority]);
+        let mut current = *authority;
+        while let Some(referrer) = self.referrer(&current) {
+            if !seen.insert(referrer) {
+                break;
+            }
+            chain.push(referrer);
+            current = referrer;
+        }
+        chain
+    }
+
+    /// Return the authorities of all cached users referred by `referrer`
+    pub fn referees(&self, referrer: &Pubkey) -> Vec<Pubkey> {
+        let mut referees = vec![];
+        self.account_map
+            .iter_accounts_with::<UserStats>(|_pubkey, stats, _slot| {
+                if stats.is_referred() && &stats.referrer == referrer {
+                    referees.push(stats.authority);
+                }
+            });
+        referees
+    }
+
+    /// Return the 30d volumes of `authority` decayed to unix timestamp `now`
+    pub fn volume_30d(&self, authority: &Pubkey, now: i64) -> Option<Volume30d> {
+        self.get(authority)
+            .map(|DataAndSlot { data: stats, .. }| Volume30d {
+                maker: decay_30d_volume(stats.maker_volume30d, stats.last_maker_volume30d_ts, now),
+                taker: decay_30d_volume(stats.taker_volume30d, stats.last_taker_volume30d_ts, now),
+                filler: decay_30d_volume(
+                    stats.filler_volume30d,
+                    stats.last_filler_volume30d_ts,
+                    now,
+                ),
+            })
+    }
+
+    /// Return the fee tier `authority` qualifies for in markets of `market_type`
+    ///
+    /// Uses the volumes last recorded on-chain, as the program does when filling.
+    /// Returns `None` if the user's stats are not cached
+    ///
+    /// * `high_leverage_mode` - the trading sub-account is in high leverage mode
+    pub fn fee_tier(
+        &self,
+        authority: &Pubkey,
+        market_type: MarketType,
+        state: &State,
+        high_leverage_mode: bool,
+    ) -> Option<FeeTier> {
+        let fee_structure = match market_type {
+            MarketType::Perp => &state.perp_fee_structure,
+            MarketType::Spot => &state.spot_fee_structure,
+        };
+        self.get(authority).map(|stats| {
+            determine_user_fee_tier(&stats.data, fee_structure, market_type, high_leverage_mode)
+        })
+    }
+}
+
+/// GPA filters of `sync_referrals`, one per referrer status of referred users
+///
+/// memcmp matches the status exactly, so referred users and referred referrers need a query each
+fn referral_filters() -> [RpcFilterType; 2] {
+    [
+        get_user_stats_is_referred_filter(),
+        get_user_stats_is_referred_or_referrer_filter(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::AccountSerialize;
+
+    use super::*;
+
+    #[test]
+    fn referral_filters_match_all_referred_users() {
+        for referrer_status in 0..4_u8 {
+            let stats = UserStats {
+                referrer_status,
+                ..Default::default()
+            };
+            let mut data = vec![];
+            stats.try_serialize(&mut data).unwrap();
+
+            let matched = referral_filters().iter().any(|filter| match filter {
+                RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(&data),
+                _ => false,
+            });
+            assert_eq!(matched, stats.is_referred(), "status: {referrer_status}");
+        }
+    }
+}
@@ -11,7 +11,10 @@ use crate::solana_sdk::{
 
 use crate::{
     constants::{self},
-    types::{accounts::SpotMarket, SdkError, SdkResult},
+    types::{
+        accounts::{LPPool, SpotMarket},
+        SdkError, SdkResult,
+    },
     utils,
 };
 
@@ -159,6 +162,15 @@ impl Wallet {
         account_drift_pda
     }
 
+    /// Calculate the address of `authority`s swift Ws delegates account
+    pub fn derive_swift_ws_delegates_account(authority: &Pubkey) -> Pubkey {
+        let (account_drift_pda, _seed) = Pubkey::find_program_address(
+            &[&b"SIGNED_MSG_WS_DELEGATES"[..], authority.as_ref()],
+            &constants::PROGRAM_ID,
+        );
+        account_drift_pda
+    }
+
     /// Calculate the wallet's ATA for drift spot market
     pub fn derive_associated_token_address(authority: &Pubkey, market: &SpotMarket) -> Pubkey {
         spl_associated_token_account::get_associated_token_address_with_program_id(
@@ -168,6 +180,15 @@ impl Wallet {
         )
     }
 
+    /// Calculate the wallet's ATA for the LP token of `lp_pool`
+    pub fn derive_lp_token_account(authority: &Pubkey, lp_pool: &LPPool) -> Pubkey {
+        spl_associated_token_account::get_associated_token_address_with_program_id(
+            authority,
+            &lp_pool.mint,
+            &constants::TOKEN_PROGRAM_ID,
+        )
+    }
+
     /// Signs a solana message (ixs, accounts) and builds a signed tx
     /// ready for sending over RPC
     ///
@@ -1,23 +1,40 @@
-use std::{str::FromStr, sync::Arc};
+use std::{str::FromStr, sync::Arc, time::Duration};
 
 use crate::solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
-use drift_pubsub_client::PubsubClient;
+use drift_pubsub_client::{ConnectionState, PubsubClient};
 use futures_util::StreamExt;
 use log::warn;
 use solana_account_decoder_client_types::UiAccountEncoding;
 use solana_rpc_client::nonblocking::rpc_client::RpcClient;
 use solana_rpc_client_api::config::RpcAccountInfoConfig;
-use tokio::sync::oneshot;
+use tokio::sync::{oneshot, Notify};
 
 use crate::{utils::get_http_url, AccountUpdate, SdkError, SdkResult, UnsubHandle};
 
 const LOG_TARGET: &str = "wsaccsub";
 
+/// Delay before retrying a failed Ws subscription, paced by `pubsub`'s reconnect policy
+///
+/// * `attempt` - consecutive failed attempts, starting at 1
+///
+/// Returns `None` if `pubsub` failed for good or was shutdown, the subscription should end
+pub(crate) fn resubscribe_delay(pubsub: &PubsubClient, attempt: u32) -> Option<Duration> {
+    match pubsub.connection_state() {
+        ConnectionState::Failed | ConnectionState::Shutdown => None,
+        _ => {
+            let policy = pubsub.reconnect_policy();
+            Some(policy.delay(attempt).unwrap_or(policy.max_delay))
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct WebsocketAccountSubscriber {
     pubsub: Arc<PubsubClient>,
     pub pubkey: Pubkey,
     pub commitment: CommitmentConfig,
+    /// signals the subscription task to restart its Ws subscription
+    resubscribe: Arc<Notify>,
 }
 
 impl WebsocketAccountSubscriber {
@@ -26,16 +43,23 @@ impl WebsocketAccountSubscriber {
             pubsub,
             pubkey,
             commitment,
+            resubscribe: Arc::default(),
         }
     }
 
+    /// Restart the Ws subscription e.g. if it has stopped receiving updates
+    pub fn resubscribe(&self) {
+        self.resubscribe.notify_one();
+    }
+
     /// Start a Ws account subscription task
     ///
     /// * `subscription_name` - some user defined identifier for the subscription
     /// * `sync` - true if subscription should fetch account data on start
     /// * `on_update` - function to call on updates from the subscription
     ///
-    /// Fetches the account to set the initial value, then uses event based updates
+    /// Fetches the account to set the initial value, then uses event based updates.
+    /// The task ends if the `PubsubClient` fails for good, closing the returned `UnsubHandle`
     pub async fn subscribe<F>(
         &self,
         subscription_name: &'static str,
@@ -89,8 +113,10 @@ impl WebsocketAccountSubscriber {
         };
         let pubkey = self.pubkey;
         let pubsub = Arc::clone(&self.pubsub);
+        let resubscribe = Arc::clone(&self.resubscribe);
 
         tokio::spawn(async move {
+            let mut attempt = 0;
             loop {
                 log::debug!(
                     target: LOG_TARGET,
@@ -101,13 +127,28 @@ impl WebsocketAccountSubscriber {
                     .account_subscribe(&pubkey, Some(account_config.clone()))
                     .await
                 {
-                    Ok(res) => res,
+                    Ok(res) => {
+                        attempt = 0;
+                        res
+                    }
                     Err(err) => {
+                        attempt += 1;
+                        let Some(delay) = resubscribe_delay(&pubsub, attempt) else {
+                            log::error!(
+                                target: LOG_TARGET,
+                                "{subscription_name}: Ws client failed, ending subscription: {pubkey:?}, {err:?}"
+                            );
+                            break;
+                        };
                         log::error!(
                             target: LOG_TARGET,
-                            "account subscribe {pubkey} failed: {err:?}"
+                            "account subscribe {pubkey} failed, retry in {delay:?}: {err:?}"
                         );
-                        continue;
+                        tokio::select! {
+                            biased;
+                            _ = &mut unsub_rx => break,
+                            _ = tokio::time::sleep(delay) => continue,
+                        }
                     }
                 };
                 log::debug!(
@@ -136,7 +177,7 @@ impl WebsocketAccountSubscriber {
                                     }
                                 }
                                 None => {
-                                    log::warn!(target: LOG_TARGET, "{subscription_name}: Ws ended unexpectedly: {pubkey:?}");
+                                    log::warn!(target: LOG_TARGET, "{subscription_name}: Ws ended unexpectedly: {pubkey:?}, {:?}", account_updates.take_error());
                                     break Err(());
                                 }
                             }
@@ -146,6 +187,11 @@ impl WebsocketAccountSubscriber {
                             account_unsubscribe().await;
                             break Ok(());
                         }
+                        _ = resubscribe.notified() => {
+                            log::warn!(target: LOG_TARGET, "{subscription_name}: resubscribing account stream: {pubkey:?}");
+                            account_unsubscribe().await;
+                            break Err(());
+                        }
                     }
                 };
 
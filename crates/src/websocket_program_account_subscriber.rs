@@ -12,7 +12,9 @@ use solana_rpc_client_api::{
 };
 use tokio::sync::oneshot;
 
-use crate::{constants, types::DataAndSlot, UnsubHandle};
+use crate::{
+    constants, types::DataAndSlot, websocket_account_subscriber::resubscribe_delay, UnsubHandle,
+};
 
 #[derive(Clone, Debug)]
 pub struct ProgramAccountUpdate<T: AnchorDeserialize + Send> {
@@ -73,22 +75,38 @@ impl WebsocketProgramAccountSubscriber {
 
         tokio::spawn(async move {
             let mut latest_slot = 0;
+            let mut attempt = 0;
             loop {
                 let pubsub = match PubsubClient::new(&url).await {
                     Ok(pubsub) => pubsub,
                     Err(err) => {
-                        log::error!("GPA stream connect failed: {err:?}");
-                        continue;
+                        // invalid url, retrying will not help
+                        log::error!("{subscription_name}: GPA stream connect failed: {err:?}");
+                        break;
                     }
                 };
                 let (mut accounts, unsub) = match pubsub
                     .program_subscribe(&constants::PROGRAM_ID, Some(config.clone()))
                     .await
                 {
-                    Ok(res) => res,
+                    Ok(res) => {
+                        attempt = 0;
+                        res
+                    }
                     Err(err) => {
-                        log::error!("GPA stream subscribe failed: {err:?}");
-                        continue;
+                        attempt += 1;
+                        let Some(delay) = resubscribe_delay(&pubsub, attempt) else {
+                            log::error!(
+                                "{subscription_name}: Ws client failed, ending GPA stream: {err:?}"
+                            );
+                            break;
+                        };
+                        log::error!("{subscription_name}: GPA stream subscribe failed, retry in {delay:?}: {err:?}");
+                        tokio::select! {
+                            biased;
+                            _ = &mut unsub_rx => break,
+                            _ = tokio::time::sleep(delay) => continue,
+                        }
                     }
                 };
 
@@ -108,7 +126,7 @@ impl WebsocketProgramAccountSubscriber {
                                     }
                                 },
                                 None => {
-                                    log::warn!("{subscription_name}: Ws GPA stream ended unexpectedly");
+                                    log::warn!("{subscription_name}: Ws GPA stream ended unexpectedly: {:?}", accounts.take_error());
                                     break Err(());
                                 }
                             }
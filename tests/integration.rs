@@ -5,7 +5,9 @@ use drift_rs::{
     constants::DEFAULT_PUBKEY,
     event_subscriber::RpcClient,
     grpc::grpc_subscriber::AccountFilter,
-    math::constants::{BASE_PRECISION_I64, LAMPORTS_PER_SOL_I64, PRICE_PRECISION_U64},
+    math::constants::{
+        BASE_PRECISION_I64, LAMPORTS_PER_SOL_I64, PRICE_PRECISION_U64, QUOTE_PRECISION,
+    },
     types::{
         accounts::User, solana_sdk::clock::Slot, Context, MarketId, MarketType, NewOrder,
         OrderParams, OrderType, PositionDirection, PostOnlyParam, SettlePnlMode,
@@ -501,3 +503,31 @@ async fn place_order_sim_via_privy_account() {
         }
     }
 }
+
+#[ignore = "requires an lp pool on devnet"]
+#[tokio::test]
+async fn lp_pool_quotes_devnet() {
+    let _ = env_logger::try_init();
+    let client = DriftClient::new(
+        Context::DevNet,
+        RpcClient::new(devnet_endpoint()),
+        test_keypair().into(),
+    )
+    .await
+    .expect("connects");
+    let lp_pool = client.get_lp_pool(0).await.expect("lp pool exists");
+
+    let mint = client
+        .quote_lp_pool_add_liquidity(&lp_pool, 0, 1_000 * QUOTE_PRECISION)
+        .await
+        .expect("quotes");
+    dbg!(&mint);
+    assert!(mint.lp_amount > 0);
+
+    let redeem = client
+        .quote_lp_pool_remove_liquidity(&lp_pool, 0, mint.lp_amount)
+        .await
+        .expect("quotes");
+    dbg!(&redeem);
+    assert!(redeem.out_amount > 0);
+}